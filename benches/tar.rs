@@ -0,0 +1,232 @@
+//! Benchmarks of opening archives and looking up entries, on the synthetic archives
+//! of [`vfs_tar::test_util`].
+//!
+//! The large archive has 100000 entries by default, and the count could be set by
+//! `VFS_TAR_BENCH_ENTRIES`, e.g. to a million.
+//!
+//! The allocations are counted, and the `deep` group fails if a lookup allocates
+//! for every component of the path. The `overlay` group fails if a small write to
+//! a large file allocates nearly as much as copying it, like a naive copy-on-write,
+//! or if writing a file in small chunks copies the written data again for every chunk.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    io::{Seek, SeekFrom, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use vfs::FileSystem;
+use vfs_tar::{test_util, TarFS, TarOverlayFS};
+
+/// The system allocator, counting the allocations and the allocated bytes.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Count the allocations in `f`.
+fn allocations(f: impl FnOnce()) -> usize {
+    let start = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - start
+}
+
+/// Count the bytes allocated in `f`.
+fn allocated(f: impl FnOnce()) -> usize {
+    let start = ALLOCATED.load(Ordering::Relaxed);
+    f();
+    ALLOCATED.load(Ordering::Relaxed) - start
+}
+
+/// The count of the lookups in an iteration.
+const LOOKUPS: usize = 1000;
+
+fn entries() -> usize {
+    std::env::var("VFS_TAR_BENCH_ENTRIES")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(100000)
+}
+
+/// Sample the paths of the files evenly.
+fn sample_paths(fs: &TarFS<Vec<u8>>) -> Vec<String> {
+    let count = fs.entries().count();
+    fs.entries()
+        .step_by((count / LOOKUPS).max(1))
+        .map(|(path, ..)| format!("/{path}"))
+        .collect()
+}
+
+fn open(c: &mut Criterion) {
+    let count = entries();
+    let archive = test_util::large_tree(count, 100);
+    let mut group = c.benchmark_group("open");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(count as u64));
+    group.bench_function("large_tree", |b| {
+        b.iter(|| TarFS::from_slice(black_box(&archive)).unwrap())
+    });
+    group.finish();
+}
+
+fn lookup(c: &mut Criterion) {
+    let fs = TarFS::new(test_util::large_tree(entries(), 100)).unwrap();
+    let paths = sample_paths(&fs);
+    let mut group = c.benchmark_group("lookup");
+    group.throughput(Throughput::Elements(paths.len() as u64));
+    // A clone starts with empty lookup caches.
+    group.bench_function("cold", |b| {
+        b.iter_batched(
+            || fs.clone(),
+            |fs| {
+                for path in &paths {
+                    black_box(fs.metadata(path).unwrap());
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            for path in &paths {
+                black_box(fs.metadata(path).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn deep(c: &mut Criterion) {
+    let lookup = |depth: usize| {
+        let fs = TarFS::new(test_util::deep_tree(depth)).unwrap();
+        let path = (0..depth).map(|i| format!("/{i}")).collect::<String>() + "/file";
+        (fs, path)
+    };
+    let (fs, path) = lookup(256);
+    // A miss allocates only the entry of the lookup cache, however deep the path is.
+    let (shallow, shallow_path) = lookup(1);
+    let cold = allocations(|| assert!(fs.clone().exists(&path).unwrap()));
+    let shallow_cold = allocations(|| assert!(shallow.clone().exists(&shallow_path).unwrap()));
+    assert_eq!(cold, shallow_cold, "a lookup allocates per component");
+    // A hit allocates nothing.
+    fs.exists(&path).unwrap();
+    assert_eq!(allocations(|| assert!(fs.exists(&path).unwrap())), 0);
+
+    let mut group = c.benchmark_group("deep");
+    group.bench_function("cold", |b| {
+        b.iter_batched(
+            || fs.clone(),
+            |fs| black_box(fs.exists(&path).unwrap()),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| black_box(fs.exists(&path).unwrap()))
+    });
+    group.finish();
+}
+
+fn read_dir(c: &mut Criterion) {
+    let count = entries();
+    let fs = TarFS::new(test_util::many_files(count)).unwrap();
+    let mut group = c.benchmark_group("read_dir");
+    group.throughput(Throughput::Elements(count as u64));
+    group.bench_function("wide", |b| {
+        b.iter(|| fs.read_dir("/files").unwrap().count())
+    });
+    group.finish();
+}
+
+fn symlinks(c: &mut Criterion) {
+    let depth = 32;
+    let fs = TarFS::new(test_util::symlink_chains(LOOKUPS, depth)).unwrap();
+    let paths = (0..LOOKUPS)
+        .map(|i| format!("/links/{i}/{}", depth - 1))
+        .collect::<Vec<_>>();
+    let mut group = c.benchmark_group("symlinks");
+    group.throughput(Throughput::Elements((LOOKUPS * depth) as u64));
+    group.bench_function("chains", |b| {
+        b.iter_batched(
+            || fs.clone(),
+            |fs| {
+                for path in &paths {
+                    black_box(fs.metadata(path).unwrap());
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn overlay(c: &mut Criterion) {
+    const LEN: usize = 16 << 20;
+    let archive = test_util::ArchiveBuilder::new()
+        .file("data.bin", &vec![1; LEN])
+        .finish();
+    let fs = TarFS::new(archive).unwrap();
+    // Write a few bytes in the middle of the large file.
+    let patch = |fs: &TarFS<Vec<u8>>| {
+        let overlay = TarOverlayFS::new(fs.clone());
+        let mut file = overlay.append_file("/data.bin").unwrap();
+        file.seek(SeekFrom::Start(LEN as u64 / 2)).unwrap();
+        file.write_all(b"patch").unwrap();
+        drop(file);
+        overlay
+    };
+    // A naive copy-on-write copies the whole file before writing.
+    let copy = |fs: &TarFS<Vec<u8>>| {
+        let mut copy = fs.file_slice("/data.bin").unwrap().to_vec();
+        copy[LEN / 2..LEN / 2 + 5].copy_from_slice(b"patch");
+        copy
+    };
+    let patched = allocated(|| drop(black_box(patch(&fs))));
+    let copied = allocated(|| drop(black_box(copy(&fs))));
+    assert!(
+        patched * 100 < copied,
+        "a write allocates {patched} bytes, and a copy allocates {copied} bytes"
+    );
+
+    // Write a new file sequentially in small chunks, which grows a single patch.
+    const CHUNKED_LEN: usize = 4 << 20;
+    let data = vec![2; CHUNKED_LEN];
+    let chunked = |fs: &TarFS<Vec<u8>>| {
+        let overlay = TarOverlayFS::new(fs.clone());
+        let mut file = overlay.create_file("/new.bin").unwrap();
+        for chunk in data.chunks(4096) {
+            file.write_all(chunk).unwrap();
+        }
+        drop(file);
+        overlay
+    };
+    let written = allocated(|| drop(black_box(chunked(&fs))));
+    assert!(
+        written < 4 * CHUNKED_LEN,
+        "the chunked writes of {CHUNKED_LEN} bytes allocate {written} bytes"
+    );
+
+    let mut group = c.benchmark_group("overlay");
+    group.bench_function("patch", |b| b.iter(|| patch(&fs)));
+    group.bench_function("copy", |b| b.iter(|| copy(&fs)));
+    group.bench_function("chunked", |b| b.iter(|| chunked(&fs)));
+    group.finish();
+}
+
+criterion_group!(benches, open, lookup, deep, read_dir, symlinks, overlay);
+criterion_main!(benches);
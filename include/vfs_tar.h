@@ -0,0 +1,40 @@
+#ifndef VFS_TAR_H
+#define VFS_TAR_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A readonly tar archive. */
+typedef struct VfsTar VfsTar;
+
+typedef void (*VfsTarDirCallback)(const char *name, void *user_data);
+
+/* Open an archive from a path. Returns NULL on failure. */
+VfsTar *vfs_tar_open(const char *path);
+
+/* Open an archive from a buffer, which is copied. Returns NULL on failure. */
+VfsTar *vfs_tar_open_buffer(const uint8_t *data, size_t len);
+
+/* Close an archive. NULL is ignored. */
+void vfs_tar_free(VfsTar *fs);
+
+/* Call `callback` with each name in a directory. Returns 0 on success, or -1 if not found. */
+int vfs_tar_read_dir(const VfsTar *fs, const char *path, VfsTarDirCallback callback,
+                     void *user_data);
+
+/* Get the size of a file. Returns 0 on success, or -1 if not found. */
+int vfs_tar_file_size(const VfsTar *fs, const char *path, uint64_t *size);
+
+/* Read a file from `offset` into `buf`. Returns the count of bytes read, or -1 if not found. */
+ptrdiff_t vfs_tar_read_file(const VfsTar *fs, const char *path, uint64_t offset, uint8_t *buf,
+                            size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
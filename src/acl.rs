@@ -0,0 +1,158 @@
+//! POSIX ACLs stored in the `SCHILY.acl.access` and `SCHILY.acl.default` PAX records
+//! by star, GNU tar and bsdtar.
+
+use std::fmt::Display;
+
+/// The tag of an ACL entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclTag {
+    /// The owner of the file, `user::`.
+    UserObj,
+    /// A named user.
+    User,
+    /// The owning group of the file, `group::`.
+    GroupObj,
+    /// A named group.
+    Group,
+    /// The maximum permissions of the named users and the groups.
+    Mask,
+    /// The others.
+    Other,
+}
+
+/// An entry of an ACL, like `user:alice:rw-:1000`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AclEntry {
+    /// The tag.
+    pub tag: AclTag,
+    /// The name of the user or the group, for [`AclTag::User`] and [`AclTag::Group`].
+    pub qualifier: Option<String>,
+    /// The numeric id of the user or the group, appended by star and GNU tar.
+    pub id: Option<u32>,
+    /// The permission bits, `0o4` for read, `0o2` for write and `0o1` for execute.
+    pub permissions: u32,
+}
+
+/// The POSIX ACLs of an entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PosixAcl {
+    /// The access ACL, from `SCHILY.acl.access`.
+    pub access: Vec<AclEntry>,
+    /// The default ACL of a directory, from `SCHILY.acl.default`.
+    pub default: Vec<AclEntry>,
+}
+
+impl PosixAcl {
+    /// Parse the texts of the access and the default ACLs.
+    /// The entries that can't be parsed are skipped.
+    pub(crate) fn parse(access: Option<&str>, default: Option<&str>) -> Option<Self> {
+        if access.is_none() && default.is_none() {
+            return None;
+        }
+        Some(Self {
+            access: access.map(parse_entries).unwrap_or_default(),
+            default: default.map(parse_entries).unwrap_or_default(),
+        })
+    }
+}
+
+/// Parse the entries separated by commas or newlines. The comments start with `#`.
+fn parse_entries(text: &str) -> Vec<AclEntry> {
+    text.split([',', '\n'])
+        .map(|entry| entry.split('#').next().unwrap_or_default().trim())
+        .filter(|entry| !entry.is_empty())
+        .filter_map(parse_entry)
+        .collect()
+}
+
+fn parse_entry(entry: &str) -> Option<AclEntry> {
+    let mut fields = entry.split(':');
+    let tag = fields.next()?;
+    let qualifier = fields.next()?;
+    let permissions = fields.next()?;
+    let id = fields.next().and_then(|id| id.parse().ok());
+    let named = !qualifier.is_empty();
+    let tag = match tag {
+        "user" | "u" if named => AclTag::User,
+        "user" | "u" => AclTag::UserObj,
+        "group" | "g" if named => AclTag::Group,
+        "group" | "g" => AclTag::GroupObj,
+        "mask" | "m" => AclTag::Mask,
+        "other" | "o" => AclTag::Other,
+        _ => return None,
+    };
+    let permissions = permissions.chars().try_fold(0, |bits, c| match c {
+        'r' => Some(bits | 0o4),
+        'w' => Some(bits | 0o2),
+        'x' => Some(bits | 0o1),
+        '-' => Some(bits),
+        _ => None,
+    })?;
+    Some(AclEntry {
+        tag,
+        qualifier: named.then(|| qualifier.to_string()),
+        id,
+        permissions,
+    })
+}
+
+impl Display for AclEntry {
+    /// Format the entry in the long text form, with the id appended if known.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tag = match self.tag {
+            AclTag::UserObj | AclTag::User => "user",
+            AclTag::GroupObj | AclTag::Group => "group",
+            AclTag::Mask => "mask",
+            AclTag::Other => "other",
+        };
+        let bit = |mask, c| if self.permissions & mask != 0 { c } else { '-' };
+        write!(
+            f,
+            "{tag}:{}:{}{}{}",
+            self.qualifier.as_deref().unwrap_or_default(),
+            bit(0o4, 'r'),
+            bit(0o2, 'w'),
+            bit(0o1, 'x')
+        )?;
+        if let Some(id) = self.id {
+            write!(f, ":{id}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{test_util::ArchiveBuilder, AclTag, TarFS};
+
+    #[test]
+    fn acl() {
+        let archive = ArchiveBuilder::new()
+            .pax(&[
+                (
+                    "SCHILY.acl.access",
+                    "user::rw-,user:alice:r--:1000,group::r--,mask::r--,other::---",
+                ),
+                ("SCHILY.acl.default", "u::rwx\ng:staff:r-x #comment\no::r-x"),
+            ])
+            .dir("dir")
+            .file("plain", b"")
+            .finish();
+        let fs = TarFS::new(archive).unwrap();
+
+        let acl = fs.header_for("/dir").unwrap().acl.unwrap();
+        assert_eq!(acl.access.len(), 5);
+        assert_eq!(acl.access[1].tag, AclTag::User);
+        assert_eq!(acl.access[1].qualifier.as_deref(), Some("alice"));
+        assert_eq!(acl.access[1].id, Some(1000));
+        assert_eq!(acl.access[1].permissions, 0o4);
+        assert_eq!(acl.access[1].to_string(), "user:alice:r--:1000");
+        assert_eq!(acl.access[4].to_string(), "other::---");
+        assert_eq!(acl.default.len(), 3);
+        assert_eq!(acl.default[0].tag, AclTag::UserObj);
+        assert_eq!(acl.default[1].to_string(), "group:staff:r-x");
+        assert_eq!(fs.header_for("/plain").unwrap().acl, None);
+    }
+}
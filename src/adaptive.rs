@@ -0,0 +1,110 @@
+//! Opening an archive path by mapping or reading it, whichever is cheaper.
+
+use crate::{TarFS, TarOptions};
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+use memmap2::{Mmap, MmapOptions};
+use std::{fs::File, io::Read, ops::Deref, path::Path};
+use vfs::VfsResult;
+
+/// The size from which an archive is mapped by [`TarFS::open_path`], 1 MiB by default.
+const MMAP_THRESHOLD: u64 = 1 << 20;
+
+/// The backing of [`TarFS`] created by [`TarFS::open_path`].
+#[derive(Debug)]
+pub enum TarBacking {
+    /// The archive is mapped.
+    #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+    Mmap(Mmap),
+    /// The archive is read into memory.
+    Memory(Vec<u8>),
+}
+
+impl TarBacking {
+    /// Whether the archive is mapped.
+    pub fn is_mmap(&self) -> bool {
+        match self {
+            #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+            Self::Mmap(_) => true,
+            Self::Memory(_) => false,
+        }
+    }
+}
+
+impl Deref for TarBacking {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+            Self::Mmap(file) => &file[..],
+            Self::Memory(buf) => &buf[..],
+        }
+    }
+}
+
+impl TarFS<TarBacking> {
+    /// Create [`TarFS`] from the archive path, mapped if it's large and read into memory
+    /// if it's small, see [`TarOptions::mmap_threshold`].
+    ///
+    /// The archive is always read without the `mmap` feature, and on wasm32.
+    pub fn open_path(path: impl AsRef<Path>) -> VfsResult<Self> {
+        Self::open_path_with_options(path, TarOptions::default())
+    }
+
+    /// Create [`TarFS`] from the archive path with options.
+    pub fn open_path_with_options(path: impl AsRef<Path>, options: TarOptions) -> VfsResult<Self> {
+        let mut file = File::open(path)?;
+        let threshold = options.mmap_threshold.unwrap_or(MMAP_THRESHOLD);
+        let len = file.metadata()?.len();
+        // An empty file can't be mapped.
+        #[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+        if len >= threshold && len > 0 {
+            // SAFETY: mmap with COW
+            let file = unsafe { MmapOptions::new().map_copy_read_only(&file) }?;
+            return Self::with_options(TarBacking::Mmap(file), options);
+        }
+        #[cfg(not(all(feature = "mmap", not(target_arch = "wasm32"))))]
+        let _ = threshold;
+        let mut buffer = Vec::with_capacity(len as usize);
+        file.read_to_end(&mut buffer)?;
+        Self::with_options(TarBacking::Memory(buffer), options)
+    }
+
+    /// Whether the archive is mapped.
+    pub fn is_mmap(&self) -> bool {
+        self.file.is_mmap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{TarFS, TarOptions};
+    use std::io::Write;
+    use vfs::FileSystem;
+
+    #[test]
+    fn open_path() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "a.txt", b"data".as_slice())
+            .unwrap();
+        let archive = archive.into_inner().unwrap();
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&archive).unwrap();
+
+        let fs = TarFS::open_path(file.path()).unwrap();
+        assert!(!fs.is_mmap());
+        assert_eq!(fs.metadata("/a.txt").unwrap().len, 4);
+
+        let options = TarOptions::new().mmap_threshold(archive.len() as u64);
+        let fs = TarFS::open_path_with_options(file.path(), options).unwrap();
+        assert_eq!(fs.is_mmap(), cfg!(feature = "mmap"));
+        assert_eq!(fs.metadata("/a.txt").unwrap().len, 4);
+
+        let options = TarOptions::new().mmap_threshold(u64::MAX);
+        let fs = TarFS::open_path_with_options(file.path(), options).unwrap();
+        assert!(!fs.is_mmap());
+    }
+}
@@ -0,0 +1,109 @@
+use std::{io::Write, process::ExitCode};
+use vfs::{FileSystem, PhysicalFS, VfsFileType, VfsPath};
+use vfs_tar::{ExtractOptions, TarFS, TarFileType, TreeOptions};
+
+const USAGE: &str = "usage: vfs-tar <archive> <command>
+
+commands:
+    ls              list all entries
+    tree            print the directory tree
+    cat <path>      write a file to stdout
+    stat <path>     print the metadata of an entry
+    extract <dest>  extract all entries into a directory";
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let (archive, command) = match args.as_slice() {
+        [archive, command @ ..] if !command.is_empty() => (archive, command),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let fs = match TarFS::new_mmap(archive) {
+        Ok(fs) => fs,
+        Err(e) => {
+            eprintln!("cannot open {archive}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let res = match command {
+        ["ls"] => ls(&fs),
+        ["tree"] => tree(&fs),
+        ["cat", path] => cat(&fs, path),
+        ["stat", path] => stat(&fs, path),
+        ["extract", dest] => extract(&fs, dest),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+type Result<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+fn ls(fs: &TarFS<memmap2::Mmap>) -> Result {
+    let mut entries = fs.walk("")?.collect::<Vec<_>>();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    for (path, metadata) in entries {
+        match metadata.file_type {
+            VfsFileType::Directory => println!("D {path}"),
+            VfsFileType::File => println!("F {path} {}", metadata.len),
+        }
+    }
+    Ok(())
+}
+
+fn tree(fs: &TarFS<memmap2::Mmap>) -> Result {
+    let mut tree = String::new();
+    fs.format_tree(&mut tree, &TreeOptions::new())?;
+    print!("{tree}");
+    Ok(())
+}
+
+fn cat(fs: &TarFS<memmap2::Mmap>, path: &str) -> Result {
+    let mut file = fs.open_file(path)?;
+    std::io::copy(&mut file, &mut std::io::stdout().lock())?;
+    Ok(())
+}
+
+fn stat(fs: &TarFS<memmap2::Mmap>, path: &str) -> Result {
+    let metadata = fs.tar_metadata(path)?;
+    let file_type = match metadata.file_type {
+        TarFileType::File => "file".to_string(),
+        TarFileType::Directory => "directory".to_string(),
+        TarFileType::Special(special) => format!("{special:?}"),
+    };
+    let mut out = std::io::stdout().lock();
+    writeln!(out, "path:        {path}")?;
+    writeln!(out, "type:        {file_type}")?;
+    writeln!(out, "size:        {}", fs.metadata(path)?.len)?;
+    writeln!(out, "mode:        {:o}", fs.permissions(path)?)?;
+    writeln!(out, "links:       {}", metadata.links)?;
+    if let Some(header) = fs.header_for(path) {
+        writeln!(out, "owner:       {}/{}", header.uid, header.gid)?;
+        if let (Some(uname), Some(gname)) = (&header.uname, &header.gname) {
+            writeln!(out, "owner names: {uname}/{gname}")?;
+        }
+        writeln!(out, "mtime:       {}", header.mtime)?;
+        if let Some(target) = &header.link_target {
+            writeln!(out, "link:        {target}")?;
+        }
+    }
+    Ok(())
+}
+
+fn extract(fs: &TarFS<memmap2::Mmap>, dest: &str) -> Result {
+    std::fs::create_dir_all(dest)?;
+    let target = VfsPath::new(PhysicalFS::new(dest));
+    fs.extract_to(&target, &ExtractOptions::new().times(true))?;
+    Ok(())
+}
@@ -0,0 +1,188 @@
+//! A cache of the resolved entries, to avoid walking the tree on repeated lookups.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+};
+
+/// The maximum count of cached paths. The cache is cleared when it's full.
+const CAPACITY: usize = 4096;
+
+/// The paths reaching the entries without links by the looked up paths,
+/// including the missing ones.
+#[derive(Default)]
+pub(crate) struct LookupCache {
+    map: RwLock<HashMap<String, Option<Arc<str>>>>,
+}
+
+impl LookupCache {
+    /// Get the cached resolved path of the path.
+    pub fn get(&self, path: &str) -> Option<Option<Arc<str>>> {
+        self.map
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+            .cloned()
+    }
+
+    /// Cache the resolved path of the path, or `None` if it's missing.
+    pub fn insert(&self, path: &str, resolved: Option<Arc<str>>) {
+        let mut map = self.map.write().unwrap_or_else(PoisonError::into_inner);
+        if map.len() >= CAPACITY {
+            map.clear();
+        }
+        map.insert(path.to_string(), resolved);
+    }
+
+    /// Remove all cached entries, when the resolution changes.
+    pub fn clear(&mut self) {
+        self.map
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+/// The resolved target of a link, and the count of links resolved.
+type ResolvedLink = (Option<Arc<str>>, usize);
+
+/// The paths reaching the targets of the symbolic links without links, by the paths
+/// of the links, with the counts of the links resolved, including themselves.
+///
+/// Only the links are keyed, not the paths through them, so the cache is bounded
+/// by the count of links in the archive, and never evicted.
+#[derive(Default)]
+pub(crate) struct LinkCache {
+    map: RwLock<HashMap<PathBuf, ResolvedLink>>,
+}
+
+impl LinkCache {
+    /// Get the resolved target of a link and the count of links resolved, if it's cached.
+    pub fn get(&self, path: &Path) -> Option<ResolvedLink> {
+        self.map
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(path)
+            .cloned()
+    }
+
+    /// Cache the resolved target of a link, or `None` if it's missing.
+    pub fn insert(&self, path: PathBuf, resolved: Option<Arc<str>>, hops: usize) {
+        self.map
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(path, (resolved, hops));
+    }
+
+    /// Remove all cached entries, when the resolution changes.
+    pub fn clear(&mut self) {
+        self.map
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+impl Debug for LinkCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let len = self
+            .map
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len();
+        f.debug_struct("LinkCache").field("len", &len).finish()
+    }
+}
+
+impl Debug for LookupCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let len = self
+            .map
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len();
+        f.debug_struct("LookupCache").field("len", &len).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use std::io::Read;
+    use vfs::FileSystem;
+
+    #[test]
+    fn cache() {
+        let mut archive = tar::Builder::new(vec![]);
+        for i in 0..5000 {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, format!("dir/{i}"), b"data".as_slice())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, "link", "dir/42").unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        for _ in 0..3 {
+            let mut buffer = String::new();
+            fs.open_file("/link")
+                .unwrap()
+                .read_to_string(&mut buffer)
+                .unwrap();
+            assert_eq!(buffer, "data");
+            assert!(!fs.exists("/missing").unwrap());
+        }
+        // More paths than the capacity.
+        for i in 0..5000 {
+            assert_eq!(fs.metadata(&format!("/dir/{i}")).unwrap().len, 4);
+        }
+        assert!(fs.exists("/link").unwrap());
+        // The clones have their own caches.
+        assert!(fs.clone().exists("/dir/4999").unwrap());
+    }
+
+    #[test]
+    fn links() {
+        let mut archive = tar::Builder::new(vec![]);
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, "bin/python3.11", b"data".as_slice())
+                .unwrap();
+        }
+        for (name, target) in [
+            ("bin/python3", "python3.11"),
+            ("bin/python", "python3"),
+            ("python", "bin/python"),
+            ("loop1", "loop2"),
+            ("loop2", "loop1"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, name, target).unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        for path in ["/python", "/bin/python", "bin/python3", "/bin/python3.11"] {
+            assert_eq!(fs.metadata(path).unwrap().len, 4);
+        }
+        // A cycle of links is never resolved.
+        assert!(!fs.exists("/loop1").unwrap());
+        assert!(!fs.exists("/loop2").unwrap());
+
+        // The paths through the links aren't cached.
+        for i in 0..1000 {
+            assert!(!fs.exists(&format!("/python/missing{i}")).unwrap());
+            assert!(!fs.exists(&format!("/bin/python/missing{i}")).unwrap());
+        }
+        assert_eq!(fs.links.map.read().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,188 @@
+//! Canonical paths of the entries, and the chains of links to them.
+
+use crate::{normalize_path, resolver, strip_path, DirTree, Entry, EntryRef, TarFS};
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+use vfs::VfsResult;
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Get the absolute path of an entry with all symbolic links resolved and the `.` and `..`
+    /// components removed, like [`std::fs::canonicalize`], and whether any link is traversed.
+    ///
+    /// The names are reported as stored in the archive, even if matched case-insensitively.
+    pub fn canonicalize(&self, path: &str) -> VfsResult<(String, bool)> {
+        let mut hops = vec![];
+        let resolved = self.resolve_hops(path, &mut hops)?;
+        Ok((stored_names(&self.root, &resolved), !hops.is_empty()))
+    }
+
+    /// Get the symbolic links traversed to reach an entry, in order, including the links
+    /// in the middle of the path. It's empty if no link is traversed.
+    ///
+    /// Broken links, loops and chains longer than
+    /// [`TarOptions::max_links`](crate::TarOptions::max_links) fail as not found.
+    pub fn resolve_chain(&self, path: &str) -> VfsResult<Vec<LinkHop>> {
+        let mut hops = vec![];
+        self.resolve_hops(path, &mut hops)?;
+        Ok(hops)
+    }
+
+    /// Resolve the path, recording the traversed links.
+    fn resolve_hops(&self, path: &str, hops: &mut Vec<LinkHop>) -> VfsResult<PathBuf> {
+        #[cfg(feature = "unicode")]
+        if self.normalize_unicode {
+            if let std::borrow::Cow::Owned(path) = crate::unicode::to_nfc(path.into()) {
+                return self.resolve_hops(&path, hops);
+            }
+        }
+        let not_found = || self.not_found(path);
+        let mut resolved = normalize_path(strip_path(path)).into_owned();
+        loop {
+            let mut components = resolved.iter().filter_map(|c| c.to_str());
+            match self.find_entry_impl(&self.root, &mut components) {
+                Some(EntryRef::Link(target)) => {
+                    if hops.len() >= self.max_links {
+                        return Err(not_found());
+                    }
+                    let rest = components.collect::<PathBuf>();
+                    let depth = resolved.iter().count() - rest.iter().count();
+                    let link = resolved.iter().take(depth).collect::<PathBuf>();
+                    hops.push(LinkHop {
+                        link: stored_names(&self.root, &link),
+                        target: target.to_string(),
+                    });
+                    // The links redirected out of the archive have no path in it.
+                    if self.link_resolver.as_ref().is_some_and(|resolver| {
+                        resolver::escapes(&link, target)
+                            && resolver.resolve(&link, target).is_some()
+                    }) {
+                        return Err(not_found());
+                    }
+                    resolved = match self.read_link(link.into(), target) {
+                        Some(target) if rest.as_os_str().is_empty() => target.into_owned(),
+                        Some(target) => normalize_path(&target.join(rest)).into_owned(),
+                        None => return Err(not_found()),
+                    };
+                }
+                Some(_) => return Ok(resolved),
+                None if path.contains('\\') => {
+                    hops.clear();
+                    return self.resolve_hops(&path.replace('\\', "/"), hops);
+                }
+                None => return Err(not_found()),
+            }
+        }
+    }
+}
+
+/// A symbolic link traversed by [`TarFS::resolve_chain`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkHop {
+    /// The absolute path of the link, with the names as stored.
+    pub link: String,
+    /// The target of the link as stored, relative to the directory of the link
+    /// unless it's absolute.
+    pub target: String,
+}
+
+/// Join the names of an existing path as stored in the tree.
+fn stored_names(mut dir: &DirTree, path: &Path) -> String {
+    let mut res = String::new();
+    for name in path.iter() {
+        let name = name.to_string_lossy();
+        let found = dir.get_folded(&name, true);
+        res.push('/');
+        match found {
+            Some((name, entry)) => {
+                res.push_str(name);
+                if let Entry::Directory(sub) = entry {
+                    dir = sub;
+                }
+            }
+            None => res.push_str(&name),
+        }
+    }
+    if res.is_empty() {
+        res.push('/');
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{LinkHop, TarFS, TarOptions};
+
+    #[test]
+    fn canonicalize() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "real/Dir/file", b"data".as_slice())
+            .unwrap();
+        for (name, target) in [("link", "real/Dir"), ("real/up", "../link")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, name, target).unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+        let fs = TarFS::new(archive.clone()).unwrap();
+
+        assert_eq!(fs.canonicalize("/").unwrap(), ("/".to_string(), false));
+        assert_eq!(
+            fs.canonicalize("/real/./Dir/../Dir/file").unwrap(),
+            ("/real/Dir/file".to_string(), false)
+        );
+        assert_eq!(
+            fs.canonicalize("/real/up/file").unwrap(),
+            ("/real/Dir/file".to_string(), true)
+        );
+        assert_eq!(
+            fs.canonicalize("/link").unwrap(),
+            ("/real/Dir".to_string(), true)
+        );
+        assert!(fs.canonicalize("/link/missing").is_err());
+
+        let fs = TarFS::with_options(archive, TarOptions::new().case_insensitive(true)).unwrap();
+        assert_eq!(
+            fs.canonicalize("/REAL/dir/FILE").unwrap(),
+            ("/real/Dir/file".to_string(), false)
+        );
+    }
+
+    #[test]
+    fn resolve_chain() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "real/file", b"data".as_slice())
+            .unwrap();
+        for (name, target) in [
+            ("a", "b"),
+            ("b", "/real"),
+            ("dir/up", "../a"),
+            ("loop1", "loop2"),
+            ("loop2", "loop1"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, name, target).unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+        let hop = |link: &str, target: &str| LinkHop {
+            link: link.to_string(),
+            target: target.to_string(),
+        };
+
+        assert!(fs.resolve_chain("/real/file").unwrap().is_empty());
+        assert_eq!(
+            fs.resolve_chain("/dir/up/file").unwrap(),
+            [hop("/dir/up", "../a"), hop("/a", "b"), hop("/b", "/real")]
+        );
+        assert!(fs.resolve_chain("/loop1").is_err());
+        assert!(fs.resolve_chain("/a/missing").is_err());
+    }
+}
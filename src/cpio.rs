@@ -0,0 +1,144 @@
+//! Parser of cpio archives in the newc (`070701`/`070702`) and odc (`070707`) formats.
+
+use crate::SpecialFile;
+use std::str::from_utf8;
+
+const NEWC_HEADER_LEN: usize = 110;
+const ODC_HEADER_LEN: usize = 76;
+const TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFIFO: u32 = 0o010000;
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFBLK: u32 = 0o060000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+const S_IFSOCK: u32 = 0o140000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CpioKind {
+    File,
+    Directory,
+    Symlink,
+    /// A device node, a FIFO or a socket.
+    Special(SpecialFile),
+    Other,
+}
+
+#[derive(Debug)]
+pub(crate) struct CpioEntry<'a> {
+    /// The byte offset of the header.
+    pub offset: u64,
+    pub name: &'a str,
+    pub mode: u32,
+    /// The major and minor numbers of a device node.
+    pub rdev: (u32, u32),
+    pub contents: &'a [u8],
+}
+
+impl CpioEntry<'_> {
+    pub fn kind(&self) -> CpioKind {
+        let (major, minor) = self.rdev;
+        match self.mode & S_IFMT {
+            S_IFDIR => CpioKind::Directory,
+            S_IFLNK => CpioKind::Symlink,
+            S_IFREG => CpioKind::File,
+            S_IFCHR => CpioKind::Special(SpecialFile::CharDevice { major, minor }),
+            S_IFBLK => CpioKind::Special(SpecialFile::BlockDevice { major, minor }),
+            S_IFIFO => CpioKind::Special(SpecialFile::Fifo),
+            S_IFSOCK => CpioKind::Special(SpecialFile::Socket),
+            _ => CpioKind::Other,
+        }
+    }
+}
+
+/// Parse all entries until the `TRAILER!!!` record.
+pub(crate) fn parse_cpio(mut i: &[u8]) -> Result<Vec<CpioEntry<'_>>, String> {
+    let total = i.len();
+    let mut entries = vec![];
+    loop {
+        let offset = total - i.len();
+        let res = match i.get(..6) {
+            Some(b"070701") | Some(b"070702") => parse_newc(i),
+            Some(b"070707") => parse_odc(i),
+            _ => return Err(format!("invalid cpio magic at offset {offset}")),
+        };
+        let (mut entry, rest) =
+            res.ok_or_else(|| format!("invalid cpio entry at offset {offset}"))?;
+        entry.offset = offset as u64;
+        if entry.name == TRAILER {
+            break;
+        }
+        entries.push(entry);
+        i = rest;
+    }
+    Ok(entries)
+}
+
+fn parse_newc(i: &[u8]) -> Option<(CpioEntry<'_>, &[u8])> {
+    let header = i.get(..NEWC_HEADER_LEN)?;
+    // Fields after the magic are 8 hex digits each.
+    let field = |n: usize| parse_number(&header[6 + n * 8..14 + n * 8], 16);
+    let mode = field(1)? as u32;
+    let filesize = field(6)? as usize;
+    let rdev = (field(9)? as u32, field(10)? as u32);
+    let namesize = field(11)? as usize;
+    let name_end = NEWC_HEADER_LEN.checked_add(namesize)?;
+    // The name size includes the trailing NUL.
+    let name = parse_name(i.get(NEWC_HEADER_LEN..name_end)?)?;
+    let data_start = align4(name_end);
+    let data_end = data_start.checked_add(filesize)?;
+    let contents = i.get(data_start..data_end)?;
+    let rest = &i[align4(data_end).min(i.len())..];
+    Some((
+        CpioEntry {
+            offset: 0,
+            name,
+            mode,
+            rdev,
+            contents,
+        },
+        rest,
+    ))
+}
+
+fn parse_odc(i: &[u8]) -> Option<(CpioEntry<'_>, &[u8])> {
+    let header = i.get(..ODC_HEADER_LEN)?;
+    let mode = parse_number(&header[18..24], 8)? as u32;
+    // The device number is packed in the old way.
+    let rdev = parse_number(&header[42..48], 8)? as u32;
+    let namesize = parse_number(&header[59..65], 8)? as usize;
+    // 11 octal digits may exceed `usize` on 32-bit targets.
+    let filesize = usize::try_from(parse_number(&header[65..76], 8)?).ok()?;
+    let name_end = ODC_HEADER_LEN.checked_add(namesize)?;
+    let name = parse_name(i.get(ODC_HEADER_LEN..name_end)?)?;
+    let data_end = name_end.checked_add(filesize)?;
+    let contents = i.get(name_end..data_end)?;
+    Some((
+        CpioEntry {
+            offset: 0,
+            name,
+            mode,
+            rdev: (rdev >> 8, rdev & 0xff),
+            contents,
+        },
+        &i[data_end..],
+    ))
+}
+
+fn parse_number(i: &[u8], radix: u32) -> Option<u64> {
+    u64::from_str_radix(from_utf8(i).ok()?, radix).ok()
+}
+
+fn parse_name(i: &[u8]) -> Option<&str> {
+    let (last, name) = i.split_last()?;
+    if *last != 0 {
+        return None;
+    }
+    from_utf8(name).ok()
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
@@ -0,0 +1,272 @@
+//! Creation of archives from any filesystem, the inverse of [`TarFS`](crate::TarFS).
+
+use crate::{not_dir_error, parser::typeflag, path_key, writer, DirTree, Entry, EntryRef, TarFS};
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    ops::Deref,
+    time::SystemTime,
+};
+use vfs::{VfsFileType, VfsPath, VfsResult};
+
+/// Options of [`write_tar`].
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    times: bool,
+    file_mode: Option<u32>,
+    dir_mode: Option<u32>,
+}
+
+impl WriteOptions {
+    /// Create the default options, which write reproducible archives without timestamps.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the modification times, if the source filesystem supports.
+    pub fn times(mut self, times: bool) -> Self {
+        self.times = times;
+        self
+    }
+
+    /// Set the permission bits of the files, `0o644` by default.
+    pub fn file_mode(mut self, mode: u32) -> Self {
+        self.file_mode = Some(mode);
+        self
+    }
+
+    /// Set the permission bits of the directories, `0o755` by default.
+    pub fn dir_mode(mut self, mode: u32) -> Self {
+        self.dir_mode = Some(mode);
+        self
+    }
+
+    fn mtime(&self, modified: Option<SystemTime>) -> u64 {
+        modified
+            .filter(|_| self.times)
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+/// Write the descendants of a directory of any filesystem as a tar archive.
+///
+/// The archive is in the POSIX ustar format, with PAX extended headers for the long paths
+/// and the large files. The entries are written in the order of their names.
+pub fn write_tar(src: &VfsPath, mut out: impl Write, options: &WriteOptions) -> VfsResult<()> {
+    write_dir(src, src.as_str().len(), &mut out, options)?;
+    writer::write_end(&mut out)?;
+    Ok(())
+}
+
+fn write_dir(
+    dir: &VfsPath,
+    prefix: usize,
+    out: &mut impl Write,
+    options: &WriteOptions,
+) -> VfsResult<()> {
+    let mut children = dir.read_dir()?.collect::<Vec<_>>();
+    children.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    for child in children {
+        let path = &child.as_str()[prefix + 1..];
+        let metadata = child.metadata()?;
+        let mtime = options.mtime(metadata.modified);
+        match metadata.file_type {
+            VfsFileType::Directory => {
+                let mode = options.dir_mode.unwrap_or(0o755);
+                let path = format!("{path}/");
+                writer::write_header(out, &path, typeflag::DIRECTORY, mode, mtime, 0)?;
+                write_dir(&child, prefix, out, options)?;
+            }
+            VfsFileType::File => {
+                let mode = options.file_mode.unwrap_or(0o644);
+                let len = metadata.len;
+                writer::write_header(out, path, typeflag::REGULAR, mode, mtime, len)?;
+                let copied = io::copy(&mut child.open_file()?.take(len), out)?;
+                if copied != len {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("{} is shorter than its metadata", child.as_str()),
+                    )
+                    .into());
+                }
+                writer::write_padding(out, len)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Write a directory and its descendants as a fresh tar archive, e.g. to serve
+    /// a sub-archive over HTTP. The paths are relative to the directory.
+    ///
+    /// Unlike [`write_tar`], the permissions, the modification times and the symbolic links
+    /// are kept. The hard links and the sparse files are written as regular files, and
+    /// the device nodes and FIFOs are skipped.
+    pub fn read_dir_recursive_tarball(&self, path: &str) -> VfsResult<Vec<u8>> {
+        let dir = match self.find_entry(path) {
+            Some(EntryRef::Directory(dir)) => dir,
+            Some(_) => return Err(not_dir_error(path)),
+            None => return Err(self.not_found(path)),
+        };
+        // The modification times are keyed by the real paths, not the ones through the links.
+        let (real, _) = self.canonicalize(path)?;
+        let mut out = vec![];
+        self.write_subtree(dir, &real, "", &self.mtimes(), &mut out)?;
+        writer::write_end(&mut out)?;
+        Ok(out)
+    }
+
+    pub(crate) fn write_subtree(
+        &self,
+        dir: &DirTree,
+        real: &str,
+        prefix: &str,
+        mtimes: &HashMap<String, u64>,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let mut children = dir.iter().collect::<Vec<_>>();
+        children.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        for (name, entry) in children {
+            let path = format!("{prefix}{name}");
+            let mtime = mtimes
+                .get(&path_key(&format!("{real}/{path}")))
+                .copied()
+                .unwrap_or_default();
+            match entry {
+                Entry::Directory(sub) => {
+                    let (mode, mtime) = sub
+                        .attrs
+                        .as_ref()
+                        .map_or((0o755, 0), |dir| (dir.mode, dir.mtime));
+                    let path = format!("{path}/");
+                    writer::write_header(out, &path, typeflag::DIRECTORY, mode, mtime, 0)?;
+                    self.write_subtree(sub, real, &path, mtimes, out)?;
+                }
+                Entry::File(range, attrs) => {
+                    let data = self.buf().get(range.clone()).unwrap_or_default();
+                    write_file(out, &path, attrs.mode, mtime, data)?;
+                }
+                Entry::Sparse(sparse) => {
+                    let data = self.buf().get(sparse.data.clone()).unwrap_or_default();
+                    let contents = EntryRef::Sparse(data, sparse)
+                        .contents()
+                        .unwrap_or_default();
+                    write_file(out, &path, sparse.attrs.mode, mtime, &contents)?;
+                }
+                Entry::Link(target) => {
+                    writer::write_link_header(out, &path, target, typeflag::SYMLINK, mtime)?
+                }
+                Entry::Special(..) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_file(
+    out: &mut impl Write,
+    path: &str,
+    mode: u32,
+    mtime: u64,
+    data: &[u8],
+) -> io::Result<()> {
+    writer::write_header(out, path, typeflag::REGULAR, mode, mtime, data.len() as u64)?;
+    out.write_all(data)?;
+    writer::write_padding(out, data.len() as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{write_tar, TarFS, WriteOptions};
+    use std::io::Write;
+    use tar_parser2::TypeFlag;
+    use vfs::{FileSystem, MemoryFS, VfsPath};
+
+    #[test]
+    fn write() {
+        let src = VfsPath::from(MemoryFS::new());
+        let long = format!("{}/{}", "d".repeat(150), "f".repeat(150));
+        src.join("dir/sub").unwrap().create_dir_all().unwrap();
+        src.join("dir/sub/a.txt")
+            .unwrap()
+            .create_file()
+            .unwrap()
+            .write_all(b"hello")
+            .unwrap();
+        let file = src.join(&long).unwrap();
+        file.parent().create_dir_all().unwrap();
+        file.create_file().unwrap().write_all(b"long").unwrap();
+
+        let mut archive = vec![];
+        let options = WriteOptions::new().file_mode(0o600);
+        write_tar(&src, &mut archive, &options).unwrap();
+
+        let fs = TarFS::new(archive).unwrap();
+        assert_eq!(fs.permissions("/dir/sub/a.txt").unwrap(), 0o600);
+        let root = VfsPath::from(fs);
+        assert_eq!(
+            root.join("dir/sub/a.txt")
+                .unwrap()
+                .read_to_string()
+                .unwrap(),
+            "hello"
+        );
+        assert_eq!(root.join(&long).unwrap().read_to_string().unwrap(), "long");
+
+        // Write a subdirectory of another archive.
+        let mut archive = vec![];
+        write_tar(&root.join("dir").unwrap(), &mut archive, &options).unwrap();
+        let root = VfsPath::from(TarFS::new(archive).unwrap());
+        assert_eq!(
+            root.join("sub/a.txt").unwrap().read_to_string().unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn read_dir_recursive_tarball() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o700);
+        header.set_mtime(100);
+        archive
+            .append_data(&mut header, "dir/sub/", [].as_slice())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o600);
+        header.set_mtime(200);
+        archive
+            .append_data(&mut header, "dir/sub/a.txt", b"hello".as_slice())
+            .unwrap();
+        for (name, target) in [("dir/link", "sub/a.txt"), ("alias", "dir")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, name, target).unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert!(fs.read_dir_recursive_tarball("/dir/sub/a.txt").is_err());
+        // Through a link, the entries keep their metadata.
+        let sub = TarFS::new(fs.read_dir_recursive_tarball("/alias").unwrap()).unwrap();
+        let entries = sub
+            .entries()
+            .map(|(path, header, _)| (path.to_string(), header.typeflag, header.mtime))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                ("link".to_string(), TypeFlag::SymbolicLink, 0),
+                ("sub/".to_string(), TypeFlag::Directory, 100),
+                ("sub/a.txt".to_string(), TypeFlag::NormalFile, 200),
+            ]
+        );
+        assert_eq!(sub.permissions("/sub").unwrap(), 0o700);
+        assert_eq!(sub.permissions("/sub/a.txt").unwrap(), 0o600);
+        assert_eq!(sub.metadata("/link").unwrap().len, 5);
+    }
+}
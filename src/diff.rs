@@ -0,0 +1,113 @@
+//! Comparison of two archives.
+
+use crate::{
+    index::{fnv1a, FNV_OFFSET},
+    TarFS,
+};
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+    ops::Deref,
+};
+use vfs::VfsResult;
+
+/// The differences between two archives, created by [`diff`].
+/// The paths are absolute.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TarDiff {
+    /// The paths only in the new archive.
+    pub added: BTreeSet<String>,
+    /// The paths only in the old archive.
+    pub removed: BTreeSet<String>,
+    /// The paths in both archives with different contents or types.
+    pub modified: BTreeSet<String>,
+}
+
+impl TarDiff {
+    /// Whether the archives have the same entries.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// Compare the entries of two archives. A file is modified if the size or the checksum differs.
+/// The symbolic links are compared by their targets.
+pub fn diff<F1, F2>(old: &TarFS<F1>, new: &TarFS<F2>) -> VfsResult<TarDiff>
+where
+    F1: Deref<Target = [u8]>,
+    F2: Deref<Target = [u8]>,
+{
+    let old = contents(old)?;
+    let mut new = contents(new)?;
+    let mut diff = TarDiff::default();
+    for (path, old_buf) in old {
+        match new.remove(&path) {
+            None => {
+                diff.removed.insert(path);
+            }
+            Some(new_buf) => {
+                let modified = match (old_buf, new_buf) {
+                    (Some(a), Some(b)) => {
+                        a.len() != b.len() || fnv1a(FNV_OFFSET, &a) != fnv1a(FNV_OFFSET, &b)
+                    }
+                    (None, None) => false,
+                    _ => true,
+                };
+                if modified {
+                    diff.modified.insert(path);
+                }
+            }
+        }
+    }
+    diff.added.extend(new.into_keys());
+    Ok(diff)
+}
+
+/// Collect the paths with the file contents, or `None` for directories.
+fn contents<F: Deref<Target = [u8]>>(
+    fs: &TarFS<F>,
+) -> VfsResult<HashMap<String, Option<Cow<'_, [u8]>>>> {
+    Ok(fs
+        .walk("")?
+        .map(|(path, _)| {
+            let buf = fs.find_entry(&path).and_then(|entry| entry.contents());
+            (path, buf)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{diff, TarFS};
+
+    fn archive(files: &[(&str, &str)]) -> TarFS<Vec<u8>> {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        TarFS::new(archive.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn compare() {
+        let old = archive(&[("a/same", "1"), ("a/size", "1"), ("a/sum", "1"), ("b", "1")]);
+        let new = archive(&[
+            ("a/same", "1"),
+            ("a/size", "12"),
+            ("a/sum", "2"),
+            ("c", "1"),
+        ]);
+        let diff = diff(&old, &new).unwrap();
+        assert_eq!(diff.added.iter().collect::<Vec<_>>(), ["/c"]);
+        assert_eq!(diff.removed.iter().collect::<Vec<_>>(), ["/b"]);
+        assert_eq!(
+            diff.modified.iter().collect::<Vec<_>>(),
+            ["/a/size", "/a/sum"]
+        );
+        assert!(crate::diff(&old, &old).unwrap().is_empty());
+    }
+}
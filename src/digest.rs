@@ -0,0 +1,94 @@
+//! Digests of the file contents, with the `digest` feature.
+
+use crate::TarFS;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, ops::Deref};
+use vfs::VfsResult;
+
+/// A hash algorithm of [`TarFS::digest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// SHA-256.
+    Sha256,
+}
+
+impl Algorithm {
+    fn hash(self, buf: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha256 => Sha256::digest(buf).to_vec(),
+        }
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Compute the digest of a file. The symbolic links are followed.
+    pub fn digest(&self, path: &str, algorithm: Algorithm) -> VfsResult<Vec<u8>> {
+        let contents = self.file_entry(path)?.contents().unwrap_or_default();
+        Ok(algorithm.hash(&contents))
+    }
+
+    /// Compute the digests of all files, keyed by the absolute paths like [`TarFS::walk`].
+    /// The device nodes and FIFOs are skipped.
+    pub fn digest_all(&self, algorithm: Algorithm) -> VfsResult<HashMap<String, Vec<u8>>> {
+        Ok(self
+            .file_paths()?
+            .into_iter()
+            .filter_map(|path| {
+                let contents = self.find_entry(&path)?.contents()?;
+                Some((path, algorithm.hash(&contents)))
+            })
+            .collect())
+    }
+
+    /// Compute the digests of all files in parallel, like [`TarFS::digest_all`].
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    pub fn par_digest_all(&self, algorithm: Algorithm) -> VfsResult<HashMap<String, Vec<u8>>>
+    where
+        F: Send + Sync,
+    {
+        use rayon::prelude::*;
+
+        Ok(self
+            .file_paths()?
+            .into_par_iter()
+            .filter_map(|path| {
+                let contents = self.find_entry(&path)?.contents()?;
+                Some((path, algorithm.hash(&contents)))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Algorithm, TarFS};
+
+    const HELLO: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn hex(digest: &[u8]) -> String {
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn digest() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in [("a.txt", "hello"), ("dir/b.txt", "")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert_eq!(hex(&fs.digest("/a.txt", Algorithm::Sha256).unwrap()), HELLO);
+        assert!(fs.digest("/dir", Algorithm::Sha256).is_err());
+
+        let all = fs.digest_all(Algorithm::Sha256).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(hex(&all["/a.txt"]), HELLO);
+        #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+        assert_eq!(fs.par_digest_all(Algorithm::Sha256).unwrap(), all);
+    }
+}
@@ -0,0 +1,61 @@
+//! Listing of the directories, sharing the names with the tree.
+
+use crate::DirTree;
+use std::{sync::Arc, vec};
+
+/// An iterator of the names in a directory, which outlives the filesystem.
+///
+/// The names are collected when created, but they are shared with the tree, so only the
+/// pointers are copied, and each [`String`] is allocated when yielded.
+pub(crate) struct DirNames {
+    keys: vec::IntoIter<Arc<str>>,
+}
+
+impl DirNames {
+    pub fn new(dir: &DirTree) -> Self {
+        let keys = dir.keys().cloned().collect::<Vec<_>>().into_iter();
+        Self { keys }
+    }
+}
+
+impl Iterator for DirNames {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.keys.next().map(|name| name.to_string())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl ExactSizeIterator for DirNames {}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use vfs::FileSystem;
+
+    #[test]
+    fn dir_names() {
+        let mut archive = tar::Builder::new(vec![]);
+        for i in 0..10 {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, format!("dir/{i}"), b"data".as_slice())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let names = fs.read_dir("/dir").unwrap();
+        let sub = fs.clone().subfs("dir").unwrap().read_dir("").unwrap();
+        // The names outlive the filesystem.
+        drop(fs);
+        let mut names = names.collect::<Vec<_>>();
+        names.sort_by_key(|name| name.parse::<i32>().unwrap());
+        assert_eq!(names, (0..10).map(|i| i.to_string()).collect::<Vec<_>>());
+        assert_eq!(sub.count(), 10);
+    }
+}
@@ -0,0 +1,111 @@
+//! GNU dumpdirs, the listings of the directories in `tar --listed-incremental` backups.
+
+use crate::{path_key, TarFS};
+use std::ops::Deref;
+use tar_parser2::TypeFlag;
+use vfs::{error::VfsErrorKind, VfsResult};
+
+/// The state of a child recorded in a GNU dumpdir.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DumpdirState {
+    /// A file dumped in this archive, `Y`.
+    Dumped,
+    /// A file not dumped, because it's unchanged since the last backup, `N`.
+    Unchanged,
+    /// A subdirectory, `D`.
+    Directory,
+    /// The old path of a renamed directory, `R`, followed by the new one.
+    RenameFrom,
+    /// The new path of a renamed directory, `T`.
+    RenameTo,
+    /// A temporary path used by the renames, `X`.
+    Temporary,
+}
+
+impl DumpdirState {
+    fn from_control(c: u8) -> Option<Self> {
+        match c {
+            b'Y' => Some(Self::Dumped),
+            b'N' => Some(Self::Unchanged),
+            b'D' => Some(Self::Directory),
+            b'R' => Some(Self::RenameFrom),
+            b'T' => Some(Self::RenameTo),
+            b'X' => Some(Self::Temporary),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the dumpdir, a list of names prefixed by the control codes and terminated by NULs.
+/// The unknown control codes are skipped.
+fn parse(data: &[u8]) -> Vec<(String, DumpdirState)> {
+    data.split(|b| *b == 0)
+        .take_while(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let state = DumpdirState::from_control(entry[0])?;
+            Some((String::from_utf8_lossy(&entry[1..]).into_owned(), state))
+        })
+        .collect()
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Get the children recorded in the dumpdir of a directory, in the archive order.
+    /// The symbolic links are not followed.
+    ///
+    /// Returns `None` if the directory isn't a GNU dumpdir entry.
+    pub fn dumpdir(&self, path: &str) -> VfsResult<Option<Vec<(String, DumpdirState)>>> {
+        let key = path_key(path);
+        let record = self
+            .records
+            .iter()
+            .rfind(|record| path_key(&record.path) == key);
+        match record {
+            Some(record) => match self.record_header(record) {
+                header if header.typeflag == TypeFlag::GnuDirectory => {
+                    Ok(self.buf().get(record.contents.clone()).map(parse))
+                }
+                _ => Ok(None),
+            },
+            None if self.find_entry(&key).is_some() => Ok(None),
+            None => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{DumpdirState, TarFS};
+
+    #[test]
+    fn dumpdir() {
+        let mut archive = tar::Builder::new(vec![]);
+        let data = b"Ya.txt\0Nb.txt\0Dsub\0Rold\0Tnew\0?unknown\0\0";
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::new(b'D'));
+        header.set_size(data.len() as u64);
+        archive
+            .append_data(&mut header, "dir/", data.as_slice())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "dir/a.txt", b"data".as_slice())
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let dumpdir = fs.dumpdir("/dir").unwrap().unwrap();
+        assert_eq!(
+            dumpdir,
+            [
+                ("a.txt".to_string(), DumpdirState::Dumped),
+                ("b.txt".to_string(), DumpdirState::Unchanged),
+                ("sub".to_string(), DumpdirState::Directory),
+                ("old".to_string(), DumpdirState::RenameFrom),
+                ("new".to_string(), DumpdirState::RenameTo),
+            ]
+        );
+        assert_eq!(fs.dumpdir("/dir/a.txt").unwrap(), None);
+        assert!(fs.dumpdir("/missing").is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Legacy encodings of the entry names, with the `encodings` feature.
+
+use crate::{
+    lenient::block_at,
+    parser::{typeflag::*, *},
+};
+use encoding_rs::SHIFT_JIS;
+use std::{collections::HashMap, ops::Range, str::from_utf8};
+
+/// The encoding of the names which aren't valid UTF-8, see [`TarOptions::encoding`](crate::TarOptions::encoding).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NameEncoding {
+    /// ISO-8859-1.
+    Latin1,
+    /// The DOS code page 437.
+    Cp437,
+    /// Shift-JIS, of the Japanese Windows.
+    ShiftJis,
+    /// Shift-JIS if all legacy names are valid Shift-JIS, or Latin-1 otherwise.
+    Auto,
+}
+
+impl NameEncoding {
+    fn decode(self, raw: &[u8]) -> String {
+        match self {
+            Self::Latin1 => raw.iter().map(|b| *b as char).collect(),
+            Self::Cp437 => raw
+                .iter()
+                .map(|b| match b {
+                    0..=0x7f => *b as char,
+                    _ => CP437_HIGH
+                        .chars()
+                        .nth((b - 0x80) as usize)
+                        .unwrap_or(char::REPLACEMENT_CHARACTER),
+                })
+                .collect(),
+            Self::ShiftJis | Self::Auto => {
+                SHIFT_JIS.decode_without_bom_handling(raw).0.into_owned()
+            }
+        }
+    }
+
+    /// Decode a single name, e.g. a binary PAX path.
+    pub(crate) fn decode_name(self, raw: &[u8]) -> String {
+        self.resolve(std::iter::once(raw)).decode(raw)
+    }
+
+    /// Choose the encoding for [`NameEncoding::Auto`].
+    fn resolve<'a>(self, mut raws: impl Iterator<Item = &'a [u8]>) -> Self {
+        match self {
+            Self::Auto => {
+                let sjis = raws.all(|raw| {
+                    SHIFT_JIS
+                        .decode_without_bom_handling_and_without_replacement(raw)
+                        .is_some()
+                });
+                if sjis {
+                    Self::ShiftJis
+                } else {
+                    Self::Latin1
+                }
+            }
+            encoding => encoding,
+        }
+    }
+}
+
+/// The characters of 0x80 to 0xFF in CP437.
+const CP437_HIGH: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»\
+    ░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀\
+    αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+/// A copy of the archive with the legacy names replaced by ASCII placeholders,
+/// which has the same layout as the original one.
+pub(crate) struct Transcoded {
+    pub buf: Vec<u8>,
+    /// The decoded names by the header offsets, including the GNU long names.
+    pub names: HashMap<u64, String>,
+    /// The decoded link targets by the header offsets, including the GNU long links.
+    pub links: HashMap<u64, String>,
+}
+
+/// A name or a link target which isn't valid UTF-8.
+struct LegacyName {
+    /// The offset of the header.
+    offset: usize,
+    link: bool,
+    /// The name, joined with the POSIX ustar prefix.
+    raw: Vec<u8>,
+    /// The byte ranges in the archive to be replaced.
+    fields: Vec<Range<usize>>,
+}
+
+/// Transcode the legacy names. Returns `None` if all names are UTF-8.
+pub(crate) fn transcode(buf: &[u8], encoding: NameEncoding) -> Option<Transcoded> {
+    let legacy = legacy_names(buf);
+    if legacy.is_empty() {
+        return None;
+    }
+    let encoding = encoding.resolve(legacy.iter().map(|name| name.raw.as_slice()));
+    let mut transcoded = Transcoded {
+        buf: buf.to_vec(),
+        names: HashMap::new(),
+        links: HashMap::new(),
+    };
+    let mut headers = vec![];
+    for name in legacy {
+        for field in name.fields {
+            if field.start < name.offset + BLOCK_SIZE as usize {
+                headers.push(name.offset);
+            }
+            for b in &mut transcoded.buf[field] {
+                if !b.is_ascii() {
+                    *b = b'_';
+                }
+            }
+        }
+        let names = if name.link {
+            &mut transcoded.links
+        } else {
+            &mut transcoded.names
+        };
+        names.insert(name.offset as u64, encoding.decode(&name.raw));
+    }
+    for offset in headers {
+        let block = &mut transcoded.buf[offset..offset + BLOCK_SIZE as usize];
+        let checksum = format!("{:06o}\0 ", checksum(block));
+        block[148..156].copy_from_slice(checksum.as_bytes());
+    }
+    Some(transcoded)
+}
+
+fn legacy_names(buf: &[u8]) -> Vec<LegacyName> {
+    let mut legacy = vec![];
+    let mut offset = 0;
+    while let Some(block) = block_at(buf, offset) {
+        let Some(size) = (!is_zero_block(block) && verify_checksum(block))
+            .then(|| parse_numeric(&block[124..136]))
+            .flatten()
+        else {
+            offset += BLOCK_SIZE as usize;
+            continue;
+        };
+        let data = offset + BLOCK_SIZE as usize;
+        match block[156] {
+            typeflag @ (GNU_LONGNAME | GNU_LONGLINK) => {
+                let end = buf
+                    .len()
+                    .min(data.saturating_add(usize::try_from(size).unwrap_or(usize::MAX)));
+                let field = until_nul(buf, data..end);
+                if from_utf8(&buf[field.clone()]).is_err() {
+                    legacy.push(LegacyName {
+                        offset,
+                        link: typeflag == GNU_LONGLINK,
+                        raw: buf[field.clone()].to_vec(),
+                        fields: vec![field],
+                    });
+                }
+            }
+            _ => {
+                let name = until_nul(buf, offset..offset + 100);
+                let mut fields = vec![name.clone()];
+                let mut raw = vec![];
+                if &block[257..263] == b"ustar\0" {
+                    let prefix = until_nul(buf, offset + 345..offset + 500);
+                    if !prefix.is_empty() {
+                        raw.extend_from_slice(&buf[prefix.clone()]);
+                        raw.push(b'/');
+                    }
+                    fields.push(prefix);
+                }
+                raw.extend_from_slice(&buf[name]);
+                if from_utf8(&raw).is_err() {
+                    legacy.push(LegacyName {
+                        offset,
+                        link: false,
+                        raw,
+                        fields,
+                    });
+                }
+                let linkname = until_nul(buf, offset + 157..offset + 257);
+                if from_utf8(&buf[linkname.clone()]).is_err() {
+                    legacy.push(LegacyName {
+                        offset,
+                        link: true,
+                        raw: buf[linkname.clone()].to_vec(),
+                        fields: vec![linkname],
+                    });
+                }
+            }
+        }
+        offset = data.saturating_add(usize::try_from(align_block(size)).unwrap_or(usize::MAX));
+    }
+    legacy
+}
+
+/// Shrink the range of a field to the first NUL.
+fn until_nul(buf: &[u8], range: Range<usize>) -> Range<usize> {
+    let len = buf[range.clone()]
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(range.len());
+    range.start..range.start + len
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{NameEncoding, TarFS, TarOptions};
+    use vfs::FileSystem;
+
+    fn header(name: &[u8], entry_type: tar::EntryType, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_ustar();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_cksum();
+        header
+    }
+
+    #[test]
+    fn encodings() {
+        let mut archive = tar::Builder::new(vec![]);
+        let latin1 = header(b"caf\xe9.txt", tar::EntryType::Regular, 5);
+        archive.append(&latin1, b"hello".as_slice()).unwrap();
+        let mut link = header(b"link", tar::EntryType::Symlink, 0);
+        link.as_old_mut().linkname[..8].copy_from_slice(b"caf\xe9.txt");
+        link.set_cksum();
+        archive.append(&link, [].as_slice()).unwrap();
+        let archive = archive.into_inner().unwrap();
+
+        let options = TarOptions::new().encoding(NameEncoding::Latin1);
+        let fs = TarFS::with_options(archive.clone(), options).unwrap();
+        let mut names = fs.read_dir("/").unwrap().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["café.txt", "link"]);
+        assert_eq!(fs.metadata("/link").unwrap().len, 5);
+        let entries = fs.entries().map(|(path, ..)| path).collect::<Vec<_>>();
+        assert_eq!(entries, ["café.txt", "link"]);
+
+        let options = TarOptions::new().encoding(NameEncoding::Cp437);
+        let fs = TarFS::with_options(archive, options).unwrap();
+        assert!(fs.exists("/cafΘ.txt").unwrap());
+
+        // "日本.txt" in Shift-JIS.
+        let mut archive = tar::Builder::new(vec![]);
+        let sjis = header(b"\x93\xfa\x96\x7b.txt", tar::EntryType::Regular, 0);
+        archive.append(&sjis, [].as_slice()).unwrap();
+        let options = TarOptions::new().encoding(NameEncoding::Auto);
+        let fs = TarFS::with_options(archive.into_inner().unwrap(), options).unwrap();
+        assert!(fs.exists("/日本.txt").unwrap());
+    }
+}
@@ -0,0 +1,373 @@
+//! The structured errors of parsing archives.
+
+use crate::{parser::*, TarOptions};
+use std::{error::Error, fmt::Display, io};
+use vfs::{error::VfsErrorKind, VfsError};
+
+/// An error of parsing an archive, with the position of the failing entry.
+///
+/// It is converted into [`VfsError`] as an [`io::Error`] of [`io::ErrorKind::InvalidData`],
+/// and could be recovered with [`TarFsError::downcast`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TarFsError {
+    /// The header block is malformed.
+    InvalidHeader {
+        /// The index of the entry, counting the metadata entries.
+        index: usize,
+        /// The byte offset of the header.
+        offset: u64,
+    },
+    /// The checksum of the header block doesn't match.
+    Checksum {
+        /// The index of the entry, counting the metadata entries.
+        index: usize,
+        /// The byte offset of the header.
+        offset: u64,
+    },
+    /// The archive ends in the middle of an entry, or without the end-of-archive blocks.
+    Truncated {
+        /// The index of the entry, counting the metadata entries.
+        index: usize,
+        /// The byte offset of the header.
+        offset: u64,
+    },
+    /// There are bytes after the end-of-archive blocks.
+    TrailingData {
+        /// The count of the entries.
+        index: usize,
+        /// The byte offset of the trailing bytes.
+        offset: u64,
+    },
+    /// An entry has the same path as a previous one, with [`DuplicatePolicy::Error`](crate::DuplicatePolicy::Error).
+    Duplicate {
+        /// The index of the entry, counting the metadata entries.
+        index: usize,
+        /// The byte offset of the header.
+        offset: u64,
+        /// The path of the entry.
+        path: String,
+    },
+    /// A file and a directory have the same path, with [`CollisionPolicy::Error`](crate::CollisionPolicy::Error).
+    Collision {
+        /// The index of the entry, counting the metadata entries.
+        index: usize,
+        /// The byte offset of the header.
+        offset: u64,
+        /// The colliding path.
+        path: String,
+    },
+    /// Building was cancelled before the entry, with [`TarOptions::cancel`](crate::TarOptions::cancel).
+    Cancelled {
+        /// The index of the entry, counting the metadata entries.
+        index: usize,
+        /// The byte offset of the header.
+        offset: u64,
+    },
+    /// The metadata of an entry is inconsistent, with [`TarOptions::strict`](crate::TarOptions::strict),
+    /// or a PAX key is repeated, with [`TarOptions::pax_duplicates`](crate::TarOptions::pax_duplicates).
+    Inconsistent {
+        /// The index of the entry, counting the metadata entries.
+        index: usize,
+        /// The byte offset of the header.
+        offset: u64,
+        /// What is inconsistent.
+        reason: &'static str,
+    },
+    /// An entry has an unsafe name, with [`NamePolicy::Reject`](crate::NamePolicy::Reject).
+    UnsafeName {
+        /// The index of the entry, counting the metadata entries.
+        index: usize,
+        /// The byte offset of the header.
+        offset: u64,
+        /// The raw path of the entry.
+        path: String,
+    },
+    /// An entry has an unknown typeflag, with [`TarOptions::reject_unknown_types`].
+    UnknownType {
+        /// The index of the entry, counting the metadata entries.
+        index: usize,
+        /// The byte offset of the header.
+        offset: u64,
+        /// The typeflag of the entry.
+        typeflag: u8,
+        /// The path of the entry.
+        path: String,
+    },
+}
+
+impl TarFsError {
+    /// The index of the failing entry.
+    pub fn index(&self) -> usize {
+        match self {
+            Self::InvalidHeader { index, .. }
+            | Self::Checksum { index, .. }
+            | Self::Truncated { index, .. }
+            | Self::TrailingData { index, .. }
+            | Self::Duplicate { index, .. }
+            | Self::Collision { index, .. }
+            | Self::Cancelled { index, .. }
+            | Self::Inconsistent { index, .. }
+            | Self::UnsafeName { index, .. }
+            | Self::UnknownType { index, .. } => *index,
+        }
+    }
+
+    /// The byte offset of the failing entry.
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::InvalidHeader { offset, .. }
+            | Self::Checksum { offset, .. }
+            | Self::Truncated { offset, .. }
+            | Self::TrailingData { offset, .. }
+            | Self::Duplicate { offset, .. }
+            | Self::Collision { offset, .. }
+            | Self::Cancelled { offset, .. }
+            | Self::Inconsistent { offset, .. }
+            | Self::UnsafeName { offset, .. }
+            | Self::UnknownType { offset, .. } => *offset,
+        }
+    }
+
+    /// Get the [`TarFsError`] from a [`VfsError`], if it is the cause.
+    pub fn downcast(err: &VfsError) -> Option<&Self> {
+        match err.kind() {
+            VfsErrorKind::IoError(e) => e.get_ref()?.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl Display for TarFsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (index, offset) = (self.index(), self.offset());
+        match self {
+            Self::InvalidHeader { .. } => write!(f, "invalid header")?,
+            Self::Checksum { .. } => write!(f, "checksum mismatch")?,
+            Self::Truncated { .. } => write!(f, "truncated archive")?,
+            Self::TrailingData { .. } => write!(f, "trailing data")?,
+            Self::Duplicate { path, .. } => write!(f, "duplicate entry {path}")?,
+            Self::Collision { path, .. } => write!(f, "file and directory collision at {path}")?,
+            Self::Cancelled { .. } => write!(f, "cancelled")?,
+            Self::Inconsistent { reason, .. } => write!(f, "inconsistent entry: {reason}")?,
+            // The name is escaped, not to break the logs.
+            Self::UnsafeName { path, .. } => write!(f, "unsafe name {path:?}")?,
+            Self::UnknownType { typeflag, path, .. } => {
+                write!(f, "unknown typeflag {:?} of {path}", *typeflag as char)?
+            }
+        }
+        write!(f, " at entry {index}, offset {offset}")
+    }
+}
+
+impl Error for TarFsError {}
+
+impl From<TarFsError> for io::Error {
+    fn from(e: TarFsError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+impl From<TarFsError> for VfsError {
+    fn from(e: TarFsError) -> Self {
+        VfsErrorKind::IoError(e.into()).into()
+    }
+}
+
+/// Find the failing entry of an archive rejected by the parser, by walking the header blocks.
+/// The walking continues after the end-of-archive blocks with [`TarOptions::concatenated`],
+/// and after the zero blocks followed by a valid header with [`TarOptions::skip_zero_blocks`].
+pub(crate) fn diagnose(buf: &[u8], options: &TarOptions) -> TarFsError {
+    let mut index = 0;
+    let mut offset = 0;
+    loop {
+        let Some(block) = buf.get(offset as usize..(offset + BLOCK_SIZE) as usize) else {
+            return TarFsError::Truncated { index, offset };
+        };
+        if is_zero_block(block) {
+            while buf
+                .get(offset as usize..(offset + BLOCK_SIZE) as usize)
+                .is_some_and(is_zero_block)
+            {
+                offset += BLOCK_SIZE;
+            }
+            if offset >= buf.len() as u64 {
+                return TarFsError::Truncated { index, offset };
+            } else if !options.concatenated
+                && (!options.zero_blocks
+                    || buf
+                        .get(offset as usize..(offset + BLOCK_SIZE) as usize)
+                        .and_then(parse_header)
+                        .is_none())
+            {
+                return TarFsError::TrailingData { index, offset };
+            }
+            continue;
+        }
+        if !verify_checksum(block) {
+            return TarFsError::Checksum { index, offset };
+        }
+        let Some(header) = parse_header(block) else {
+            return TarFsError::InvalidHeader { index, offset };
+        };
+        let next = (offset + BLOCK_SIZE).saturating_add(align_block(header.size));
+        if next > buf.len() as u64 {
+            return TarFsError::Truncated { index, offset };
+        }
+        index += 1;
+        offset = next;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{DuplicatePolicy, TarFS, TarFsError, TarOptions};
+    use vfs::{FileSystem, VfsFileType};
+
+    fn archive() -> Vec<u8> {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a", "b", "a"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        archive.into_inner().unwrap()
+    }
+
+    #[test]
+    fn error() {
+        let mut buf = archive();
+        // Corrupt the name of the second entry.
+        buf[1024] = b'x';
+        // The parser stops at the corrupt entry, and the rest is checked with require_end.
+        let strict = TarOptions::new().require_end(true);
+        assert!(TarFS::new(buf.clone()).is_ok());
+        let err = TarFS::with_options(buf, strict.clone()).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Checksum {
+                index: 1,
+                offset: 1024
+            })
+        );
+
+        let mut buf = archive();
+        buf.truncate(2048 + 100);
+        let err = TarFS::with_options(buf, strict.clone()).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Truncated {
+                index: 2,
+                offset: 2048
+            })
+        );
+
+        // The size close to `u64::MAX` doesn't overflow the offsets.
+        let mut huge = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_path("huge").unwrap();
+        header.set_size(u64::MAX - 100);
+        header.set_cksum();
+        huge.append(&header, b"data".as_slice()).unwrap();
+        let err = TarFS::with_options(huge.into_inner().unwrap(), strict).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Truncated {
+                index: 0,
+                offset: 0
+            })
+        );
+
+        let options = TarOptions::new().duplicates(DuplicatePolicy::Error);
+        let err = TarFS::with_options(archive(), options).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Duplicate {
+                index: 2,
+                offset: 2048,
+                path: "a".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn strict() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a", "a/b"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        let buf = archive.into_inner().unwrap();
+        let fs = TarFS::new(buf.clone()).unwrap();
+        assert_eq!(fs.metadata("/a/b").unwrap().file_type, VfsFileType::File);
+        let err = TarFS::with_options(buf, TarOptions::new().strict(true)).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Inconsistent {
+                index: 1,
+                offset: 1024,
+                reason: "parent isn't a directory"
+            })
+        );
+
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["first", "second"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::GNULongName);
+            header.set_size(name.len() as u64 + 1);
+            archive
+                .append_data(&mut header, "././@LongLink", format!("{name}\0").as_bytes())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "short", b"data".as_slice())
+            .unwrap();
+        let buf = archive.into_inner().unwrap();
+        assert!(TarFS::new(buf.clone()).unwrap().exists("/second").unwrap());
+        let err = TarFS::with_options(buf, TarOptions::new().strict(true)).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Inconsistent {
+                index: 1,
+                offset: 1024,
+                reason: "long name already set"
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_types() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, typeflag) in [("a", b'0'), ("b", b'Q')] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            header.set_entry_type(tar::EntryType::new(typeflag));
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        let buf = archive.into_inner().unwrap();
+        let fs = TarFS::new(buf.clone()).unwrap();
+        assert_eq!(fs.metadata("/b").unwrap().file_type, VfsFileType::File);
+        let options = TarOptions::new().reject_unknown_types(true);
+        let err = TarFS::with_options(buf, options).unwrap_err();
+        let expected = TarFsError::UnknownType {
+            index: 1,
+            offset: 1024,
+            typeflag: b'Q',
+            path: "b".to_string(),
+        };
+        assert_eq!(TarFsError::downcast(&err), Some(&expected));
+        assert_eq!(
+            expected.to_string(),
+            "unknown typeflag 'Q' of b at entry 1, offset 1024"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Estimation of the compression ratios of archive entries.
+
+use crate::{strip_path, DirTree, Entry, EntryRef, TarFS};
+use std::{collections::HashMap, ops::Deref, path::Path};
+use vfs::{error::VfsErrorKind, VfsResult};
+
+/// The length of a sampled chunk.
+const SAMPLE_LEN: usize = 1024;
+/// The maximum count of sampled chunks of a file.
+const SAMPLE_COUNT: usize = 4;
+
+/// Estimated compressibility of a group of files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compressibility {
+    /// Count of the files.
+    pub files: usize,
+    /// Total size of the files.
+    pub size: u64,
+    /// Count of the sampled bytes.
+    pub sampled: u64,
+    /// Estimated ratio of the compressed size to the original size, in `0.0..=1.0`.
+    pub ratio: f64,
+}
+
+struct Histogram {
+    files: usize,
+    size: u64,
+    counts: [u64; 256],
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            files: 0,
+            size: 0,
+            counts: [0; 256],
+        }
+    }
+
+    fn add(&mut self, buf: &[u8]) {
+        for b in buf {
+            self.counts[*b as usize] += 1;
+        }
+    }
+
+    fn estimate(&self) -> Compressibility {
+        let sampled = self.counts.iter().sum::<u64>();
+        let ratio = if sampled == 0 {
+            1.0
+        } else {
+            let entropy = self
+                .counts
+                .iter()
+                .filter(|c| **c > 0)
+                .map(|c| {
+                    let p = *c as f64 / sampled as f64;
+                    -p * p.log2()
+                })
+                .sum::<f64>();
+            entropy / 8.0
+        };
+        Compressibility {
+            files: self.files,
+            size: self.size,
+            sampled,
+            ratio,
+        }
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Estimate the compression ratios of a file, or the files in a directory,
+    /// grouped by the lowercase extensions. Files without an extension are grouped under `""`.
+    ///
+    /// A few chunks of each file are sampled, and the ratio is estimated by
+    /// the order-0 entropy of the sampled bytes.
+    pub fn estimate_compressibility(
+        &self,
+        path: &str,
+    ) -> VfsResult<HashMap<String, Compressibility>> {
+        let mut groups = HashMap::new();
+        match self.find_entry(path) {
+            Some(EntryRef::File(buf, _) | EntryRef::Sparse(buf, _)) => {
+                sample(&mut groups, strip_path(path), buf)
+            }
+            Some(EntryRef::Directory(dir)) => sample_dir(&mut groups, self.buf(), dir),
+            _ => return Err(VfsErrorKind::FileNotFound.into()),
+        }
+        Ok(groups
+            .into_iter()
+            .map(|(ext, histogram)| (ext, histogram.estimate()))
+            .collect())
+    }
+}
+
+fn sample_dir(groups: &mut HashMap<String, Histogram>, data: &[u8], dir: &DirTree) {
+    for (name, entry) in dir {
+        match entry {
+            Entry::File(range, _) => sample(groups, Path::new(&**name), &data[range.clone()]),
+            // The holes aren't stored, so only the data is sampled.
+            Entry::Sparse(sparse) => sample(groups, Path::new(&**name), &data[sparse.data.clone()]),
+            Entry::Directory(dir) => sample_dir(groups, data, dir),
+            // Links are counted at their targets.
+            Entry::Link(_) | Entry::Special(..) => {}
+        }
+    }
+}
+
+fn sample(groups: &mut HashMap<String, Histogram>, name: &Path, buf: &[u8]) {
+    let ext = name
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let histogram = groups.entry(ext).or_insert_with(Histogram::new);
+    histogram.files += 1;
+    histogram.size += buf.len() as u64;
+    if buf.len() <= SAMPLE_LEN * SAMPLE_COUNT {
+        histogram.add(buf);
+    } else {
+        // Sample evenly distributed chunks, including the first and the last.
+        let step = (buf.len() - SAMPLE_LEN) / (SAMPLE_COUNT - 1);
+        for i in 0..SAMPLE_COUNT {
+            let start = i * step;
+            histogram.add(&buf[start..start + SAMPLE_LEN]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+
+    #[test]
+    fn estimate() {
+        let mut archive = tar::Builder::new(vec![]);
+        let zeros = vec![0u8; 8192];
+        let bytes = (0..8192).map(|i| i as u8).collect::<Vec<_>>();
+        for (name, data) in [("dir/a.txt", &zeros), ("dir/b.BIN", &bytes)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_slice())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let groups = fs.estimate_compressibility("/dir").unwrap();
+        assert_eq!(groups.len(), 2);
+        let txt = &groups["txt"];
+        assert_eq!(txt.files, 1);
+        assert_eq!(txt.size, 8192);
+        assert_eq!(txt.sampled, 4096);
+        assert!(txt.ratio < 0.01);
+        assert!(groups["bin"].ratio > 0.99);
+
+        let groups = fs.estimate_compressibility("/dir/a.txt").unwrap();
+        assert_eq!(groups.keys().collect::<Vec<_>>(), ["txt"]);
+        assert!(fs.estimate_compressibility("/missing").is_err());
+    }
+}
@@ -0,0 +1,577 @@
+//! Extraction of the archive into another filesystem.
+
+use crate::{walk::glob_matches, TarFS};
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+use crate::{EntryRef, ListingType, Progress};
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+use rayon::prelude::*;
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use std::{
+    io::Write,
+    ops::Deref,
+    path::{Component, Path},
+};
+use vfs::{error::VfsErrorKind, FileSystem, MemoryFS, VfsFileType, VfsPath, VfsResult};
+
+/// Options of [`TarFS::extract_to`].
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    times: bool,
+    permissions: bool,
+}
+
+impl ExtractOptions {
+    /// Create the default options, which extract all entries without timestamps.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Extract the paths matching the glob pattern.
+    /// All paths are extracted if no pattern is included.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Skip the paths matching the glob pattern.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Copy the modification times, if the target filesystem supports.
+    pub fn times(mut self, times: bool) -> Self {
+        self.times = times;
+        self
+    }
+
+    /// Copy the permission bits on Unix. It's only applied by `TarFS::extract_parallel`,
+    /// as [`VfsPath`] has no permissions.
+    pub fn permissions(mut self, permissions: bool) -> Self {
+        self.permissions = permissions;
+        self
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| glob_matches(p, path)))
+            && !self.exclude.iter().any(|p| glob_matches(p, path))
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Extract the entries into a directory of any filesystem.
+    ///
+    /// The symbolic links are extracted as copies of their targets, and the
+    /// parent directories of the extracted files are always created.
+    /// Like [`TarFS::walk`], the links to directories aren't descended into,
+    /// so they're extracted as empty directories, and the loops are harmless.
+    /// Fails on an entry with a root or `..` component, which would leave the target.
+    pub fn extract_to(&self, target: &VfsPath, options: &ExtractOptions) -> VfsResult<()> {
+        for (path, metadata) in self.walk("")? {
+            let relative = &path[1..];
+            if !options.matches(relative) {
+                continue;
+            }
+            if escapes(relative) {
+                return Err(escaping_error(&path));
+            }
+            let dest = target.join(relative)?;
+            match metadata.file_type {
+                VfsFileType::Directory => dest.create_dir_all()?,
+                VfsFileType::File => {
+                    dest.parent().create_dir_all()?;
+                    if let Some(buf) = self.find_entry(&path).and_then(|e| e.contents()) {
+                        dest.create_file()?.write_all(&buf)?;
+                    }
+                }
+            }
+            if let (true, Some(modified)) = (options.times, metadata.modified) {
+                match dest.set_modification_time(modified) {
+                    Err(e) if matches!(e.kind(), VfsErrorKind::NotSupported) => {}
+                    res => res?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the whole tree into a writable [`MemoryFS`], for the mutations
+    /// not served by [`TarOverlayFS`](crate::TarOverlayFS).
+    ///
+    /// The symbolic links are copied as their targets, and the links to directories
+    /// as empty directories, like [`TarFS::extract_to`].
+    pub fn into_memory_fs(self) -> VfsResult<MemoryFS> {
+        let memory = MemoryFS::new();
+        for (path, metadata) in self.walk("")? {
+            match metadata.file_type {
+                VfsFileType::Directory => memory.create_dir(&path)?,
+                VfsFileType::File => {
+                    let mut file = memory.create_file(&path)?;
+                    if let Some(buf) = self.find_entry(&path).and_then(|e| e.contents()) {
+                        file.write_all(&buf)?;
+                    }
+                }
+            }
+        }
+        Ok(memory)
+    }
+}
+
+/// Whether the path of an entry leaves the target when joined, as the tree keeps
+/// the root and `..` components of the names in the archive.
+fn escapes(relative: &str) -> bool {
+    relative.split('/').any(|c| c.is_empty() || c == "..")
+        || Path::new(relative)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+fn escaping_error(path: &str) -> vfs::VfsError {
+    VfsErrorKind::Other(format!("{path} is outside of the target")).into()
+}
+
+/// A file or a link to be written by [`TarFS::extract_parallel`].
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+struct Job<'a> {
+    dest: PathBuf,
+    kind: JobKind<'a>,
+    mode: Option<u32>,
+    mtime: Option<SystemTime>,
+}
+
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+enum JobKind<'a> {
+    File(Cow<'a, [u8]>),
+    /// The target, and whether it's a directory.
+    Link(String, bool),
+}
+
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+impl Job<'_> {
+    fn len(&self) -> u64 {
+        match &self.kind {
+            JobKind::File(contents) => contents.len() as u64,
+            JobKind::Link(..) => 0,
+        }
+    }
+
+    fn write(&self, root: &Path) -> io::Result<()> {
+        check_inside(root, self.dest.parent().unwrap_or(root))?;
+        // Replace an existing file rather than writing through it, like `tar -x`.
+        match fs::remove_file(&self.dest) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        match &self.kind {
+            JobKind::File(contents) => fs::write(&self.dest, contents)?,
+            JobKind::Link(target, dir) => symlink(target, &self.dest, *dir)?,
+        }
+        set_attrs(&self.dest, false, self.mode, self.mtime)
+    }
+}
+
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Extract the entries into a directory on the disk, writing the files in parallel.
+    ///
+    /// Unlike [`TarFS::extract_to`], the symbolic links are created as links, and the
+    /// special files are skipped. The progress is reported after every file or link
+    /// written, with the bytes of the contents.
+    ///
+    /// Fails on an entry with a root or `..` component, or whose parent is resolved out
+    /// of the target through an existing link. The links are created after the files,
+    /// so that nothing is written through them.
+    pub fn extract_parallel(
+        &self,
+        target: impl AsRef<Path>,
+        options: &ExtractOptions,
+        progress: impl Fn(Progress) + Sync,
+    ) -> VfsResult<()> {
+        let target = target.as_ref();
+        fs::create_dir_all(target)?;
+        let root = target.canonicalize()?;
+        let mut dirs = vec![];
+        let mut jobs = vec![];
+        for entry in self.to_listing() {
+            let relative = &entry.path[1..];
+            if !options.matches(relative) {
+                continue;
+            }
+            if escapes(relative) {
+                return Err(escaping_error(&entry.path));
+            }
+            let dest = root.join(relative);
+            let mut mode = options
+                .permissions
+                .then(|| self.permissions(&entry.path).ok())
+                .flatten();
+            let mut mtime = entry
+                .mtime
+                .filter(|_| options.times)
+                .map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime));
+            let kind = match entry.file_type {
+                ListingType::Directory => {
+                    dirs.push((dest, mode, mtime));
+                    continue;
+                }
+                ListingType::File => JobKind::File(
+                    self.find_entry(&entry.path)
+                        .and_then(|e| e.contents())
+                        .unwrap_or_default(),
+                ),
+                ListingType::Link => {
+                    // Both would be applied to the target through the link.
+                    (mode, mtime) = (None, None);
+                    JobKind::Link(
+                        entry.link.unwrap_or_default(),
+                        matches!(self.find_entry(&entry.path), Some(EntryRef::Directory(_))),
+                    )
+                }
+                _ => continue,
+            };
+            jobs.push(Job {
+                dest,
+                kind,
+                mode,
+                mtime,
+            });
+        }
+
+        let parents = jobs
+            .iter()
+            .filter_map(|job| job.dest.parent())
+            .collect::<BTreeSet<_>>();
+        for dir in dirs.iter().map(|(dest, ..)| dest.as_path()).chain(parents) {
+            check_inside(&root, dir)?;
+            fs::create_dir_all(dir)?;
+        }
+
+        let total = jobs.iter().map(Job::len).sum();
+        let bytes = AtomicU64::new(0);
+        let entries = AtomicUsize::new(0);
+        let write = |job: &Job| {
+            job.write(&root)?;
+            progress(Progress {
+                bytes: bytes.fetch_add(job.len(), Ordering::Relaxed) + job.len(),
+                total,
+                entries: entries.fetch_add(1, Ordering::Relaxed) + 1,
+            });
+            io::Result::Ok(())
+        };
+        let (links, files) = jobs
+            .iter()
+            .partition::<Vec<_>, _>(|job| matches!(job.kind, JobKind::Link(..)));
+        files.into_par_iter().try_for_each(write)?;
+        // One by one, as a link may be the parent of another.
+        links.into_iter().try_for_each(write)?;
+
+        // The directories are the last, the deepest first, because writing the children
+        // changes the times, and a read-only directory can't be written.
+        for (dest, mode, mtime) in dirs.iter().rev() {
+            set_attrs(dest, true, *mode, *mtime)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that the nearest existing ancestor of a path, with the links resolved,
+/// is in the canonical root, as the missing directories are created in it.
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+fn check_inside(root: &Path, path: &Path) -> io::Result<()> {
+    let mut existing = path;
+    while existing != root && fs::symlink_metadata(existing).is_err() {
+        existing = existing.parent().unwrap_or(root);
+    }
+    if existing.canonicalize()?.starts_with(root) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is outside of the target", path.display()),
+        ))
+    }
+}
+
+/// Set the modification time, and then the permissions which may make it read-only.
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+fn set_attrs(
+    path: &Path,
+    dir: bool,
+    mode: Option<u32>,
+    mtime: Option<SystemTime>,
+) -> io::Result<()> {
+    if let Some(mtime) = mtime {
+        // The times of a directory are set through a read-only handle, which is Unix only.
+        if !dir {
+            OpenOptions::new()
+                .write(true)
+                .open(path)?
+                .set_modified(mtime)?;
+        } else if cfg!(unix) {
+            File::open(path)?.set_modified(mtime)?;
+        }
+    }
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
+#[cfg(all(feature = "rayon", unix))]
+fn symlink(target: &str, link: &Path, _dir: bool) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Windows distinguishes the links to the directories.
+#[cfg(all(feature = "rayon", windows))]
+fn symlink(target: &str, link: &Path, dir: bool) -> io::Result<()> {
+    if dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+#[cfg(all(feature = "rayon", not(any(unix, windows, target_arch = "wasm32"))))]
+fn symlink(_target: &str, _link: &Path, _dir: bool) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ExtractOptions, TarFS};
+    use vfs::{MemoryFS, VfsPath};
+
+    #[test]
+    fn extract() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a/b.txt", "a/c.bin", "skip/d.txt", "e.txt"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(name.len() as u64);
+            archive
+                .append_data(&mut header, name, name.as_bytes())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let target = VfsPath::from(MemoryFS::new());
+        let options = ExtractOptions::new().include("**/*.txt").exclude("skip/**");
+        fs.extract_to(&target, &options).unwrap();
+
+        let mut files = target
+            .walk_dir()
+            .unwrap()
+            .map(|p| p.unwrap().as_str().to_string())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files, ["/a", "/a/b.txt", "/e.txt"]);
+        assert_eq!(
+            target.join("a/b.txt").unwrap().read_to_string().unwrap(),
+            "a/b.txt"
+        );
+    }
+
+    #[test]
+    fn extract_dir_links() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "dir/file", b"data".as_slice())
+            .unwrap();
+        for (name, target) in [("dirlink", "dir"), ("dir/loop", "..")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, name, target).unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        // The links to the directories aren't descended into, even in a loop.
+        let target = VfsPath::from(MemoryFS::new());
+        fs.extract_to(&target, &ExtractOptions::new()).unwrap();
+        let mut paths = target
+            .walk_dir()
+            .unwrap()
+            .map(|p| p.unwrap().as_str().to_string())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, ["/dir", "/dir/file", "/dir/loop", "/dirlink"]);
+        for path in ["dir/loop", "dirlink"] {
+            let dir = target.join(path).unwrap();
+            assert!(dir.is_dir().unwrap());
+            assert_eq!(dir.read_dir().unwrap().count(), 0);
+        }
+    }
+
+    /// The raw names, which `append_data` would reject.
+    fn escaping_archive(names: &[&str]) -> Vec<u8> {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in names {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_cksum();
+            archive.append(&header, b"data".as_slice()).unwrap();
+        }
+        archive.into_inner().unwrap()
+    }
+
+    #[test]
+    fn extract_escaping() {
+        for name in ["../x", "/x", "a/../../x"] {
+            let fs = TarFS::new(escaping_archive(&[name])).unwrap();
+            let root = VfsPath::from(MemoryFS::new());
+            let target = root.join("target").unwrap();
+            target.create_dir().unwrap();
+            assert!(
+                fs.extract_to(&target, &ExtractOptions::new()).is_err(),
+                "{name}"
+            );
+            assert!(!root.join("x").unwrap().exists().unwrap(), "{name}");
+        }
+    }
+
+    #[test]
+    fn into_memory_fs() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "dir/file", b"data".as_slice())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive
+            .append_link(&mut header, "link", "dir/file")
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let root = VfsPath::from(fs.into_memory_fs().unwrap());
+        assert_eq!(
+            root.join("dir/file").unwrap().read_to_string().unwrap(),
+            "data"
+        );
+        assert_eq!(root.join("link").unwrap().read_to_string().unwrap(), "data");
+        root.join("dir/new").unwrap().create_file().unwrap();
+        root.join("link").unwrap().remove_file().unwrap();
+        assert!(root.join("dir/new").unwrap().exists().unwrap());
+    }
+
+    #[test]
+    #[cfg(all(feature = "rayon", unix))]
+    fn extract_parallel() {
+        use std::{
+            os::unix::fs::{MetadataExt, PermissionsExt},
+            sync::Mutex,
+        };
+
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o700);
+        header.set_mtime(1000);
+        archive
+            .append_data(&mut header, "dir", [].as_slice())
+            .unwrap();
+        for (name, mode) in [("dir/a.txt", 0o640), ("dir/b.sh", 0o755), ("c.txt", 0o644)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(name.len() as u64);
+            header.set_mode(mode);
+            header.set_mtime(1234567890);
+            archive
+                .append_data(&mut header, name, name.as_bytes())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive
+            .append_link(&mut header, "link", "dir/a.txt")
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let target = tempfile::tempdir().unwrap();
+        let options = ExtractOptions::new().times(true).permissions(true);
+        let reports = Mutex::new(vec![]);
+        fs.extract_parallel(target.path(), &options, |p| reports.lock().unwrap().push(p))
+            .unwrap();
+
+        let reports = reports.into_inner().unwrap();
+        assert_eq!(reports.len(), 4);
+        assert!(reports.iter().all(|p| p.total == 22));
+        assert_eq!(reports.iter().map(|p| p.bytes).max(), Some(22));
+        assert_eq!(reports.iter().map(|p| p.entries).max(), Some(4));
+
+        let path = |name| target.path().join(name);
+        assert_eq!(
+            std::fs::read_to_string(path("dir/a.txt")).unwrap(),
+            "dir/a.txt"
+        );
+        assert_eq!(
+            std::fs::read_link(path("link")).unwrap().to_str(),
+            Some("dir/a.txt")
+        );
+        let metadata = std::fs::metadata(path("dir/b.sh")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        assert_eq!(metadata.mtime(), 1234567890);
+        let metadata = std::fs::metadata(path("dir")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o700);
+        assert_eq!(metadata.mtime(), 1000);
+    }
+
+    #[test]
+    #[cfg(all(feature = "rayon", unix))]
+    fn extract_parallel_escaping() {
+        let outside = tempfile::tempdir().unwrap();
+        let target = outside.path().join("target");
+        for name in ["../x", "a/../../x"] {
+            let fs = TarFS::new(escaping_archive(&[name])).unwrap();
+            assert!(fs
+                .extract_parallel(&target, &ExtractOptions::new(), |_| {})
+                .is_err());
+        }
+        let absolute = outside.path().join("x");
+        let fs = TarFS::new(escaping_archive(&[absolute.to_str().unwrap()])).unwrap();
+        assert!(fs
+            .extract_parallel(&target, &ExtractOptions::new(), |_| {})
+            .is_err());
+        assert!(!absolute.exists());
+
+        // Neither through an existing link, nor a link in the archive.
+        std::os::unix::fs::symlink(outside.path(), target.join("link")).unwrap();
+        let fs = TarFS::new(escaping_archive(&["link/x"])).unwrap();
+        assert!(fs
+            .extract_parallel(&target, &ExtractOptions::new(), |_| {})
+            .is_err());
+        assert!(!absolute.exists());
+        std::fs::remove_file(target.join("link")).unwrap();
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive
+            .append_link(&mut header, "link", outside.path())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "link/x", b"data".as_slice())
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+        let _ = fs.extract_parallel(&target, &ExtractOptions::new(), |_| {});
+        assert!(!absolute.exists());
+    }
+}
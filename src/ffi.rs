@@ -0,0 +1,222 @@
+//! A small C API, enabled by the `ffi` feature. See `include/vfs_tar.h`.
+
+use crate::{sparse, EntryRef, TarFS};
+use std::{
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    ptr::null_mut,
+};
+
+/// The archive handle of the C API.
+pub type VfsTar = TarFS<Vec<u8>>;
+
+/// The callback receiving the names in a directory.
+pub type VfsTarDirCallback = extern "C" fn(name: *const c_char, user_data: *mut c_void);
+
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        CStr::from_ptr(s).to_str().ok()
+    }
+}
+
+/// Open an archive from a path. Returns null on failure.
+///
+/// # Safety
+///
+/// `path` should be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vfs_tar_open(path: *const c_char) -> *mut VfsTar {
+    let Some(path) = to_str(path) else {
+        return null_mut();
+    };
+    match std::fs::read(path)
+        .ok()
+        .and_then(|buf| TarFS::new(buf).ok())
+    {
+        Some(fs) => Box::into_raw(Box::new(fs)),
+        None => null_mut(),
+    }
+}
+
+/// Open an archive from a buffer, which is copied. Returns null on failure.
+///
+/// # Safety
+///
+/// `data` should be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vfs_tar_open_buffer(data: *const u8, len: usize) -> *mut VfsTar {
+    if data.is_null() {
+        return null_mut();
+    }
+    let buf = std::slice::from_raw_parts(data, len).to_vec();
+    match TarFS::new(buf) {
+        Ok(fs) => Box::into_raw(Box::new(fs)),
+        Err(_) => null_mut(),
+    }
+}
+
+/// Close an archive. Null is ignored.
+///
+/// # Safety
+///
+/// `fs` should be returned by the open functions and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn vfs_tar_free(fs: *mut VfsTar) {
+    if !fs.is_null() {
+        drop(Box::from_raw(fs));
+    }
+}
+
+/// Call `callback` with each name in a directory. Returns 0 on success, or -1 if not found.
+///
+/// # Safety
+///
+/// `fs` should be a valid handle, and `path` a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vfs_tar_read_dir(
+    fs: *const VfsTar,
+    path: *const c_char,
+    callback: VfsTarDirCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let (Some(fs), Some(path)) = (fs.as_ref(), to_str(path)) else {
+        return -1;
+    };
+    let Some(EntryRef::Directory(dir)) = fs.find_entry(path) else {
+        return -1;
+    };
+    for name in dir.keys() {
+        // Names with NUL can't be passed to C.
+        if let Ok(name) = CString::new(&**name) {
+            callback(name.as_ptr(), user_data);
+        }
+    }
+    0
+}
+
+/// Get the size of a file. Returns 0 on success, or -1 if not found.
+///
+/// # Safety
+///
+/// `fs` should be a valid handle, `path` a valid NUL-terminated string,
+/// and `size` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vfs_tar_file_size(
+    fs: *const VfsTar,
+    path: *const c_char,
+    size: *mut u64,
+) -> c_int {
+    let (Some(fs), Some(path)) = (fs.as_ref(), to_str(path)) else {
+        return -1;
+    };
+    match fs.find_entry(path) {
+        Some(EntryRef::File(buf, _)) if !size.is_null() => {
+            *size = buf.len() as u64;
+            0
+        }
+        Some(EntryRef::Sparse(_, sparse)) if !size.is_null() => {
+            *size = sparse.size;
+            0
+        }
+        _ => -1,
+    }
+}
+
+/// Read a file from `offset` into the caller buffer.
+/// Returns the count of bytes read, or -1 if not found.
+///
+/// # Safety
+///
+/// `fs` should be a valid handle, `path` a valid NUL-terminated string,
+/// and `buf` valid for writes of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vfs_tar_read_file(
+    fs: *const VfsTar,
+    path: *const c_char,
+    offset: u64,
+    buf: *mut u8,
+    len: usize,
+) -> isize {
+    let (Some(fs), Some(path)) = (fs.as_ref(), to_str(path)) else {
+        return -1;
+    };
+    match fs.find_entry(path) {
+        // A null `buf` is allowed for an empty read.
+        Some(EntryRef::File(..) | EntryRef::Sparse(..)) if len == 0 => 0,
+        Some(EntryRef::File(data, _)) if !buf.is_null() => {
+            let data = usize::try_from(offset)
+                .ok()
+                .and_then(|offset| data.get(offset..))
+                .unwrap_or_default();
+            let count = data.len().min(len);
+            if count > 0 {
+                std::ptr::copy_nonoverlapping(data.as_ptr(), buf, count);
+            }
+            count as isize
+        }
+        Some(EntryRef::Sparse(data, sparse)) if !buf.is_null() => {
+            let buf = std::slice::from_raw_parts_mut(buf, len);
+            sparse::read_at(data, &sparse.map, sparse.size, offset, buf) as isize
+        }
+        _ => -1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern "C" fn collect(name: *const c_char, user_data: *mut c_void) {
+        let names = unsafe { &mut *(user_data as *mut Vec<String>) };
+        names.push(
+            unsafe { CStr::from_ptr(name) }
+                .to_str()
+                .unwrap()
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn ffi() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        archive
+            .append_data(&mut header, "dir/a.txt", b"hello".as_slice())
+            .unwrap();
+        let data = archive.into_inner().unwrap();
+
+        unsafe {
+            let fs = vfs_tar_open_buffer(data.as_ptr(), data.len());
+            assert!(!fs.is_null());
+
+            let mut names = Vec::<String>::new();
+            let res = vfs_tar_read_dir(
+                fs,
+                c"/dir".as_ptr(),
+                collect,
+                &mut names as *mut _ as *mut c_void,
+            );
+            assert_eq!(res, 0);
+            assert_eq!(names, ["a.txt"]);
+
+            let mut size = 0;
+            assert_eq!(vfs_tar_file_size(fs, c"/dir/a.txt".as_ptr(), &mut size), 0);
+            assert_eq!(size, 5);
+
+            let mut buf = [0u8; 3];
+            let path = c"/dir/a.txt".as_ptr();
+            assert_eq!(vfs_tar_read_file(fs, path, 2, buf.as_mut_ptr(), 3), 3);
+            assert_eq!(&buf, b"llo");
+            let path = c"/missing".as_ptr();
+            assert_eq!(vfs_tar_read_file(fs, path, 0, buf.as_mut_ptr(), 3), -1);
+            let path = c"/dir/a.txt".as_ptr();
+            assert_eq!(vfs_tar_read_file(fs, path, 0, null_mut(), 0), 0);
+            let end = u64::MAX;
+            assert_eq!(vfs_tar_read_file(fs, path, end, buf.as_mut_ptr(), 3), 0);
+
+            vfs_tar_free(fs);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! A view of the archive with some entries hidden.
+
+use crate::{path_key, unix_time, TarFS};
+use std::{
+    fmt::Debug,
+    ops::{Bound, Deref, RangeBounds},
+    time::SystemTime,
+};
+use vfs::{error::VfsErrorKind, *};
+
+type Filter = dyn Fn(&str, &VfsMetadata) -> bool + Send + Sync;
+
+/// A readonly filesystem exposing the entries of a [`TarFS`] accepted by a filter,
+/// created by [`TarFS::filtered`].
+pub struct TarFilteredFS<F: Deref<Target = [u8]>> {
+    fs: TarFS<F>,
+    filter: Box<Filter>,
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Create a view exposing the entries accepted by the filter, which is called with
+    /// the absolute path and the metadata of an entry.
+    ///
+    /// An entry is hidden if it or any of its parent directories is rejected,
+    /// so the filter should accept the directories to expose the files in them.
+    pub fn filtered(
+        self,
+        filter: impl Fn(&str, &VfsMetadata) -> bool + Send + Sync + 'static,
+    ) -> TarFilteredFS<F> {
+        TarFilteredFS {
+            fs: self,
+            filter: Box::new(filter),
+        }
+    }
+
+    /// Create a view exposing the entries modified in the range, e.g. `cutoff..` for the ones
+    /// modified since the cutoff. The modification times come from the headers and PAX `mtime`.
+    ///
+    /// The directories are always exposed, to expose the files in them. The other entries
+    /// without known modification times, e.g. from [`TarFS::with_cached_index`], are hidden.
+    pub fn filtered_by_mtime(self, range: impl RangeBounds<SystemTime>) -> TarFilteredFS<F> {
+        let range: (Bound<SystemTime>, Bound<SystemTime>) =
+            (range.start_bound().cloned(), range.end_bound().cloned());
+        let mtimes = self.mtimes();
+        self.filtered(move |path, metadata| {
+            metadata.file_type == VfsFileType::Directory
+                || mtimes
+                    .get(&path_key(path))
+                    .and_then(|mtime| unix_time(*mtime))
+                    .is_some_and(|mtime| range.contains(&mtime))
+        })
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarFilteredFS<F> {
+    /// Get the underlying [`TarFS`].
+    pub fn into_inner(self) -> TarFS<F> {
+        self.fs
+    }
+}
+
+impl<F: Deref<Target = [u8]> + Debug + Send + Sync + 'static> TarFilteredFS<F> {
+    /// Get the metadata of a visible entry, checking the entry and its parent directories.
+    fn visible(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let mut metadata = self.fs.metadata("")?;
+        let mut prefix = String::new();
+        for c in path.split('/').filter(|c| !c.is_empty()) {
+            prefix.push('/');
+            prefix.push_str(c);
+            metadata = self.fs.metadata(&prefix)?;
+            if !(self.filter)(&prefix, &metadata) {
+                return Err(VfsErrorKind::FileNotFound.into());
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+impl<F: Deref<Target = [u8]>> Debug for TarFilteredFS<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TarFilteredFS").finish_non_exhaustive()
+    }
+}
+
+impl<F: Deref<Target = [u8]> + Debug + Send + Sync + 'static> FileSystem for TarFilteredFS<F> {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        self.visible(path)?;
+        let dir = path.trim_end_matches('/');
+        let names = self
+            .fs
+            .read_dir(path)?
+            .filter(|name| {
+                let path = format!("{dir}/{name}");
+                match self.fs.metadata(&path) {
+                    Ok(metadata) => (self.filter)(&path, &metadata),
+                    Err(_) => false,
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(names.into_iter()))
+    }
+
+    fn create_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        self.visible(path)?;
+        self.fs.open_file(path)
+    }
+
+    fn create_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn append_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.visible(path)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        Ok(self.visible(path).is_ok())
+    }
+
+    fn remove_file(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use std::time::{Duration, UNIX_EPOCH};
+    use vfs::{VfsFileType, VfsPath};
+
+    #[test]
+    fn filtered() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["app.wasm", "lib/dep.wasm", "lib/dep.txt", ".git/config"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(name.len() as u64);
+            archive
+                .append_data(&mut header, name, name.as_bytes())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+        let root = VfsPath::from(fs.filtered(|path, metadata| {
+            !path.ends_with("/.git")
+                && (metadata.file_type == VfsFileType::Directory || path.ends_with(".wasm"))
+        }));
+
+        let mut files = root
+            .walk_dir()
+            .unwrap()
+            .map(|p| p.unwrap().as_str().to_string())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files, ["/app.wasm", "/lib", "/lib/dep.wasm"]);
+        assert!(!root.join(".git/config").unwrap().exists().unwrap());
+        assert!(root.join("lib/dep.txt").unwrap().open_file().is_err());
+        assert_eq!(
+            root.join("lib/dep.wasm").unwrap().read_to_string().unwrap(),
+            "lib/dep.wasm"
+        );
+    }
+
+    #[test]
+    fn filtered_by_mtime() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, mtime) in [
+            ("old.txt", 1000),
+            ("dir/new.txt", 3000),
+            ("dir/old.txt", 1000),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(0);
+            header.set_mtime(mtime);
+            archive
+                .append_data(&mut header, name, [].as_slice())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+        let cutoff = UNIX_EPOCH + Duration::from_secs(2000);
+
+        let walk = |root: VfsPath| {
+            let mut files = root
+                .walk_dir()
+                .unwrap()
+                .map(|p| p.unwrap().as_str().to_string())
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        };
+        let newer = VfsPath::from(fs.clone().filtered_by_mtime(cutoff..));
+        assert_eq!(walk(newer), ["/dir", "/dir/new.txt"]);
+        let older = VfsPath::from(fs.filtered_by_mtime(..cutoff));
+        assert_eq!(walk(older), ["/dir", "/dir/old.txt", "/old.txt"]);
+    }
+}
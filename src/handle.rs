@@ -0,0 +1,182 @@
+//! Stable handles of the entries, to access them repeatedly without resolving the paths.
+
+use crate::{dir_error, sparse::SparseFile, special_error, EntryRef, SparseReader, TarFS};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    ops::{Deref, Range},
+    sync::{Arc, PoisonError, RwLock},
+};
+use vfs::{error::VfsErrorKind, SeekAndRead, VfsMetadata, VfsResult};
+
+/// A handle of an entry, returned by [`TarFS::entry_id`].
+///
+/// It's valid for the filesystem which returned it and its clones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntryId(usize);
+
+/// The entry of a handle, copied out of the tree to access it without resolving the path again.
+struct Handle {
+    /// The path resolved first.
+    path: String,
+    metadata: VfsMetadata,
+    kind: HandleKind,
+}
+
+/// The parts of an entry needed to open it.
+enum HandleKind {
+    /// The byte range in the archive.
+    File(Range<usize>),
+    Sparse(Arc<SparseFile>),
+    Special,
+    Directory,
+}
+
+/// The handles, and their indices by the addresses of the entries.
+type Handles = (Vec<Arc<Handle>>, HashMap<usize, usize>);
+
+/// The entries with handles, shared by the clones of a [`TarFS`].
+#[derive(Default)]
+pub(crate) struct HandleTable {
+    entries: RwLock<Handles>,
+}
+
+impl std::fmt::Debug for HandleTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HandleTable")
+    }
+}
+
+/// The address identifying an entry in the tree.
+fn address(entry: &EntryRef) -> usize {
+    match entry {
+        EntryRef::File(_, attrs) | EntryRef::Special(_, attrs, _) => *attrs as *const _ as usize,
+        EntryRef::Directory(dir) => *dir as *const _ as usize,
+        EntryRef::Link(target) => target.as_ptr() as usize,
+        EntryRef::Sparse(_, sparse) => *sparse as *const _ as usize,
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Resolve a path to a handle, to access the entry later without resolving the path.
+    /// The symbolic links are followed. The same entry always has the same handle.
+    pub fn entry_id(&self, path: &str) -> VfsResult<EntryId> {
+        let entry = match self.find_entry(path) {
+            Some(EntryRef::Link(_)) | None => return Err(self.not_found(path)),
+            Some(entry) => entry,
+        };
+        let mut table = self
+            .handles
+            .entries
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (entries, ids) = &mut *table;
+        let id = *ids.entry(address(&entry)).or_insert_with(|| {
+            let kind = match &entry {
+                EntryRef::File(buf, _) => {
+                    let start = self.archive_offset(buf);
+                    HandleKind::File(start..start + buf.len())
+                }
+                EntryRef::Sparse(_, sparse) => HandleKind::Sparse(Arc::new((*sparse).clone())),
+                EntryRef::Directory(_) => HandleKind::Directory,
+                _ => HandleKind::Special,
+            };
+            entries.push(Arc::new(Handle {
+                path: path.to_string(),
+                metadata: self.entry_metadata(&entry),
+                kind,
+            }));
+            entries.len() - 1
+        });
+        Ok(EntryId(id))
+    }
+
+    /// Get the entry of a handle from the table, without walking the tree.
+    fn entry_by_id(&self, id: EntryId) -> VfsResult<Arc<Handle>> {
+        let table = self
+            .handles
+            .entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(table.0.get(id.0).ok_or(VfsErrorKind::FileNotFound)?.clone())
+    }
+
+    /// Open a file by its handle, like [`vfs::FileSystem::open_file`].
+    pub fn open_by_id(&self, id: EntryId) -> VfsResult<Box<dyn SeekAndRead + Send>>
+    where
+        F: Send + Sync + 'static,
+    {
+        let handle = self.entry_by_id(id)?;
+        match &handle.kind {
+            HandleKind::File(range) => {
+                let buf = self.contents(range).ok_or(VfsErrorKind::FileNotFound)?;
+                Ok(Box::new(Cursor::new(self.share(buf))))
+            }
+            HandleKind::Sparse(sparse) => {
+                let data = self
+                    .buf()
+                    .get(sparse.data.clone())
+                    .ok_or(VfsErrorKind::FileNotFound)?;
+                Ok(Box::new(SparseReader::new(self.share(data), sparse)))
+            }
+            HandleKind::Special => Err(special_error(&handle.path)),
+            HandleKind::Directory => Err(dir_error(&handle.path)),
+        }
+    }
+
+    /// Get the metadata of an entry by its handle, like [`vfs::FileSystem::metadata`].
+    pub fn metadata_by_id(&self, id: EntryId) -> VfsResult<VfsMetadata> {
+        let metadata = &self.entry_by_id(id)?.metadata;
+        Ok(VfsMetadata {
+            file_type: metadata.file_type,
+            len: metadata.len,
+            created: metadata.created,
+            modified: metadata.modified,
+            accessed: metadata.accessed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use std::io::Read;
+    use vfs::VfsFileType;
+
+    #[test]
+    fn open_by_id() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        archive
+            .append_data(&mut header, "assets/logo.png", b"hello".as_slice())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive
+            .append_link(&mut header, "logo", "assets/logo.png")
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let id = fs.entry_id("/assets/logo.png").unwrap();
+        assert_eq!(fs.entry_id("/logo").unwrap(), id);
+        // The handles are shared by the clones.
+        let clone = fs.clone();
+        let mut buffer = String::new();
+        clone
+            .open_by_id(id)
+            .unwrap()
+            .read_to_string(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, "hello");
+        assert_eq!(fs.metadata_by_id(id).unwrap().len, 5);
+
+        let dir = fs.entry_id("/assets").unwrap();
+        assert_eq!(
+            fs.metadata_by_id(dir).unwrap().file_type,
+            VfsFileType::Directory
+        );
+        assert!(fs.open_by_id(dir).is_err());
+        assert!(fs.entry_id("/missing").is_err());
+    }
+}
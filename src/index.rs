@@ -0,0 +1,313 @@
+//! A serializable index of the archive, to skip parsing when reopening it.
+
+use crate::{sparse::SparseFile, Attrs, DirTree, Entry, SpecialFile, TarFS};
+use std::{
+    collections::HashMap,
+    ops::{Deref, Range},
+};
+use vfs::{error::VfsErrorKind, VfsResult};
+
+/// The count of bytes hashed at the start and the end of the archive.
+const FINGERPRINT_SAMPLE: usize = 65536;
+
+pub(crate) const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// The index of a [`TarFS`], created by [`TarFS::index`].
+///
+/// With the `serde` feature, it could be serialized to a sidecar file,
+/// and be used to reopen the archive with [`TarFS::with_cached_index`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TarIndex {
+    len: u64,
+    fingerprint: u64,
+    root: HashMap<String, IndexEntry>,
+    /// The metadata of the explicit root entry, like `./`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    attrs: Option<Attrs>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum IndexEntry {
+    File {
+        offset: u64,
+        len: u64,
+        attrs: Attrs,
+    },
+    Directory {
+        entries: HashMap<String, IndexEntry>,
+        /// The metadata of the explicit directory entry. The synthesized parents have none.
+        #[cfg_attr(feature = "serde", serde(default))]
+        attrs: Option<Attrs>,
+    },
+    Link(String),
+    Special(SpecialFile, Attrs),
+    Sparse {
+        offset: u64,
+        len: u64,
+        map: Vec<(u64, u64)>,
+        size: u64,
+        attrs: Attrs,
+    },
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Create the index of the filesystem.
+    pub fn index(&self) -> VfsResult<TarIndex> {
+        let data = self.buf();
+        let root = export_dir(&self.root);
+        Ok(TarIndex {
+            len: data.len() as u64,
+            fingerprint: fingerprint(data, &root),
+            root,
+            attrs: self.root.attrs.clone(),
+        })
+    }
+
+    /// Create [`TarFS`] from the archive and its index, without parsing the archive.
+    /// Fails if the fingerprint of the index doesn't match the archive.
+    ///
+    /// The raw entries aren't available with [`TarFS::entries`].
+    pub fn with_cached_index(file: F, index: TarIndex) -> VfsResult<Self> {
+        let data = file.deref();
+        if data.len() as u64 != index.len || fingerprint(data, &index.root) != index.fingerprint {
+            return Err(
+                VfsErrorKind::Other("the index doesn't match the archive".to_string()).into(),
+            );
+        }
+        let mut root = import_dir(data, index.root)?;
+        root.attrs = index.attrs;
+        Ok(Self::from_parts(file, vec![], root))
+    }
+
+    /// A stable hash of the archive, the same as [`TarIndex::fingerprint`] of its index.
+    ///
+    /// It covers the length, the header blocks of the files, and the start and the end
+    /// of the archive, without reading the whole archive.
+    pub fn fingerprint(&self) -> u64 {
+        fingerprint(self.buf(), &export_dir(&self.root))
+    }
+
+    /// Like [`TarFS::fingerprint`], with the contents of all files hashed.
+    pub fn fingerprint_with_contents(&self) -> u64 {
+        let data = self.buf();
+        let root = export_dir(&self.root);
+        fingerprint(data, &root).wrapping_add(contents_hash(data, &root))
+    }
+}
+
+impl TarIndex {
+    /// The fingerprint of the indexed archive, to be compared with [`TarFS::fingerprint`].
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+}
+
+fn export_dir(dir: &DirTree) -> HashMap<String, IndexEntry> {
+    dir.iter()
+        .map(|(name, entry)| {
+            let entry = match entry {
+                Entry::File(range, attrs) => IndexEntry::File {
+                    offset: range.start as u64,
+                    len: range.len() as u64,
+                    attrs: attrs.clone(),
+                },
+                Entry::Directory(dir) => IndexEntry::Directory {
+                    entries: export_dir(dir),
+                    attrs: dir.attrs.clone(),
+                },
+                Entry::Link(target) => IndexEntry::Link(target.clone()),
+                Entry::Special(special, attrs, _) => IndexEntry::Special(*special, attrs.clone()),
+                Entry::Sparse(sparse) => IndexEntry::Sparse {
+                    offset: sparse.data.start as u64,
+                    len: sparse.data.len() as u64,
+                    map: sparse.map.clone(),
+                    size: sparse.size,
+                    attrs: sparse.attrs.clone(),
+                },
+            };
+            (name.to_string(), entry)
+        })
+        .collect()
+}
+
+fn import_dir(data: &[u8], dir: HashMap<String, IndexEntry>) -> VfsResult<DirTree> {
+    dir.into_iter()
+        .map(|(name, entry)| {
+            let entry = match entry {
+                IndexEntry::File { offset, len, attrs } => {
+                    Entry::File(range_at(data, offset, len)?, attrs)
+                }
+                IndexEntry::Directory { entries, attrs } => {
+                    let mut dir = import_dir(data, entries)?;
+                    dir.attrs = attrs;
+                    Entry::Directory(dir)
+                }
+                IndexEntry::Link(target) => Entry::Link(target),
+                IndexEntry::Special(special, attrs) => Entry::Special(special, attrs, 0),
+                IndexEntry::Sparse {
+                    offset,
+                    len,
+                    map,
+                    size,
+                    attrs,
+                } => Entry::Sparse(Box::new(SparseFile {
+                    data: range_at(data, offset, len)?,
+                    map,
+                    size,
+                    attrs,
+                })),
+            };
+            Ok((name.into(), entry))
+        })
+        .collect()
+}
+
+fn range_at(data: &[u8], offset: u64, len: u64) -> VfsResult<Range<usize>> {
+    offset
+        .checked_add(len)
+        .filter(|end| *end <= data.len() as u64)
+        .map(|end| offset as usize..end as usize)
+        .ok_or_else(|| VfsErrorKind::Other("the entry is out of the archive".to_string()).into())
+}
+
+/// Hash the length, the start and the end of the archive, and the header blocks before the files.
+fn fingerprint(data: &[u8], root: &HashMap<String, IndexEntry>) -> u64 {
+    let mut hash = fnv1a(FNV_OFFSET, &(data.len() as u64).to_le_bytes());
+    hash = fnv1a(hash, &data[..data.len().min(FINGERPRINT_SAMPLE)]);
+    hash = fnv1a(hash, &data[data.len().saturating_sub(FINGERPRINT_SAMPLE)..]);
+    hash.wrapping_add(headers_hash(data, root))
+}
+
+/// The order of the entries is unspecified, so the hashes are summed up.
+fn headers_hash(data: &[u8], dir: &HashMap<String, IndexEntry>) -> u64 {
+    dir.values()
+        .map(|entry| match entry {
+            IndexEntry::File { offset, .. } | IndexEntry::Sparse { offset, .. } => {
+                let end = usize::try_from(*offset).unwrap_or(usize::MAX);
+                let header = data.get(end.saturating_sub(512)..end).unwrap_or_default();
+                fnv1a(FNV_OFFSET, header)
+            }
+            IndexEntry::Directory { entries, .. } => headers_hash(data, entries),
+            IndexEntry::Link(_) | IndexEntry::Special(..) => 0,
+        })
+        .fold(0, u64::wrapping_add)
+}
+
+/// Like [`headers_hash`], the hashes of the contents are summed up.
+fn contents_hash(data: &[u8], dir: &HashMap<String, IndexEntry>) -> u64 {
+    dir.values()
+        .map(|entry| match entry {
+            IndexEntry::File { offset, len, .. } | IndexEntry::Sparse { offset, len, .. } => {
+                let range = *offset as usize..(*offset + *len) as usize;
+                fnv1a(FNV_OFFSET, data.get(range).unwrap_or_default())
+            }
+            IndexEntry::Directory { entries, .. } => contents_hash(data, entries),
+            IndexEntry::Link(_) | IndexEntry::Special(..) => 0,
+        })
+        .fold(0, u64::wrapping_add)
+}
+
+pub(crate) fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use std::time::SystemTime;
+    use vfs::{FileSystem, VfsPath};
+
+    #[test]
+    fn cached_index() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (path, mtime) in [("./", 1000), ("a/", 2000)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o750);
+            header.set_mtime(mtime);
+            archive
+                .append_data(&mut header, path, std::io::empty())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(5);
+            archive
+                .append_data(&mut header, "a/data", b"hello".as_slice())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, "link", "a/data").unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+
+        let parsed = TarFS::new(archive.clone()).unwrap();
+        let index = parsed.index().unwrap();
+        #[cfg(feature = "serde")]
+        let index: crate::TarIndex =
+            serde_json::from_str(&serde_json::to_string(&index).unwrap()).unwrap();
+
+        let fs = TarFS::with_cached_index(archive.clone(), index.clone()).unwrap();
+        // The metadata of the explicit directories is kept.
+        for path in ["", "/a"] {
+            let metadata = fs.metadata(path).unwrap();
+            assert_eq!(metadata.modified, parsed.metadata(path).unwrap().modified);
+            assert_ne!(metadata.modified, Some(SystemTime::UNIX_EPOCH));
+            assert_eq!(
+                fs.permissions(path).unwrap(),
+                parsed.permissions(path).unwrap()
+            );
+        }
+        assert_eq!(fs.permissions("/a").unwrap(), 0o750);
+        let root = VfsPath::from(fs);
+        assert_eq!(
+            root.join("link").unwrap().read_to_string().unwrap(),
+            "hello"
+        );
+
+        let mut modified = archive;
+        modified[512] = b'j';
+        assert!(TarFS::with_cached_index(modified, index).is_err());
+    }
+
+    #[test]
+    fn fingerprint() {
+        let archive = |mtime, data: &[u8]| {
+            let mut archive = tar::Builder::new(vec![]);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mtime(mtime);
+            archive.append_data(&mut header, "a/data", data).unwrap();
+            archive.into_inner().unwrap()
+        };
+        let fs = TarFS::new(archive(1, b"hello")).unwrap();
+        let index = fs.index().unwrap();
+        assert_eq!(fs.fingerprint(), index.fingerprint());
+        assert_eq!(fs.clone().fingerprint(), fs.fingerprint());
+
+        // The filesystem from the index has the same fingerprints.
+        let cached = TarFS::with_cached_index(archive(1, b"hello"), index).unwrap();
+        assert_eq!(cached.fingerprint(), fs.fingerprint());
+        assert_eq!(
+            cached.fingerprint_with_contents(),
+            fs.fingerprint_with_contents()
+        );
+
+        let changed = TarFS::new(archive(2, b"hello")).unwrap();
+        assert_ne!(changed.fingerprint(), fs.fingerprint());
+        let changed = TarFS::new(archive(1, b"world")).unwrap();
+        assert_ne!(
+            changed.fingerprint_with_contents(),
+            fs.fingerprint_with_contents()
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Interning of the names in the tree, with [`TarOptions::intern_names`](crate::TarOptions).
+
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+use crate::{DirTree, Entry};
+use std::{collections::HashSet, sync::Arc};
+
+/// The names of the tree, shared by the entries with the same name when enabled.
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    names: Option<HashSet<Arc<str>>>,
+}
+
+impl Interner {
+    pub fn new(enable: bool) -> Self {
+        Self {
+            names: enable.then(HashSet::new),
+        }
+    }
+
+    /// Get the shared name, or allocate a new one if disabled.
+    pub fn intern(&mut self, name: &str) -> Arc<str> {
+        let Some(names) = &mut self.names else {
+            return name.into();
+        };
+        match names.get(name) {
+            Some(name) => name.clone(),
+            None => {
+                let name = Arc::<str>::from(name);
+                names.insert(name.clone());
+                name
+            }
+        }
+    }
+
+    /// Share the names of a tree built with other interners, e.g. by the parallel shards.
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    pub fn intern_tree(&mut self, dir: &mut DirTree) {
+        if self.names.is_none() {
+            return;
+        }
+        dir.children = std::mem::take(&mut dir.children)
+            .into_iter()
+            .map(|(name, mut entry)| {
+                if let Entry::Directory(dir) = &mut entry {
+                    self.intern_tree(dir);
+                }
+                (self.intern_shared(name), entry)
+            })
+            .collect();
+    }
+
+    /// Get the shared name, or share the allocated one.
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    fn intern_shared(&mut self, name: Arc<str>) -> Arc<str> {
+        let Some(names) = &mut self.names else {
+            return name;
+        };
+        match names.get(&name) {
+            Some(name) => name.clone(),
+            None => {
+                names.insert(name.clone());
+                name
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Entry, TarFS, TarOptions};
+    use std::sync::Arc;
+    use vfs::FileSystem;
+
+    #[test]
+    fn intern_names() {
+        let mut archive = tar::Builder::new(vec![]);
+        for i in 0..10 {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(1);
+            archive
+                .append_data(&mut header, format!("{i}/assets/data.bin"), b"a".as_slice())
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+        let plain = TarFS::new(archive.clone()).unwrap();
+        let fs = TarFS::with_options(archive, TarOptions::new().intern_names(true)).unwrap();
+
+        let names = |fs: &TarFS<Vec<u8>>| {
+            (0..10)
+                .map(|i| {
+                    let Entry::Directory(dir) = &fs.root[i.to_string().as_str()] else {
+                        panic!("not a directory");
+                    };
+                    dir.get_key_value("assets").unwrap().0.clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        let shared = names(&fs);
+        assert!(shared.iter().all(|name| Arc::ptr_eq(name, &shared[0])));
+        let unshared = names(&plain);
+        assert!(!Arc::ptr_eq(&unshared[0], &unshared[1]));
+        assert!(fs.index_memory_usage() < plain.index_memory_usage());
+
+        assert_eq!(
+            fs.read_dir("/3/assets").unwrap().collect::<Vec<_>>(),
+            ["data.bin"]
+        );
+    }
+}
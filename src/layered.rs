@@ -0,0 +1,240 @@
+//! A union filesystem over several tar archives.
+
+use crate::{EntryRef, TarFS};
+use std::{collections::HashSet, fmt::Debug, ops::Deref};
+use vfs::{error::VfsErrorKind, *};
+
+/// The prefix of whiteout files, which delete the entries from the lower layers.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// The opaque whiteout file, which hides the directory contents of the lower layers.
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// A readonly union filesystem of several [`TarFS`] layers.
+///
+/// The layers are ordered from the lowest to the uppermost, like the layers of a container image.
+/// An entry in an upper layer shadows the entries with the same path in the lower layers,
+/// while the directories are merged. Symbolic links are resolved within their own layers.
+///
+/// The whiteout files `.wh.<name>` and `.wh..wh..opq` are interpreted as
+/// the OCI image spec describes, and are hidden from the merged view.
+#[derive(Debug)]
+pub struct LayeredTarFS<F: Deref<Target = [u8]>> {
+    layers: Vec<TarFS<F>>,
+}
+
+impl<F: Deref<Target = [u8]>> LayeredTarFS<F> {
+    /// Create [`LayeredTarFS`] from the layers, ordered from the lowest to the uppermost.
+    pub fn new(layers: Vec<TarFS<F>>) -> Self {
+        Self { layers }
+    }
+
+    /// Get the layers, ordered from the lowest to the uppermost.
+    pub fn layers(&self) -> &[TarFS<F>] {
+        &self.layers
+    }
+
+    /// Get the uppermost layer containing the path.
+    fn find_layer(&self, path: &str) -> Option<&TarFS<F>> {
+        if path
+            .split('/')
+            .any(|name| name.starts_with(WHITEOUT_PREFIX))
+        {
+            return None;
+        }
+        for layer in self.layers.iter().rev() {
+            if layer.find_entry(path).is_some() {
+                return Some(layer);
+            }
+            if masked(layer, path) {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+/// Whether the layer hides the path in the lower layers, by a whiteout file,
+/// an opaque ancestor directory, or a non-directory ancestor.
+fn masked<F: Deref<Target = [u8]>>(layer: &TarFS<F>, path: &str) -> bool {
+    let path = path.trim_matches('/');
+    let mut start = 0;
+    loop {
+        let end = path[start..].find('/').map(|i| start + i);
+        let parent = &path[..start.saturating_sub(1)];
+        let name = &path[start..end.unwrap_or(path.len())];
+        let whiteout = if parent.is_empty() {
+            format!("{WHITEOUT_PREFIX}{name}")
+        } else {
+            format!("{parent}/{WHITEOUT_PREFIX}{name}")
+        };
+        if layer.find_entry(&whiteout).is_some() {
+            return true;
+        }
+        match end {
+            Some(end) => {
+                match layer.find_entry(&path[..end]) {
+                    Some(EntryRef::File(..) | EntryRef::Sparse(..)) => return true,
+                    Some(EntryRef::Directory(dir)) if dir.contains_key(OPAQUE_WHITEOUT) => {
+                        return true
+                    }
+                    _ => {}
+                }
+                start = end + 1;
+            }
+            None => return false,
+        }
+    }
+}
+
+impl<F: Deref<Target = [u8]> + Debug + Send + Sync + 'static> FileSystem for LayeredTarFS<F> {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let mut names = HashSet::new();
+        let mut hidden = HashSet::<&str>::new();
+        let mut found = false;
+        for layer in self.layers.iter().rev() {
+            match layer.find_entry(path) {
+                Some(EntryRef::Directory(dir)) => {
+                    found = true;
+                    names.extend(
+                        dir.keys()
+                            .filter(|name| {
+                                !name.starts_with(WHITEOUT_PREFIX) && !hidden.contains(&name[..])
+                            })
+                            .map(|name| name.to_string()),
+                    );
+                    // The whiteouts only apply to the lower layers.
+                    hidden.extend(dir.keys().filter_map(|name| {
+                        name.strip_prefix(WHITEOUT_PREFIX)
+                            .filter(|_| &**name != OPAQUE_WHITEOUT)
+                    }));
+                    if dir.contains_key(OPAQUE_WHITEOUT) || masked(layer, path) {
+                        break;
+                    }
+                }
+                // A non-directory shadows the lower directories.
+                Some(_) => break,
+                None if masked(layer, path) => break,
+                None => {}
+            }
+        }
+        if found {
+            Ok(Box::new(names.into_iter()))
+        } else {
+            Err(VfsErrorKind::FileNotFound.into())
+        }
+    }
+
+    fn create_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        match self.find_layer(path) {
+            Some(layer) => layer.open_file(path),
+            None => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+
+    fn create_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn append_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        match self.find_layer(path) {
+            Some(layer) => layer.metadata(path),
+            None => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        Ok(self.find_layer(path).is_some())
+    }
+
+    fn remove_file(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{LayeredTarFS, TarFS};
+    use vfs::VfsPath;
+
+    fn layer(files: &[(&str, &str)]) -> TarFS<Vec<u8>> {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        TarFS::new(archive.into_inner().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn layers() {
+        let lower = layer(&[("a/x", "lower x"), ("a/y", "lower y"), ("c/z", "lower z")]);
+        let upper = layer(&[("a/y", "upper y"), ("b", "upper b"), ("c", "upper c")]);
+        let root = VfsPath::from(LayeredTarFS::new(vec![lower, upper]));
+
+        let mut files = root
+            .join("a")
+            .unwrap()
+            .read_dir()
+            .unwrap()
+            .map(|p| p.filename())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(&files, &["x", "y"]);
+
+        let read = |path: &str| root.join(path).unwrap().read_to_string().unwrap();
+        assert_eq!(read("a/x"), "lower x");
+        assert_eq!(read("a/y"), "upper y");
+        assert_eq!(read("b"), "upper b");
+        // The file shadows the lower directory.
+        assert_eq!(read("c"), "upper c");
+        assert!(!root.join("c/z").unwrap().exists().unwrap());
+    }
+
+    #[test]
+    fn whiteout() {
+        let lower = layer(&[("a/x", "x"), ("a/y", "y"), ("d/p", "p"), ("e/q", "q")]);
+        let upper = layer(&[
+            ("a/.wh.x", ""),
+            (".wh.d", ""),
+            ("e/.wh..wh..opq", ""),
+            ("e/r", "r"),
+        ]);
+        let root = VfsPath::from(LayeredTarFS::new(vec![lower, upper]));
+
+        let read_dir = |path: &VfsPath| {
+            let mut files = path
+                .read_dir()
+                .unwrap()
+                .map(|p| p.filename())
+                .collect::<Vec<_>>();
+            files.sort();
+            files
+        };
+        assert_eq!(read_dir(&root), ["a", "e"]);
+        assert_eq!(read_dir(&root.join("a").unwrap()), ["y"]);
+        assert_eq!(read_dir(&root.join("e").unwrap()), ["r"]);
+
+        let exists = |path: &str| root.join(path).unwrap().exists().unwrap();
+        assert!(!exists("a/x"));
+        assert!(!exists("a/.wh.x"));
+        assert!(!exists("d"));
+        assert!(!exists("d/p"));
+        assert!(!exists("e/q"));
+        assert!(exists("e/r"));
+    }
+}
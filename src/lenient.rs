@@ -0,0 +1,415 @@
+//! Lenient parsing, which tolerates corrupt entries and truncated archives instead of failing.
+
+use crate::{error::diagnose, offset_of, parser::*, sparse, TarFsError, TarOptions};
+use std::ops::Range;
+use tar_parser2::{parse_entry_streaming, parse_tar, TarEntry};
+use vfs::VfsResult;
+
+/// The result of parsing an archive entry by entry.
+#[derive(Default)]
+pub(crate) struct Parsed<'a> {
+    pub entries: Vec<TarEntry<'a>>,
+    /// The byte ranges of the skipped corrupt entries.
+    pub skipped: Vec<Range<u64>>,
+    /// Whether the end-of-archive blocks or the padding of the last entry are missing.
+    pub truncated: bool,
+    /// The byte range after the end of archive, including the end-of-archive blocks.
+    pub trailing: Option<Range<u64>>,
+}
+
+impl Parsed<'_> {
+    fn skip(&mut self, range: Range<usize>) {
+        let range = range.start as u64..range.end as u64;
+        match self.skipped.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => self.skipped.push(range),
+        }
+    }
+}
+
+/// Parse the entries one by one.
+///
+/// With [`TarOptions::lenient`], a corrupt entry is skipped to the next block
+/// looking like a valid header. With [`TarOptions::allow_truncated`], the archive
+/// could end without the end-of-archive blocks, or the padding of the last entry.
+/// With [`TarOptions::concatenated`], the parsing continues after the end-of-archive blocks,
+/// and with [`TarOptions::ignore_trailing`], the bytes after the archive are ignored.
+/// With [`TarOptions::skip_zero_blocks`], the zero blocks followed by a valid header are skipped.
+/// With [`TarOptions::record_padding`], the padding to a record boundary followed by
+/// a valid header is skipped, and the archive could end with zeros which aren't whole blocks.
+pub(crate) fn parse_entries<'a>(buf: &'a [u8], options: &TarOptions) -> VfsResult<Parsed<'a>> {
+    let mut parsed = Parsed::default();
+    let mut offset = 0;
+    loop {
+        let Some(block) = block_at(buf, offset) else {
+            // A short final block of padding.
+            if options.record_size.is_some() && offset < buf.len() && is_zero_block(&buf[offset..])
+            {
+                break;
+            }
+            // A trailing partial block.
+            if offset < buf.len() && options.lenient {
+                parsed.skip(offset..buf.len());
+            }
+            parsed.truncated = true;
+            break;
+        };
+        if is_zero_block(block) {
+            let end = offset;
+            while block_at(buf, offset).is_some_and(is_zero_block) {
+                offset += BLOCK_SIZE as usize;
+            }
+            if let Some(next) = padded_header(buf, end, options) {
+                offset = next;
+                continue;
+            }
+            match block_at(buf, offset) {
+                // The next archive of a concatenated archive.
+                Some(block) if options.concatenated && is_header(block) => continue,
+                // The padding between the entries.
+                Some(block) if options.zero_blocks && is_header(block) => continue,
+                // The end of archive.
+                _ if offset >= buf.len() => break,
+                // The end of archive, padded with a short final block.
+                None if options.record_size.is_some() && is_zero_block(&buf[offset..]) => break,
+                _ if options.ignore_trailing => {
+                    parsed.trailing = Some(end as u64..buf.len() as u64);
+                    break;
+                }
+                // The garbage between the archives is skipped below if lenient.
+                _ if options.concatenated => {}
+                _ if options.lenient => {
+                    parsed.skip(offset..buf.len());
+                    break;
+                }
+                _ => return Err(diagnose(buf, options).into()),
+            }
+        }
+        match entry_end(buf, offset) {
+            Some((_, end)) if end <= buf.len() => {
+                if let Ok((_, entries)) = parse_tar(&buf[offset..end]) {
+                    parsed.entries.extend(entries);
+                    offset = end;
+                    continue;
+                }
+            }
+            // The contents of the last entry are complete, but not padded.
+            // The parser requires the padding, so the contents are taken here.
+            Some((contents_end, _)) if options.truncated && contents_end <= buf.len() => {
+                if let Ok((rest, Some(entry))) = parse_entry_streaming(&buf[offset..]) {
+                    if let Some(contents) =
+                        rest.get(..usize::try_from(entry.content_len).unwrap_or(usize::MAX))
+                    {
+                        parsed.entries.push(TarEntry {
+                            header: entry.header,
+                            contents,
+                        });
+                        parsed.truncated = true;
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+        if !block_at(buf, offset).is_some_and(is_header) {
+            if let Some(next) = padded_header(buf, offset, options) {
+                offset = next;
+                continue;
+            }
+        }
+        if !options.lenient {
+            return Err(diagnose(buf, options).into());
+        }
+        let start = offset;
+        offset += BLOCK_SIZE as usize;
+        while block_at(buf, offset).is_some_and(|block| !is_header(block)) {
+            offset += BLOCK_SIZE as usize;
+        }
+        parsed.skip(start..offset.min(buf.len()));
+    }
+    if parsed.truncated && !options.truncated && !options.lenient {
+        return Err(diagnose(buf, options).into());
+    }
+    Ok(parsed)
+}
+
+/// Parse the whole archive at once, which ends at the first zero blocks.
+///
+/// The parser stops at a corrupt or truncated entry without failing.
+/// With [`TarOptions::require_end`], the rest must be zeros, or the error is found by [`diagnose`].
+pub(crate) fn parse_all<'a>(buf: &'a [u8], options: &TarOptions) -> VfsResult<Vec<TarEntry<'a>>> {
+    let (_, entries) = parse_tar(buf).map_err(|_| diagnose(buf, options))?;
+    let mut end = 0;
+    for entry in &entries {
+        // The parser skips the zero blocks between the entries, which end the archive instead.
+        if block_at(buf, end).is_some_and(is_zero_block) {
+            return Err(diagnose(buf, options).into());
+        }
+        end = offset_of(buf, entry.contents) + align_block(entry.contents.len() as u64) as usize;
+    }
+    if options.require_end && !buf.get(end..).is_some_and(is_zero_block) {
+        return Err(diagnose(buf, options).into());
+    }
+    Ok(entries)
+}
+
+/// Check the end-of-archive blocks after the last entry, and the zeros after them.
+pub(crate) fn check_end(buf: &[u8], entries: &[TarEntry]) -> Result<(), TarFsError> {
+    let index = entries.len();
+    let offset = entries
+        .last()
+        .map(|entry| {
+            offset_of(buf, entry.contents) as u64 + align_block(entry.contents.len() as u64)
+        })
+        .unwrap_or_default();
+    let rest = usize::try_from(offset)
+        .ok()
+        .and_then(|offset| buf.get(offset..))
+        .unwrap_or_default();
+    if rest.len() < 2 * BLOCK_SIZE as usize || !is_zero_block(&rest[..2 * BLOCK_SIZE as usize]) {
+        Err(TarFsError::Truncated { index, offset })
+    } else if let Some(pos) = rest.iter().position(|b| *b != 0) {
+        Err(TarFsError::TrailingData {
+            index,
+            offset: offset + pos as u64,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// The next record boundary after the padding starting at `offset`,
+/// if there's a valid header, with [`TarOptions::record_padding`].
+fn padded_header(buf: &[u8], offset: usize, options: &TarOptions) -> Option<usize> {
+    let record = options.record_size? as usize;
+    let next = offset.checked_next_multiple_of(record)?;
+    (next > offset && block_at(buf, next).is_some_and(is_header)).then_some(next)
+}
+
+pub(crate) fn block_at(buf: &[u8], offset: usize) -> Option<&[u8]> {
+    buf.get(offset..offset.saturating_add(BLOCK_SIZE as usize))
+}
+
+/// Whether the block looks like a valid header.
+fn is_header(block: &[u8]) -> bool {
+    !is_zero_block(block) && parse_header(block).is_some()
+}
+
+/// The end of the contents, and the end of the padding,
+/// of the entry with a valid header at `offset`.
+///
+/// The contents of an old-style sparse entry follow its extension blocks.
+pub(crate) fn entry_end(buf: &[u8], offset: usize) -> Option<(usize, usize)> {
+    let header = parse_header(block_at(buf, offset)?)?;
+    let start = sparse::extension_end(buf, offset)? as u64;
+    let contents_end = start.checked_add(header.size)?;
+    let end = start.checked_add(align_block(header.size))?;
+    Some((contents_end.try_into().ok()?, end.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{test_util::ArchiveBuilder, TarFS, TarFsError, TarOptions};
+    use vfs::FileSystem;
+
+    fn archive() -> Vec<u8> {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a", "b", "c"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        archive.into_inner().unwrap()
+    }
+
+    #[test]
+    fn lenient() {
+        let mut buf = archive();
+        // Corrupt the header of the second entry.
+        buf[1024] = b'x';
+        let strict = TarOptions::new().require_end(true);
+        assert!(TarFS::with_options(buf.clone(), strict).is_err());
+
+        let fs = TarFS::with_options(buf, TarOptions::new().lenient(true)).unwrap();
+        assert_eq!(fs.skipped().len(), 1);
+        assert_eq!(fs.skipped()[0], 1024..2048);
+        assert!(!fs.is_truncated());
+        assert!(fs.exists("/a").unwrap());
+        assert!(!fs.exists("/x").unwrap());
+        assert_eq!(fs.metadata("/c").unwrap().len, 4);
+    }
+
+    #[test]
+    fn lenient_old_sparse() {
+        let segments = [b"a", b"b", b"c", b"d", b"e", b"f"];
+        let segments = (0..6)
+            .map(|i| (i * 1000, segments[i as usize].as_slice()))
+            .collect::<Vec<_>>();
+        let buf = ArchiveBuilder::new()
+            .old_sparse("big.bin", 6000, &segments)
+            .file("next", b"data")
+            .finish();
+
+        // The contents start after the extension block.
+        let fs = TarFS::with_options(buf, TarOptions::new().lenient(true)).unwrap();
+        assert!(fs.skipped().is_empty());
+        assert_eq!(fs.sparse_map("/big.bin").unwrap().unwrap().len(), 6);
+        let mut buffer = [0; 2];
+        fs.read_at("/big.bin", 5000, &mut buffer).unwrap();
+        assert_eq!(&buffer, b"f\0");
+        assert_eq!(fs.metadata("/next").unwrap().len, 4);
+    }
+
+    #[test]
+    fn truncated() {
+        let options = TarOptions::new().allow_truncated(true);
+        let mut buf = archive();
+
+        // Without the end-of-archive blocks.
+        buf.truncate(3072);
+        assert!(TarFS::new(buf.clone()).is_ok());
+        let strict = TarOptions::new().require_end(true);
+        assert!(TarFS::with_options(buf.clone(), strict).is_err());
+        let fs = TarFS::with_options(buf.clone(), options.clone()).unwrap();
+        assert!(fs.is_truncated());
+        assert_eq!(fs.metadata("/c").unwrap().len, 4);
+
+        // Without the padding of the last entry.
+        buf.truncate(2048 + 512 + 4);
+        let fs = TarFS::with_options(buf.clone(), options.clone()).unwrap();
+        assert!(fs.is_truncated());
+        assert_eq!(fs.metadata("/c").unwrap().len, 4);
+        assert_eq!(fs.entries().count(), 3);
+
+        // The last entry is incomplete.
+        buf.truncate(2048 + 512 + 2);
+        assert!(TarFS::with_options(buf, options).is_err());
+
+        let fs = TarFS::with_options(archive(), TarOptions::new().allow_truncated(true)).unwrap();
+        assert!(!fs.is_truncated());
+    }
+
+    #[test]
+    fn concatenated() {
+        let mut buf = archive();
+        let len = buf.len() as u64;
+        let mut other = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        other
+            .append_data(&mut header, "d", b"other".as_slice())
+            .unwrap();
+        buf.extend(other.into_inner().unwrap());
+
+        let fs = TarFS::with_options(buf.clone(), TarOptions::new().concatenated(true)).unwrap();
+        assert_eq!(fs.metadata("/c").unwrap().len, 4);
+        assert_eq!(fs.metadata("/d").unwrap().len, 5);
+
+        // A signature after the archive.
+        let mut buf = archive();
+        buf.extend_from_slice(b"signature");
+        let fs = TarFS::with_options(buf.clone(), TarOptions::new().ignore_trailing(true)).unwrap();
+        assert_eq!(fs.metadata("/c").unwrap().len, 4);
+        assert_eq!(fs.trailing(), Some(3072..len + 9));
+        assert!(TarFS::with_options(buf, TarOptions::new().concatenated(true)).is_err());
+    }
+
+    #[test]
+    fn require_end() {
+        let options = TarOptions::new().require_end(true);
+        let buf = archive();
+        assert!(TarFS::with_options(buf.clone(), options.clone()).is_ok());
+        // The padding to the blocking factor.
+        let mut padded = buf.clone();
+        padded.resize(10240, 0);
+        assert!(TarFS::with_options(padded, options.clone()).is_ok());
+
+        // Only one end-of-archive block.
+        let mut truncated = buf.clone();
+        truncated.truncate(3584);
+        let options = options.allow_truncated(true).ignore_trailing(true);
+        let err = TarFS::with_options(truncated, options.clone()).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Truncated {
+                index: 3,
+                offset: 3072
+            })
+        );
+
+        let mut appended = buf;
+        appended.extend_from_slice(b"signature");
+        let err = TarFS::with_options(appended, options).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::TrailingData {
+                index: 3,
+                offset: 4096
+            })
+        );
+    }
+
+    #[test]
+    fn zero_blocks() {
+        let mut buf = archive();
+        // Zero blocks between the second and the third entries.
+        buf.splice(2048..2048, [0; 1536]);
+        let err = TarFS::new(buf.clone()).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::TrailingData {
+                index: 2,
+                offset: 3584
+            })
+        );
+
+        let options = TarOptions::new().skip_zero_blocks(true);
+        let fs = TarFS::with_options(buf.clone(), options.clone()).unwrap();
+        assert!(fs.skipped().is_empty());
+        assert_eq!(fs.metadata("/c").unwrap().len, 4);
+
+        // The zero blocks before the garbage are still the end of archive.
+        buf.truncate(2048 + 1536);
+        buf.extend([b'x'; 512]);
+        assert!(TarFS::with_options(buf, options).is_err());
+    }
+
+    #[test]
+    fn record_padding() {
+        let options = TarOptions::new().record_padding(10240);
+        let buf = archive();
+
+        // The first entry is padded to the record boundary with garbage.
+        let mut padded = buf[..1024].to_vec();
+        padded.resize(10240, b'x');
+        padded.extend_from_slice(&buf[1024..]);
+        // Either rejected or stopped at the padding.
+        assert!(TarFS::new(padded.clone()).map_or(true, |fs| !fs.exists("/c").unwrap()));
+        let fs = TarFS::with_options(padded, options.clone()).unwrap();
+        assert!(fs.skipped().is_empty());
+        assert_eq!(fs.metadata("/a").unwrap().len, 4);
+        assert_eq!(fs.metadata("/c").unwrap().len, 4);
+
+        // The second entry is padded with zeros.
+        let mut padded = buf[..2048].to_vec();
+        padded.resize(10240, 0);
+        padded.extend_from_slice(&buf[2048..]);
+        let fs = TarFS::with_options(padded, options.clone()).unwrap();
+        assert_eq!(fs.metadata("/c").unwrap().len, 4);
+
+        // A short final block, without the end-of-archive blocks.
+        let mut short = buf[..3072].to_vec();
+        short.extend([0; 100]);
+        let fs = TarFS::with_options(short, options.clone()).unwrap();
+        assert!(!fs.is_truncated());
+        assert_eq!(fs.metadata("/c").unwrap().len, 4);
+
+        // The zeros after the end-of-archive blocks aren't whole blocks.
+        let mut trailing = buf;
+        trailing.extend([0; 100]);
+        let fs = TarFS::with_options(trailing, options).unwrap();
+        assert!(fs.trailing().is_none());
+    }
+}
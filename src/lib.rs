@@ -2,96 +2,1188 @@
 
 #![warn(missing_docs)]
 
-use stable_deref_trait::StableDeref;
 use std::time::SystemTime;
 #[allow(unused_imports)]
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{hash_map, HashMap},
     fmt::Debug,
     fs::File,
-    io::{Cursor, Write},
-    ops::Deref,
-    path::{Iter, Path},
+    io::{Cursor, Read, Write},
+    ops::{Deref, DerefMut, Range},
+    path::{Component, Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
 };
 use tar_parser2::*;
 use vfs::{error::VfsErrorKind, *};
 
+mod acl;
+pub use acl::{AclEntry, AclTag, PosixAcl};
+
+mod adaptive;
+pub use adaptive::TarBacking;
+
+#[cfg(feature = "cpio")]
+mod cpio;
+
+mod cache;
+use cache::{LinkCache, LookupCache};
+
+mod canonical;
+pub use canonical::LinkHop;
+
+mod create;
+pub use create::{write_tar, WriteOptions};
+
+mod diff;
+pub use diff::{diff, TarDiff};
+
+mod dir;
+use dir::DirNames;
+
+#[cfg(feature = "digest")]
+mod digest;
+#[cfg(feature = "digest")]
+pub use digest::Algorithm;
+
+mod dumpdir;
+pub use dumpdir::DumpdirState;
+
+#[cfg(feature = "encodings")]
+mod encoding;
+#[cfg(feature = "encodings")]
+pub use encoding::NameEncoding;
+
+mod error;
+pub use error::TarFsError;
+
+mod estimate;
+pub use estimate::Compressibility;
+
+mod extract;
+pub use extract::ExtractOptions;
+
+mod filter;
+pub use filter::TarFilteredFS;
+
+#[cfg(feature = "ffi")]
+mod ffi;
+
+mod handle;
+pub use handle::EntryId;
+use handle::HandleTable;
+
+mod index;
+pub use index::TarIndex;
+
+mod intern;
+use intern::Interner;
+
+mod layered;
+pub use layered::LayeredTarFS;
+
+mod lenient;
+
+mod listing;
+pub use listing::{ListingEntry, ListingType, SortBy};
+
+#[cfg(feature = "digest")]
+mod manifest;
+#[cfg(feature = "digest")]
+pub use manifest::{ManifestReport, ManifestStatus};
+
+mod memory;
+
+mod metadata;
+use metadata::PaxOverrides;
+pub use metadata::{SpecialFile, TarFileType, TarHeaderInfo, TarMetadata};
+
+mod mtree;
+
+mod multi;
+
+mod nested;
+pub use nested::NestedArchive;
+
+mod ordered;
+pub use ordered::OrderedEntry;
+
+mod overlay;
+pub use overlay::TarOverlayFS;
+
+mod owned;
+use owned::OwnedFiles;
+
+#[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+mod parallel;
+
+pub mod parser;
+
+pub mod prelude;
+
+mod progress;
+pub use progress::Progress;
+use progress::ProgressHook;
+
+mod sanitize;
+pub use sanitize::NamePolicy;
+
+mod search;
+
+#[cfg(any(feature = "bgzf", feature = "zstd"))]
+mod seekable;
+#[cfg(any(feature = "bgzf", feature = "zstd"))]
+pub use seekable::SeekableSource;
+
+mod shared;
+#[cfg(feature = "bytes")]
+pub use shared::TarBytes;
+
+mod sparse;
+use sparse::{SparseFile, SparseReader};
+
+mod stats;
+pub use stats::AccessStats;
+use stats::StatsMap;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+
+mod transform;
+pub use transform::XorReader;
+
+#[cfg(feature = "unicode")]
+mod unicode;
+
+mod vendor;
+pub use vendor::VendorAction;
+use vendor::VendorHook;
+
+mod versions;
+pub use versions::EntryVersion;
+
+mod vfs_path;
+pub use vfs_path::TarPathExt;
+
+mod visit;
+pub use visit::{visit, VisitedEntry, Visitor};
+
+mod tree;
+pub use tree::TreeOptions;
+
+mod walk;
+pub use walk::Walk;
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+mod window;
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+pub use window::WindowedMmap;
+
+mod writer;
+
+mod xattr;
+
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+mod reload;
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
+pub use reload::ReloadableTarFS;
+
+mod remote;
+#[cfg(all(feature = "http", not(target_arch = "wasm32")))]
+pub use remote::HttpSource;
+pub use remote::{RangeSource, RemoteTarFS};
+
+mod resolver;
+use resolver::LinkResolver;
+
+mod sub;
+pub use sub::TarSubFS;
+
 /// A readonly tar archive filesystem.
+///
+/// Cloning is cheap: the archive and the parsed tree are shared between the clones.
 #[derive(Debug)]
-pub struct TarFS<F: StableDeref<Target = [u8]>> {
-    #[allow(dead_code)]
-    file: F,
-    root: DirTree,
+pub struct TarFS<F: Deref<Target = [u8]>> {
+    /// Shared with the opened files.
+    file: Arc<F>,
+    /// The copies of the small files, shared with the opened files.
+    owned: Arc<OwnedFiles>,
+    records: Arc<Vec<EntryRecord>>,
+    root: Arc<DirTree>,
+    escaping_links: EscapingLinks,
+    max_links: usize,
+    case_insensitive: bool,
+    link_resolver: Option<LinkResolver>,
+    #[cfg(feature = "unicode")]
+    normalize_unicode: bool,
+    /// Shared by the clones.
+    access_stats: Option<StatsMap>,
+    /// Shared by the clones.
+    handles: Arc<HandleTable>,
+    skipped: Vec<Range<u64>>,
+    truncated: bool,
+    trailing: Option<Range<u64>>,
+    xattrs: Arc<HashMap<String, Xattrs>>,
+    global_pax: Arc<PaxRecords>,
+    /// Whether the paths differ from the names in the archive,
+    /// see [`TarOptions::strip_components`] and [`TarOptions::rename_prefix`].
+    renamed: bool,
+    /// The paths resolved without links, by the looked up paths.
+    cache: LookupCache,
+    /// The paths resolved without links, by the paths of the symbolic links.
+    links: LinkCache,
+}
+
+impl<F: Deref<Target = [u8]>> Clone for TarFS<F> {
+    fn clone(&self) -> Self {
+        Self {
+            file: self.file.clone(),
+            owned: self.owned.clone(),
+            records: self.records.clone(),
+            root: self.root.clone(),
+            escaping_links: self.escaping_links,
+            max_links: self.max_links,
+            case_insensitive: self.case_insensitive,
+            link_resolver: self.link_resolver.clone(),
+            #[cfg(feature = "unicode")]
+            normalize_unicode: self.normalize_unicode,
+            access_stats: self.access_stats.clone(),
+            handles: self.handles.clone(),
+            skipped: self.skipped.clone(),
+            truncated: self.truncated,
+            trailing: self.trailing.clone(),
+            xattrs: self.xattrs.clone(),
+            global_pax: self.global_pax.clone(),
+            renamed: self.renamed,
+            cache: LookupCache::default(),
+            links: LinkCache::default(),
+        }
+    }
+}
+
+/// The policy of symbolic links whose targets escape the archive root with `..`.
+///
+/// Absolute targets are always resolved from the archive root.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EscapingLinks {
+    /// Stop `..` at the archive root, like `chroot`.
+    #[default]
+    Clamp,
+    /// Treat the escaping links as broken.
+    Reject,
+}
+
+/// The policy of entries with the same path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// The last entry wins, like extracting with `tar`.
+    #[default]
+    LastWins,
+    /// The first entry wins.
+    FirstWins,
+    /// Fail to build the filesystem.
+    Error,
+}
+
+/// The policy of a file and a directory with the same path, e.g. a file `foo`
+/// followed by `foo/bar`, or a directory `foo` followed by a file `foo`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// The later entry replaces the earlier one, with its descendants.
+    #[default]
+    Replace,
+    /// The earlier entry is kept, and the later one is skipped.
+    KeepFirst,
+    /// Fail to build the filesystem.
+    Error,
+}
+
+/// Options of building a [`TarFS`].
+#[derive(Debug, Clone, Default)]
+pub struct TarOptions {
+    duplicates: DuplicatePolicy,
+    collisions: CollisionPolicy,
+    escaping_links: EscapingLinks,
+    max_links: Option<usize>,
+    case_insensitive: bool,
+    link_resolver: Option<LinkResolver>,
+    vendor_hook: Option<VendorHook>,
+    progress: Option<ProgressHook>,
+    cancel: Option<Arc<AtomicBool>>,
+    lenient: bool,
+    truncated: bool,
+    concatenated: bool,
+    ignore_trailing: bool,
+    strict: bool,
+    names: NamePolicy,
+    strip_components: usize,
+    rename_prefix: Option<(String, String)>,
+    intern_names: bool,
+    mmap_threshold: Option<u64>,
+    copy_small_files: usize,
+    pax_duplicates: DuplicatePolicy,
+    reject_unknown_types: bool,
+    zero_blocks: bool,
+    record_size: Option<u64>,
+    require_end: bool,
+    #[cfg(feature = "encodings")]
+    encoding: Option<NameEncoding>,
+    #[cfg(feature = "unicode")]
+    normalize_unicode: bool,
+    access_stats: bool,
+}
+
+impl TarOptions {
+    /// Create the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the policy of entries with the same path. Directories are always merged.
+    pub fn duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
+    /// Set the policy of a file and a directory with the same path.
+    pub fn collisions(mut self, policy: CollisionPolicy) -> Self {
+        self.collisions = policy;
+        self
+    }
+
+    /// Set the policy of symbolic links escaping the archive root,
+    /// see [`TarFS::set_escaping_links`].
+    pub fn escaping_links(mut self, policy: EscapingLinks) -> Self {
+        self.escaping_links = policy;
+        self
+    }
+
+    /// Set the maximum count of symbolic links followed in a lookup, 40 by default.
+    /// A lookup following more links fails like a broken link.
+    pub fn max_links(mut self, max: usize) -> Self {
+        self.max_links = Some(max);
+        self
+    }
+
+    /// Match the path components case-insensitively if there's no exact match,
+    /// for the archives created on case-insensitive filesystems.
+    /// The least of the names differing only in case is matched.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Redirect the symbolic links with absolute targets, or escaping the archive root,
+    /// into another filesystem, e.g. the host root. The resolver is called with the path
+    /// of the link and its target, and the rest of the looked up path is joined to the result.
+    ///
+    /// It's called in every lookup through such links, and should be cheap.
+    /// If it returns `None`, the link is resolved in the archive as before.
+    pub fn link_resolver(
+        mut self,
+        resolver: impl Fn(&str, &str) -> Option<VfsPath> + Send + Sync + 'static,
+    ) -> Self {
+        self.link_resolver = Some(LinkResolver::new(resolver));
+        self
+    }
+
+    /// Collect the statistics of the files opened, see [`TarFS::access_stats`].
+    pub fn access_stats(mut self, enable: bool) -> Self {
+        self.access_stats = enable;
+        self
+    }
+
+    /// Decide the action on the entries with vendor-specific typeflags, `A` to `Z`,
+    /// with the hook called with the path, the raw header and the contents of each entry.
+    /// Without the hook, they are stored as regular files.
+    pub fn vendor_hook(
+        mut self,
+        hook: impl Fn(&str, &parser::RawHeader, &[u8]) -> VendorAction + Send + Sync + 'static,
+    ) -> Self {
+        self.vendor_hook = Some(VendorHook::new(hook));
+        self
+    }
+
+    /// Report the progress of building the tree to the callback, before each entry and at the end.
+    /// The archive is parsed before, which isn't reported.
+    pub fn progress(mut self, callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(ProgressHook::new(callback));
+        self
+    }
+
+    /// Check the token before each entry, and fail with [`TarFsError::Cancelled`] once it's set.
+    pub fn cancel(mut self, token: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Skip the corrupt entries instead of failing, see [`TarFS::skipped`].
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Accept an archive whose last entry is complete, but without the end-of-archive blocks
+    /// or the padding of the last entry, e.g. an interrupted download. See [`TarFS::is_truncated`].
+    pub fn allow_truncated(mut self, allow: bool) -> Self {
+        self.truncated = allow;
+        self
+    }
+
+    /// Continue parsing after the end-of-archive blocks, for archives concatenated like `tar -A`.
+    pub fn concatenated(mut self, concatenated: bool) -> Self {
+        self.concatenated = concatenated;
+        self
+    }
+
+    /// Ignore the bytes after the end-of-archive blocks, e.g. an appended signature.
+    /// See [`TarFS::trailing`].
+    pub fn ignore_trailing(mut self, ignore: bool) -> Self {
+        self.ignore_trailing = ignore;
+        self
+    }
+
+    /// Skip the runs of zero blocks between the entries, left by some writers,
+    /// instead of taking the first one as the end of archive.
+    /// A run is skipped only if it's followed by a valid header.
+    pub fn skip_zero_blocks(mut self, skip: bool) -> Self {
+        self.zero_blocks = skip;
+        self
+    }
+
+    /// Tolerate the padding to the records of the size, e.g. 10240 bytes for the blocking
+    /// factor 20, left by some embedded toolchains. The bytes after an entry are skipped to
+    /// the next record boundary with a valid header, and the archive could end with zeros
+    /// which aren't whole blocks.
+    pub fn record_padding(mut self, record_size: u64) -> Self {
+        self.record_size = Some(parser::align_block(record_size.max(1)));
+        self
+    }
+
+    /// Require the end-of-archive blocks after the last entry, followed by nothing but zeros,
+    /// to reject the archives truncated or with data appended.
+    /// It takes precedence over [`TarOptions::allow_truncated`] and [`TarOptions::ignore_trailing`].
+    pub fn require_end(mut self, require: bool) -> Self {
+        self.require_end = require;
+        self
+    }
+
+    /// Reject the inconsistent metadata entries, like a long name overriding another one
+    /// or a PAX header followed by the end of the archive, and the entries under a path
+    /// of a file, instead of taking the last one silently.
+    /// See [`TarFsError::Inconsistent`].
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Set the policy of the names with control characters or bidirectional overrides,
+    /// which could break the terminals and the logs. See [`NamePolicy`].
+    pub fn sanitize_names(mut self, policy: NamePolicy) -> Self {
+        self.names = policy;
+        self
+    }
+
+    /// Remove the first `count` components of the paths, like `tar --strip-components`,
+    /// to mount `package-1.2.3/...` without the top-level directory. The targets of
+    /// the hard links are stripped too, and the entries with no component left are skipped.
+    pub fn strip_components(mut self, count: usize) -> Self {
+        self.strip_components = count;
+        self
+    }
+
+    /// Rename a leading directory of the paths, after [`TarOptions::strip_components`].
+    /// Only whole components match, and an empty `to` removes the directory.
+    pub fn rename_prefix(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        let (from, to) = (from.into(), to.into());
+        self.rename_prefix = Some((
+            from.trim_matches('/').to_string(),
+            to.trim_matches('/').to_string(),
+        ));
+        self
+    }
+
+    /// Set the policy of the keys repeated in a PAX header.
+    /// With [`DuplicatePolicy::Error`], they're rejected as [`TarFsError::Inconsistent`],
+    /// like the malformed records in the strict mode.
+    pub fn pax_duplicates(mut self, policy: DuplicatePolicy) -> Self {
+        self.pax_duplicates = policy;
+        self
+    }
+
+    /// Reject the entries of unknown typeflags, see [`TarFsError::UnknownType`],
+    /// instead of treating them as regular files as POSIX requires.
+    pub fn reject_unknown_types(mut self, reject: bool) -> Self {
+        self.reject_unknown_types = reject;
+        self
+    }
+
+    /// Decode the names and link targets which aren't valid UTF-8 with a legacy encoding,
+    /// instead of failing. The raw headers of such entries aren't yielded by [`TarFS::entries`].
+    #[cfg(feature = "encodings")]
+    pub fn encoding(mut self, encoding: NameEncoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Normalize the names, the link targets and the looked up paths to NFC,
+    /// for the archives created on macOS, which stores the names decomposed.
+    #[cfg(feature = "unicode")]
+    pub fn normalize_unicode(mut self, normalize: bool) -> Self {
+        self.normalize_unicode = normalize;
+        self
+    }
+
+    /// Share the repeated names in the tree, like the directory names of a large archive,
+    /// instead of allocating them for every entry. It cuts the memory of the index,
+    /// see [`TarFS::index_memory_usage`], but takes a little longer to build.
+    pub fn intern_names(mut self, intern: bool) -> Self {
+        self.intern_names = intern;
+        self
+    }
+
+    /// Set the size from which [`TarFS::open_path`] maps the archive instead of reading it,
+    /// 1 MiB by default. `u64::MAX` always reads it, e.g. on a network filesystem
+    /// where mapping is slow or unsupported.
+    pub fn mmap_threshold(mut self, bytes: u64) -> Self {
+        self.mmap_threshold = Some(bytes);
+        self
+    }
+
+    /// Copy the files shorter than `threshold` bytes out of the archive when building,
+    /// for the locality of many small files, while the larger ones are still read in place.
+    /// It trades memory for speed, and is disabled by default, with 0.
+    pub fn copy_small_files(mut self, threshold: usize) -> Self {
+        self.copy_small_files = threshold;
+        self
+    }
+
+    /// Whether the archive should be parsed entry by entry.
+    fn is_lenient(&self) -> bool {
+        self.lenient
+            || self.truncated
+            || self.concatenated
+            || self.ignore_trailing
+            || self.zero_blocks
+            || self.record_size.is_some()
+    }
 }
 
-impl<F: StableDeref<Target = [u8]>> TarFS<F> {
+impl<F: Deref<Target = [u8]>> TarFS<F> {
     /// Create [`TarFS`] from a specified file or buffer.
+    ///
+    /// This is the way to go on `wasm32`, where memory mapping is unavailable:
+    /// download or read the archive into a [`Vec<u8>`] and pass it here.
     pub fn new(file: F) -> VfsResult<Self> {
-        // SAFETY: the entries won't live longer than mmap
-        let (_, entries) = parse_tar(unsafe { &*(file.deref() as *const [u8]) })
-            .map_err(|e| VfsErrorKind::Other(e.to_string()))?;
-        let root = DirTreeBuilder::default().build(&entries);
-        Ok(Self { file, root })
+        Self::with_options(file, TarOptions::default())
+    }
+
+    /// Create [`TarFS`] from a specified file or buffer with options.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(len = file.len()))
+    )]
+    pub fn with_options(file: F, options: TarOptions) -> VfsResult<Self> {
+        let buf = file.deref();
+        // The legacy names are parsed from a copy with the same layout.
+        #[cfg(feature = "encodings")]
+        let transcoded = options
+            .encoding
+            .and_then(|encoding| encoding::transcode(buf, encoding));
+        #[cfg(feature = "encodings")]
+        let buf = transcoded.as_ref().map(|t| t.buf.as_slice()).unwrap_or(buf);
+        let lenient::Parsed {
+            entries,
+            skipped,
+            truncated,
+            trailing,
+        } = if options.is_lenient() {
+            lenient::parse_entries(buf, &options)?
+        } else {
+            #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+            let entries = parallel::parse_entries(buf);
+            #[cfg(not(all(feature = "rayon", not(target_arch = "wasm32"))))]
+            let entries = None;
+            let entries = match entries {
+                Some(entries) => entries,
+                None => lenient::parse_all(buf, &options)?,
+            };
+            lenient::Parsed {
+                entries,
+                ..Default::default()
+            }
+        };
+        if options.require_end {
+            lenient::check_end(buf, &entries)?;
+        }
+        #[allow(unused_mut)]
+        let mut builder = DirTreeBuilder::new(buf, options.clone());
+        #[cfg(feature = "encodings")]
+        if let Some(transcoded) = transcoded.as_ref() {
+            builder.names = transcoded.names.clone();
+            builder.links = transcoded.links.clone();
+        }
+        #[allow(unused_mut)]
+        let (root, mut records, xattrs, global_pax) = builder.build(&entries)?;
+        // The headers with the legacy names are parsed from the copy.
+        #[cfg(feature = "encodings")]
+        if let Some(transcoded) = transcoded.as_ref() {
+            for record in &mut records {
+                let offset = record.entry.start as u64;
+                if transcoded.names.contains_key(&offset) || transcoded.links.contains_key(&offset)
+                {
+                    let end = record
+                        .contents
+                        .start
+                        .max(record.entry.start + parser::BLOCK_SIZE as usize);
+                    record.header = Some(transcoded.buf[record.entry.start..end].into());
+                }
+            }
+        }
+        let mut fs = Self::from_built(file, (root, records, xattrs, global_pax), &options);
+        if options.copy_small_files > 0 {
+            fs.owned = Arc::new(OwnedFiles::new(
+                fs.buf(),
+                &fs.root,
+                options.copy_small_files,
+            ));
+        }
+        fs.skipped = skipped;
+        fs.truncated = truncated;
+        fs.trailing = trailing;
+        Ok(fs)
+    }
+
+    /// Create [`TarFS`] from a tree built with the options, and apply the lookup options.
+    fn from_built(file: F, built: BuiltTree, options: &TarOptions) -> Self {
+        let (mut root, records, xattrs, global_pax) = built;
+        if options.case_insensitive {
+            root.fold_names();
+        }
+        let mut fs = Self::from_parts(file, records, root);
+        fs.escaping_links = options.escaping_links;
+        fs.max_links = options.max_links.unwrap_or(MAX_LINKS);
+        fs.case_insensitive = options.case_insensitive;
+        fs.link_resolver = options.link_resolver.clone();
+        fs.set_access_stats(options.access_stats);
+        #[cfg(feature = "unicode")]
+        {
+            fs.normalize_unicode = options.normalize_unicode;
+        }
+        fs.xattrs = Arc::new(xattrs);
+        fs.global_pax = Arc::new(global_pax);
+        fs.renamed = options.strip_components > 0 || options.rename_prefix.is_some();
+        fs
+    }
+
+    /// Create [`TarFS`] from a newc or odc cpio archive, e.g. an initramfs image.
+    #[cfg(feature = "cpio")]
+    pub fn new_cpio(file: F) -> VfsResult<Self> {
+        let buf = file.deref();
+        let entries = cpio::parse_cpio(buf).map_err(VfsErrorKind::Other)?;
+        let root = DirTreeBuilder::new(buf, TarOptions::default()).build_cpio(&entries)?;
+        Ok(Self::from_parts(file, vec![], root))
+    }
+
+    fn from_parts(file: F, records: Vec<EntryRecord>, mut root: DirTree) -> Self {
+        metadata::dir_sizes(&mut root);
+        metadata::number_inodes(&mut root);
+        let root = Arc::new(root);
+        Self {
+            file: Arc::new(file),
+            owned: Arc::default(),
+            records: Arc::new(records),
+            root,
+            escaping_links: EscapingLinks::default(),
+            max_links: MAX_LINKS,
+            case_insensitive: false,
+            link_resolver: None,
+            #[cfg(feature = "unicode")]
+            normalize_unicode: false,
+            access_stats: None,
+            handles: Arc::default(),
+            skipped: vec![],
+            truncated: false,
+            trailing: None,
+            xattrs: Arc::default(),
+            global_pax: Arc::default(),
+            renamed: false,
+            cache: LookupCache::default(),
+            links: LinkCache::default(),
+        }
+    }
+
+    /// The byte ranges skipped by the lenient parsing, see [`TarOptions::lenient`].
+    pub fn skipped(&self) -> &[Range<u64>] {
+        &self.skipped
+    }
+
+    /// Whether the archive is truncated, see [`TarOptions::allow_truncated`].
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The byte range after the archive, starting from the end-of-archive blocks,
+    /// see [`TarOptions::ignore_trailing`].
+    pub fn trailing(&self) -> Option<Range<u64>> {
+        self.trailing.clone()
+    }
+
+    /// Iterate the entries in the archive order, with their full paths, raw headers and contents.
+    ///
+    /// The metadata entries, like GNU long names and PAX headers, are applied to
+    /// the following entries and not yielded.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, TarHeader<'_>, &[u8])> {
+        let buf = self.buf();
+        self.records.iter().map(move |record| {
+            (
+                record.path.as_str(),
+                self.record_header(record),
+                &buf[record.contents.clone()],
+            )
+        })
+    }
+
+    /// Like [`TarFS::entries`], with the raw paths before [`TarOptions::sanitize_names`].
+    pub fn raw_entries(&self) -> impl Iterator<Item = (&str, TarHeader<'_>, &[u8])> {
+        let buf = self.buf();
+        self.records.iter().map(move |record| {
+            (
+                record.raw_path.as_deref().unwrap_or(&record.path),
+                self.record_header(record),
+                &buf[record.contents.clone()],
+            )
+        })
+    }
+
+    /// The raw header of a record, parsed from its header block only.
+    fn record_header<'s>(&'s self, record: &'s EntryRecord) -> TarHeader<'s> {
+        let block = match &record.header {
+            Some(header) => header,
+            None => &self.buf()[record.entry.start..],
+        };
+        match parse_entry_streaming(block) {
+            Ok((_, Some(entry))) => entry.header,
+            _ => unreachable!("the header of a record is parsed when building the tree"),
+        }
+    }
+
+    /// The whole archive.
+    fn buf(&self) -> &[u8] {
+        &self.file
+    }
+
+    /// The contents of a file at the range of the archive, or the copy of it.
+    fn contents(&self, range: &Range<usize>) -> Option<&[u8]> {
+        self.owned
+            .get(range)
+            .or_else(|| self.buf().get(range.clone()))
+    }
+
+    /// The offset in the archive of a slice returned by [`TarFS::find_entry`],
+    /// which may be in the copies of the small files.
+    fn archive_offset(&self, slice: &[u8]) -> usize {
+        self.owned
+            .archive_offset(slice)
+            .unwrap_or_else(|| offset_of(self.buf(), slice))
+    }
+
+    /// Share a slice of the archive, which should be returned by [`TarFS::find_entry`].
+    fn share(&self, slice: &[u8]) -> ArcSlice<F> {
+        if let Some(range) = self.owned.range_of(slice) {
+            return ArcSlice {
+                file: self.file.clone(),
+                owned: Some(self.owned.clone()),
+                range,
+            };
+        }
+        let start = offset_of(self.buf(), slice);
+        ArcSlice {
+            file: self.file.clone(),
+            owned: None,
+            range: start..start + slice.len(),
+        }
+    }
+
+    /// Open a range of a file. The range is clamped to the file.
+    pub fn open_file_range(
+        &self,
+        path: &str,
+        offset: u64,
+        len: u64,
+    ) -> VfsResult<Box<dyn SeekAndRead + Send>>
+    where
+        F: Send + Sync + 'static,
+    {
+        match self.file_entry(path)? {
+            EntryRef::Sparse(data, sparse) => {
+                let start = offset.min(sparse.size);
+                let end = offset.saturating_add(len).min(sparse.size);
+                // The holes are read lazily, as the size is untrusted.
+                let data = self.share(data);
+                Ok(Box::new(SparseReader::range(data, sparse, start, end)))
+            }
+            EntryRef::File(buf, _) => {
+                let start = offset.min(buf.len() as u64) as usize;
+                let end = offset.saturating_add(len).min(buf.len() as u64) as usize;
+                Ok(Box::new(Cursor::new(self.share(&buf[start..end]))))
+            }
+            _ => Err(self.not_found(path)),
+        }
+    }
+
+    /// Read a file from the offset into the buffer, without opening it.
+    /// Returns the count of bytes read, which is 0 at the end of the file.
+    pub fn read_at(&self, path: &str, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        match self.file_entry(path)? {
+            EntryRef::Sparse(data, sparse) => {
+                Ok(sparse::read_at(data, &sparse.map, sparse.size, offset, buf))
+            }
+            EntryRef::File(contents, _) => {
+                let start = offset.min(contents.len() as u64) as usize;
+                let len = buf.len().min(contents.len() - start);
+                buf[..len].copy_from_slice(&contents[start..start + len]);
+                Ok(len)
+            }
+            _ => Err(self.not_found(path)),
+        }
+    }
+
+    /// Get the contents of a file in the archive, without copying,
+    /// or its copy with [`TarOptions::copy_small_files`].
+    ///
+    /// The sparse files aren't stored contiguously, and should be read with [`TarFS::read_at`].
+    pub fn file_slice(&self, path: &str) -> VfsResult<&[u8]> {
+        match self.file_entry(path)? {
+            EntryRef::File(contents, _) => Ok(contents),
+            _ => Err(VfsErrorKind::Other(format!(
+                "{path} is a sparse file, which isn't stored contiguously"
+            ))
+            .into()),
+        }
     }
 
-    fn find_entry(&self, path: &str) -> Option<EntryRef> {
-        let mut path: Cow<Path> = strip_path(path).into();
-        loop {
-            let res = Self::find_entry_impl(&self.root, path.iter());
-            if let Some(EntryRef::Link(p)) = res {
-                path = Self::read_link(path, p);
-            } else {
-                return res;
+    /// Find a regular or sparse file.
+    fn file_entry(&self, path: &str) -> VfsResult<EntryRef<'_>> {
+        match self.find_entry(path) {
+            Some(entry @ (EntryRef::File(..) | EntryRef::Sparse(..))) => Ok(entry),
+            Some(EntryRef::Special(..)) => Err(special_error(path)),
+            Some(EntryRef::Directory(_)) => Err(dir_error(path)),
+            _ => Err(self.not_found(path)),
+        }
+    }
+
+    /// The error of a missing entry. A path under a file is invalid rather than not found.
+    fn not_found(&self, path: &str) -> VfsError {
+        let path = path.trim_matches('/');
+        let under_file = path.match_indices('/').any(|(i, _)| {
+            matches!(
+                self.find_entry(&path[..i]),
+                Some(entry) if !matches!(entry, EntryRef::Directory(_))
+            )
+        });
+        if under_file {
+            VfsErrorKind::InvalidPath.into()
+        } else {
+            VfsErrorKind::FileNotFound.into()
+        }
+    }
+
+    /// Set the policy of symbolic links escaping the archive root.
+    /// The resolution never escapes the archive root with either policy.
+    pub fn set_escaping_links(&mut self, policy: EscapingLinks) {
+        self.escaping_links = policy;
+        self.cache.clear();
+        self.links.clear();
+    }
+
+    fn entry_metadata(&self, entry: &EntryRef) -> VfsMetadata {
+        // The synthesized parents and the links have no header.
+        let modified = Some(SystemTime::UNIX_EPOCH);
+        match entry {
+            EntryRef::File(buf, attrs) => VfsMetadata {
+                file_type: VfsFileType::File,
+                len: buf.len() as u64,
+                created: attrs.ctime.and_then(unix_time),
+                modified: unix_time(attrs.mtime),
+                accessed: attrs.atime.and_then(unix_time),
+            },
+            EntryRef::Directory(dir) => match &dir.attrs {
+                Some(dir) => VfsMetadata {
+                    file_type: VfsFileType::Directory,
+                    len: 0,
+                    created: dir.ctime.and_then(unix_time),
+                    modified: unix_time(dir.mtime),
+                    accessed: dir.atime.and_then(unix_time),
+                },
+                None => VfsMetadata {
+                    file_type: VfsFileType::Directory,
+                    len: 0,
+                    created: None,
+                    modified,
+                    accessed: None,
+                },
+            },
+            // vfs only knows files and directories.
+            EntryRef::Sparse(_, sparse) => VfsMetadata {
+                file_type: VfsFileType::File,
+                len: sparse.size,
+                created: sparse.attrs.ctime.and_then(unix_time),
+                modified: unix_time(sparse.attrs.mtime),
+                accessed: sparse.attrs.atime.and_then(unix_time),
+            },
+            EntryRef::Special(_, attrs, _) => VfsMetadata {
+                file_type: VfsFileType::File,
+                len: 0,
+                created: attrs.ctime.and_then(unix_time),
+                modified: unix_time(attrs.mtime),
+                accessed: attrs.atime.and_then(unix_time),
+            },
+            // The links are resolved before, and never returned by `find_entry`.
+            EntryRef::Link(_) => VfsMetadata {
+                file_type: VfsFileType::File,
+                len: 0,
+                created: None,
+                modified,
+                accessed: None,
+            },
+        }
+    }
+
+    /// Find the entry from the archive root. The results are cached.
+    ///
+    /// The backslash separators from Windows are accepted, if no entry has them in the name.
+    fn find_entry(&self, path: &str) -> Option<EntryRef<'_>> {
+        self.resolve_entry(path).map(|(entry, _)| entry)
+    }
+
+    /// Find the entry from the archive root, with the path reaching it without links.
+    fn resolve_entry(&self, path: &str) -> Option<(EntryRef<'_>, Arc<str>)> {
+        #[cfg(feature = "unicode")]
+        if self.normalize_unicode {
+            if let Cow::Owned(path) = unicode::to_nfc(path.into()) {
+                return self.resolve_entry(&path);
+            }
+        }
+        // The cache keeps the resolved paths, which are walked again without links.
+        let res = match self.cache.get(path) {
+            Some(resolved) => resolved.and_then(|resolved| {
+                let entry = self.find_entry_impl(&self.root, &mut components(&resolved))?;
+                Some((entry, resolved))
+            }),
+            None => {
+                let (entry, resolved) = self.resolve_from(&self.root, path);
+                let resolved = entry.map(|_| Arc::<str>::from(resolved));
+                self.cache.insert(path, resolved.clone());
+                entry.zip(resolved)
+            }
+        };
+        match res {
+            None if path.contains('\\') => self.resolve_entry(&path.replace('\\', "/")),
+            res => res,
+        }
+    }
+
+    /// Find the entry from the specified root. The links are resolved within the root.
+    fn find_entry_from<'a>(&'a self, root: &'a DirTree, path: &str) -> Option<EntryRef<'a>> {
+        self.resolve_from(root, path).0
+    }
+
+    /// Find the entry from the specified root, and the last path walked,
+    /// which reaches the entry without links if it's found.
+    fn resolve_from<'a, 'p>(
+        &'a self,
+        root: &'a DirTree,
+        path: &'p str,
+    ) -> (Option<EntryRef<'a>>, Cow<'p, str>) {
+        // The links are cached only when resolved from the archive root.
+        let cached = std::ptr::eq(root, self.root.deref());
+        // The path is split in place, and only copied to resolve `..` or the links.
+        let mut path = normalize_str(path);
+        // The links whose targets are being resolved, innermost last, with the rest
+        // of the path after each one, and the count of links resolved before it.
+        let mut pending: Vec<(PathBuf, PathBuf, usize)> = vec![];
+        // The count of links resolved, including the ones resolved by the cache.
+        let mut hops = 0;
+        let res = loop {
+            let mut rest = components(&path);
+            match self.find_entry_impl(root, &mut rest) {
+                Some(EntryRef::Link(p)) => {
+                    let rest = rest.collect::<PathBuf>();
+                    let full = components(&path).collect::<PathBuf>();
+                    // A link in the middle of the path is resolved, and the rest is appended.
+                    let depth = full.iter().count() - rest.iter().count();
+                    let link = full.iter().take(depth).collect::<PathBuf>();
+                    if let Some((resolved, count)) = cached.then(|| self.links.get(&link)).flatten()
+                    {
+                        // The cached links count against the limit, like the resolved ones.
+                        if hops + count > self.max_links {
+                            return (None, path);
+                        }
+                        hops += count;
+                        // The resolved path is walked again without links.
+                        match resolved {
+                            Some(resolved) => {
+                                path = join_path(Path::new(resolved.as_ref()), &rest).into();
+                                continue;
+                            }
+                            None => break None,
+                        }
+                    }
+                    // The exceeded resolution isn't cached, as it depends on the links before.
+                    if hops >= self.max_links {
+                        return (None, path);
+                    }
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(link = %path, target = p, "resolve link");
+                    pending.push((link.clone(), rest, hops));
+                    hops += 1;
+                    // The redirected links are looked up by `resolve_external`.
+                    if self.link_resolver.as_ref().is_some_and(|resolver| {
+                        resolver::escapes(&link, p) && resolver.resolve(&link, p).is_some()
+                    }) {
+                        break None;
+                    }
+                    match self.read_link(link.into(), p) {
+                        Some(target) => path = join_path(&target, Path::new("")).into(),
+                        None => break None,
+                    }
+                }
+                // The target of the innermost link is resolved, and the rest after it follows.
+                Some(_) if !pending.is_empty() => {
+                    drop(rest);
+                    let (link, rest, before) = pending.pop().unwrap();
+                    if cached {
+                        self.links
+                            .insert(link, Some(Arc::from(path.as_ref())), hops - before);
+                    }
+                    path = join_path(Path::new(path.as_ref()), &rest).into();
+                }
+                res => break res,
+            }
+        };
+        // The missing target of a link is the missing target of the links through it.
+        if cached && res.is_none() {
+            for (link, _, before) in pending {
+                self.links.insert(link, None, hops - before);
             }
         }
+        (res, path)
     }
 
-    fn find_entry_impl<'a>(dir: &'a DirTree, mut path: Iter) -> Option<EntryRef<'a>> {
-        let next_path = match path.next() {
-            Some(str) => str.to_string_lossy(),
-            None => return Some(EntryRef::Directory(dir)),
+    /// Find the entry in the directory. A link is returned even in the middle of the path,
+    /// with the rest of the components left in `path`. Nothing is allocated unless
+    /// a name is matched case-insensitively.
+    fn find_entry_impl<'a, 'p>(
+        &'a self,
+        dir: &'a DirTree,
+        path: &mut impl Iterator<Item = &'p str>,
+    ) -> Option<EntryRef<'a>> {
+        let buf = self.buf();
+        let Some(next_path) = path.next() else {
+            return Some(EntryRef::Directory(dir));
         };
-        if let Some(entry) = dir.get(next_path.as_ref()) {
+        if let Some((_, entry)) = dir.get_folded(next_path, self.case_insensitive) {
             match entry {
-                Entry::File(buf) => {
-                    debug_assert!(path.next().is_none());
-                    Some(EntryRef::File(buf))
+                // A file doesn't contain other entries.
+                Entry::File(range, attrs) if path.next().is_none() => {
+                    Some(EntryRef::File(self.contents(range)?, attrs))
                 }
-                Entry::Directory(dir) => Self::find_entry_impl(dir, path),
-                Entry::Link(p) => {
-                    debug_assert!(path.next().is_none());
-                    Some(EntryRef::Link(p))
+                Entry::Sparse(sparse) if path.next().is_none() => {
+                    Some(EntryRef::Sparse(buf.get(sparse.data.clone())?, sparse))
                 }
+                Entry::File(..) | Entry::Sparse(_) => None,
+                Entry::Special(special, attrs, ino) => path
+                    .next()
+                    .is_none()
+                    .then_some(EntryRef::Special(*special, attrs, *ino)),
+                Entry::Directory(dir) => self.find_entry_impl(dir, path),
+                Entry::Link(p) => Some(EntryRef::Link(p)),
             }
         } else {
             None
         }
     }
 
-    fn read_link<'a>(path: Cow<Path>, target: &'a str) -> Cow<'a, Path> {
+    fn read_link<'a>(&self, path: Cow<Path>, target: &'a str) -> Option<Cow<'a, Path>> {
         if let Some(target) = target.strip_prefix('/') {
-            Path::new(target).into()
+            Some(Path::new(target).into())
         } else {
             let mut path = path.into_owned();
             path.pop();
             let target_components = Path::new(target).iter();
             for c in target_components {
                 if c == ".." {
-                    path.pop();
+                    // An empty path can't be popped, so it never escapes the root.
+                    if !path.pop() && self.escaping_links == EscapingLinks::Reject {
+                        return None;
+                    }
                 } else {
                     path.push(c);
                 }
             }
-            path.into()
+            Some(path.into())
         }
     }
 }
 
-#[cfg(feature = "mmap")]
+impl TarFS<Vec<u8>> {
+    /// Create [`TarFS`] by reading the whole archive into memory,
+    /// e.g. from a WASI file or a fetched response body.
+    pub fn from_reader(mut reader: impl Read) -> VfsResult<Self> {
+        let mut buffer = vec![];
+        reader.read_to_end(&mut buffer)?;
+        Self::new(buffer)
+    }
+
+    /// Create [`TarFS`] from an archive stored in another filesystem, e.g. in memory,
+    /// on disk or in another [`TarFS`]. The buffer is allocated once with the file length.
+    pub fn from_vfs_path(path: &VfsPath) -> VfsResult<Self> {
+        let len = path.metadata()?.len;
+        let mut buffer = vec![];
+        buffer
+            .try_reserve_exact(usize::try_from(len).unwrap_or(usize::MAX))
+            .map_err(|e| VfsError::from(VfsErrorKind::Other(e.to_string())))?;
+        path.open_file()?.read_to_end(&mut buffer)?;
+        Self::new(buffer)
+    }
+}
+
+/// Embed a tar archive into the binary, and create a [`TarFS<&'static [u8]>`](TarFS) from it.
+///
+/// The path is resolved like [`include_bytes!`], relative to the current file.
+///
+/// # Panics
+///
+/// Panics if the embedded archive is invalid.
+///
+/// ```ignore
+/// let fs = vfs_tar::include_tar!("assets.tar");
+/// ```
+#[macro_export]
+macro_rules! include_tar {
+    ($path:expr) => {
+        $crate::TarFS::new(::core::include_bytes!($path).as_slice())
+            .expect(::core::concat!("invalid tar archive ", $path))
+    };
+}
+
+impl<'a> TarFS<&'a [u8]> {
+    /// Create [`TarFS`] borrowing a buffer, e.g. a local buffer or an arena.
+    ///
+    /// [`FileSystem`] requires `'static`, so the borrowing filesystem is read
+    /// with the inherent methods, like [`TarFS::file_slice`] and [`TarFS::read_at`].
+    pub fn from_slice(buf: &'a [u8]) -> VfsResult<Self> {
+        Self::new(buf)
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+use memmap2::Advice;
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
 use memmap2::{Mmap, MmapOptions};
 
-#[cfg(feature = "mmap")]
+#[cfg(all(feature = "mmap", not(target_arch = "wasm32")))]
 impl TarFS<Mmap> {
     /// Create [`TarFS`] from the archive path.
     pub fn new_mmap(p: impl AsRef<Path>) -> VfsResult<Self> {
@@ -112,38 +1204,94 @@ impl TarFS<Mmap> {
     }
 
     /// Get the inner [`Mmap`].
-    pub fn into_inner(self) -> Mmap {
-        self.file
+    /// Fails if any opened file is still alive, which shares the [`Mmap`].
+    #[allow(clippy::result_large_err)]
+    pub fn into_inner(self) -> Result<Mmap, Self> {
+        match Arc::try_unwrap(self.file) {
+            Ok(file) => Ok(file),
+            Err(file) => Err(Self { file, ..self }),
+        }
+    }
+
+    /// Advise the kernel that the archive will be read sequentially,
+    /// e.g. before extracting or hashing all files.
+    #[cfg(unix)]
+    pub fn advise_sequential(&self) -> VfsResult<()> {
+        Ok(self.file.advise(Advice::Sequential)?)
+    }
+
+    /// Advise the kernel that the contents of a file, or all files in a directory,
+    /// will be read soon, so that the pages are prefetched.
+    #[cfg(unix)]
+    pub fn advise_willneed(&self, path: &str) -> VfsResult<()> {
+        let mut ranges = vec![];
+        match self.find_entry(path) {
+            Some(EntryRef::File(buf, _) | EntryRef::Sparse(buf, _)) => {
+                let start = self.archive_offset(buf);
+                ranges.push(start..start + buf.len());
+            }
+            Some(EntryRef::Directory(dir)) => file_ranges(dir, &mut ranges),
+            _ => return Err(VfsErrorKind::FileNotFound.into()),
+        }
+        for range in ranges.into_iter().filter(|range| !range.is_empty()) {
+            self.file
+                .advise_range(Advice::WillNeed, range.start, range.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// The byte ranges of all files in a directory. Links are not followed.
+#[cfg(all(feature = "mmap", unix))]
+fn file_ranges(dir: &DirTree, ranges: &mut Vec<Range<usize>>) {
+    for entry in dir.values() {
+        match entry {
+            Entry::File(range, _) => ranges.push(range.clone()),
+            Entry::Sparse(sparse) => ranges.push(sparse.data.clone()),
+            Entry::Directory(dir) => file_ranges(dir, ranges),
+            Entry::Link(_) | Entry::Special(..) => {}
+        }
     }
 }
 
-impl<F: StableDeref<Target = [u8]> + Debug + Send + Sync + 'static> FileSystem for TarFS<F> {
+impl<F: Deref<Target = [u8]> + Debug + Send + Sync + 'static> FileSystem for TarFS<F> {
     fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
         let dir = if path.is_empty() {
-            &self.root
+            self.root.deref()
         } else {
             match self.find_entry(path) {
                 Some(EntryRef::Directory(dir)) => dir,
-                _ => return Err(VfsErrorKind::FileNotFound.into()),
+                Some(_) => return Err(not_dir_error(path)),
+                None => match self.resolve_external(path) {
+                    Some(path) => {
+                        return Ok(Box::new(path.read_dir()?.map(|path| path.filename())))
+                    }
+                    None => return Err(self.not_found(path)),
+                },
             }
         };
-        Ok(Box::new(
-            dir.keys()
-                .map(|s| s.to_string())
-                .collect::<Vec<_>>()
-                .into_iter(),
-        ))
+        Ok(Box::new(DirNames::new(dir)))
     }
 
     fn create_dir(&self, _path: &str) -> VfsResult<()> {
         Err(VfsErrorKind::NotSupported.into())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
-        match self.find_entry(path) {
-            Some(EntryRef::File(buf)) => Ok(Box::new(Cursor::new(buf))),
-            _ => Err(VfsErrorKind::FileNotFound.into()),
-        }
+        let file: Box<dyn SeekAndRead + Send> = match self.find_entry(path) {
+            Some(EntryRef::File(buf, _)) => Box::new(Cursor::new(self.share(buf))),
+            Some(EntryRef::Sparse(data, sparse)) => {
+                Box::new(SparseReader::new(self.share(data), sparse))
+            }
+            Some(EntryRef::Special(..)) => return Err(special_error(path)),
+            Some(EntryRef::Directory(_)) => return Err(dir_error(path)),
+            _ => match self.resolve_external(path) {
+                Some(path) => path.open_file()?,
+                None => return Err(self.not_found(path)),
+            },
+        };
+        Ok(self.track_access(path, file))
     }
 
     fn create_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
@@ -156,33 +1304,22 @@ impl<F: StableDeref<Target = [u8]> + Debug + Send + Sync + 'static> FileSystem f
 
     fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
         match self.find_entry(path) {
-            Some(e) => {
-                //FIXME get mtime from Header also ctime atime from Pax Headers
-                let modified = Some(SystemTime::UNIX_EPOCH);
-                match e {
-                    EntryRef::File(buf) => Ok(VfsMetadata {
-                        file_type: VfsFileType::File,
-                        len: buf.len() as u64,
-                        created: None,
-                        modified,
-                        accessed: None,
-                    }),
-                    EntryRef::Directory(_) => Ok(VfsMetadata {
-                        file_type: VfsFileType::Directory,
-                        len: 0,
-                        created: None,
-                        modified,
-                        accessed: None,
-                    }),
-                    EntryRef::Link(_) => unreachable!(),
-                }
-            }
-            None => Err(VfsErrorKind::FileNotFound.into()),
+            Some(e) => Ok(self.entry_metadata(&e)),
+            None => match self.resolve_external(path) {
+                Some(path) => path.metadata(),
+                None => Err(self.not_found(path)),
+            },
         }
     }
 
     fn exists(&self, path: &str) -> VfsResult<bool> {
-        Ok(self.find_entry(path).is_some())
+        match self.find_entry(path) {
+            Some(_) => Ok(true),
+            None => match self.resolve_external(path) {
+                Some(path) => path.exists(),
+                None => Ok(false),
+            },
+        }
     }
 
     fn remove_file(&self, _path: &str) -> VfsResult<()> {
@@ -194,99 +1331,736 @@ impl<F: StableDeref<Target = [u8]> + Debug + Send + Sync + 'static> FileSystem f
     }
 }
 
-#[derive(Debug)]
+/// A shared range of the archive, kept alive by the opened files.
+#[derive(Debug, Clone)]
+struct ArcSlice<F> {
+    file: Arc<F>,
+    /// The copies of the small files, which the range is in if set.
+    owned: Option<Arc<OwnedFiles>>,
+    range: Range<usize>,
+}
+
+impl<F: Deref<Target = [u8]>> AsRef<[u8]> for ArcSlice<F> {
+    fn as_ref(&self) -> &[u8] {
+        match &self.owned {
+            Some(owned) => &owned.data()[self.range.clone()],
+            None => &self.file[self.range.clone()],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 enum Entry {
-    File(&'static [u8]),
+    /// The byte range of the contents in the archive.
+    File(Range<usize>, Attrs),
     Directory(DirTree),
-    Link(&'static str),
+    Link(String),
+    /// The kind, the metadata and the inode number, see [`TarFS::tar_metadata`].
+    Special(SpecialFile, Attrs, u64),
+    Sparse(Box<SparseFile>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum EntryRef<'a> {
-    File(&'static [u8]),
+    File(&'a [u8], &'a Attrs),
     Directory(&'a DirTree),
-    Link(&'static str),
+    Link(&'a str),
+    Special(SpecialFile, &'a Attrs, u64),
+    /// The stored data and the map of a sparse file.
+    Sparse(&'a [u8], &'a SparseFile),
+}
+
+impl<'a> EntryRef<'a> {
+    /// The contents of a file, with the holes of a sparse file filled.
+    fn contents(&self) -> Option<Cow<'a, [u8]>> {
+        match self {
+            EntryRef::File(buf, _) => Some(Cow::Borrowed(buf)),
+            EntryRef::Sparse(data, sparse) => {
+                // The size is untrusted, and may be too large to allocate.
+                let size = usize::try_from(sparse.size).ok()?;
+                let mut buffer = vec![];
+                buffer.try_reserve_exact(size).ok()?;
+                buffer.resize(size, 0);
+                sparse::read_at(data, &sparse.map, sparse.size, 0, &mut buffer);
+                Some(Cow::Owned(buffer))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The attributes of a file from its headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Attrs {
+    /// The access time in seconds, from the GNU header or PAX.
+    atime: Option<u64>,
+    /// The status change time in seconds, from the GNU header or PAX.
+    ctime: Option<u64>,
+    /// The modification time in seconds, with the PAX `mtime` applied.
+    #[cfg_attr(feature = "serde", serde(default))]
+    mtime: u64,
+    /// The permission bits.
+    #[cfg_attr(feature = "serde", serde(default))]
+    mode: u32,
+}
+
+/// A directory with its children by name, and its metadata computed when building.
+#[derive(Debug, Clone, Default)]
+struct DirTree {
+    children: HashMap<Arc<str>, Entry>,
+    /// The metadata of the explicit directory entry. The synthesized parents have none.
+    attrs: Option<Attrs>,
+    /// The recursive size of the files, see [`TarFS::dir_size`].
+    size: u64,
+    /// The inode number, see [`TarFS::tar_metadata`].
+    ino: u64,
+    /// The names by their lowercase forms, with [`TarOptions::case_insensitive`].
+    folded: HashMap<Box<str>, Arc<str>>,
+}
+
+impl DirTree {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index the names by their lowercase forms recursively.
+    /// The least name wins if several differ only in case, regardless of the archive order.
+    fn fold_names(&mut self) {
+        let mut folded = HashMap::<Box<str>, Arc<str>>::new();
+        for (name, entry) in &mut self.children {
+            folded
+                .entry(name.to_lowercase().into())
+                .and_modify(|least| {
+                    if name < least {
+                        *least = name.clone();
+                    }
+                })
+                .or_insert_with(|| name.clone());
+            if let Entry::Directory(dir) = entry {
+                dir.fold_names();
+            }
+        }
+        self.folded = folded;
+    }
+
+    /// Find a child by its name, or its lowercase form if `fold`.
+    fn get_folded(&self, name: &str, fold: bool) -> Option<(&Arc<str>, &Entry)> {
+        self.get_key_value(name).or_else(|| {
+            fold.then(|| self.folded.get(name.to_lowercase().as_str()))
+                .flatten()
+                .and_then(|name| self.get_key_value(name))
+        })
+    }
+}
+
+impl Deref for DirTree {
+    type Target = HashMap<Arc<str>, Entry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.children
+    }
+}
+
+impl DerefMut for DirTree {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.children
+    }
+}
+
+impl FromIterator<(Arc<str>, Entry)> for DirTree {
+    fn from_iter<T: IntoIterator<Item = (Arc<str>, Entry)>>(iter: T) -> Self {
+        Self {
+            children: iter.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl IntoIterator for DirTree {
+    type Item = (Arc<str>, Entry);
+    type IntoIter = hash_map::IntoIter<Arc<str>, Entry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.children.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a DirTree {
+    type Item = (&'a Arc<str>, &'a Entry);
+    type IntoIter = hash_map::Iter<'a, Arc<str>, Entry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.children.iter()
+    }
 }
 
-type DirTree = HashMap<String, Entry>;
+/// The default maximum count of links followed in a lookup, like `SYMLOOP_MAX`.
+const MAX_LINKS: usize = 40;
+
+/// The extended attributes of an entry.
+type Xattrs = HashMap<String, Vec<u8>>;
+
+/// The records of PAX headers by key. The values may be binary.
+type PaxRecords = HashMap<String, Vec<u8>>;
+
+/// A non-metadata entry in the archive.
+#[derive(Debug)]
+struct EntryRecord {
+    path: String,
+    /// The start of the metadata entries applied to this entry, or of the header.
+    start: usize,
+    /// The byte range of the header and the padded contents.
+    entry: Range<usize>,
+    /// The byte range of the contents.
+    contents: Range<usize>,
+    /// The header fields overridden by PAX.
+    overrides: Option<Box<PaxOverrides>>,
+    /// The raw path, if it's changed by [`TarOptions::sanitize_names`].
+    raw_path: Option<String>,
+    /// The header blocks with the legacy names replaced, if they aren't valid UTF-8,
+    /// see [`TarOptions::encoding`].
+    header: Option<Box<[u8]>>,
+}
+
+/// The tree, the records, the extended attributes and the global PAX records,
+/// built by [`DirTreeBuilder`].
+type BuiltTree = (
+    DirTree,
+    Vec<EntryRecord>,
+    HashMap<String, Xattrs>,
+    PaxRecords,
+);
 
 #[derive(Debug, Default)]
-struct DirTreeBuilder {
+struct DirTreeBuilder<'a> {
     root: DirTree,
-    longname: Option<Cow<'static, str>>,
-    longlink: Option<&'static str>,
+    records: Vec<EntryRecord>,
+    options: TarOptions,
+    /// The GNU long name and long link of the next entry. The last ones win.
+    longname: Option<Cow<'a, str>>,
+    longlink: Option<Cow<'a, str>>,
+    /// The PAX path and linkpath of the next entry, which take precedence over the GNU ones.
+    pax_name: Option<Cow<'a, str>>,
+    pax_link: Option<Cow<'a, str>>,
+    /// The raw name of the next entry, if it's escaped.
+    raw_name: Option<String>,
+    /// The names and link targets decoded from a legacy encoding, by the header offsets.
+    names: HashMap<u64, String>,
+    links: HashMap<u64, String>,
     realsize: Option<u64>,
+    /// The xattrs of the next entry.
+    pending_xattrs: Xattrs,
+    /// The times of the next entry from PAX.
+    pax_attrs: Attrs,
+    /// The real size of the next entry, if it is a sparse file.
+    sparse: Option<u64>,
+    /// The real size of the next old-style sparse entry from star.
+    star_realsize: Option<u64>,
+    /// The header fields of the next entry overridden by PAX.
+    pax_overrides: PaxOverrides,
+    /// The defaults of the following entries from global PAX.
+    global_attrs: Attrs,
+    global_overrides: PaxOverrides,
+    global_xattrs: Xattrs,
+    /// All records of the global PAX headers.
+    global_pax: PaxRecords,
+    xattrs: HashMap<String, Xattrs>,
+    dir_attrs: HashMap<String, Attrs>,
+    /// The names of the tree, shared with [`TarOptions::intern_names`].
+    interner: Interner,
+    /// The archive, to locate the entries.
+    buf: &'a [u8],
+    /// The index and the offset of the current entry.
+    position: (usize, u64),
+    /// The offset of the first metadata entry applied to the next entry.
+    metadata_start: Option<u64>,
+    /// The entries to be inserted in parallel.
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    pending: Option<Vec<parallel::PendingEntry>>,
+    /// Only the headers and the metadata entries are loaded, see [`RemoteTarFS`].
+    /// The files are inserted empty, and the sparse maps aren't parsed.
+    headers_only: bool,
 }
 
-impl DirTreeBuilder {
-    pub fn build(mut self, entries: &[TarEntry<'static>]) -> DirTree {
-        for entry in entries {
+impl<'a> DirTreeBuilder<'a> {
+    pub fn new(buf: &'a [u8], options: TarOptions) -> Self {
+        Self {
+            interner: Interner::new(options.intern_names),
+            options,
+            buf,
+            ..Default::default()
+        }
+    }
+
+    pub fn build(mut self, entries: &[TarEntry<'a>]) -> VfsResult<BuiltTree> {
+        #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+        {
+            self.pending = Some(vec![]);
+        }
+        self.scan(entries)?;
+        #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+        self.insert_pending()?;
+        self.report(self.buf.len() as u64, entries.len());
+        self.apply_dir_attrs();
+        Ok((self.root, self.records, self.xattrs, self.global_pax))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(entries = entries.len()))
+    )]
+    fn scan(&mut self, entries: &[TarEntry<'a>]) -> VfsResult<()> {
+        for (index, entry) in entries.iter().enumerate() {
+            // The contents follow the header block, and the sparse extension blocks.
+            let offset =
+                sparse::header_offset(self.buf, offset_of(self.buf, entry.contents)) as u64;
+            #[cfg(feature = "tracing")]
+            tracing::trace!(index, offset, typeflag = ?entry.header.typeflag, "parse entry");
+            self.position = (index, offset);
+            self.report(offset, index);
+            self.check_cancelled()?;
+            if matches!(
+                entry.header.typeflag,
+                TypeFlag::GnuLongName | TypeFlag::GnuLongLink | TypeFlag::Pax | TypeFlag::PaxGlobal
+            ) {
+                self.metadata_start.get_or_insert(offset);
+            }
             match entry.header.typeflag {
-                // Don't handle directory diff.
-                TypeFlag::Directory | TypeFlag::GnuDirectory => {
-                    let name = self.get_name(entry);
-                    self.insert_dir(Path::new(name.deref()));
+                TypeFlag::Directory => {
+                    let name = self.get_name(entry)?;
+                    self.dir_attrs(&name, entry);
+                    self.record(&name, entry, 0);
+                    self.add_dir(Path::new(name.deref()))?;
+                }
+                // The dumpdir is kept in the record, see `TarFS::dumpdir`.
+                TypeFlag::GnuDirectory => {
+                    let name = self.get_name(entry)?;
+                    self.dir_attrs(&name, entry);
+                    self.record(&name, entry, entry.contents.len());
+                    self.add_dir(Path::new(name.deref()))?;
                 }
-                // Treat links as redirects.
-                TypeFlag::HardLink | TypeFlag::SymbolicLink => {
-                    let name = self.get_name(entry);
-                    let target = self.longlink.take().unwrap_or(entry.header.linkname);
-                    self.insert_link(Path::new(name.deref()), target)
+                // Hard links share the contents of the target seen so far.
+                // A dangling hard link is ignored.
+                TypeFlag::HardLink => {
+                    let name = self.get_name(entry)?;
+                    let target = self.get_link(entry);
+                    let target = self.rewrite_name(normalize_name(target));
+                    self.record(&name, entry, 0);
+                    let target = normalize_path(strip_path(&target));
+                    self.add_hard_link(Path::new(name.deref()), &target)?
+                }
+                // Treat symbolic links as redirects.
+                TypeFlag::SymbolicLink => {
+                    let name = self.get_name(entry)?;
+                    let target = self.get_link(entry);
+                    self.record(&name, entry, 0);
+                    self.insert_link(Path::new(name.deref()), &target)?
+                }
+                // Device nodes and FIFOs have no contents.
+                TypeFlag::CharacterSpecial | TypeFlag::BlockSpecial | TypeFlag::Fifo => {
+                    let name = self.get_name(entry)?;
+                    let attrs = self.attrs(entry);
+                    self.record(&name, entry, 0);
+                    let special = special_file(entry);
+                    self.insert_entry(Path::new(name.deref()), Entry::Special(special, attrs, 0))?
                 }
                 // Handle long name.
                 TypeFlag::GnuLongName => {
-                    debug_assert!(entry.header.size > 1);
-                    if let Ok((_, name)) = parse_long_name(entry.contents) {
-                        debug_assert!(self.longname.is_none());
-                        self.longname = Some(Cow::Borrowed(name));
+                    self.check(entry.header.size > 1, "empty long name")?;
+                    let name = match self.names.remove(&offset) {
+                        Some(name) => Some(Cow::Owned(name)),
+                        // The name may span several blocks, and the NUL is optional.
+                        None => parser::parse_long_name(entry.contents).map(Cow::Borrowed),
+                    };
+                    if let Some(name) = name {
+                        self.check(self.longname.is_none(), "long name already set")?;
+                        self.longname = Some(name);
                     }
                 }
                 // Handle long link name.
                 TypeFlag::GnuLongLink => {
-                    debug_assert!(entry.header.size > 1);
-                    if let Ok((_, target)) = parse_long_name(entry.contents) {
-                        debug_assert!(self.longlink.is_none());
+                    self.check(entry.header.size > 1, "empty long link name")?;
+                    let target = match self.links.remove(&offset) {
+                        Some(target) => Some(Cow::Owned(target)),
+                        None => parser::parse_long_name(entry.contents).map(Cow::Borrowed),
+                    };
+                    if let Some(target) = target {
+                        self.check(self.longlink.is_none(), "long link name already set")?;
                         self.longlink = Some(target);
                     }
                 }
                 // Handle PAX.
                 TypeFlag::Pax => {
-                    if let Ok((_, pax)) = parse_pax(entry.contents) {
-                        if let Some(name) = pax.get("path") {
-                            debug_assert!(self.longname.is_none());
-                            self.longname = Some(Cow::Borrowed(name));
-                        }
-                        if let Some(target) = pax.get("linkpath") {
-                            debug_assert!(self.longlink.is_none());
-                            self.longlink = Some(target);
-                        }
-                        if let Some(size) = pax.get("size") {
-                            debug_assert!(self.realsize.is_none());
-                            self.realsize = size.parse().ok();
+                    let records = self.pax_records(entry.contents)?;
+                    let pax = utf8_records(&records);
+                    if let Some(&name) = pax.get("path") {
+                        self.check(self.pax_name.is_none(), "long name already set")?;
+                        self.pax_name = Some(Cow::Borrowed(name));
+                    }
+                    if let Some(&target) = pax.get("linkpath") {
+                        self.check(self.pax_link.is_none(), "long link name already set")?;
+                        self.pax_link = Some(Cow::Borrowed(target));
+                    }
+                    if let Some(size) = pax.get("size") {
+                        self.check(self.realsize.is_none(), "size already set")?;
+                        self.realsize = size.parse().ok();
+                    }
+                    if let Some(atime) = pax.get("atime") {
+                        self.pax_attrs.atime = parse_pax_time(atime);
+                    }
+                    if let Some(ctime) = pax.get("ctime") {
+                        self.pax_attrs.ctime = parse_pax_time(ctime);
+                    }
+                    // The xattr values may be binary.
+                    self.binary_pax_names(&records)?;
+                    if let Some((size, name)) = sparse::from_pax(&records) {
+                        self.sparse = Some(size);
+                        if let Some(name) = name {
+                            self.pax_name = Some(Cow::Borrowed(name));
                         }
                     }
+                    self.star_realsize = sparse::schily_realsize(&records);
+                    self.pax_overrides.apply(&records);
+                    self.pending_xattrs = xattr::from_pax(records);
+                }
+                // Global PAX sets the defaults of the following entries.
+                // The file-specific settings, i.e. path, linkpath and size, are ignored.
+                // The ownership and mtime are applied to the entries without their own.
+                TypeFlag::PaxGlobal => {
+                    let records = self.pax_records(entry.contents)?;
+                    let pax = utf8_records(&records);
+                    if let Some(atime) = pax.get("atime") {
+                        self.global_attrs.atime = parse_pax_time(atime);
+                    }
+                    if let Some(ctime) = pax.get("ctime") {
+                        self.global_attrs.ctime = parse_pax_time(ctime);
+                    }
+                    self.global_overrides.apply(&records);
+                    self.global_pax.extend(
+                        records
+                            .iter()
+                            .map(|(key, value)| (key.to_string(), value.to_vec())),
+                    );
+                    self.global_xattrs.extend(xattr::from_pax(records));
                 }
-                // The file-specific settings should not appear in global PAX.
                 // GNU volume header should be ignored.
-                TypeFlag::PaxGlobal | TypeFlag::GnuVolumeHeader => {}
+                TypeFlag::GnuVolumeHeader => {}
+                TypeFlag::VendorSpecific(_) if self.options.vendor_hook.is_some() => {
+                    let name = self.get_name(entry)?;
+                    let size = self.realsize.take().unwrap_or(entry.header.size);
+                    let size = usize::try_from(size).unwrap_or(usize::MAX);
+                    let contents = entry
+                        .contents
+                        .get(..size)
+                        .ok_or(TarFsError::InvalidHeader { index, offset })?;
+                    let range = match self.vendor_action(&name, contents) {
+                        VendorAction::Skip => {
+                            self.discard();
+                            continue;
+                        }
+                        VendorAction::Directory => {
+                            self.record(&name, entry, 0);
+                            self.add_dir(Path::new(name.deref()))?;
+                            continue;
+                        }
+                        VendorAction::Contents(range) => range,
+                        VendorAction::Store => 0..size,
+                    };
+                    let attrs = self.attrs(entry);
+                    self.record(&name, entry, size);
+                    let contents = contents
+                        .get(range)
+                        .ok_or(TarFsError::InvalidHeader { index, offset })?;
+                    self.insert_file(Path::new(name.deref()), contents, attrs)?
+                }
                 // A POSIX-compliant impl must treat any unrecognized typeflag as normal file.
                 _ => {
-                    let name = self.get_name(entry);
-                    let size = self.realsize.take().unwrap_or(entry.header.size) as usize;
-                    self.insert_file(Path::new(name.deref()), &entry.contents[..size])
+                    let name = self.get_name(entry)?;
+                    let typeflag = self.header_block().get(156).copied().unwrap_or_default();
+                    if self.options.reject_unknown_types && !is_file_typeflag(typeflag) {
+                        return Err(TarFsError::UnknownType {
+                            index,
+                            offset,
+                            typeflag,
+                            path: name.into_owned(),
+                        }
+                        .into());
+                    }
+                    // Old V7 archives mark directories by a trailing slash only.
+                    // A contiguous file is a regular file even with the slash, as it's typed.
+                    if name.ends_with('/') && entry.header.typeflag == TypeFlag::NormalFile {
+                        self.record(&name, entry, 0);
+                        self.add_dir(Path::new(name.deref()))?;
+                        continue;
+                    }
+                    let size = self.realsize.take().unwrap_or(entry.header.size);
+                    let size = usize::try_from(size).unwrap_or(usize::MAX);
+                    // The contents are read from the source later, see [`RemoteTarFS`].
+                    let size = if self.headers_only { 0 } else { size };
+                    let contents = entry
+                        .contents
+                        .get(..size)
+                        .ok_or(TarFsError::InvalidHeader { index, offset })?;
+                    let attrs = self.attrs(entry);
+                    let sparse = self.sparse.take().filter(|_| !self.headers_only);
+                    let star_realsize = self.star_realsize.take();
+                    self.record(&name, entry, size);
+                    let header = offset as usize;
+                    let old_sparse = !self.headers_only
+                        && self.buf.get(header + 156) == Some(&parser::typeflag::GNU_SPARSE);
+                    match sparse {
+                        Some(size) => {
+                            let sparse = SparseFile::parse(self.buf, contents, size, attrs)
+                                .ok_or(TarFsError::InvalidHeader { index, offset })?;
+                            let entry = Entry::Sparse(Box::new(sparse));
+                            self.insert_entry(Path::new(name.deref()), entry)?
+                        }
+                        // The old-style sparse files, written by star and GNU tar.
+                        None if old_sparse => {
+                            let sparse =
+                                SparseFile::parse_old(self.buf, header, star_realsize, attrs)
+                                    .ok_or(TarFsError::InvalidHeader { index, offset })?;
+                            let entry = Entry::Sparse(Box::new(sparse));
+                            self.insert_entry(Path::new(name.deref()), entry)?
+                        }
+                        None => self.insert_file(Path::new(name.deref()), contents, attrs)?,
+                    }
+                }
+            }
+        }
+        // The metadata entries followed by the end of the archive apply to nothing.
+        self.check(!self.has_pending(), "dangling metadata entry")?;
+        Ok(())
+    }
+
+    /// Whether any metadata entry is waiting for the entry it applies to.
+    /// The global PAX headers apply to all following entries, and are never pending.
+    fn has_pending(&self) -> bool {
+        self.longname.is_some()
+            || self.longlink.is_some()
+            || self.pax_name.is_some()
+            || self.pax_link.is_some()
+            || self.realsize.is_some()
+            || self.sparse.is_some()
+            || self.star_realsize.is_some()
+            || !self.pending_xattrs.is_empty()
+    }
+
+    /// Record an entry with the first `len` bytes of its contents.
+    /// The metadata of the next entry is reset, even if it's unused by this one.
+    fn record(&mut self, path: &str, entry: &TarEntry<'a>, len: usize) {
+        if self.stripped(path) {
+            self.discard();
+            return;
+        }
+        self.reset_metadata();
+        let mut xattrs = self.global_xattrs.clone();
+        xattrs.extend(std::mem::take(&mut self.pending_xattrs));
+        if !xattrs.is_empty() {
+            self.xattrs.insert(path_key(path), xattrs);
+        }
+        let start = offset_of(self.buf, entry.contents);
+        let end = (start as u64 + parser::align_block(entry.contents.len() as u64))
+            .min(self.buf.len() as u64) as usize;
+        let overrides = std::mem::take(&mut self.pax_overrides).or(&self.global_overrides);
+        let header = sparse::header_offset(self.buf, start);
+        self.records.push(EntryRecord {
+            path: path.to_string(),
+            start: self
+                .metadata_start
+                .take()
+                .map_or(header, |start| start as usize),
+            entry: header..end,
+            contents: start..start + len,
+            overrides,
+            raw_path: self.raw_name.take(),
+            header: None,
+        });
+    }
+
+    /// Drop the metadata of an entry which isn't recorded, like a stripped or skipped one.
+    fn discard(&mut self) {
+        self.reset_metadata();
+        self.pending_xattrs.clear();
+        self.metadata_start = None;
+        self.raw_name = None;
+        self.pax_overrides = PaxOverrides::default();
+    }
+
+    /// Reset the metadata applied to a single entry, except the ones kept in its record.
+    fn reset_metadata(&mut self) {
+        self.pax_attrs = Attrs::default();
+        // The PAX size of an entry without contents, like a link, isn't carried to the next one.
+        self.realsize = None;
+        self.sparse = None;
+        self.star_realsize = None;
+        // The long links of the entries other than links are dropped.
+        self.longlink = None;
+        self.pax_link = None;
+    }
+
+    #[cfg(feature = "cpio")]
+    pub fn build_cpio(mut self, entries: &[cpio::CpioEntry<'a>]) -> VfsResult<DirTree> {
+        for (index, entry) in entries.iter().enumerate() {
+            self.position = (index, entry.offset);
+            let name = entry.name.trim_start_matches("./");
+            if name.is_empty() || name == "." {
+                continue;
+            }
+            let path = Path::new(name);
+            match entry.kind() {
+                cpio::CpioKind::Directory => {
+                    self.insert_dir(path)?;
+                }
+                cpio::CpioKind::Symlink => {
+                    if let Ok(target) = std::str::from_utf8(entry.contents) {
+                        self.insert_link(path, target)?
+                    }
+                }
+                cpio::CpioKind::Special(special) => {
+                    let attrs = Attrs {
+                        mode: entry.mode & 0o7777,
+                        ..Default::default()
+                    };
+                    self.insert_entry(path, Entry::Special(special, attrs, 0))?
+                }
+                // A POSIX-compliant impl must treat any unrecognized type as normal file, like in tar.
+                cpio::CpioKind::File | cpio::CpioKind::Other => {
+                    let attrs = Attrs {
+                        mode: entry.mode & 0o7777,
+                        ..Default::default()
+                    };
+                    self.insert_file(path, entry.contents, attrs)?
+                }
+            }
+        }
+        Ok(self.root)
+    }
+
+    /// Get the name, sanitized by [`TarOptions::sanitize_names`] and then rewritten by
+    /// [`TarOptions::strip_components`] and [`TarOptions::rename_prefix`].
+    fn get_name(&mut self, entry: &TarEntry<'a>) -> VfsResult<Cow<'a, str>> {
+        let name = self.get_sanitized_name(entry)?;
+        Ok(self.rewrite_name(name))
+    }
+
+    fn get_sanitized_name(&mut self, entry: &TarEntry<'a>) -> VfsResult<Cow<'a, str>> {
+        let name = self.get_raw_name(entry);
+        if !name.chars().any(sanitize::is_unsafe) {
+            return Ok(name);
+        }
+        match self.options.names {
+            NamePolicy::PassThrough => Ok(name),
+            NamePolicy::Escape => {
+                let escaped = sanitize::escape(&name);
+                self.raw_name = Some(name.into_owned());
+                Ok(Cow::Owned(escaped))
+            }
+            NamePolicy::Reject => {
+                let (index, offset) = self.position;
+                Err(TarFsError::UnsafeName {
+                    index,
+                    offset,
+                    path: name.into_owned(),
+                }
+                .into())
+            }
+        }
+    }
+
+    /// Strip the leading components and rename the prefix of a name.
+    fn rewrite_name(&self, name: Cow<'a, str>) -> Cow<'a, str> {
+        let count = self.options.strip_components;
+        let mut name = match name {
+            _ if count == 0 => name,
+            Cow::Borrowed(name) => Cow::Borrowed(strip_components(name, count)),
+            Cow::Owned(name) => Cow::Owned(strip_components(&name, count).to_string()),
+        };
+        if let Some((from, to)) = &self.options.rename_prefix {
+            let rest = name
+                .strip_prefix(from.as_str())
+                .filter(|rest| rest.is_empty() || rest.starts_with('/'));
+            if let Some(rest) = rest {
+                name = match to.as_str() {
+                    "" => rest.trim_start_matches('/').to_string(),
+                    to => format!("{to}{rest}"),
                 }
+                .into();
             }
         }
-        self.root
+        name
+    }
+
+    /// Whether the entry is skipped, as [`TarOptions::strip_components`] leaves no component.
+    fn stripped(&self, path: &str) -> bool {
+        path.is_empty() && self.options.strip_components > 0
+    }
+
+    fn get_raw_name(&mut self, entry: &TarEntry<'a>) -> Cow<'a, str> {
+        let decoded = self.names.remove(&self.position.1);
+        let longname = self.longname.take();
+        let name = self
+            .pax_name
+            .take()
+            .or(longname)
+            .or(decoded.map(Cow::Owned))
+            .unwrap_or_else(|| self.get_full_name(entry));
+        #[cfg(feature = "unicode")]
+        if self.options.normalize_unicode {
+            return unicode::to_nfc(normalize_name(name));
+        }
+        normalize_name(name)
     }
 
-    fn get_name(&mut self, entry: &TarEntry<'static>) -> Cow<'static, str> {
-        self.longname
+    fn get_link(&mut self, entry: &TarEntry<'a>) -> Cow<'a, str> {
+        let decoded = self.links.remove(&self.position.1);
+        let longlink = self.longlink.take();
+        let link = self
+            .pax_link
             .take()
-            .unwrap_or_else(|| Self::get_full_name(entry))
+            .or(longlink)
+            .or(decoded.map(Cow::Owned))
+            .unwrap_or(Cow::Borrowed(entry.header.linkname));
+        #[cfg(feature = "unicode")]
+        if self.options.normalize_unicode {
+            return unicode::to_nfc(link);
+        }
+        link
+    }
+
+    /// Decode the PAX path and linkpath which aren't UTF-8, as allowed by `hdrcharset=BINARY`,
+    /// with the legacy encoding if set, or lossily.
+    fn binary_pax_names(&mut self, records: &[(&str, &[u8])]) -> VfsResult<()> {
+        let binary = records
+            .iter()
+            .any(|(key, value)| *key == "hdrcharset" && *value == b"BINARY");
+        for (key, value) in records {
+            if !matches!(*key, "path" | "linkpath") || std::str::from_utf8(value).is_ok() {
+                continue;
+            }
+            self.check(binary, "binary name without hdrcharset")?;
+            #[cfg(feature = "encodings")]
+            let decoded = match self.options.encoding {
+                Some(encoding) => encoding.decode_name(value),
+                None => String::from_utf8_lossy(value).into_owned(),
+            };
+            #[cfg(not(feature = "encodings"))]
+            let decoded = String::from_utf8_lossy(value).into_owned();
+            match *key {
+                "path" => self.pax_name = Some(Cow::Owned(decoded)),
+                _ => self.pax_link = Some(Cow::Owned(decoded)),
+            }
+        }
+        Ok(())
     }
 
-    fn get_full_name(entry: &TarEntry<'static>) -> Cow<'static, str> {
+    /// The name joined with the prefix. The prefix field differs between the producers,
+    /// so it's read from the header block first, see [`parser::parse_header`].
+    fn get_full_name(&self, entry: &TarEntry<'a>) -> Cow<'a, str> {
+        if let Some(header) = parser::parse_header(self.header_block()) {
+            return header.name;
+        }
         if let ExtraHeader::UStar(ustar) = &entry.header.ustar {
             if let UStarExtraHeader::Posix(header) = &ustar.extra {
                 if !header.prefix.is_empty() {
@@ -297,55 +2071,739 @@ impl DirTreeBuilder {
         Cow::Borrowed(entry.header.name)
     }
 
-    fn insert_dir(&mut self, path: &Path) -> &mut DirTree {
-        let path = path.iter();
+    /// The header block of the current entry.
+    fn header_block(&self) -> &'a [u8] {
+        self.buf.get(self.position.1 as usize..).unwrap_or_default()
+    }
+
+    /// Fail with [`TarFsError::Inconsistent`] if `valid` is false in the strict mode.
+    fn check(&self, valid: bool, reason: &'static str) -> VfsResult<()> {
+        if self.options.strict && !valid {
+            return Err(self.inconsistent(reason));
+        }
+        Ok(())
+    }
+
+    fn inconsistent(&self, reason: &'static str) -> VfsError {
+        let (index, offset) = self.position;
+        TarFsError::Inconsistent {
+            index,
+            offset,
+            reason,
+        }
+        .into()
+    }
+
+    /// Parse the records of a PAX header, with the repeated keys handled by
+    /// [`TarOptions::pax_duplicates`].
+    /// A malformed header is ignored, or rejected in the strict mode.
+    fn pax_records(&self, data: &'a [u8]) -> VfsResult<Vec<(&'a str, &'a [u8])>> {
+        let records = match parser::try_parse_pax_records(data) {
+            Ok(records) => records,
+            Err(reason) => {
+                self.check(false, reason)?;
+                return Ok(vec![]);
+            }
+        };
+        let mut unique = Vec::with_capacity(records.len());
+        let mut indices = HashMap::new();
+        for (key, value) in records {
+            // The old GNU sparse maps repeat the keys by design.
+            if matches!(key, "GNU.sparse.offset" | "GNU.sparse.numbytes") {
+                unique.push((key, value));
+                continue;
+            }
+            match indices.entry(key) {
+                hash_map::Entry::Vacant(e) => {
+                    e.insert(unique.len());
+                    unique.push((key, value));
+                }
+                hash_map::Entry::Occupied(e) => match self.options.pax_duplicates {
+                    DuplicatePolicy::LastWins => unique[*e.get()].1 = value,
+                    DuplicatePolicy::FirstWins => {}
+                    DuplicatePolicy::Error => return Err(self.inconsistent("duplicate PAX key")),
+                },
+            }
+        }
+        Ok(unique)
+    }
+
+    /// Insert the directory and its ancestors.
+    /// Returns `None` if a file is in the way with [`CollisionPolicy::KeepFirst`].
+    fn insert_dir(&mut self, path: &Path) -> VfsResult<Option<&mut DirTree>> {
+        self.check(self.path_is_dir(path), "parent isn't a directory")?;
+        let policy = self.options.collisions;
+        let (index, offset) = self.position;
         let mut current = &mut self.root;
-        for p in path {
+        for (i, p) in path.iter().enumerate() {
             let entry = current
-                .entry(p.to_string_lossy().into_owned())
+                .entry(self.interner.intern(&p.to_string_lossy()))
                 .or_insert_with(|| Entry::Directory(DirTree::new()));
-            current = if let Entry::Directory(dir) = entry {
-                dir
-            } else {
-                unreachable!()
+            if !matches!(entry, Entry::Directory(_)) {
+                match policy {
+                    CollisionPolicy::Replace => *entry = Entry::Directory(DirTree::new()),
+                    CollisionPolicy::KeepFirst => return Ok(None),
+                    CollisionPolicy::Error => {
+                        let path = path.iter().take(i + 1).collect::<PathBuf>();
+                        return Err(TarFsError::Collision {
+                            index,
+                            offset,
+                            path: path.to_string_lossy().into_owned(),
+                        }
+                        .into());
+                    }
+                }
+            }
+            let Entry::Directory(dir) = entry else {
+                return Err(VfsErrorKind::InvalidPath.into());
             };
+            current = dir;
         }
-        current
+        Ok(Some(current))
     }
 
-    fn insert_file(&mut self, path: &Path, buf: &'static [u8]) {
-        let current = if let Some(parent) = path.parent() {
-            self.insert_dir(parent)
-        } else {
-            &mut self.root
-        };
-        if let Some(filename) = path.file_name() {
-            current.insert(filename.to_string_lossy().into_owned(), Entry::File(buf));
-        }
+    /// Whether every existing component of the path is a directory.
+    fn path_is_dir(&self, path: &Path) -> bool {
+        let mut current = &self.root;
+        for p in path.iter() {
+            match current.get(p.to_string_lossy().as_ref()) {
+                Some(Entry::Directory(dir)) => current = dir,
+                Some(_) => return false,
+                None => return true,
+            }
+        }
+        true
+    }
+
+    fn add_dir(&mut self, path: &Path) -> VfsResult<()> {
+        #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+        if let Some(pending) = &mut self.pending {
+            pending.push(parallel::PendingEntry {
+                position: self.position,
+                path: path.to_path_buf(),
+                kind: parallel::PendingKind::Directory,
+            });
+            return Ok(());
+        }
+        self.insert_dir(path)?;
+        Ok(())
+    }
+
+    fn add_hard_link(&mut self, path: &Path, target: &Path) -> VfsResult<()> {
+        #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+        if let Some(pending) = &mut self.pending {
+            pending.push(parallel::PendingEntry {
+                position: self.position,
+                path: path.to_path_buf(),
+                kind: parallel::PendingKind::HardLink(target.to_path_buf()),
+            });
+            return Ok(());
+        }
+        self.insert_hard_link(path, target)
+    }
+
+    fn insert_hard_link(&mut self, path: &Path, target: &Path) -> VfsResult<()> {
+        match self.find_file(target) {
+            Some(entry) => self.insert_entry(path, entry),
+            // A directory, a symbolic link or a later entry is redirected to, like before.
+            None => {
+                let target = target.iter().map(|c| c.to_string_lossy());
+                let target = format!("/{}", target.collect::<Vec<_>>().join("/"));
+                self.insert_link(path, &target)
+            }
+        }
+    }
+
+    fn insert_file(&mut self, path: &Path, contents: &[u8], attrs: Attrs) -> VfsResult<()> {
+        let start = offset_of(self.buf, contents);
+        self.insert_entry(path, Entry::File(start..start + contents.len(), attrs))
+    }
+
+    /// The attributes from the GNU or star header,
+    /// overridden by the global and the entry PAX records.
+    fn attrs(&mut self, entry: &TarEntry) -> Attrs {
+        let pax = std::mem::take(&mut self.pax_attrs);
+        let (atime, ctime) = match &entry.header.ustar {
+            ExtraHeader::UStar(ustar) => match &ustar.extra {
+                UStarExtraHeader::Gnu(gnu) => (gnu.atime, gnu.ctime),
+                _ => parser::star_times(self.header_block()).unwrap_or_default(),
+            },
+            _ => (0, 0),
+        };
+        let (atime, ctime) = (
+            Some(atime).filter(|t| *t > 0),
+            Some(ctime).filter(|t| *t > 0),
+        );
+        let mtime = self.pax_overrides.mtime().or(self.global_overrides.mtime());
+        Attrs {
+            atime: pax.atime.or(self.global_attrs.atime).or(atime),
+            ctime: pax.ctime.or(self.global_attrs.ctime).or(ctime),
+            mtime: mtime.unwrap_or(entry.header.mtime),
+            mode: (entry.header.mode & 0o7777) as u32,
+        }
+    }
+
+    /// Keep the metadata of an explicit directory entry. The last one wins.
+    fn dir_attrs(&mut self, path: &str, entry: &TarEntry) {
+        let attrs = self.attrs(entry);
+        if !self.stripped(path) {
+            self.dir_attrs.insert(path_key(path), attrs);
+        }
+    }
+
+    /// Store the kept metadata in the directories, after all entries are inserted.
+    /// The directories replaced by other entries are skipped.
+    fn apply_dir_attrs(&mut self) {
+        'keys: for (key, attrs) in std::mem::take(&mut self.dir_attrs) {
+            let mut dir = &mut self.root;
+            for name in components(&key) {
+                match dir.get_mut(name) {
+                    Some(Entry::Directory(sub)) => dir = sub,
+                    _ => continue 'keys,
+                }
+            }
+            dir.attrs = Some(attrs);
+        }
+    }
+
+    fn insert_link(&mut self, path: &Path, target: &str) -> VfsResult<()> {
+        self.insert_entry(path, Entry::Link(target.to_string()))
+    }
+
+    fn insert_entry(&mut self, path: &Path, entry: Entry) -> VfsResult<()> {
+        #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+        if let Some(pending) = &mut self.pending {
+            pending.push(parallel::PendingEntry {
+                position: self.position,
+                path: path.to_path_buf(),
+                kind: parallel::PendingKind::Entry(entry),
+            });
+            return Ok(());
+        }
+        let policy = self.options.duplicates;
+        let collisions = self.options.collisions;
+        let (index, offset) = self.position;
+        let filename = path
+            .file_name()
+            .map(|name| self.interner.intern(&name.to_string_lossy()));
+        let current = match path.parent() {
+            Some(parent) => match self.insert_dir(parent)? {
+                Some(dir) => dir,
+                None => return Ok(()),
+            },
+            None => &mut self.root,
+        };
+        if let Some(filename) = filename {
+            match current.entry(filename) {
+                hash_map::Entry::Vacant(e) => {
+                    e.insert(entry);
+                }
+                hash_map::Entry::Occupied(mut e) => match (e.get(), policy, collisions) {
+                    (Entry::Directory(_), _, CollisionPolicy::Replace) => {
+                        e.insert(entry);
+                    }
+                    (Entry::Directory(_), _, CollisionPolicy::KeepFirst) => {}
+                    (Entry::Directory(_), _, CollisionPolicy::Error) => {
+                        return Err(TarFsError::Collision {
+                            index,
+                            offset,
+                            path: path.to_string_lossy().into_owned(),
+                        }
+                        .into());
+                    }
+                    (_, DuplicatePolicy::LastWins, _) => {
+                        e.insert(entry);
+                    }
+                    (_, DuplicatePolicy::FirstWins, _) => {}
+                    (_, DuplicatePolicy::Error, _) => {
+                        return Err(TarFsError::Duplicate {
+                            index,
+                            offset,
+                            path: path.to_string_lossy().into_owned(),
+                        }
+                        .into());
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn find_file(&self, path: &Path) -> Option<Entry> {
+        let mut current = &self.root;
+        for p in path.parent()?.iter() {
+            match current.get(p.to_string_lossy().as_ref())? {
+                Entry::Directory(dir) => current = dir,
+                _ => return None,
+            }
+        }
+        match current.get(path.file_name()?.to_string_lossy().as_ref())? {
+            entry @ (Entry::File(..) | Entry::Sparse(_)) => Some(entry.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The PAX records with UTF-8 values.
+fn utf8_records<'a>(records: &[(&'a str, &'a [u8])]) -> HashMap<&'a str, &'a str> {
+    records
+        .iter()
+        .filter_map(|(key, value)| Some((*key, std::str::from_utf8(value).ok()?)))
+        .collect()
+}
+
+/// Parse a PAX time record, which may have a fractional part.
+fn parse_pax_time(s: &str) -> Option<u64> {
+    s.split('.').next()?.parse().ok()
+}
+
+/// The time of the seconds since the Unix epoch, or `None` if it overflows.
+fn unix_time(secs: u64) -> Option<SystemTime> {
+    SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(secs))
+}
+
+/// The offset of a slice in the archive.
+fn offset_of(buf: &[u8], slice: &[u8]) -> usize {
+    (slice.as_ptr() as usize).saturating_sub(buf.as_ptr() as usize)
+}
+
+/// The error of opening a device node or FIFO.
+fn special_error(path: &str) -> VfsError {
+    VfsErrorKind::Other(format!(
+        "{path} is a device node or FIFO, which has no contents"
+    ))
+    .into()
+}
+
+/// The error of opening a directory as a file, which is told apart by its kind.
+fn dir_error(path: &str) -> VfsError {
+    std::io::Error::new(
+        std::io::ErrorKind::IsADirectory,
+        format!("{path} is a directory"),
+    )
+    .into()
+}
+
+fn not_dir_error(path: &str) -> VfsError {
+    VfsErrorKind::Other(format!("{path} is not a directory")).into()
+}
+
+/// Whether the typeflag is of a file, rather than an unknown one treated as a file.
+fn is_file_typeflag(typeflag: u8) -> bool {
+    use parser::typeflag::*;
+
+    matches!(
+        typeflag,
+        REGULAR | AREGULAR | CONTIGUOUS | GNU_SPARSE | GNU_MULTIVOLUME
+    )
+}
+
+/// The type and the device numbers of a device node or FIFO entry.
+fn special_file(entry: &TarEntry) -> SpecialFile {
+    let (major, minor) = match &entry.header.ustar {
+        ExtraHeader::UStar(ustar) => (ustar.devmajor as u32, ustar.devminor as u32),
+        _ => (0, 0),
+    };
+    match entry.header.typeflag {
+        TypeFlag::CharacterSpecial => SpecialFile::CharDevice { major, minor },
+        TypeFlag::BlockSpecial => SpecialFile::BlockDevice { major, minor },
+        _ => SpecialFile::Fifo,
+    }
+}
+
+/// The normalized path without `.` and the slashes around, as the key of path maps.
+fn path_key(path: &str) -> String {
+    path.split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Strip the leading `./` and collapse the duplicate slashes of an entry name,
+/// like `./dir//file` written by `tar -cf x.tar ./dir/`.
+fn normalize_name(name: Cow<str>) -> Cow<str> {
+    if !name.starts_with("./") && !name.contains("//") {
+        return name;
+    }
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if !(c == '/' && normalized.ends_with('/')) {
+            normalized.push(c);
+        }
+    }
+    normalized.trim_start_matches("./").to_string().into()
+}
+
+/// Remove the first components of an entry name, keeping a trailing slash.
+fn strip_components(name: &str, count: usize) -> &str {
+    let mut rest = name.trim_start_matches('/');
+    for _ in 0..count {
+        match rest.split_once('/') {
+            Some((_, next)) => rest = next.trim_start_matches('/'),
+            None => return "",
+        }
+    }
+    rest
+}
+
+/// Split a path into its components without allocations, skipping the empty and `.` ones.
+fn components(path: &str) -> impl Iterator<Item = &str> + Clone {
+    path.split('/').filter(|c| !c.is_empty() && *c != ".")
+}
+
+/// Join the rest of a path to the target of a link, and resolve the `..` components.
+fn join_path(target: &Path, rest: &Path) -> String {
+    let path = target.join(rest);
+    let path = path.iter().map(|c| c.to_string_lossy());
+    normalize_str(&path.collect::<Vec<_>>().join("/")).into_owned()
+}
+
+/// Resolve the `..` components of a path, which never leave the root.
+/// The path is borrowed if there's none.
+fn normalize_str(path: &str) -> Cow<'_, str> {
+    if !components(path).any(|c| c == "..") {
+        return path.into();
+    }
+    let mut normalized = vec![];
+    for c in components(path) {
+        if c == ".." {
+            normalized.pop();
+        } else {
+            normalized.push(c);
+        }
+    }
+    normalized.join("/").into()
+}
+
+/// [`Path`] doesn't iterate well with the prefix `/`.
+fn strip_path(path: &str) -> &Path {
+    Path::new(path.strip_prefix('/').unwrap_or(path))
+}
+
+/// Remove the `.` and `..` components lexically. `..` stops at the root.
+fn normalize_path(path: &Path) -> Cow<'_, Path> {
+    let dots = path
+        .components()
+        .any(|c| matches!(c, Component::CurDir | Component::ParentDir));
+    if !dots {
+        return path.into();
+    }
+    let mut normalized = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized.into()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        test_util::ArchiveBuilder, CollisionPolicy, DuplicatePolicy, EscapingLinks, TarFS,
+        TarFsError, TarOptions,
+    };
+    use std::io::{Read, Write};
+    use tar_parser2::TypeFlag;
+    use tempfile::tempfile;
+    use vfs::{error::VfsErrorKind, FileSystem, MemoryFS, VfsFileType, VfsPath};
+
+    #[test]
+    fn include() {
+        let fs: TarFS<&'static [u8]> = crate::include_tar!("../testdata/assets.tar");
+        let root = VfsPath::from(fs);
+        assert_eq!(
+            root.join("hello.txt").unwrap().read_to_string().unwrap(),
+            "hello"
+        );
+        assert!(root.join("textures/a.png").unwrap().exists().unwrap());
+    }
+
+    #[test]
+    fn producers() {
+        // The same tree archived by GNU tar, bsdtar and star, with the paths longer than 100 bytes.
+        // The star one has a 131-byte prefix, followed by the atime and the ctime.
+        let gnu: TarFS<&'static [u8]> = crate::include_tar!("../testdata/quirks/gnu.tar");
+        let bsdtar: TarFS<&'static [u8]> = crate::include_tar!("../testdata/quirks/bsdtar.tar");
+        let star: TarFS<&'static [u8]> = crate::include_tar!("../testdata/quirks/star.tar");
+        let listing = gnu.to_listing();
+        assert_eq!(listing.len(), 8);
+        assert_eq!(bsdtar.to_listing(), listing);
+        assert_eq!(star.to_listing(), listing);
+
+        let file = format!("/deep/{}/{}/file.txt", "d".repeat(60), "e".repeat(65));
+        for fs in [gnu, bsdtar, star.clone()] {
+            let root = VfsPath::from(fs);
+            for (path, data) in [
+                (file.as_str(), "world"),
+                ("link", "world"),
+                ("hard", "hello"),
+            ] {
+                assert_eq!(root.join(path).unwrap().read_to_string().unwrap(), data);
+            }
+        }
+        let metadata = star.metadata(&file).unwrap();
+        let secs =
+            |t: std::time::SystemTime| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        assert_eq!(metadata.accessed.map(secs), Some(1700000001));
+        assert_eq!(metadata.created.map(secs), Some(1700000002));
+    }
+
+    #[test]
+    fn reader() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        archive
+            .append_data(&mut header, "a.txt", b"hello".as_slice())
+            .unwrap();
+        let buffer = archive.into_inner().unwrap();
+
+        let fs = TarFS::from_reader(buffer.as_slice()).unwrap();
+        assert_eq!(fs.metadata("/a.txt").unwrap().len, 5);
+    }
+
+    #[test]
+    fn inline_buffer() {
+        // The contents move with the buffer, which isn't a problem for the byte ranges.
+        #[derive(Debug)]
+        struct Inline([u8; 2048]);
+
+        impl std::ops::Deref for Inline {
+            type Target = [u8];
+
+            fn deref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        archive
+            .append_data(&mut header, "a.txt", b"hello".as_slice())
+            .unwrap();
+        let mut buffer = [0; 2048];
+        buffer.copy_from_slice(&archive.into_inner().unwrap());
+
+        let fs = Box::new(TarFS::new(Inline(buffer)).unwrap());
+        let root = VfsPath::from(*fs);
+        assert_eq!(
+            root.join("a.txt").unwrap().read_to_string().unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn times() {
+        let mut archive = tar::Builder::new(vec![]);
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(5);
+            let gnu = header.as_gnu_mut().unwrap();
+            gnu.set_atime(1000);
+            gnu.set_ctime(2000);
+            archive
+                .append_data(&mut header, "gnu.txt", b"hello".as_slice())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(5);
+            archive
+                .append_data(&mut header, "ustar.txt", b"hello".as_slice())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let metadata = fs.metadata("/gnu.txt").unwrap();
+        let secs =
+            |t: std::time::SystemTime| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        assert_eq!(metadata.accessed.map(secs), Some(1000));
+        assert_eq!(metadata.created.map(secs), Some(2000));
+
+        let metadata = fs.metadata("/ustar.txt").unwrap();
+        assert_eq!(metadata.accessed, None);
+        assert_eq!(metadata.created, None);
+    }
+
+    #[test]
+    fn pax_global() {
+        let archive = ArchiveBuilder::new()
+            .global_pax(&[
+                ("atime", "500"),
+                ("SCHILY.xattr.user.a", "global"),
+                ("path", "ignored"),
+            ])
+            .pax(&[("atime", "700.5")])
+            .file("b", b"")
+            .file("a", b"")
+            .finish();
+        let fs = TarFS::new(archive).unwrap();
+
+        let accessed = |path| {
+            let accessed = fs.metadata(path).unwrap().accessed.unwrap();
+            accessed
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        assert_eq!(accessed("/a"), 500);
+        assert_eq!(accessed("/b"), 700);
+        assert_eq!(fs.xattrs("/a").unwrap()["user.a"], b"global");
+        assert_eq!(fs.xattrs("/b").unwrap()["user.a"], b"global");
+    }
+
+    #[test]
+    fn pax_binary_names() {
+        let archive = |pax: &[u8]| {
+            ArchiveBuilder::new()
+                .pax_data(pax)
+                .file("placeholder", b"hello")
+                .finish()
+        };
+        // "café.txt" in Latin-1.
+        let binary = archive(b"21 hdrcharset=BINARY\n17 path=caf\xe9.txt\n");
+
+        let fs = TarFS::new(binary.clone()).unwrap();
+        assert!(fs.exists("/caf\u{fffd}.txt").unwrap());
+        #[cfg(feature = "encodings")]
+        {
+            let options = TarOptions::new().encoding(crate::NameEncoding::Latin1);
+            let fs = TarFS::with_options(binary.clone(), options).unwrap();
+            assert!(fs.exists("/café.txt").unwrap());
+        }
+
+        let strict = TarOptions::new().strict(true);
+        assert!(TarFS::with_options(binary, strict.clone()).is_ok());
+        let undeclared = archive(b"17 path=caf\xe9.txt\n");
+        assert!(TarFS::with_options(undeclared, strict).is_err());
+    }
+
+    #[test]
+    fn pax_duplicates() {
+        let archive = |pax: &[u8]| {
+            ArchiveBuilder::new()
+                .pax_data(pax)
+                .file("placeholder", b"hello")
+                .finish()
+        };
+        let repeated = archive(b"14 path=first\n15 path=second\n");
+        let fs = TarFS::new(repeated.clone()).unwrap();
+        assert!(fs.exists("/second").unwrap());
+        let options = TarOptions::new().pax_duplicates(DuplicatePolicy::FirstWins);
+        let fs = TarFS::with_options(repeated.clone(), options).unwrap();
+        assert!(fs.exists("/first").unwrap());
+        let options = TarOptions::new().pax_duplicates(DuplicatePolicy::Error);
+        let err = TarFS::with_options(repeated, options).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Inconsistent {
+                index: 0,
+                offset: 0,
+                reason: "duplicate PAX key"
+            })
+        );
+
+        // The length doesn't match the record.
+        let malformed = archive(b"99 path=long\n");
+        let fs = TarFS::new(malformed.clone()).unwrap();
+        assert!(fs.exists("/placeholder").unwrap());
+        let err = TarFS::with_options(malformed, TarOptions::new().strict(true)).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Inconsistent {
+                index: 0,
+                offset: 0,
+                reason: "PAX record length out of range"
+            })
+        );
+    }
+
+    #[test]
+    fn dangling_metadata() {
+        // The size of a link isn't carried to the next file.
+        let archive = ArchiveBuilder::new()
+            .pax(&[("size", "10")])
+            .symlink("link", "file")
+            .file("file", b"data")
+            .finish();
+        let fs = TarFS::new(archive).unwrap();
+        assert_eq!(fs.metadata("/link").unwrap().len, 4);
+
+        // A PAX header followed by the end of the archive.
+        let buf = ArchiveBuilder::new()
+            .file("file", b"data")
+            .pax(&[("path", "last")])
+            .finish();
+        let fs = TarFS::new(buf.clone()).unwrap();
+        assert_eq!(fs.read_dir("/").unwrap().collect::<Vec<_>>(), ["file"]);
+        let err = TarFS::with_options(buf, TarOptions::new().strict(true)).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Inconsistent {
+                index: 1,
+                offset: 1024,
+                reason: "dangling metadata entry"
+            })
+        );
     }
 
-    fn insert_link(&mut self, path: &Path, target: &'static str) {
-        let current = if let Some(parent) = path.parent() {
-            self.insert_dir(parent)
-        } else {
-            &mut self.root
-        };
-        if let Some(filename) = path.file_name() {
-            current.insert(filename.to_string_lossy().into_owned(), Entry::Link(target));
+    #[test]
+    fn clone() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        archive
+            .append_data(&mut header, "a.txt", b"hello".as_slice())
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let handles = (0..4)
+            .map(|_| {
+                let root = VfsPath::from(fs.clone());
+                std::thread::spawn(move || root.join("a.txt").unwrap().read_to_string().unwrap())
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "hello");
         }
     }
-}
 
-/// [`Path`] doesn't iterate well with the prefix `/`.
-fn strip_path(path: &str) -> &Path {
-    Path::new(path.strip_prefix('/').unwrap_or(path))
-}
+    #[test]
+    fn range() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(11);
+        archive
+            .append_data(&mut header, "a.txt", b"hello world".as_slice())
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
 
-#[cfg(test)]
-mod test {
-    use crate::TarFS;
-    use tempfile::tempfile;
-    use vfs::VfsPath;
+        let mut buffer = String::new();
+        fs.open_file_range("/a.txt", 6, 100)
+            .unwrap()
+            .read_to_string(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, "world");
+        let mut file = fs.open_file_range("/a.txt", 20, 5).unwrap();
+        assert_eq!(file.read(&mut [0; 4]).unwrap(), 0);
+
+        let mut buf = [0u8; 4];
+        assert_eq!(fs.read_at("/a.txt", 2, &mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"llo ");
+        assert_eq!(fs.read_at("/a.txt", 9, &mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"ld");
+        assert_eq!(fs.read_at("/a.txt", 11, &mut buf).unwrap(), 0);
+        assert!(fs.read_at("/missing", 0, &mut buf).is_err());
+
+        assert_eq!(fs.file_slice("/a.txt").unwrap(), b"hello world");
+        assert!(fs.file_slice("/").is_err());
+    }
 
     #[test]
     fn basic() {
@@ -364,7 +2822,12 @@ mod test {
             .map(|p| p.filename())
             .collect::<Vec<_>>();
         files.sort();
-        assert_eq!(&files, &["lib.rs"]);
+        let mut real_files = std::fs::read_dir("src")
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        real_files.sort();
+        assert_eq!(files, real_files);
 
         let mut buffer = String::new();
         root.join("src/lib.rs")
@@ -400,6 +2863,21 @@ mod test {
         assert_eq!(buffer, real_content);
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn advise() {
+        let file = tempfile().unwrap();
+        let mut archive = tar::Builder::new(file);
+        archive.append_dir_all("src", "src").unwrap();
+        let file = archive.into_inner().unwrap();
+
+        let fs = TarFS::from_std_file(&file).unwrap();
+        fs.advise_sequential().unwrap();
+        fs.advise_willneed("src/lib.rs").unwrap();
+        fs.advise_willneed("/src").unwrap();
+        assert!(fs.advise_willneed("src/missing.rs").is_err());
+    }
+
     #[test]
     fn link() {
         let name = "a".repeat(1024);
@@ -465,4 +2943,676 @@ mod test {
         let real_content = std::fs::read_to_string("src/lib.rs").unwrap();
         assert_eq!(buffer, real_content);
     }
+
+    #[cfg(feature = "cpio")]
+    fn newc_entry(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        newc_device(archive, name, mode, (0, 0), data);
+    }
+
+    #[cfg(feature = "cpio")]
+    fn newc_device(archive: &mut Vec<u8>, name: &str, mode: u32, rdev: (u32, u32), data: &[u8]) {
+        let header = format!(
+            "070701{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            0,
+            mode,
+            0,
+            0,
+            1,
+            0,
+            data.len(),
+            0,
+            0,
+            rdev.0,
+            rdev.1,
+            name.len() + 1,
+            0
+        );
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize((archive.len() + 3) & !3, 0);
+        archive.extend_from_slice(data);
+        archive.resize((archive.len() + 3) & !3, 0);
+    }
+
+    #[cfg(feature = "cpio")]
+    #[test]
+    fn cpio() {
+        use crate::{SpecialFile, TarFileType};
+
+        let real_content = std::fs::read_to_string("src/lib.rs").unwrap();
+
+        let mut archive = vec![];
+        newc_entry(&mut archive, ".", 0o040755, &[]);
+        newc_entry(&mut archive, "src", 0o040755, &[]);
+        newc_entry(
+            &mut archive,
+            "src/lib.rs",
+            0o100644,
+            real_content.as_bytes(),
+        );
+        newc_entry(&mut archive, "lib.rs", 0o120777, b"src/lib.rs");
+        newc_device(&mut archive, "null", 0o020666, (1, 3), &[]);
+        newc_entry(&mut archive, "pipe", 0o010644, &[]);
+        newc_entry(&mut archive, "socket", 0o140755, &[]);
+        newc_entry(&mut archive, "TRAILER!!!", 0, &[]);
+
+        let fs = TarFS::new_cpio(archive).unwrap();
+        let special = |path| match fs.tar_metadata(path).unwrap().file_type {
+            TarFileType::Special(special) => special,
+            file_type => panic!("{path}: {file_type:?}"),
+        };
+        assert_eq!(
+            special("/null"),
+            SpecialFile::CharDevice { major: 1, minor: 3 }
+        );
+        assert_eq!(special("/pipe"), SpecialFile::Fifo);
+        assert_eq!(special("/socket"), SpecialFile::Socket);
+        assert_eq!(fs.permissions("/null").unwrap(), 0o666);
+        let root = VfsPath::from(fs);
+
+        let mut buffer = String::new();
+        root.join("lib.rs")
+            .unwrap()
+            .open_file()
+            .unwrap()
+            .read_to_string(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, real_content);
+        assert!(root.join("src").unwrap().is_dir().unwrap());
+    }
+
+    #[test]
+    fn hard_link() {
+        let mut archive = tar::Builder::new(vec![]);
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(3);
+            archive
+                .append_data(&mut header, "a/data", b"old".as_slice())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Link);
+            archive.append_link(&mut header, "b", "a/data").unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(3);
+            archive
+                .append_data(&mut header, "a/data", b"new".as_slice())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+        let root = VfsPath::from(fs);
+
+        assert_eq!(root.join("b").unwrap().read_to_string().unwrap(), "old");
+        assert_eq!(
+            root.join("a/data").unwrap().read_to_string().unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn dangling_hard_links() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, target) in [("a/later", "b/data"), ("a/dir", "b"), ("a/sym", "c")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Link);
+            archive.append_link(&mut header, name, target).unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "b/data", b"data".as_slice())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive.append_link(&mut header, "c", "b/data").unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+        let root = VfsPath::from(fs);
+
+        assert_eq!(
+            root.join("a/later").unwrap().read_to_string().unwrap(),
+            "data"
+        );
+        assert_eq!(
+            root.join("a/dir/data").unwrap().read_to_string().unwrap(),
+            "data"
+        );
+        assert_eq!(
+            root.join("a/sym").unwrap().read_to_string().unwrap(),
+            "data"
+        );
+    }
+
+    #[test]
+    fn escaping_link() {
+        let mut archive = tar::Builder::new(vec![]);
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(6);
+            archive
+                .append_data(&mut header, "secret", b"secret".as_slice())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive
+                .append_link(&mut header, "a/link", "../../secret")
+                .unwrap();
+        }
+        let mut fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+        assert!(fs.exists("a/link").unwrap());
+        assert_eq!(fs.metadata("a/link").unwrap().len, 6);
+
+        fs.set_escaping_links(EscapingLinks::Reject);
+        assert!(!fs.exists("a/link").unwrap());
+    }
+
+    #[test]
+    fn intermediate_links() {
+        let mut archive = tar::Builder::new(vec![]);
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, "real/sub/file", b"data".as_slice())
+                .unwrap();
+        }
+        for (path, target) in [
+            ("linkdir", "real"),
+            ("chain", "linkdir/sub"),
+            ("abs", "/chain"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, path, target).unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert_eq!(fs.metadata("/linkdir/sub/file").unwrap().len, 4);
+        assert_eq!(fs.metadata("/chain/file").unwrap().len, 4);
+        assert_eq!(fs.metadata("/abs/file").unwrap().len, 4);
+        assert_eq!(
+            fs.read_dir("/linkdir/sub").unwrap().collect::<Vec<_>>(),
+            ["file"]
+        );
+        assert!(!fs.exists("/linkdir/missing").unwrap());
+    }
+
+    #[test]
+    fn dot_components() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "a/file", b"data".as_slice())
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        for path in [
+            "/a/./file",
+            "/a/../a/file",
+            "./a/file",
+            "/../../a/file",
+            "/a/b/../file",
+        ] {
+            assert_eq!(fs.metadata(path).unwrap().len, 4, "{path}");
+        }
+        assert!(!fs.exists("/a/../file").unwrap());
+    }
+
+    #[test]
+    fn dot_names() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["./", "./dir/", "./dir//file"] {
+            let mut header = tar::Header::new_gnu();
+            let data: &[u8] = if name.ends_with('/') { b"" } else { b"data" };
+            if data.is_empty() {
+                header.set_entry_type(tar::EntryType::Directory);
+            }
+            header.set_size(data.len() as u64);
+            // `append_data` normalizes the names.
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_cksum();
+            archive.append(&header, data).unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Link);
+            header.set_size(0);
+            header.as_old_mut().name[..10].copy_from_slice(b"./dir/link");
+            header.set_link_name("./dir/file").unwrap();
+            header.set_cksum();
+            archive.append(&header, [].as_slice()).unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert_eq!(fs.read_dir("/").unwrap().collect::<Vec<_>>(), ["dir"]);
+        assert_eq!(fs.metadata("/dir/file").unwrap().len, 4);
+        assert_eq!(fs.metadata("/dir/link").unwrap().len, 4);
+    }
+
+    #[test]
+    fn strip_components() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        archive
+            .append_data(&mut header, "pkg-1.0/", [].as_slice())
+            .unwrap();
+        for name in ["pkg-1.0/README", "pkg-1.0/src/lib.rs", "top.txt"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(name.len() as u64);
+            archive
+                .append_data(&mut header, name, name.as_bytes())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Link);
+        archive
+            .append_link(&mut header, "pkg-1.0/LINK", "pkg-1.0/README")
+            .unwrap();
+        let archive = archive.into_inner().unwrap();
+
+        let fs =
+            TarFS::with_options(archive.clone(), TarOptions::new().strip_components(1)).unwrap();
+        let mut names = fs.read_dir("/").unwrap().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["LINK", "README", "src"]);
+        assert_eq!(fs.metadata("/src/lib.rs").unwrap().len, 18);
+        assert_eq!(fs.metadata("/LINK").unwrap().len, 14);
+        assert!(fs.entries().all(|(path, ..)| !path.is_empty()));
+
+        let options = TarOptions::new().rename_prefix("pkg-1.0/", "vendor/pkg");
+        let fs = TarFS::with_options(archive.clone(), options).unwrap();
+        assert!(fs.exists("/vendor/pkg/src/lib.rs").unwrap());
+        assert!(fs.exists("/top.txt").unwrap());
+        let options = TarOptions::new().rename_prefix("pkg", "");
+        let fs = TarFS::with_options(archive, options).unwrap();
+        assert!(fs.exists("/pkg-1.0/README").unwrap());
+    }
+
+    #[test]
+    fn lookup_options() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "Dir/File.txt", b"data".as_slice())
+            .unwrap();
+        for (path, target) in [("a", "b"), ("b", "Dir/File.txt"), ("up", "../Dir")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, path, target).unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+
+        let fs = TarFS::new(archive.clone()).unwrap();
+        assert!(!fs.exists("/dir/file.txt").unwrap());
+        assert!(fs.exists("/a").unwrap());
+        assert!(fs.exists("/up").unwrap());
+
+        let options = TarOptions::new()
+            .case_insensitive(true)
+            .max_links(1)
+            .escaping_links(EscapingLinks::Reject);
+        let fs = TarFS::with_options(archive, options).unwrap();
+        assert_eq!(fs.metadata("/dir/file.txt").unwrap().len, 4);
+        assert!(fs.exists("/b").unwrap());
+        assert!(!fs.exists("/a").unwrap());
+        assert!(!fs.exists("/up").unwrap());
+    }
+
+    #[test]
+    fn case_insensitive_ties() {
+        // The names differing only in case resolve to the least one, in either archive order.
+        for names in [["Dir/File", "dir/file"], ["dir/file", "Dir/File"]] {
+            let mut archive = tar::Builder::new(vec![]);
+            for name in names {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(name.len() as u64);
+                archive
+                    .append_data(&mut header, name, name.as_bytes())
+                    .unwrap();
+            }
+            let options = TarOptions::new().case_insensitive(true);
+            let fs = TarFS::with_options(archive.into_inner().unwrap(), options).unwrap();
+            let read = |path| {
+                let mut buffer = String::new();
+                fs.open_file(path)
+                    .unwrap()
+                    .read_to_string(&mut buffer)
+                    .unwrap();
+                buffer
+            };
+            assert_eq!(read("/dir/file"), "dir/file");
+            assert_eq!(read("/DIR/FILE"), "Dir/File");
+            assert_eq!(read("/dIr/fIlE"), "Dir/File");
+            assert_eq!(fs.canonicalize("/DIR/FILE").unwrap().0, "/Dir/File");
+        }
+    }
+
+    #[test]
+    fn backslashes() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "dir/sub/file", b"data".as_slice())
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        for path in ["\\dir\\sub\\file", "dir/sub\\file", "/dir\\sub/file"] {
+            assert_eq!(fs.metadata(path).unwrap().len, 4);
+        }
+        assert_eq!(fs.read_dir("dir\\sub").unwrap().count(), 1);
+        assert!(!fs.exists("dir\\missing").unwrap());
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut archive = tar::Builder::new(vec![]);
+        for data in [b"old", b"new"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(3);
+            archive
+                .append_data(&mut header, "a/data", data.as_slice())
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+
+        let read = |policy| {
+            let fs = TarFS::with_options(archive.clone(), TarOptions::new().duplicates(policy))?;
+            VfsPath::from(fs).join("a/data")?.read_to_string()
+        };
+        assert_eq!(read(DuplicatePolicy::LastWins).unwrap(), "new");
+        assert_eq!(read(DuplicatePolicy::FirstWins).unwrap(), "old");
+        assert!(read(DuplicatePolicy::Error).is_err());
+    }
+
+    #[test]
+    fn collisions() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["foo", "foo/bar", "baz/qux", "baz"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+        let open =
+            |policy| TarFS::with_options(archive.clone(), TarOptions::new().collisions(policy));
+
+        let fs = open(CollisionPolicy::Replace).unwrap();
+        assert_eq!(
+            fs.metadata("/foo/bar").unwrap().file_type,
+            VfsFileType::File
+        );
+        assert_eq!(fs.metadata("/baz").unwrap().file_type, VfsFileType::File);
+        assert!(!fs.exists("/baz/qux").unwrap());
+
+        let fs = open(CollisionPolicy::KeepFirst).unwrap();
+        assert_eq!(fs.metadata("/foo").unwrap().file_type, VfsFileType::File);
+        assert!(!fs.exists("/foo/bar").unwrap());
+        assert_eq!(
+            fs.metadata("/baz/qux").unwrap().file_type,
+            VfsFileType::File
+        );
+
+        let err = open(CollisionPolicy::Error).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Collision {
+                index: 1,
+                offset: 1024,
+                path: "foo".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn entries() {
+        let name = "a".repeat(200);
+        let mut archive = tar::Builder::new(vec![]);
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(5);
+            header.set_mode(0o755);
+            archive
+                .append_data(&mut header, &name, b"hello".as_slice())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, "link", &name).unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let entries = fs.entries().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].0, name);
+        assert_eq!(entries[0].1.mode, 0o755);
+        assert_eq!(entries[0].2, b"hello");
+        assert_eq!(entries[1].0, "link");
+        assert_eq!(entries[1].1.typeflag, TypeFlag::SymbolicLink);
+    }
+
+    #[test]
+    fn error_kinds() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "dir/file", b"data".as_slice())
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let kind = |err: vfs::VfsError| match err.kind() {
+            VfsErrorKind::FileNotFound => "not found".to_string(),
+            VfsErrorKind::InvalidPath => "invalid".to_string(),
+            VfsErrorKind::Other(msg) => msg.clone(),
+            VfsErrorKind::IoError(e) => format!("{:?}: {e}", e.kind()),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            kind(fs.open_file("/dir").err().unwrap()),
+            "IsADirectory: /dir is a directory"
+        );
+        assert_eq!(
+            kind(fs.read_dir("/dir/file").err().unwrap()),
+            "/dir/file is not a directory"
+        );
+        assert_eq!(
+            kind(fs.open_file("/dir/missing").err().unwrap()),
+            "not found"
+        );
+        assert_eq!(kind(fs.metadata("/dir/file/child").unwrap_err()), "invalid");
+    }
+
+    #[test]
+    fn long_names() {
+        let long = |archive: &mut tar::Builder<Vec<u8>>, ty, data: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(ty);
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, "././@LongLink", data)
+                .unwrap();
+        };
+        let pax = |archive: &mut tar::Builder<Vec<u8>>, path: &str| {
+            let record = format!(" path={path}\n");
+            let record = format!("{}{record}", record.len() + 2);
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(tar::EntryType::XHeader);
+            header.set_size(record.len() as u64);
+            archive
+                .append_data(&mut header, "PaxHeader", record.as_bytes())
+                .unwrap();
+        };
+        let file = |archive: &mut tar::Builder<Vec<u8>>, name: &str| {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        };
+        let name = format!("{}/{}", "a".repeat(300), "b".repeat(400));
+        let target = format!("{}/{}", "c".repeat(300), "d".repeat(300));
+
+        let mut archive = tar::Builder::new(vec![]);
+        // Spanning two blocks, with the long link before the long name.
+        long(
+            &mut archive,
+            tar::EntryType::GNULongLink,
+            format!("../{name}\0").as_bytes(),
+        );
+        long(
+            &mut archive,
+            tar::EntryType::GNULongName,
+            format!("{target}\0").as_bytes(),
+        );
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, "link", "short").unwrap();
+        }
+        // Without the NUL.
+        long(&mut archive, tar::EntryType::GNULongName, name.as_bytes());
+        file(&mut archive, "short");
+        // The PAX path wins in either order.
+        pax(&mut archive, "pax1");
+        long(&mut archive, tar::EntryType::GNULongName, b"gnu1\0");
+        file(&mut archive, "short");
+        long(&mut archive, tar::EntryType::GNULongName, b"gnu2\0");
+        pax(&mut archive, "pax2");
+        file(&mut archive, "short");
+        // The long link of a file doesn't apply to the next link.
+        long(&mut archive, tar::EntryType::GNULongLink, b"stale\0");
+        file(&mut archive, "file");
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, "link2", "file").unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert_eq!(fs.metadata(&target).unwrap().len, 4);
+        assert_eq!(fs.metadata(&name).unwrap().len, 4);
+        for path in ["/pax1", "/pax2", "/link2"] {
+            assert_eq!(fs.metadata(path).unwrap().len, 4);
+        }
+        for path in ["/short", "/gnu1", "/gnu2", "/link"] {
+            assert!(!fs.exists(path).unwrap());
+        }
+    }
+
+    #[test]
+    fn v7_dir() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, size) in [("old/", 0), ("old/file", 4), ("empty/", 0)] {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(tar::EntryType::new(b'\0'));
+            header.set_size(size);
+            header.set_cksum();
+            archive.append(&header, &b"data"[..size as usize]).unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            fs.metadata("/empty").unwrap().file_type,
+            vfs::VfsFileType::Directory
+        );
+        assert_eq!(fs.read_dir("/old").unwrap().collect::<Vec<_>>(), ["file"]);
+        assert_eq!(fs.metadata("/old/file").unwrap().len, 4);
+    }
+
+    #[test]
+    fn malformed() {
+        let pax = |archive: &mut tar::Builder<Vec<u8>>, records: &[(&str, &str)]| {
+            let mut data = vec![];
+            for (key, value) in records {
+                let record = format!(" {key}={value}\n");
+                let len = record.len() + (record.len() + 1).to_string().len();
+                data.extend(format!("{len}{record}").into_bytes());
+            }
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(tar::EntryType::XHeader);
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, "PaxHeader", data.as_slice())
+                .unwrap();
+        };
+        let file = |archive: &mut tar::Builder<Vec<u8>>, name: &str, data: &[u8]| {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(data.len() as u64);
+            archive.append_data(&mut header, name, data).unwrap();
+        };
+        let max = u64::MAX.to_string();
+
+        // The times overflowing `SystemTime` are ignored.
+        let mut archive = tar::Builder::new(vec![]);
+        pax(&mut archive, &[("atime", &max), ("ctime", &max)]);
+        file(&mut archive, "time.txt", b"data");
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+        let metadata = fs.metadata("/time.txt").unwrap();
+        assert_eq!(metadata.accessed, None);
+        assert_eq!(metadata.created, None);
+
+        // The lengths of the sparse segments overflow.
+        let mut archive = tar::Builder::new(vec![]);
+        pax(
+            &mut archive,
+            &[("GNU.sparse.major", "1"), ("GNU.sparse.realsize", &max)],
+        );
+        let map = format!("2\n0\n{max}\n0\n1\n");
+        file(&mut archive, "GNUSparseFile.0/sparse.bin", map.as_bytes());
+        assert!(TarFS::new(archive.into_inner().unwrap()).is_err());
+
+        assert_eq!(crate::parser::align_block(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn from_vfs_path() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_ustar();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "a", b"data".as_slice())
+            .unwrap();
+        let root = VfsPath::new(MemoryFS::new());
+        let path = root.join("archive.tar").unwrap();
+        path.create_file()
+            .unwrap()
+            .write_all(&archive.into_inner().unwrap())
+            .unwrap();
+
+        let fs = TarFS::from_vfs_path(&path).unwrap();
+        assert_eq!(fs.file_slice("/a").unwrap(), b"data");
+        assert!(TarFS::from_vfs_path(&root.join("missing.tar").unwrap()).is_err());
+    }
+
+    #[test]
+    fn from_slice() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_ustar();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "dir/a", b"data".as_slice())
+            .unwrap();
+        let buf = archive.into_inner().unwrap();
+
+        let fs = TarFS::from_slice(&buf).unwrap();
+        assert_eq!(fs.file_slice("/dir/a").unwrap(), b"data");
+        let mut buffer = [0; 2];
+        assert_eq!(fs.read_at("/dir/a", 2, &mut buffer).unwrap(), 2);
+        assert_eq!(&buffer, b"ta");
+        assert_eq!(fs.dir_size("/dir").unwrap(), 4);
+    }
 }
@@ -0,0 +1,260 @@
+//! A flat listing of the archive, e.g. to be returned as JSON with the `serde` feature.
+
+use crate::{not_dir_error, path_key, DirTree, Entry, EntryRef, SpecialFile, TarFS};
+use std::{collections::HashMap, ops::Deref};
+use vfs::VfsResult;
+
+/// The type of a [`ListingEntry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+#[non_exhaustive]
+pub enum ListingType {
+    /// A regular file, or a hard link.
+    File,
+    /// A directory.
+    Directory,
+    /// A symbolic link.
+    Link,
+    /// A character device node.
+    Char,
+    /// A block device node.
+    Block,
+    /// A FIFO.
+    Fifo,
+    /// A Unix domain socket.
+    Socket,
+}
+
+/// An entry of [`TarFS::to_listing`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct ListingEntry {
+    /// The absolute path.
+    pub path: String,
+    /// The type of the entry.
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub file_type: ListingType,
+    /// The length of a file, or the real size of a sparse file. It's 0 for the others.
+    pub size: u64,
+    /// The modification time in seconds, with the PAX `mtime` applied.
+    /// It's `None` for the directories without explicit entries.
+    pub mtime: Option<u64>,
+    /// The target of a symbolic link.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub link: Option<String>,
+}
+
+/// The order of [`TarFS::list_sorted`]. The ties are broken by the path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// By the path.
+    Name,
+    /// By the size, the largest first, like `ls -S`.
+    Size,
+    /// By the modification time, the newest first, like `ls -t`.
+    /// The entries without a modification time come last.
+    Mtime,
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// List all entries sorted by path. The links are neither resolved nor descended into.
+    pub fn to_listing(&self) -> Vec<ListingEntry> {
+        let mtimes = self.mtimes();
+        let mut listing = vec![];
+        list_dir(&self.root, "", &mtimes, &mut listing);
+        listing.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        listing
+    }
+
+    /// List a directory with the metadata of each entry, sorted in one call.
+    /// The directory is resolved through the links, but the entries in it are not.
+    pub fn list_sorted(&self, path: &str, by: SortBy) -> VfsResult<Vec<ListingEntry>> {
+        let dir = match self.find_entry(path) {
+            Some(EntryRef::Directory(dir)) => dir,
+            Some(_) => return Err(not_dir_error(path)),
+            None => return Err(self.not_found(path)),
+        };
+        // The modification times are keyed by the real paths, not the ones through the links.
+        let real = dir_path(&self.root, dir, "").unwrap_or_default();
+        let prefix = match path.trim_matches('/') {
+            "" => String::new(),
+            path => format!("/{path}"),
+        };
+        let mtimes = self.mtimes();
+        let mut listing = dir
+            .iter()
+            .map(|(name, entry)| {
+                let mut listing = listing_entry(format!("{real}/{name}"), entry, &mtimes);
+                listing.path = format!("{prefix}/{name}");
+                listing
+            })
+            .collect::<Vec<_>>();
+        match by {
+            SortBy::Name => listing.sort_unstable_by(|a, b| a.path.cmp(&b.path)),
+            SortBy::Size => {
+                listing.sort_unstable_by(|a, b| b.size.cmp(&a.size).then(a.path.cmp(&b.path)))
+            }
+            SortBy::Mtime => {
+                listing.sort_unstable_by(|a, b| b.mtime.cmp(&a.mtime).then(a.path.cmp(&b.path)))
+            }
+        }
+        Ok(listing)
+    }
+
+    /// The modification times of the entries by [`path_key`], with the PAX `mtime` applied.
+    pub(crate) fn mtimes(&self) -> HashMap<String, u64> {
+        // The last entry with a path wins, like in the tree.
+        self.records
+            .iter()
+            .map(|record| {
+                let header = self.record_header(record);
+                let mtime = record.overrides.as_ref().and_then(|o| o.mtime());
+                (path_key(&record.path), mtime.unwrap_or(header.mtime))
+            })
+            .collect()
+    }
+}
+
+fn list_dir(
+    dir: &DirTree,
+    prefix: &str,
+    mtimes: &HashMap<String, u64>,
+    listing: &mut Vec<ListingEntry>,
+) {
+    for (name, entry) in dir {
+        let path = format!("{prefix}/{name}");
+        listing.push(listing_entry(path.clone(), entry, mtimes));
+        if let Entry::Directory(dir) = entry {
+            list_dir(dir, &path, mtimes, listing);
+        }
+    }
+}
+
+fn listing_entry(path: String, entry: &Entry, mtimes: &HashMap<String, u64>) -> ListingEntry {
+    let (file_type, size, link) = match entry {
+        Entry::File(range, _) => (ListingType::File, range.len() as u64, None),
+        Entry::Sparse(sparse) => (ListingType::File, sparse.size, None),
+        Entry::Directory(_) => (ListingType::Directory, 0, None),
+        Entry::Link(target) => (ListingType::Link, 0, Some(target.clone())),
+        Entry::Special(special, ..) => match special {
+            SpecialFile::CharDevice { .. } => (ListingType::Char, 0, None),
+            SpecialFile::BlockDevice { .. } => (ListingType::Block, 0, None),
+            SpecialFile::Fifo => (ListingType::Fifo, 0, None),
+            SpecialFile::Socket => (ListingType::Socket, 0, None),
+        },
+    };
+    ListingEntry {
+        mtime: mtimes.get(&path_key(&path)).copied(),
+        path,
+        file_type,
+        size,
+        link,
+    }
+}
+
+/// Find the path of a directory in the tree, without following the links.
+fn dir_path(dir: &DirTree, target: &DirTree, prefix: &str) -> Option<String> {
+    if std::ptr::eq(dir, target) {
+        return Some(prefix.to_string());
+    }
+    dir.iter().find_map(|(name, entry)| match entry {
+        Entry::Directory(sub) => dir_path(sub, target, &format!("{prefix}/{name}")),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ListingType, SortBy, TarFS};
+
+    #[test]
+    fn to_listing() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mtime(1234567890);
+        archive
+            .append_data(&mut header, "dir/a.txt", b"hello".as_slice())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_mtime(1);
+        archive
+            .append_link(&mut header, "link", "dir/a.txt")
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let listing = fs.to_listing();
+        let summary = listing
+            .iter()
+            .map(|e| (e.path.as_str(), e.file_type, e.size, e.mtime))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("/dir", ListingType::Directory, 0, None),
+                ("/dir/a.txt", ListingType::File, 5, Some(1234567890)),
+                ("/link", ListingType::Link, 0, Some(1)),
+            ]
+        );
+        assert_eq!(listing[2].link.as_deref(), Some("dir/a.txt"));
+
+        #[cfg(feature = "serde")]
+        assert_eq!(
+            serde_json::to_value(&listing[2]).unwrap(),
+            serde_json::json!({
+                "path": "/link",
+                "type": "link",
+                "size": 0,
+                "mtime": 1,
+                "link": "dir/a.txt",
+            })
+        );
+    }
+
+    #[test]
+    fn list_sorted() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data, mtime) in [
+            ("dir/a", "1", 30),
+            ("dir/b", "333", 10),
+            ("dir/c", "22", 20),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mtime(mtime);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive.append_link(&mut header, "link", "dir").unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let paths = |by| {
+            fs.list_sorted("/dir", by)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(SortBy::Name), ["/dir/a", "/dir/b", "/dir/c"]);
+        assert_eq!(paths(SortBy::Size), ["/dir/b", "/dir/c", "/dir/a"]);
+        assert_eq!(paths(SortBy::Mtime), ["/dir/a", "/dir/c", "/dir/b"]);
+
+        // The modification times are found through a linked directory.
+        let listing = fs.list_sorted("/link", SortBy::Mtime).unwrap();
+        assert_eq!(listing[0].path, "/link/a");
+        assert_eq!(listing[0].mtime, Some(30));
+        assert!(fs.list_sorted("/dir/a", SortBy::Name).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! Verification against checksum manifests like `SHA256SUMS`, with the `digest` feature.
+
+use crate::{path_key, Algorithm, TarFS};
+use std::{io::Read, ops::Deref};
+use vfs::{error::VfsErrorKind, VfsResult};
+
+/// The result of a file listed in a manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestStatus {
+    /// The digest matches.
+    Passed,
+    /// The digest doesn't match.
+    Failed,
+    /// The file isn't in the archive.
+    Missing,
+}
+
+/// The report of [`TarFS::verify_manifest`], in the order of the manifest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestReport {
+    /// The listed paths and their results.
+    pub files: Vec<(String, ManifestStatus)>,
+}
+
+impl ManifestReport {
+    /// Whether all listed files passed.
+    pub fn is_ok(&self) -> bool {
+        self.files
+            .iter()
+            .all(|(_, status)| *status == ManifestStatus::Passed)
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Verify the files against a checksum manifest.
+    ///
+    /// Both the GNU style `<hex>  <path>` lines of `sha256sum`, and the BSD style
+    /// `SHA256 (<path>) = <hex>` lines are accepted. Empty lines and `#` comments are skipped.
+    pub fn verify_manifest(&self, mut reader: impl Read) -> VfsResult<ManifestReport> {
+        let mut manifest = String::new();
+        reader.read_to_string(&mut manifest)?;
+        let mut report = ManifestReport::default();
+        for (i, line) in manifest.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (algorithm, path, expected) = parse_line(line)
+                .ok_or_else(|| VfsErrorKind::Other(format!("invalid manifest line {}", i + 1)))?;
+            let status = match self.digest(&path_key(path), algorithm) {
+                Ok(digest) if digest == expected => ManifestStatus::Passed,
+                Ok(_) => ManifestStatus::Failed,
+                Err(_) => ManifestStatus::Missing,
+            };
+            report.files.push((path.to_string(), status));
+        }
+        Ok(report)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(Algorithm, &str, Vec<u8>)> {
+    let (algorithm, path, hex) = if let Some(rest) = line.strip_prefix("SHA256 (") {
+        let (path, hex) = rest.rsplit_once(") = ")?;
+        (Some(Algorithm::Sha256), path, hex)
+    } else {
+        let (hex, rest) = line.split_once(' ')?;
+        // The second character marks the text or the binary mode.
+        let path = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+        (None, path, hex)
+    };
+    let expected = decode_hex(hex)?;
+    let algorithm = match (algorithm, expected.len()) {
+        (Some(algorithm), _) => algorithm,
+        (None, 32) => Algorithm::Sha256,
+        _ => return None,
+    };
+    Some((algorithm, path, expected))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ManifestStatus, TarFS};
+
+    const HELLO: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn manifest() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in [("a.txt", "hello"), ("dir/b.txt", "world")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let manifest = format!(
+            "# checksums\n{HELLO}  a.txt\n{HELLO} *./dir/b.txt\nSHA256 (c.txt) = {HELLO}\n"
+        );
+        let report = fs.verify_manifest(manifest.as_bytes()).unwrap();
+        assert_eq!(
+            report.files,
+            [
+                ("a.txt".to_string(), ManifestStatus::Passed),
+                ("./dir/b.txt".to_string(), ManifestStatus::Failed),
+                ("c.txt".to_string(), ManifestStatus::Missing),
+            ]
+        );
+        assert!(!report.is_ok());
+
+        let manifest = format!("SHA256 (a.txt) = {HELLO}\n");
+        assert!(fs.verify_manifest(manifest.as_bytes()).unwrap().is_ok());
+        assert!(fs.verify_manifest("not a manifest".as_bytes()).is_err());
+    }
+}
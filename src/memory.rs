@@ -0,0 +1,110 @@
+//! Estimation of the memory used by the index of an archive.
+
+use crate::{DirTree, Entry, EntryRecord, SparseFile, TarFS};
+use std::{
+    collections::HashMap,
+    mem::{size_of, size_of_val},
+    ops::{Deref, Range},
+    sync::Arc,
+};
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Estimate the bytes used by the index: the tree, the entry records, the side tables
+    /// and the copies of the small files. The archive itself and the lookup caches
+    /// are not counted.
+    ///
+    /// It's an estimate from the capacities, ignoring the overhead of the allocator.
+    /// The index is shared by the clones, so it's counted once for all of them.
+    pub fn index_memory_usage(&self) -> usize {
+        let records = self.records.capacity() * size_of::<EntryRecord>()
+            + self
+                .records
+                .iter()
+                .map(|record| {
+                    record.path.capacity()
+                        + record.raw_path.as_ref().map_or(0, String::capacity)
+                        + record.overrides.as_ref().map_or(0, |overrides| {
+                            size_of_val(overrides.as_ref()) + overrides.heap_size()
+                        })
+                })
+                .sum::<usize>();
+        let xattrs = map_size(&self.xattrs)
+            + self
+                .xattrs
+                .iter()
+                .map(|(path, xattrs)| {
+                    path.capacity()
+                        + map_size(xattrs)
+                        + xattrs
+                            .iter()
+                            .map(|(name, value)| name.capacity() + value.capacity())
+                            .sum::<usize>()
+                })
+                .sum::<usize>();
+        size_of::<DirTree>()
+            + tree_size(&self.root)
+            + records
+            + xattrs
+            + map_size(&self.global_pax)
+            + self
+                .global_pax
+                .iter()
+                .map(|(key, value)| key.capacity() + value.capacity())
+                .sum::<usize>()
+            + self.skipped.capacity() * size_of::<Range<u64>>()
+            + self.owned.heap_size()
+    }
+}
+
+/// The bytes of the table of a map, with a control byte for each bucket.
+fn map_size<K, V>(map: &HashMap<K, V>) -> usize {
+    map.capacity() * (size_of::<(K, V)>() + 1)
+}
+
+/// The bytes allocated by a directory and its descendants.
+fn tree_size(dir: &DirTree) -> usize {
+    map_size(dir)
+        + map_size(&dir.folded)
+        + dir.folded.keys().map(|name| name.len()).sum::<usize>()
+        + dir
+            .iter()
+            .map(|(name, entry)| {
+                // An interned name is shared by the entries, with two reference counts.
+                (name.len() + 2 * size_of::<usize>()) / Arc::strong_count(name)
+                    + match entry {
+                        Entry::Directory(dir) => tree_size(dir),
+                        Entry::Link(target) => target.capacity(),
+                        Entry::Sparse(sparse) => {
+                            size_of::<SparseFile>()
+                                + sparse.map.capacity() * size_of::<(u64, u64)>()
+                        }
+                        Entry::File(..) | Entry::Special(..) => 0,
+                    }
+            })
+            .sum::<usize>()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+
+    #[test]
+    fn index_memory_usage() {
+        let build = |count: usize| {
+            let mut archive = tar::Builder::new(vec![]);
+            for i in 0..count {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(1);
+                archive
+                    .append_data(&mut header, format!("dir/file{i}.txt"), b"a".as_slice())
+                    .unwrap();
+            }
+            TarFS::new(archive.into_inner().unwrap()).unwrap()
+        };
+        let small = build(1).index_memory_usage();
+        let large = build(100).index_memory_usage();
+        assert!(small > 0);
+        // Every entry takes at least its name in the tree, and its path in the records.
+        assert!(large >= small + 99 * ("file0.txt".len() + "dir/file0.txt".len()));
+    }
+}
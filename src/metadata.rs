@@ -0,0 +1,665 @@
+//! Extended metadata of the entries, beyond [`VfsMetadata`](vfs::VfsMetadata).
+
+use crate::{
+    acl::PosixAcl,
+    components, not_dir_error, parse_pax_time,
+    parser::{typeflag, BLOCK_SIZE},
+    path_key, DirTree, Entry, EntryRef, TarFS,
+};
+use std::{collections::HashMap, ops::Deref, str::from_utf8, time::SystemTime};
+use tar_parser2::{ExtraHeader, TypeFlag};
+use vfs::{error::VfsErrorKind, VfsFileType, VfsResult};
+
+/// A special file, which has no contents in the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SpecialFile {
+    /// A character device node.
+    CharDevice {
+        /// The major device number.
+        major: u32,
+        /// The minor device number.
+        minor: u32,
+    },
+    /// A block device node.
+    BlockDevice {
+        /// The major device number.
+        major: u32,
+        /// The minor device number.
+        minor: u32,
+    },
+    /// A named pipe.
+    Fifo,
+    /// A Unix domain socket, which is only stored in cpio archives.
+    Socket,
+}
+
+/// The type of an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarFileType {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+    /// A device node or a FIFO.
+    Special(SpecialFile),
+}
+
+/// Extended metadata of an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TarMetadata {
+    /// The type of the entry.
+    pub file_type: TarFileType,
+    /// The count of the names sharing the contents through hard links,
+    /// or 1 for directories and special files.
+    pub links: u64,
+    /// A stable id of the contents, the same for all hard links of a file.
+    /// It's `None` for directories and special files.
+    pub content_id: Option<u64>,
+    /// An inode-style number, unique in the archive and the same for every opening of it.
+    /// It's the content id of a file, and an odd number for a directory or a special file.
+    pub ino: u64,
+    /// Whether the file is stored as a contiguous file, typeflag `7`, by some old producers.
+    /// It's read as a regular file, and `false` for the others.
+    pub contiguous: bool,
+}
+
+/// The header fields overridden by PAX records.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct PaxOverrides {
+    uid: Option<u64>,
+    gid: Option<u64>,
+    uname: Option<String>,
+    gname: Option<String>,
+    mtime: Option<u64>,
+    acl_access: Option<String>,
+    acl_default: Option<String>,
+}
+
+impl PaxOverrides {
+    /// Apply the records of a PAX extended header. The later records win.
+    pub fn apply(&mut self, records: &[(&str, &[u8])]) {
+        for (key, value) in records {
+            let Ok(value) = from_utf8(value) else {
+                continue;
+            };
+            match *key {
+                "uid" => self.uid = value.parse().ok(),
+                "gid" => self.gid = value.parse().ok(),
+                "uname" => self.uname = Some(value.to_string()),
+                "gname" => self.gname = Some(value.to_string()),
+                "mtime" => self.mtime = parse_pax_time(value),
+                "SCHILY.acl.access" => self.acl_access = Some(value.to_string()),
+                "SCHILY.acl.default" => self.acl_default = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Fill the fields missing in the entry with the global defaults.
+    /// Returns `None` if nothing is overridden.
+    pub fn or(self, defaults: &Self) -> Option<Box<Self>> {
+        let overrides = Self {
+            uid: self.uid.or(defaults.uid),
+            gid: self.gid.or(defaults.gid),
+            uname: self.uname.or_else(|| defaults.uname.clone()),
+            gname: self.gname.or_else(|| defaults.gname.clone()),
+            mtime: self.mtime.or(defaults.mtime),
+            acl_access: self.acl_access.or_else(|| defaults.acl_access.clone()),
+            acl_default: self.acl_default.or_else(|| defaults.acl_default.clone()),
+        };
+        (overrides != Self::default()).then(|| Box::new(overrides))
+    }
+
+    /// The PAX `mtime`.
+    pub fn mtime(&self) -> Option<u64> {
+        self.mtime
+    }
+
+    /// The bytes allocated by the strings.
+    pub fn heap_size(&self) -> usize {
+        [
+            &self.uname,
+            &self.gname,
+            &self.acl_access,
+            &self.acl_default,
+        ]
+        .into_iter()
+        .flatten()
+        .map(String::capacity)
+        .sum()
+    }
+}
+
+/// The parsed header fields of an entry, created by [`TarFS::header_for`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TarHeaderInfo {
+    /// The type flag.
+    pub typeflag: TypeFlag,
+    /// The permission bits.
+    pub mode: u64,
+    /// The user id of the owner, with the PAX `uid` applied.
+    pub uid: u64,
+    /// The group id of the owner, with the PAX `gid` applied.
+    pub gid: u64,
+    /// The modification time, in seconds since the Unix epoch, with the PAX `mtime` applied.
+    pub mtime: u64,
+    /// The target of a link, with the GNU long link or the PAX `linkpath` applied.
+    pub link_target: Option<String>,
+    /// The user name of the owner, in the ustar formats or from PAX.
+    pub uname: Option<String>,
+    /// The group name of the owner, in the ustar formats or from PAX.
+    pub gname: Option<String>,
+    /// The major and the minor device numbers, only in the ustar formats.
+    pub device: Option<(u64, u64)>,
+    /// The POSIX ACLs, from the `SCHILY.acl.access` and `SCHILY.acl.default` PAX records.
+    pub acl: Option<PosixAcl>,
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Get the header fields of the entry with the path. The links are not followed.
+    ///
+    /// If there are several entries with the path, the last one is returned.
+    /// Returns `None` if the path isn't in the archive, or the archive is opened
+    /// with [`TarFS::with_cached_index`].
+    pub fn header_for(&self, path: &str) -> Option<TarHeaderInfo> {
+        let key = path_key(path);
+        let record = self
+            .records
+            .iter()
+            .rfind(|record| path_key(&record.path) == key)?;
+        let header = self.record_header(record);
+        let overrides = record.overrides.as_deref().cloned().unwrap_or_default();
+        let link_target = match header.typeflag {
+            TypeFlag::SymbolicLink => {
+                let mut rest = components(path);
+                match self.find_entry_impl(&self.root, &mut rest) {
+                    Some(EntryRef::Link(target)) if rest.next().is_none() => {
+                        Some(target.to_string())
+                    }
+                    _ => Some(header.linkname.to_string()),
+                }
+            }
+            TypeFlag::HardLink => Some(header.linkname.to_string()),
+            _ => None,
+        };
+        let (uname, gname, device) = match &header.ustar {
+            ExtraHeader::UStar(ustar) => (
+                Some(ustar.uname.to_string()),
+                Some(ustar.gname.to_string()),
+                Some((ustar.devmajor, ustar.devminor)),
+            ),
+            _ => (None, None, None),
+        };
+        Some(TarHeaderInfo {
+            typeflag: header.typeflag,
+            mode: header.mode,
+            uid: overrides.uid.unwrap_or(header.uid),
+            gid: overrides.gid.unwrap_or(header.gid),
+            mtime: overrides.mtime.unwrap_or(header.mtime),
+            link_target,
+            uname: overrides.uname.or(uname),
+            gname: overrides.gname.or(gname),
+            device,
+            acl: PosixAcl::parse(
+                overrides.acl_access.as_deref(),
+                overrides.acl_default.as_deref(),
+            ),
+        })
+    }
+
+    /// Get all records of the global PAX headers, including the vendor keys like
+    /// `LIBARCHIVE.creationtime` and the comments. The later headers win for each key.
+    ///
+    /// It's empty if the archive is opened with [`TarFS::with_cached_index`].
+    pub fn global_pax(&self) -> &HashMap<String, Vec<u8>> {
+        &self.global_pax
+    }
+
+    /// Get the extended metadata of an entry. The symbolic links are followed.
+    ///
+    /// The device nodes and FIFOs are reported as empty files by [`vfs::FileSystem::metadata`],
+    /// and could be told apart here.
+    pub fn tar_metadata(&self, path: &str) -> VfsResult<TarMetadata> {
+        let entry = self.find_entry(path);
+        let (file_type, content_id, ino) = match entry {
+            Some(EntryRef::File(buf, _) | EntryRef::Sparse(buf, _)) => {
+                (TarFileType::File, Some(self.archive_offset(buf) as u64), 0)
+            }
+            Some(EntryRef::Directory(dir)) => (TarFileType::Directory, None, dir.ino),
+            Some(EntryRef::Special(special, _, ino)) => (TarFileType::Special(special), None, ino),
+            _ => return Err(VfsErrorKind::FileNotFound.into()),
+        };
+        // The header block of a file is just before the contents, unlike a sparse file.
+        let contiguous = match entry {
+            Some(EntryRef::File(buf, _)) => {
+                let header = self.archive_offset(buf).checked_sub(BLOCK_SIZE as usize);
+                header.and_then(|header| self.buf().get(header + 156))
+                    == Some(&typeflag::CONTIGUOUS)
+            }
+            _ => false,
+        };
+        let links = match content_id {
+            Some(id) => count_links(&self.root, id as usize),
+            None => 1,
+        };
+        Ok(TarMetadata {
+            file_type,
+            links,
+            content_id,
+            ino: content_id.unwrap_or(ino),
+            contiguous,
+        })
+    }
+
+    /// Get the total size of the files in a directory recursively, computed when building.
+    /// The symbolic links are followed for the path, but not in the directory.
+    pub fn dir_size(&self, path: &str) -> VfsResult<u64> {
+        match self.find_entry(path) {
+            Some(EntryRef::Directory(dir)) => Ok(dir.size),
+            Some(_) => Err(not_dir_error(path)),
+            None => Err(self.not_found(path)),
+        }
+    }
+
+    /// List a directory with the type, the length and the modification time of each entry,
+    /// without looking up every entry from the root. The symbolic links are followed,
+    /// and the dangling ones are skipped.
+    pub fn read_dir_info(
+        &self,
+        path: &str,
+    ) -> VfsResult<impl Iterator<Item = (&str, VfsFileType, u64, Option<SystemTime>)> + '_> {
+        let dir = match self.find_entry(path) {
+            Some(EntryRef::Directory(dir)) => dir,
+            Some(_) => return Err(not_dir_error(path)),
+            None => return Err(self.not_found(path)),
+        };
+        let path = path.trim_end_matches('/').to_string();
+        Ok(dir.iter().filter_map(move |(name, entry)| {
+            let entry = match entry {
+                Entry::Link(_) => self.find_entry(&format!("{path}/{name}"))?,
+                _ => self.find_entry_impl(dir, &mut std::iter::once(&**name))?,
+            };
+            let metadata = self.entry_metadata(&entry);
+            Some((&**name, metadata.file_type, metadata.len, metadata.modified))
+        }))
+    }
+
+    /// Get the permission bits of an entry, like `0o755`. The symbolic links are followed.
+    ///
+    /// The directories without explicit entries are reported as `0o755`.
+    pub fn permissions(&self, path: &str) -> VfsResult<u32> {
+        match self.find_entry(path) {
+            Some(EntryRef::File(_, attrs) | EntryRef::Special(_, attrs, _)) => Ok(attrs.mode),
+            Some(EntryRef::Sparse(_, sparse)) => Ok(sparse.attrs.mode),
+            Some(EntryRef::Directory(dir)) => Ok(dir.attrs.as_ref().map_or(0o755, |dir| dir.mode)),
+            _ => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+
+    /// Whether an entry is a file with any of the execute bits set.
+    pub fn is_executable(&self, path: &str) -> VfsResult<bool> {
+        let file_type = self.tar_metadata(path)?.file_type;
+        Ok(file_type == TarFileType::File && self.permissions(path)? & 0o111 != 0)
+    }
+}
+
+/// Compute the recursive sizes of the directories and store them in the nodes.
+/// The links are not followed, and the hard links are counted for every name.
+pub(crate) fn dir_sizes(dir: &mut DirTree) -> u64 {
+    dir.size = dir
+        .values_mut()
+        .map(|entry| match entry {
+            Entry::File(range, _) => range.len() as u64,
+            Entry::Sparse(sparse) => sparse.size,
+            Entry::Directory(dir) => dir_sizes(dir),
+            Entry::Link(_) | Entry::Special(..) => 0,
+        })
+        .sum();
+    dir.size
+}
+
+/// Number the directories and the special files in the order of the paths,
+/// to be stable for every opening. The numbers are odd, unlike the offsets of the contents.
+pub(crate) fn number_inodes(root: &mut DirTree) {
+    fn number(dir: &mut DirTree, next: &mut u64) {
+        let mut entries = dir.iter_mut().collect::<Vec<_>>();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        for (_, entry) in entries {
+            match entry {
+                Entry::Directory(dir) => {
+                    dir.ino = *next;
+                    *next += 2;
+                    number(dir, next);
+                }
+                Entry::Special(_, _, ino) => {
+                    *ino = *next;
+                    *next += 2;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    root.ino = 1;
+    number(root, &mut 3);
+}
+
+/// Count the files whose contents start at the offset. The hard links share the same range.
+fn count_links(dir: &DirTree, start: usize) -> u64 {
+    dir.values()
+        .map(|entry| match entry {
+            Entry::File(range, _) => (range.start == start) as u64,
+            Entry::Sparse(sparse) => (sparse.data.start == start) as u64,
+            Entry::Directory(dir) => count_links(dir, start),
+            Entry::Link(_) | Entry::Special(..) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{test_util::ArchiveBuilder, SpecialFile, TarFS, TarFileType};
+    use std::time::{Duration, SystemTime};
+    use tar_parser2::TypeFlag;
+    use vfs::{FileSystem, VfsFileType};
+
+    #[test]
+    fn special() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, ty) in [
+            ("dev/null", tar::EntryType::Char),
+            ("dev/sda", tar::EntryType::Block),
+            ("pipe", tar::EntryType::Fifo),
+        ] {
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(ty);
+            header.set_size(0);
+            header.set_device_major(1).unwrap();
+            header.set_device_minor(3).unwrap();
+            archive
+                .append_data(&mut header, name, [].as_slice())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert_eq!(
+            fs.tar_metadata("/dev/null").unwrap().file_type,
+            TarFileType::Special(SpecialFile::CharDevice { major: 1, minor: 3 })
+        );
+        assert_eq!(
+            fs.tar_metadata("/dev/sda").unwrap().file_type,
+            TarFileType::Special(SpecialFile::BlockDevice { major: 1, minor: 3 })
+        );
+        assert_eq!(
+            fs.tar_metadata("/pipe").unwrap().file_type,
+            TarFileType::Special(SpecialFile::Fifo)
+        );
+        assert_eq!(
+            fs.tar_metadata("/dev").unwrap().file_type,
+            TarFileType::Directory
+        );
+
+        let metadata = fs.metadata("/pipe").unwrap();
+        assert_eq!(metadata.file_type, VfsFileType::File);
+        assert_eq!(metadata.len, 0);
+        assert!(fs.open_file("/pipe").is_err());
+    }
+
+    #[test]
+    fn header() {
+        let target = "b".repeat(200);
+        let mut archive = tar::Builder::new(vec![]);
+        {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(5);
+            header.set_mode(0o640);
+            header.set_mtime(1234567890);
+            header.set_username("alice").unwrap();
+            archive
+                .append_data(&mut header, "a.txt", b"hello".as_slice())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, "link", &target).unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let info = fs.header_for("/a.txt").unwrap();
+        assert_eq!(info.typeflag, TypeFlag::NormalFile);
+        assert_eq!(info.mode, 0o640);
+        assert_eq!(info.mtime, 1234567890);
+        assert_eq!(info.uname.as_deref(), Some("alice"));
+        assert_eq!(info.link_target, None);
+
+        let info = fs.header_for("link").unwrap();
+        assert_eq!(info.typeflag, TypeFlag::SymbolicLink);
+        assert_eq!(info.link_target, Some(target));
+        assert!(fs.header_for("/missing").is_none());
+    }
+
+    #[test]
+    fn pax_overrides() {
+        let mut archive = tar::Builder::new(vec![]);
+        {
+            let global = b"17 gid=987654321\n";
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(tar::EntryType::XGlobalHeader);
+            header.set_size(global.len() as u64);
+            archive
+                .append_data(&mut header, "GlobalHead", global.as_slice())
+                .unwrap();
+        }
+        archive
+            .append_pax_extensions([
+                ("uid", b"12345678901".as_slice()),
+                ("uname", "ünïcode".as_bytes()),
+                ("mtime", b"1700000000.5".as_slice()),
+            ])
+            .unwrap();
+        for name in ["a.txt", "b.txt"] {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(5);
+            header.set_uid(1000);
+            header.set_username("alice").unwrap();
+            header.set_mtime(1234567890);
+            archive
+                .append_data(&mut header, name, b"hello".as_slice())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let info = fs.header_for("/a.txt").unwrap();
+        assert_eq!(info.uid, 12345678901);
+        assert_eq!(info.gid, 987654321);
+        assert_eq!(info.uname.as_deref(), Some("ünïcode"));
+        assert_eq!(info.mtime, 1700000000);
+
+        // The PAX header applies to the next entry only.
+        let info = fs.header_for("/b.txt").unwrap();
+        assert_eq!(info.uid, 1000);
+        assert_eq!(info.gid, 987654321);
+        assert_eq!(info.uname.as_deref(), Some("alice"));
+        assert_eq!(info.mtime, 1234567890);
+    }
+
+    #[test]
+    fn global_pax() {
+        let archive = ArchiveBuilder::new()
+            .global_pax(&[
+                ("comment", "first"),
+                ("LIBARCHIVE.creationtime", "1700000000"),
+            ])
+            .global_pax(&[("comment", "built by ci"), ("gid", "42")])
+            .file("a.txt", b"")
+            .finish();
+        let fs = TarFS::new(archive).unwrap();
+
+        let pax = fs.global_pax();
+        assert_eq!(pax.len(), 3);
+        assert_eq!(pax["comment"], b"built by ci");
+        assert_eq!(pax["LIBARCHIVE.creationtime"], b"1700000000");
+        assert_eq!(fs.header_for("/a.txt").unwrap().gid, 42);
+    }
+
+    #[test]
+    fn permissions() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, mode) in [("bin/run.sh", 0o755), ("bin/data", 0o640)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            header.set_mode(mode);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert_eq!(fs.permissions("/bin/run.sh").unwrap(), 0o755);
+        assert_eq!(fs.permissions("/bin/data").unwrap(), 0o640);
+        assert!(fs.is_executable("/bin/run.sh").unwrap());
+        assert!(!fs.is_executable("/bin/data").unwrap());
+        assert!(!fs.is_executable("/bin").unwrap());
+        assert!(fs.permissions("/missing").is_err());
+    }
+
+    #[test]
+    fn dir_metadata() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o700);
+        header.set_mtime(1234567890);
+        archive
+            .append_data(&mut header, "etc/ssl/", [].as_slice())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_mtime(1700000000);
+        archive
+            .append_data(&mut header, "etc/ssl/cert.pem", [].as_slice())
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        // The files report their header mtime too.
+        let modified = fs.metadata("/etc/ssl/cert.pem").unwrap().modified;
+        assert_eq!(
+            modified,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1700000000))
+        );
+
+        assert_eq!(fs.permissions("/etc/ssl").unwrap(), 0o700);
+        let modified = fs.metadata("/etc/ssl").unwrap().modified.unwrap();
+        assert_eq!(
+            modified,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1234567890)
+        );
+        // The synthesized parent has no metadata.
+        assert_eq!(fs.permissions("/etc").unwrap(), 0o755);
+        assert_eq!(
+            fs.metadata("/etc").unwrap().modified,
+            Some(SystemTime::UNIX_EPOCH)
+        );
+    }
+
+    #[test]
+    fn hard_links() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a", "b"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Link);
+            archive.append_link(&mut header, "dir/c", "a").unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let a = fs.tar_metadata("/a").unwrap();
+        let c = fs.tar_metadata("/dir/c").unwrap();
+        let b = fs.tar_metadata("/b").unwrap();
+        assert_eq!((a.links, c.links, b.links), (2, 2, 1));
+        assert_eq!(a.content_id, c.content_id);
+        assert_ne!(a.content_id, b.content_id);
+        let dir = fs.tar_metadata("/dir").unwrap();
+        assert_eq!((dir.links, dir.content_id), (1, None));
+        assert_eq!(a.ino, c.ino);
+        assert_eq!(a.ino, a.content_id.unwrap());
+        assert_eq!(dir.ino % 2, 1);
+        assert_ne!(dir.ino, fs.tar_metadata("/").unwrap().ino);
+    }
+
+    #[test]
+    fn contiguous() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, ty) in [
+            ("contiguous", tar::EntryType::Continuous),
+            ("regular", tar::EntryType::Regular),
+        ] {
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(ty);
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let metadata = fs.tar_metadata("/contiguous").unwrap();
+        assert_eq!(metadata.file_type, TarFileType::File);
+        assert!(metadata.contiguous);
+        assert!(!fs.tar_metadata("/regular").unwrap().contiguous);
+        assert!(!fs.tar_metadata("/").unwrap().contiguous);
+        assert_eq!(fs.metadata("/contiguous").unwrap().len, 4);
+    }
+
+    #[test]
+    fn dir_size() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, size) in [("a/x", 10), ("a/b/y", 20), ("a/b/z", 30), ("c", 40)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(size as u64);
+            archive
+                .append_data(&mut header, name, vec![0; size].as_slice())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, "link", "a/b").unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert_eq!(fs.dir_size("/").unwrap(), 100);
+        assert_eq!(fs.dir_size("/a").unwrap(), 60);
+        assert_eq!(fs.dir_size("/a/b").unwrap(), 50);
+        assert_eq!(fs.dir_size("/link").unwrap(), 50);
+        assert!(fs.dir_size("/c").is_err());
+
+        let mut infos = fs
+            .read_dir_info("/")
+            .unwrap()
+            .map(|(name, file_type, len, _)| (name, file_type, len))
+            .collect::<Vec<_>>();
+        infos.sort_by_key(|(name, ..)| *name);
+        assert_eq!(
+            infos,
+            [
+                ("a", VfsFileType::Directory, 0),
+                ("c", VfsFileType::File, 40),
+                ("link", VfsFileType::Directory, 0),
+            ]
+        );
+        assert_eq!(fs.read_dir_info("/link").unwrap().count(), 2);
+        assert!(fs.read_dir_info("/c").is_err());
+    }
+}
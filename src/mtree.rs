@@ -0,0 +1,161 @@
+//! Exporting the archive as a BSD mtree specification.
+
+use crate::{DirTree, Entry, EntryRef, SpecialFile, TarFS};
+use std::{
+    io::{self, Write},
+    ops::Deref,
+};
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Write a BSD mtree specification of the archive, like `bsdtar --format=mtree`,
+    /// with the entries sorted by path.
+    ///
+    /// The files have the size and the mode, and the SHA-256 digest with the `digest` feature.
+    /// The links aren't followed, and the hard links are written as files.
+    ///
+    /// ```text
+    /// #mtree
+    /// . type=dir mode=0755
+    /// ./bin type=dir mode=0755
+    /// ./bin/app type=file mode=0755 size=4
+    /// ./latest type=link link=bin/app
+    /// ```
+    pub fn write_mtree(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "#mtree")?;
+        writeln!(out, ". type=dir mode={:04o}", self.dir_mode(&self.root))?;
+        self.write_mtree_dir(&mut out, &self.root, ".")
+    }
+
+    fn write_mtree_dir(&self, out: &mut impl Write, dir: &DirTree, prefix: &str) -> io::Result<()> {
+        let mut entries = dir.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(a, _)| *a);
+        for (name, entry) in entries {
+            let path = format!("{prefix}/{}", escape(name));
+            write!(out, "{path} ")?;
+            let contents = match entry {
+                Entry::File(range, attrs) => {
+                    write!(
+                        out,
+                        "type=file mode={:04o} size={}",
+                        attrs.mode,
+                        range.len()
+                    )?;
+                    EntryRef::File(&self.buf()[range.clone()], attrs).contents()
+                }
+                Entry::Sparse(sparse) => {
+                    write!(
+                        out,
+                        "type=file mode={:04o} size={}",
+                        sparse.attrs.mode, sparse.size
+                    )?;
+                    EntryRef::Sparse(&self.buf()[sparse.data.clone()], sparse).contents()
+                }
+                Entry::Special(special, attrs, _) => {
+                    let (kind, device) = match special {
+                        SpecialFile::CharDevice { major, minor } => ("char", Some((major, minor))),
+                        SpecialFile::BlockDevice { major, minor } => {
+                            ("block", Some((major, minor)))
+                        }
+                        SpecialFile::Fifo => ("fifo", None),
+                        SpecialFile::Socket => ("socket", None),
+                    };
+                    write!(out, "type={kind} mode={:04o}", attrs.mode)?;
+                    if let Some((major, minor)) = device {
+                        write!(out, " device=native,{major},{minor}")?;
+                    }
+                    None
+                }
+                Entry::Link(target) => {
+                    write!(out, "type=link link={}", escape(target))?;
+                    None
+                }
+                Entry::Directory(dir) => {
+                    write!(out, "type=dir mode={:04o}", self.dir_mode(dir))?;
+                    None
+                }
+            };
+            #[cfg(feature = "digest")]
+            if let Some(contents) = contents {
+                use sha2::{Digest, Sha256};
+
+                write!(out, " sha256digest=")?;
+                for b in Sha256::digest(&contents) {
+                    write!(out, "{b:02x}")?;
+                }
+            }
+            #[cfg(not(feature = "digest"))]
+            let _ = contents;
+            writeln!(out)?;
+            if let Entry::Directory(dir) = entry {
+                self.write_mtree_dir(out, dir, &path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The permission bits of a directory, `0o755` without an explicit entry.
+    fn dir_mode(&self, dir: &DirTree) -> u32 {
+        dir.attrs.as_ref().map_or(0o755, |dir| dir.mode)
+    }
+}
+
+/// Escape the whitespaces, the non-ASCII characters, `\` and `#` in octal, like `vis(3)`.
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for b in name.bytes() {
+        if b.is_ascii_graphic() && b != b'\\' && b != b'#' {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("\\{b:03o}"));
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+
+    #[test]
+    fn write_mtree() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, mode) in [("bin/app", 0o755), ("my file", 0o644)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(5);
+            header.set_mode(mode);
+            archive
+                .append_data(&mut header, name, b"hello".as_slice())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive
+            .append_link(&mut header, "latest", "bin/app")
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let mut mtree = vec![];
+        fs.write_mtree(&mut mtree).unwrap();
+        let mtree = String::from_utf8(mtree).unwrap();
+        #[cfg(feature = "digest")]
+        let (mtree, digest) = (
+            mtree.replace(
+                " sha256digest=2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+                "",
+            ),
+            mtree,
+        );
+        assert_eq!(
+            mtree,
+            "#mtree
+. type=dir mode=0755
+./bin type=dir mode=0755
+./bin/app type=file mode=0755 size=5
+./latest type=link link=bin/app
+./my\\040file type=file mode=0644 size=5
+"
+        );
+        #[cfg(feature = "digest")]
+        assert_eq!(digest.matches("sha256digest=").count(), 2);
+    }
+}
@@ -0,0 +1,136 @@
+//! GNU multi-volume archives, stitched into a single archive.
+
+use crate::{
+    lenient::block_at,
+    parser::{typeflag::*, *},
+    TarFS, TarFsError,
+};
+use std::ops::Deref;
+use vfs::VfsResult;
+
+impl TarFS<Vec<u8>> {
+    /// Create [`TarFS`] from the volumes of a GNU multi-volume archive, in order.
+    ///
+    /// The volumes are copied into a single archive in memory, where the files
+    /// split across the volumes are contiguous.
+    pub fn new_multi<F: Deref<Target = [u8]>>(volumes: Vec<F>) -> VfsResult<Self> {
+        Self::new(stitch(volumes.iter().map(|v| v.deref()))?)
+    }
+}
+
+fn stitch<'a>(volumes: impl IntoIterator<Item = &'a [u8]>) -> Result<Vec<u8>, TarFsError> {
+    let mut archive = vec![];
+    let mut index = 0;
+    // The padded length of the split file data expected in the next volume.
+    let mut remaining = 0;
+    for (i, buf) in volumes.into_iter().enumerate() {
+        let mut offset = 0;
+        if i > 0 {
+            if let Some(header) = block_at(buf, offset).and_then(parse_header) {
+                if header.typeflag == GNU_VOLUME_HEADER {
+                    offset = (offset + BLOCK_SIZE as usize).saturating_add(padded(header.size));
+                    index += 1;
+                }
+            }
+            if remaining > 0 {
+                let header = block_at(buf, offset)
+                    .and_then(parse_header)
+                    .filter(|header| header.typeflag == GNU_MULTIVOLUME)
+                    .ok_or(TarFsError::InvalidHeader {
+                        index,
+                        offset: offset as u64,
+                    })?;
+                remaining = padded(header.size);
+                offset += BLOCK_SIZE as usize;
+                index += 1;
+            }
+        }
+        if remaining > 0 {
+            let end = offset.saturating_add(remaining).min(buf.len());
+            archive.extend_from_slice(&buf[offset.min(end)..end]);
+            remaining -= end - offset.min(end);
+            offset = end;
+            if remaining > 0 {
+                continue;
+            }
+        }
+        while let Some(block) = block_at(buf, offset) {
+            if is_zero_block(block) {
+                break;
+            }
+            let header = parse_header(block).ok_or(TarFsError::InvalidHeader {
+                index,
+                offset: offset as u64,
+            })?;
+            let end = (offset + BLOCK_SIZE as usize).saturating_add(padded(header.size));
+            index += 1;
+            if end > buf.len() {
+                // The file continues in the next volume.
+                archive.extend_from_slice(&buf[offset..]);
+                remaining = end - buf.len();
+                break;
+            }
+            archive.extend_from_slice(&buf[offset..end]);
+            offset = end;
+        }
+    }
+    if remaining > 0 {
+        return Err(TarFsError::Truncated {
+            index,
+            offset: archive.len() as u64,
+        });
+    }
+    archive.resize(archive.len() + 2 * BLOCK_SIZE as usize, 0);
+    Ok(archive)
+}
+
+/// The padded length of the contents, saturated if it exceeds `usize`.
+fn padded(size: u64) -> usize {
+    usize::try_from(align_block(size)).unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use std::io::Read;
+    use vfs::VfsPath;
+
+    fn header(name: &str, typeflag: u8, size: u64) -> Vec<u8> {
+        let mut header = tar::Header::new_gnu();
+        header.set_path(name).unwrap();
+        header.set_size(size);
+        header.set_entry_type(tar::EntryType::new(typeflag));
+        header.set_cksum();
+        header.as_bytes().to_vec()
+    }
+
+    #[test]
+    fn multi() {
+        let data = (0..2000).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut first = header("a.bin", b'0', 2000);
+        first.extend(&data[..1024]);
+
+        let mut second = header("volume.2", b'V', 0);
+        second.extend(header("a.bin", b'M', 976));
+        second.extend(&data[1024..]);
+        second.resize(second.len() + 48, 0);
+        second.extend(header("b.txt", b'0', 5));
+        second.extend(b"hello");
+        second.resize(second.len() + 507 + 1024, 0);
+
+        let root = VfsPath::from(TarFS::new_multi(vec![first, second]).unwrap());
+        let mut buffer = vec![];
+        root.join("a.bin")
+            .unwrap()
+            .open_file()
+            .unwrap()
+            .read_to_end(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, data);
+        assert_eq!(
+            root.join("b.txt").unwrap().read_to_string().unwrap(),
+            "hello"
+        );
+    }
+}
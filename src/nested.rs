@@ -0,0 +1,85 @@
+//! Archives nested in the files of an archive, e.g. the layers of a container image.
+
+use crate::{ArcSlice, EntryRef, TarFS, TarOptions};
+use std::ops::Deref;
+use vfs::{error::VfsErrorKind, VfsResult};
+
+/// A file of an archive backing a nested [`TarFS`], created by [`TarFS::open_nested`].
+///
+/// It shares the buffer of the outer archive, which is kept alive by it.
+#[derive(Debug)]
+pub struct NestedArchive<F>(ArcSlice<F>);
+
+impl<F> Clone for NestedArchive<F> {
+    fn clone(&self) -> Self {
+        Self(ArcSlice {
+            file: self.0.file.clone(),
+            owned: self.0.owned.clone(),
+            range: self.0.range.clone(),
+        })
+    }
+}
+
+impl<F: Deref<Target = [u8]>> Deref for NestedArchive<F> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Open a file in the archive as another tar archive, without copying it.
+    ///
+    /// The sparse files aren't stored contiguously, and can't be opened.
+    pub fn open_nested(&self, path: &str) -> VfsResult<TarFS<NestedArchive<F>>> {
+        self.open_nested_with_options(path, TarOptions::default())
+    }
+
+    /// Open a file in the archive as another tar archive with options, without copying it.
+    pub fn open_nested_with_options(
+        &self,
+        path: &str,
+        options: TarOptions,
+    ) -> VfsResult<TarFS<NestedArchive<F>>> {
+        match self.file_entry(path)? {
+            EntryRef::File(contents, _) => {
+                TarFS::with_options(NestedArchive(self.share(contents)), options)
+            }
+            _ => Err(VfsErrorKind::Other(format!(
+                "{path} is a sparse file, which isn't stored contiguously"
+            ))
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use vfs::FileSystem;
+
+    #[test]
+    fn nested() {
+        let mut inner = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        inner
+            .append_data(&mut header, "etc/hostname", b"hello".as_slice())
+            .unwrap();
+        let inner = inner.into_inner().unwrap();
+        let mut outer = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(inner.len() as u64);
+        outer
+            .append_data(&mut header, "layer.tar", inner.as_slice())
+            .unwrap();
+        let fs = TarFS::new(outer.into_inner().unwrap()).unwrap();
+
+        let nested = fs.open_nested("/layer.tar").unwrap();
+        // The outer archive is kept alive by the nested one.
+        drop(fs);
+        assert_eq!(nested.file_slice("/etc/hostname").unwrap(), b"hello");
+        assert_eq!(nested.read_dir("/etc").unwrap().count(), 1);
+    }
+}
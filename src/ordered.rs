@@ -0,0 +1,119 @@
+//! The entries in the archive order, with the byte layout.
+
+use crate::{path_key, TarFS};
+use std::ops::{Deref, Range};
+
+/// An entry in the archive order, created by [`TarFS::entries_ordered`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OrderedEntry<'a> {
+    /// The full path, with the long names applied.
+    pub path: &'a str,
+    /// The byte range of the entry, from the first metadata entry applied to it,
+    /// e.g. a PAX header or a GNU long name, to the end of the padded contents.
+    pub range: Range<u64>,
+    /// The byte offset of the header.
+    pub header: u64,
+    /// The byte range of the stored contents.
+    pub contents: Range<u64>,
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Iterate the entries exactly in the archive order, including the duplicate paths
+    /// and the entries not in the tree, with their byte ranges.
+    ///
+    /// The ranges are contiguous, except the ignored entries, e.g. the GNU volume headers,
+    /// and the garbage skipped by [`TarOptions::lenient`](crate::TarOptions::lenient).
+    pub fn entries_ordered(&self) -> impl Iterator<Item = OrderedEntry<'_>> {
+        self.records.iter().map(|record| OrderedEntry {
+            path: &record.path,
+            range: record.start as u64..record.entry.end as u64,
+            header: record.entry.start as u64,
+            contents: record.contents.start as u64..record.contents.end as u64,
+        })
+    }
+
+    /// Get the byte offsets of the header and the contents of an entry, and the stored length
+    /// of the contents, e.g. to splice or verify the archive. The links are not followed.
+    ///
+    /// If there are several entries with the path, the last one is returned.
+    /// Returns `None` if the path isn't in the archive, or is a synthesized parent directory.
+    pub fn entry_location(&self, path: &str) -> Option<(u64, u64, u64)> {
+        let key = path_key(path);
+        let record = self
+            .records
+            .iter()
+            .rfind(|record| path_key(&record.path) == key)?;
+        Some((
+            record.entry.start as u64,
+            record.contents.start as u64,
+            record.contents.len() as u64,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+
+    #[test]
+    fn entries_ordered() {
+        let long = format!("{}a", "long/".repeat(30));
+        let pax = b"11 mtime=1\n";
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(tar::EntryType::XHeader);
+        header.set_size(pax.len() as u64);
+        archive
+            .append_data(&mut header, "PaxHeaders/b", pax.as_slice())
+            .unwrap();
+        for (name, data) in [("b", "first"), (long.as_str(), "data"), ("b", "second")] {
+            // The long name is written in a GNU long name entry.
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+        let fs = TarFS::new(archive.as_slice()).unwrap();
+
+        let entries = fs.entries_ordered().collect::<Vec<_>>();
+        let paths = entries.iter().map(|e| e.path).collect::<Vec<_>>();
+        assert_eq!(paths, ["b", long.as_str(), "b"]);
+        assert_eq!((entries[0].range.start, entries[0].header), (0, 1024));
+        assert!(entries[1].range.start < entries[1].header);
+        // The ranges cover the archive, except the end-of-archive blocks.
+        for pair in entries.windows(2) {
+            assert_eq!(pair[0].range.end, pair[1].range.start);
+        }
+        assert_eq!(entries[2].range.end as usize, archive.len() - 1024);
+        let contents = entries[2].contents.start as usize..entries[2].contents.end as usize;
+        assert_eq!(&archive[contents], b"second");
+    }
+
+    #[test]
+    fn entry_location() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in [("a", "first"), ("dir/b", "data"), ("a", "second")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+        let fs = TarFS::new(archive.as_slice()).unwrap();
+
+        assert_eq!(fs.entry_location("/dir/b"), Some((1024, 1536, 4)));
+        let (header, data, len) = fs.entry_location("a").unwrap();
+        assert_eq!(header, 2048);
+        assert_eq!(&archive[data as usize..(data + len) as usize], b"second");
+        assert_eq!(
+            &archive[header as usize + 257..header as usize + 262],
+            b"ustar"
+        );
+        assert_eq!(fs.entry_location("/dir"), None);
+        assert_eq!(fs.entry_location("/missing"), None);
+    }
+}
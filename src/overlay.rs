@@ -0,0 +1,859 @@
+//! A writable in-memory overlay over a readonly [`TarFS`].
+
+use crate::{parser::typeflag, writer, ArcSlice, EntryRef, TarFS};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Debug,
+    io::{self, Read, Seek, SeekFrom, Write},
+    ops::Deref,
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::SystemTime,
+};
+use vfs::{error::VfsErrorKind, *};
+
+/// The maximum count of patches of a file before it is copied entirely.
+const MAX_PATCHES: usize = 64;
+
+/// The length of the chunks of the files written by [`TarOverlayFS::write_archive`].
+const CHUNK_LEN: usize = 1 << 16;
+
+/// A writable filesystem layered over a readonly [`TarFS`].
+///
+/// All modifications are kept in memory. Writing to a file from the archive
+/// only stores the written ranges as patches over the original data, and the
+/// file is copied entirely only when the patches cost about as much as a copy.
+/// Removing an entry of the archive leaves a tombstone, which hides it.
+#[derive(Debug)]
+pub struct TarOverlayFS<F: Deref<Target = [u8]>> {
+    base: TarFS<F>,
+    files: RwLock<HashMap<String, Arc<Mutex<PatchedContent<F>>>>>,
+    dirs: RwLock<HashSet<String>>,
+    /// The removed entries of the archive.
+    removed: RwLock<HashSet<String>>,
+}
+
+impl<F: Deref<Target = [u8]>> TarOverlayFS<F> {
+    /// Create [`TarOverlayFS`] over a [`TarFS`].
+    pub fn new(base: TarFS<F>) -> Self {
+        Self {
+            base,
+            files: RwLock::default(),
+            dirs: RwLock::default(),
+            removed: RwLock::default(),
+        }
+    }
+
+    /// Get the reference of the underlying [`TarFS`].
+    pub fn base(&self) -> &TarFS<F> {
+        &self.base
+    }
+
+    /// Get the count of bytes held in memory by the modified files.
+    pub fn memory_usage(&self) -> usize {
+        read_lock(&self.files)
+            .values()
+            .map(|content| lock(content).memory_usage())
+            .sum()
+    }
+
+    /// Write the archive with the modifications appended as new entries, like `tar -r`.
+    ///
+    /// The entries of the archive are copied as is, followed by the modified files and
+    /// the created directories, which win over the earlier entries when read.
+    /// The removed entries can't be represented by appending, and fail the writing.
+    ///
+    /// If the paths are renamed by [`TarOptions::strip_components`](crate::TarOptions::strip_components)
+    /// or [`TarOptions::rename_prefix`](crate::TarOptions::rename_prefix), the entries of the archive are written under the paths
+    /// instead, like [`TarFS::read_dir_recursive_tarball`], so the archive is read
+    /// without the options. The entries are written from the tree like that too,
+    /// if the raw entries aren't available, like with [`TarFS::with_cached_index`].
+    pub fn write_archive(&self, mut out: impl Write) -> VfsResult<()> {
+        if !read_lock(&self.removed).is_empty() {
+            return Err(VfsErrorKind::Other(
+                "the removed entries can't be appended to the archive".to_string(),
+            )
+            .into());
+        }
+        let base = &self.base;
+        if base.renamed || base.records.is_empty() {
+            base.write_subtree(&base.root, "", "", &base.mtimes(), &mut out)?;
+        } else {
+            // The entries end with the last one parsed, before the end-of-archive blocks.
+            let end = base.records.iter().map(|record| record.entry.end).max();
+            out.write_all(&base.buf()[..end.unwrap_or_default()])?;
+        }
+        let mtime = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let mut dirs = read_lock(&self.dirs).iter().cloned().collect::<Vec<_>>();
+        dirs.sort();
+        for dir in dirs {
+            let path = format!("{dir}/");
+            writer::write_header(&mut out, &path, typeflag::DIRECTORY, 0o755, mtime, 0)?;
+        }
+        let files = read_lock(&self.files);
+        let mut keys = files.keys().collect::<Vec<_>>();
+        keys.sort();
+        // The files may be grown far beyond their data by seeking, so they are streamed.
+        let mut chunk = vec![0; CHUNK_LEN];
+        for key in keys {
+            let content = lock(&files[key]);
+            let mode = self.base.permissions(key).unwrap_or(0o644);
+            writer::write_header(&mut out, key, typeflag::REGULAR, mode, mtime, content.len)?;
+            let mut pos = 0;
+            while pos < content.len {
+                let n = content.read_at(pos, &mut chunk);
+                out.write_all(&chunk[..n])?;
+                pos += n as u64;
+            }
+            writer::write_padding(&mut out, content.len)?;
+        }
+        writer::write_end(&mut out)?;
+        Ok(())
+    }
+
+    /// The key of a path in the maps of the overlay, which is the same for all the paths
+    /// reaching an entry, like [`TarFS`] finds them.
+    ///
+    /// The `.` and `..` components and the backslash separators are resolved, the names
+    /// are normalized to NFC with [`TarOptions::normalize_unicode`](crate::TarOptions::normalize_unicode),
+    /// and the symbolic links of the archive in the parents are followed.
+    /// With `follow`, a link at the path itself is followed too.
+    fn key(&self, path: &str, follow: bool) -> String {
+        let mut path = Cow::Borrowed(path);
+        #[cfg(feature = "unicode")]
+        if self.base.normalize_unicode {
+            path = crate::unicode::to_nfc(path);
+        }
+        // The backslashes are separators, unless an entry of the archive has them.
+        if path.contains('\\')
+            && !self
+                .base
+                .canonicalize(&path)
+                .is_ok_and(|(resolved, _)| resolved.contains('\\'))
+        {
+            path = path.replace('\\', "/").into();
+        }
+        let path = crate::components(&path).collect::<Vec<_>>().join("/");
+        self.resolve_key(&crate::normalize_str(&path), follow)
+    }
+
+    /// Follow the links of the archive in a normalized path, unless the entries are
+    /// in the overlay or removed.
+    fn resolve_key(&self, path: &str, follow: bool) -> String {
+        let key = match path.rsplit_once('/') {
+            Some((parent, name)) => child_key(&self.resolve_key(parent, true), name),
+            None => path.to_string(),
+        };
+        if !follow
+            || read_lock(&self.files).contains_key(&key)
+            || read_lock(&self.dirs).contains(&key)
+            || read_lock(&self.removed).contains(&key)
+        {
+            return key;
+        }
+        match self.base.canonicalize(&key) {
+            Ok((resolved, _)) => resolved.trim_start_matches('/').to_string(),
+            Err(_) => key,
+        }
+    }
+
+    fn is_dir(&self, key: &str) -> bool {
+        key.is_empty()
+            || read_lock(&self.dirs).contains(key)
+            || matches!(self.base_entry(key), Some(EntryRef::Directory(_)))
+    }
+
+    /// Find the entry in the archive, unless it's removed.
+    fn base_entry(&self, key: &str) -> Option<EntryRef<'_>> {
+        if read_lock(&self.removed).contains(key) {
+            None
+        } else {
+            self.base.find_entry(key)
+        }
+    }
+
+    /// Hide the entry of the archive, if any.
+    fn remove_base(&self, key: &str) {
+        if self.base.find_entry(key).is_some() {
+            write_lock(&self.removed).insert(key.to_string());
+        }
+    }
+
+    fn ensure_parent(&self, key: &str) -> VfsResult<()> {
+        let parent = key.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+        if self.is_dir(parent) {
+            Ok(())
+        } else {
+            Err(VfsErrorKind::FileNotFound.into())
+        }
+    }
+}
+
+impl<F: Deref<Target = [u8]> + Debug + Send + Sync + 'static> FileSystem for TarOverlayFS<F> {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        let key = &self.key(path, true);
+        let mut names = HashSet::new();
+        let base = match self.base_entry(key) {
+            Some(EntryRef::Directory(_)) => self.base.read_dir(key),
+            _ => Err(VfsErrorKind::FileNotFound.into()),
+        };
+        match base {
+            Ok(iter) => {
+                let removed = read_lock(&self.removed);
+                names.extend(iter.filter(|name| !removed.contains(&child_key(key, name))));
+            }
+            Err(e) if !read_lock(&self.dirs).contains(key) => return Err(e),
+            Err(_) => {}
+        }
+        let files = read_lock(&self.files);
+        let dirs = read_lock(&self.dirs);
+        for child in files.keys().chain(dirs.iter()) {
+            if let Some(name) = child_name(key, child) {
+                names.insert(name.to_string());
+            }
+        }
+        Ok(Box::new(names.into_iter()))
+    }
+
+    fn create_dir(&self, path: &str) -> VfsResult<()> {
+        let key = &self.key(path, false);
+        if self.is_dir(key) {
+            return Err(VfsErrorKind::DirectoryExists.into());
+        }
+        self.ensure_parent(key)?;
+        write_lock(&self.dirs).insert(key.to_string());
+        Ok(())
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let key = &self.key(path, true);
+        match read_lock(&self.files).get(key) {
+            Some(content) => Ok(Box::new(OverlayFile::new(content.clone(), 0))),
+            None if read_lock(&self.removed).contains(key) => {
+                Err(VfsErrorKind::FileNotFound.into())
+            }
+            None => self.base.open_file(key),
+        }
+    }
+
+    fn create_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let key = &self.key(path, true);
+        // A directory isn't replaced by a file, even if it's only in the archive.
+        if self.is_dir(key) {
+            return Err(VfsErrorKind::FileExists.into());
+        }
+        self.ensure_parent(key)?;
+        // A tombstone is kept, as the new file shadows the entry of the archive.
+        let content = Arc::new(Mutex::new(PatchedContent::default()));
+        write_lock(&self.files).insert(key.to_string(), content.clone());
+        Ok(Box::new(OverlayFile::new(content, 0)))
+    }
+
+    fn append_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        let key = &self.key(path, true);
+        let mut files = write_lock(&self.files);
+        let content = match files.get(key) {
+            Some(content) => content.clone(),
+            None => match self.base_entry(key) {
+                Some(EntryRef::File(buf, _)) => {
+                    let content = Arc::new(Mutex::new(PatchedContent::new(self.base.share(buf))));
+                    files.insert(key.to_string(), content.clone());
+                    content
+                }
+                Some(entry @ EntryRef::Sparse(..)) => {
+                    // The holes are filled, so the contents are copied.
+                    let mut content = PatchedContent::default();
+                    content.write_at(0, &entry.contents().unwrap_or_default())?;
+                    let content = Arc::new(Mutex::new(content));
+                    files.insert(key.to_string(), content.clone());
+                    content
+                }
+                _ => return Err(VfsErrorKind::FileNotFound.into()),
+            },
+        };
+        let len = lock(&content).len;
+        Ok(Box::new(OverlayFile::new(content, len)))
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let key = &self.key(path, true);
+        if let Some(content) = read_lock(&self.files).get(key) {
+            return Ok(VfsMetadata {
+                file_type: VfsFileType::File,
+                len: lock(content).len,
+                created: None,
+                modified: None,
+                accessed: None,
+            });
+        }
+        if read_lock(&self.dirs).contains(key) {
+            return Ok(VfsMetadata {
+                file_type: VfsFileType::Directory,
+                len: 0,
+                created: None,
+                modified: None,
+                accessed: None,
+            });
+        }
+        if read_lock(&self.removed).contains(key) {
+            return Err(VfsErrorKind::FileNotFound.into());
+        }
+        self.base.metadata(key)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        let key = &self.key(path, true);
+        Ok(read_lock(&self.files).contains_key(key)
+            || read_lock(&self.dirs).contains(key)
+            || self.base_entry(key).is_some())
+    }
+
+    fn remove_file(&self, path: &str) -> VfsResult<()> {
+        let key = &self.key(path, false);
+        let in_overlay = write_lock(&self.files).remove(key).is_some();
+        match self.base_entry(key) {
+            Some(EntryRef::Directory(_)) | None if !in_overlay => {
+                Err(VfsErrorKind::FileNotFound.into())
+            }
+            Some(EntryRef::Directory(_)) | None => Ok(()),
+            Some(_) => {
+                self.remove_base(key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove an empty directory. Use [`VfsPath::remove_dir_all`] to remove the contents too.
+    fn remove_dir(&self, path: &str) -> VfsResult<()> {
+        let key = &self.key(path, false);
+        if key.is_empty() || !self.is_dir(key) {
+            return Err(VfsErrorKind::FileNotFound.into());
+        }
+        if self.read_dir(key)?.next().is_some() {
+            return Err(VfsErrorKind::Other("Directory to remove is not empty".to_string()).into());
+        }
+        write_lock(&self.dirs).remove(key);
+        self.remove_base(key);
+        Ok(())
+    }
+}
+
+/// File contents stored as written ranges over the original archive data.
+#[derive(Debug)]
+struct PatchedContent<F> {
+    base: Option<ArcSlice<F>>,
+    /// Non-overlapping written ranges, keyed by their offsets.
+    patches: BTreeMap<u64, Vec<u8>>,
+    len: u64,
+}
+
+impl<F> Default for PatchedContent<F> {
+    fn default() -> Self {
+        Self {
+            base: None,
+            patches: BTreeMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<F: Deref<Target = [u8]>> PatchedContent<F> {
+    fn new(base: ArcSlice<F>) -> Self {
+        let len = base.as_ref().len() as u64;
+        Self {
+            base: Some(base),
+            patches: BTreeMap::new(),
+            len,
+        }
+    }
+
+    fn base(&self) -> &[u8] {
+        self.base
+            .as_ref()
+            .map(|base| base.as_ref())
+            .unwrap_or_default()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.patches.values().map(|p| p.len()).sum()
+    }
+
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> usize {
+        if pos >= self.len {
+            return 0;
+        }
+        let n = buf
+            .len()
+            .min(usize::try_from(self.len - pos).unwrap_or(usize::MAX));
+        let buf = &mut buf[..n];
+        let end = pos + n as u64;
+        // Copy the original data, and fill zeros beyond it.
+        let base = self.base();
+        let base_start = pos.min(base.len() as u64) as usize;
+        let base_end = end.min(base.len() as u64) as usize;
+        let copied = base_end - base_start;
+        buf[..copied].copy_from_slice(&base[base_start..base_end]);
+        buf[copied..].fill(0);
+        // Apply the overlapping patches.
+        let first = self
+            .patches
+            .range(..=pos)
+            .next_back()
+            .map(|(offset, _)| *offset)
+            .unwrap_or(pos);
+        for (&offset, data) in self.patches.range(first..end) {
+            let start = offset.max(pos);
+            let stop = (offset + data.len() as u64).min(end);
+            if start < stop {
+                buf[(start - pos) as usize..(stop - pos) as usize]
+                    .copy_from_slice(&data[(start - offset) as usize..(stop - offset) as usize]);
+            }
+        }
+        n
+    }
+
+    fn write_at(&mut self, pos: u64, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let mut end = pos
+            .checked_add(data.len() as u64)
+            .filter(|end| usize::try_from(*end).is_ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "too large to write"))?;
+        // Merge with the overlapping and adjacent patches.
+        let first = self
+            .patches
+            .range(..=pos)
+            .next_back()
+            .map(|(offset, _)| *offset)
+            .unwrap_or(pos);
+        let overlapped = self
+            .patches
+            .range(first..=end)
+            .filter(|(offset, patch)| **offset + patch.len() as u64 >= pos)
+            .map(|(offset, _)| *offset)
+            .collect::<Vec<_>>();
+        let mut overlapped = overlapped
+            .into_iter()
+            .filter_map(|offset| self.patches.remove_entry(&offset))
+            .collect::<Vec<_>>();
+        // The patch before the write is grown in place, so the sequential writes
+        // append to it without copying it again.
+        let (start, mut merged) = match overlapped.first() {
+            Some((offset, _)) if *offset <= pos => overlapped.remove(0),
+            _ => (pos, vec![]),
+        };
+        for (offset, patch) in &overlapped {
+            end = end.max(offset + patch.len() as u64);
+        }
+        end = end.max(start + merged.len() as u64);
+        merged.resize((end - start) as usize, 0);
+        for (offset, patch) in overlapped {
+            let s = (offset - start) as usize;
+            merged[s..s + patch.len()].copy_from_slice(&patch);
+        }
+        let s = (pos - start) as usize;
+        merged[s..s + data.len()].copy_from_slice(data);
+        self.patches.insert(start, merged);
+        self.len = self.len.max(pos + data.len() as u64);
+
+        // The holes after a seek beyond the end are never copied, if they dominate.
+        let stored = (self.base().len() + self.memory_usage()) as u64;
+        if self.len <= stored * 2
+            && (self.patches.len() > MAX_PATCHES
+                || (!self.base().is_empty() && self.memory_usage() as u64 * 2 >= self.len))
+        {
+            self.materialize();
+        }
+        Ok(())
+    }
+
+    /// Copy the whole content into a single owned patch.
+    /// The length is bounded by twice the stored bytes.
+    fn materialize(&mut self) {
+        let mut data = vec![0; self.len as usize];
+        self.read_at(0, &mut data);
+        self.base = None;
+        self.patches.clear();
+        self.patches.insert(0, data);
+    }
+}
+
+/// A reader and writer of [`PatchedContent`].
+#[derive(Debug)]
+struct OverlayFile<F> {
+    content: Arc<Mutex<PatchedContent<F>>>,
+    pos: u64,
+}
+
+impl<F> OverlayFile<F> {
+    fn new(content: Arc<Mutex<PatchedContent<F>>>, pos: u64) -> Self {
+        Self { content, pos }
+    }
+}
+
+impl<F: Deref<Target = [u8]>> Read for OverlayFile<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = lock(&self.content).read_at(self.pos, buf);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<F: Deref<Target = [u8]>> Write for OverlayFile<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        lock(&self.content).write_at(self.pos, buf)?;
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F> Seek for OverlayFile<F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(n) => (lock(&self.content).len, n),
+            SeekFrom::Current(n) => (self.pos, n),
+        };
+        match base.checked_add_signed(offset) {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+/// The end of the entries, before the end-of-archive blocks.
+fn child_key(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+fn child_name<'a>(dir: &str, path: &'a str) -> Option<&'a str> {
+    let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+    (parent == dir).then_some(name)
+}
+
+fn lock<T>(m: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn read_lock<T>(m: &RwLock<T>) -> std::sync::RwLockReadGuard<'_, T> {
+    m.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write_lock<T>(m: &RwLock<T>) -> std::sync::RwLockWriteGuard<'_, T> {
+    m.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{TarFS, TarOptions, TarOverlayFS};
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use vfs::{error::VfsErrorKind, FileSystem, VfsPath};
+
+    fn archive(len: usize) -> Vec<u8> {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(len as u64);
+        header.set_mode(0o644);
+        archive
+            .append_data(&mut header, "data.bin", vec![1u8; len].as_slice())
+            .unwrap();
+        archive.into_inner().unwrap()
+    }
+
+    #[test]
+    fn patch() {
+        let fs = TarOverlayFS::new(TarFS::new(archive(65536)).unwrap());
+        {
+            let mut file = fs.append_file("/data.bin").unwrap();
+            file.seek(SeekFrom::Start(100)).unwrap();
+            file.write_all(b"abcd").unwrap();
+        }
+        // A naive copy-on-write would hold the whole file.
+        assert!(fs.memory_usage() < 1024);
+
+        let mut buffer = vec![];
+        fs.open_file("/data.bin")
+            .unwrap()
+            .read_to_end(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer.len(), 65536);
+        assert_eq!(&buffer[100..104], b"abcd");
+        assert!(buffer[..100].iter().chain(&buffer[104..]).all(|b| *b == 1));
+
+        {
+            let mut file = fs.append_file("/data.bin").unwrap();
+            file.write_all(&[2u8; 70000]).unwrap();
+        }
+        assert_eq!(fs.metadata("/data.bin").unwrap().len, 135536);
+        // Copied entirely when the patches are large.
+        assert_eq!(fs.memory_usage(), 135536);
+
+        {
+            let mut file = fs.create_file("/new.txt").unwrap();
+            file.write_all(b"hello").unwrap();
+        }
+        let root = VfsPath::from(fs);
+        let mut files = root
+            .read_dir()
+            .unwrap()
+            .map(|p| p.filename())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(&files, &["data.bin", "new.txt"]);
+        assert_eq!(
+            root.join("new.txt").unwrap().read_to_string().unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn seek_beyond_end() {
+        let fs = TarOverlayFS::new(TarFS::new(archive(1024)).unwrap());
+        let mut file = fs.append_file("/data.bin").unwrap();
+        // More patches than the maximum, far beyond the end.
+        for i in 0..100 {
+            file.seek(SeekFrom::Start((1 << 40) + i * 4096)).unwrap();
+            file.write_all(b"x").unwrap();
+        }
+        assert!(fs.memory_usage() < 1024);
+        assert_eq!(
+            fs.metadata("/data.bin").unwrap().len,
+            (1 << 40) + 99 * 4096 + 1
+        );
+
+        file.seek(SeekFrom::Start(u64::MAX - 1)).unwrap();
+        assert!(file.write_all(b"abcd").is_err());
+
+        // The file is streamed into the archive, which fails after a few chunks.
+        struct Limited(usize);
+        impl Write for Limited {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.0 < buf.len() {
+                    return Err(io::ErrorKind::StorageFull.into());
+                }
+                self.0 -= buf.len();
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        assert!(fs.write_archive(Limited(1 << 20)).is_err());
+    }
+
+    #[test]
+    fn remove() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a.txt", "dir/b.txt", "dir/sub/c.txt"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        let fs = TarOverlayFS::new(TarFS::new(archive.into_inner().unwrap()).unwrap());
+
+        fs.remove_file("/a.txt").unwrap();
+        assert!(!fs.exists("/a.txt").unwrap());
+        assert!(fs.open_file("/a.txt").is_err());
+        assert!(fs.remove_file("/a.txt").is_err());
+        assert!(fs.remove_file("/dir").is_err());
+        assert!(fs.remove_dir("/dir").is_err());
+
+        // A removed file could be created again.
+        fs.create_file("/a.txt").unwrap().write_all(b"new").unwrap();
+        let root = VfsPath::from(fs);
+        assert_eq!(root.join("a.txt").unwrap().read_to_string().unwrap(), "new");
+
+        root.join("dir").unwrap().remove_dir_all().unwrap();
+        assert!(!root.join("dir/sub/c.txt").unwrap().exists().unwrap());
+        assert!(!root.join("dir").unwrap().exists().unwrap());
+        let names = root
+            .read_dir()
+            .unwrap()
+            .map(|p| p.filename())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a.txt"]);
+
+        root.join("dir").unwrap().create_dir().unwrap();
+        assert_eq!(root.join("dir").unwrap().read_dir().unwrap().count(), 0);
+    }
+
+    #[test]
+    fn create_file_over_dir() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "dir/file", b"data".as_slice())
+            .unwrap();
+        let fs = TarOverlayFS::new(TarFS::new(archive.into_inner().unwrap()).unwrap());
+        fs.create_dir("/new").unwrap();
+        for path in ["/", "/dir", "/new"] {
+            let err = fs.create_file(path).map(drop).unwrap_err();
+            assert!(matches!(err.kind(), VfsErrorKind::FileExists), "{path}");
+        }
+        assert_eq!(fs.read_dir("/dir").unwrap().count(), 1);
+    }
+
+    #[test]
+    fn normalized_paths() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a/b", "a/c", "dir/file"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        for (name, target) in [("link", "a/c"), ("dirlink", "dir")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, name, target).unwrap();
+        }
+        let fs = TarOverlayFS::new(TarFS::new(archive.into_inner().unwrap()).unwrap());
+        let read = |path| {
+            let mut buffer = String::new();
+            fs.open_file(path)
+                .unwrap()
+                .read_to_string(&mut buffer)
+                .unwrap();
+            buffer
+        };
+
+        fs.remove_file("/a/./b").unwrap();
+        assert!(!fs.exists("/a/b").unwrap());
+        assert!(!fs.exists("/dir/../a/b").unwrap());
+
+        // The link is followed, and its target is patched.
+        fs.append_file("/link").unwrap().write_all(b"tail").unwrap();
+        assert_eq!(read("/a/c"), "datatail");
+        assert_eq!(read("/link"), "datatail");
+
+        fs.create_file("/dir\\x")
+            .unwrap()
+            .write_all(b"new")
+            .unwrap();
+        assert_eq!(read("/dir/x"), "new");
+        assert_eq!(read("/dirlink/x"), "new");
+        let mut names = fs.read_dir("/dirlink").unwrap().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["file", "x"]);
+
+        // The link itself is removed, not its target.
+        fs.remove_file("/link").unwrap();
+        assert!(!fs.exists("/link").unwrap());
+        assert_eq!(read("/a/c"), "datatail");
+    }
+
+    #[test]
+    fn write_archive() {
+        let fs = TarOverlayFS::new(TarFS::new(archive(1000)).unwrap());
+        fs.append_file("/data.bin")
+            .unwrap()
+            .write_all(b"tail")
+            .unwrap();
+        fs.create_dir("/dir").unwrap();
+        fs.create_file("/dir/new.txt")
+            .unwrap()
+            .write_all(b"hello")
+            .unwrap();
+        let mut archive = vec![];
+        fs.write_archive(&mut archive).unwrap();
+
+        let updated = TarFS::new(archive).unwrap();
+        assert_eq!(updated.metadata("/data.bin").unwrap().len, 1004);
+        assert_eq!(updated.permissions("/data.bin").unwrap(), 0o644);
+        let root = VfsPath::from(updated);
+        assert_eq!(
+            root.join("dir/new.txt").unwrap().read_to_string().unwrap(),
+            "hello"
+        );
+        assert_eq!(root.read_dir().unwrap().count(), 2);
+
+        fs.remove_file("/data.bin").unwrap();
+        assert!(fs.write_archive(vec![]).is_err());
+    }
+
+    #[test]
+    fn write_renamed_archive() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o600);
+        archive
+            .append_data(&mut header, "pkg-1.0/data.bin", b"data".as_slice())
+            .unwrap();
+        let archive = archive.into_inner().unwrap();
+        let base = TarFS::with_options(archive, TarOptions::new().strip_components(1)).unwrap();
+        let fs = TarOverlayFS::new(base);
+        fs.append_file("/data.bin")
+            .unwrap()
+            .write_all(b"tail")
+            .unwrap();
+        let mut archive = vec![];
+        fs.write_archive(&mut archive).unwrap();
+
+        // The entries of the archive and the appended ones are under the same paths.
+        let updated = TarFS::new(archive).unwrap();
+        assert_eq!(
+            updated.read_dir("/").unwrap().collect::<Vec<_>>(),
+            ["data.bin"]
+        );
+        assert_eq!(updated.permissions("/data.bin").unwrap(), 0o600);
+        let mut buffer = String::new();
+        updated
+            .open_file("/data.bin")
+            .unwrap()
+            .read_to_string(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, "datatail");
+    }
+
+    #[test]
+    fn write_padded_archive() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a.txt", "b.txt"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+            archive.get_mut().extend_from_slice(&[0; 1024]);
+        }
+        let options = TarOptions::new().skip_zero_blocks(true);
+        let base = TarFS::with_options(archive.into_inner().unwrap(), options.clone()).unwrap();
+        let fs = TarOverlayFS::new(base);
+        fs.create_file("/c.txt").unwrap().write_all(b"new").unwrap();
+        let mut archive = vec![];
+        fs.write_archive(&mut archive).unwrap();
+
+        // The entries after the zero blocks are copied too.
+        let updated = TarFS::with_options(archive, options).unwrap();
+        let mut names = updated.read_dir("/").unwrap().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["a.txt", "b.txt", "c.txt"]);
+    }
+}
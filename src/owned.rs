@@ -0,0 +1,133 @@
+//! Copies of the small files out of the archive, with [`TarOptions::copy_small_files`].
+//!
+//! [`TarOptions::copy_small_files`]: crate::TarOptions::copy_small_files
+
+use crate::{DirTree, Entry};
+use std::ops::Range;
+
+/// The contents of the small files, copied together for locality.
+#[derive(Debug, Default)]
+pub(crate) struct OwnedFiles {
+    data: Vec<u8>,
+    /// The starts of the contents in the archive and in `data`, both ascending.
+    starts: Vec<(usize, usize)>,
+}
+
+impl OwnedFiles {
+    /// Copy the non-empty files shorter than `threshold`. The hard links share the copy.
+    pub fn new(buf: &[u8], root: &DirTree, threshold: usize) -> Self {
+        let mut ranges = vec![];
+        small_files(root, threshold, &mut ranges);
+        ranges.sort_unstable_by_key(|range| range.start);
+        ranges.dedup_by_key(|range| range.start);
+        let mut owned = Self::default();
+        owned
+            .data
+            .reserve_exact(ranges.iter().map(Range::len).sum());
+        for range in ranges {
+            if let Some(contents) = buf.get(range.clone()) {
+                owned.starts.push((range.start, owned.data.len()));
+                owned.data.extend_from_slice(contents);
+            }
+        }
+        owned
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The bytes allocated by the copies.
+    pub fn heap_size(&self) -> usize {
+        self.data.capacity() + self.starts.capacity() * std::mem::size_of::<(usize, usize)>()
+    }
+
+    /// Get the copy of the contents at the range of the archive.
+    pub fn get(&self, range: &Range<usize>) -> Option<&[u8]> {
+        let i = self
+            .starts
+            .binary_search_by_key(&range.start, |(start, _)| *start)
+            .ok()?;
+        let start = self.starts[i].1;
+        self.data.get(start..start + range.len())
+    }
+
+    /// Get the range of a slice of the copies in `data`, if it's in them.
+    pub fn range_of(&self, slice: &[u8]) -> Option<Range<usize>> {
+        let start = (slice.as_ptr() as usize).checked_sub(self.data.as_ptr() as usize)?;
+        (!slice.is_empty() && start + slice.len() <= self.data.len())
+            .then_some(start..start + slice.len())
+    }
+
+    /// Get the offset in the archive of a slice of the copies.
+    pub fn archive_offset(&self, slice: &[u8]) -> Option<usize> {
+        let start = self.range_of(slice)?.start;
+        let i = self.starts.partition_point(|(_, owned)| *owned <= start) - 1;
+        let (archive, owned) = self.starts[i];
+        Some(archive + start - owned)
+    }
+}
+
+fn small_files(dir: &DirTree, threshold: usize, ranges: &mut Vec<Range<usize>>) {
+    for entry in dir.values() {
+        match entry {
+            Entry::File(range, _) if !range.is_empty() && range.len() < threshold => {
+                ranges.push(range.clone())
+            }
+            Entry::Directory(dir) => small_files(dir, threshold, ranges),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{TarFS, TarOptions};
+    use std::io::Read;
+    use vfs::FileSystem;
+
+    #[test]
+    fn copy_small_files() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in [("small", "data"), ("large", "large data")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Link);
+        archive.append_link(&mut header, "hard", "small").unwrap();
+        let archive = archive.into_inner().unwrap();
+        let plain = TarFS::new(archive.clone()).unwrap();
+        let fs = TarFS::with_options(archive, TarOptions::new().copy_small_files(8)).unwrap();
+
+        let in_archive = |fs: &TarFS<Vec<u8>>, path: &str| {
+            let range = fs.buf().as_ptr_range();
+            range.contains(&fs.file_slice(path).unwrap().as_ptr())
+        };
+        assert!(!in_archive(&fs, "/small"));
+        assert!(in_archive(&fs, "/large"));
+        assert!(in_archive(&plain, "/small"));
+        assert_eq!(fs.file_slice("/small").unwrap(), b"data");
+        assert_eq!(
+            fs.file_slice("/hard").unwrap().as_ptr(),
+            fs.file_slice("/small").unwrap().as_ptr()
+        );
+        assert!(fs.index_memory_usage() > plain.index_memory_usage());
+
+        // The copies are opened and identified like the contents in the archive.
+        let mut buffer = String::new();
+        fs.open_file("/small")
+            .unwrap()
+            .read_to_string(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, "data");
+        assert_eq!(
+            fs.tar_metadata("/small").unwrap().content_id,
+            plain.tar_metadata("/small").unwrap().content_id
+        );
+        assert_eq!(fs.tar_metadata("/hard").unwrap().links, 2);
+    }
+}
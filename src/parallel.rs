@@ -0,0 +1,176 @@
+//! Parallel parsing and tree building of large archives, with the `rayon` feature.
+
+use crate::{lenient, parser::*, DirTree, DirTreeBuilder, Entry};
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+use tar_parser2::{parse_tar, TarEntry};
+use vfs::{VfsError, VfsResult};
+
+/// An entry waiting to be inserted into the tree.
+#[derive(Debug)]
+pub(crate) struct PendingEntry {
+    /// The index and the offset of the entry.
+    pub position: (usize, u64),
+    pub path: PathBuf,
+    pub kind: PendingKind,
+}
+
+#[derive(Debug)]
+pub(crate) enum PendingKind {
+    Directory,
+    Entry(Entry),
+    /// A hard link to the target path.
+    HardLink(PathBuf),
+}
+
+/// Parse the entries in parallel, after locating the headers sequentially.
+///
+/// Returns `None` if the archive isn't a plain well-formed one,
+/// which is left to the sequential parser to accept or report.
+pub(crate) fn parse_entries(buf: &[u8]) -> Option<Vec<TarEntry<'_>>> {
+    let mut ranges = vec![];
+    let mut offset = 0;
+    loop {
+        let block = lenient::block_at(buf, offset)?;
+        if is_zero_block(block) {
+            break;
+        }
+        let (_, end) = lenient::entry_end(buf, offset)?;
+        if end > buf.len() {
+            return None;
+        }
+        ranges.push(offset..end);
+        offset = end;
+    }
+    if buf[offset..].iter().any(|b| *b != 0) {
+        return None;
+    }
+    ranges
+        .into_par_iter()
+        .map(|range| {
+            let (_, entries) = parse_tar(&buf[range]).ok()?;
+            let mut entries = entries.into_iter();
+            let entry = entries.next()?;
+            entries.next().is_none().then_some(entry)
+        })
+        .collect()
+}
+
+impl DirTreeBuilder<'_> {
+    /// Insert the pending entries, with the subtrees of the top-level components built in parallel.
+    ///
+    /// The entries of a subtree are inserted in the archive order, so the result is the same
+    /// as the sequential build. Hard links across the subtrees depend on the order of all
+    /// entries, and such archives are built sequentially.
+    pub(crate) fn insert_pending(&mut self) -> VfsResult<()> {
+        let pending = self.pending.take().unwrap_or_default();
+        let crossed = pending.iter().any(|entry| match &entry.kind {
+            PendingKind::HardLink(target) => shard_key(&entry.path) != shard_key(target),
+            _ => false,
+        });
+        if crossed {
+            return pending
+                .into_iter()
+                .try_for_each(|entry| self.insert_pending_entry(entry));
+        }
+
+        let mut shards = HashMap::<OsString, Vec<PendingEntry>>::new();
+        for entry in pending {
+            shards
+                .entry(shard_key(&entry.path))
+                .or_default()
+                .push(entry);
+        }
+        let (buf, options) = (self.buf, &self.options);
+        let results = shards
+            .into_par_iter()
+            .map(|(_, entries)| {
+                let mut builder = DirTreeBuilder::new(buf, options.clone());
+                for entry in entries {
+                    let index = entry.position.0;
+                    builder
+                        .insert_pending_entry(entry)
+                        .map_err(|err| (index, err))?;
+                }
+                Ok(builder.root)
+            })
+            .collect::<Vec<Result<DirTree, (usize, VfsError)>>>();
+        let (roots, errors): (Vec<_>, Vec<_>) = results.into_iter().partition(Result::is_ok);
+        // The error of the first failing entry is reported, like the sequential build.
+        if let Some((_, err)) = errors
+            .into_iter()
+            .filter_map(Result::err)
+            .min_by_key(|(index, _)| *index)
+        {
+            return Err(err);
+        }
+        // The shards have distinct top-level names.
+        self.root
+            .extend(roots.into_iter().flat_map(Result::ok).flatten());
+        self.interner.intern_tree(&mut self.root);
+        Ok(())
+    }
+
+    fn insert_pending_entry(&mut self, entry: PendingEntry) -> VfsResult<()> {
+        self.position = entry.position;
+        match entry.kind {
+            PendingKind::Directory => {
+                self.insert_dir(&entry.path)?;
+                Ok(())
+            }
+            PendingKind::Entry(e) => self.insert_entry(&entry.path, e),
+            PendingKind::HardLink(target) => self.insert_hard_link(&entry.path, &target),
+        }
+    }
+}
+
+/// The top-level component, which the entry is inserted under.
+fn shard_key(path: &Path) -> OsString {
+    path.iter().next().map(OsString::from).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use vfs::VfsPath;
+
+    #[test]
+    fn parallel() {
+        let mut archive = tar::Builder::new(vec![]);
+        for i in 0..100 {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(
+                    &mut header,
+                    format!("dir{}/file{i}", i % 7),
+                    b"data".as_slice(),
+                )
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Link);
+            archive
+                .append_link(&mut header, "dir0/hard", "dir0/file0")
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+        assert_eq!(super::parse_entries(&archive).unwrap().len(), 101);
+
+        let root = VfsPath::from(TarFS::new(archive).unwrap());
+        assert_eq!(root.read_dir().unwrap().count(), 7);
+        assert_eq!(
+            root.join("dir3/file10").unwrap().read_to_string().unwrap(),
+            "data"
+        );
+        assert_eq!(
+            root.join("dir0/hard").unwrap().read_to_string().unwrap(),
+            "data"
+        );
+    }
+}
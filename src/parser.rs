@@ -0,0 +1,239 @@
+//! A block-level parser of tar headers, independent of the VFS layer.
+//!
+//! It reads the V7, POSIX ustar and GNU header blocks, the GNU long names,
+//! and the PAX extended headers. The data of the entries is left to the caller.
+//!
+//! ```
+//! use vfs_tar::parser::{align_block, is_zero_block, parse_header, BLOCK_SIZE};
+//!
+//! fn list(archive: &[u8]) -> Vec<String> {
+//!     let mut names = vec![];
+//!     let mut offset = 0;
+//!     while let Some(block) = archive.get(offset..offset + BLOCK_SIZE as usize) {
+//!         if is_zero_block(block) {
+//!             break;
+//!         }
+//!         let Some(header) = parse_header(block) else {
+//!             break;
+//!         };
+//!         names.push(header.name.into_owned());
+//!         offset += (BLOCK_SIZE + align_block(header.size)) as usize;
+//!     }
+//!     names
+//! }
+//! ```
+
+use std::{borrow::Cow, ops::Range, str::from_utf8};
+
+/// The size of a tar block.
+pub const BLOCK_SIZE: u64 = 512;
+
+/// The typeflags of the entries.
+pub mod typeflag {
+    /// A regular file.
+    pub const REGULAR: u8 = b'0';
+    /// A regular file in the old archives.
+    pub const AREGULAR: u8 = b'\0';
+    /// A hard link.
+    pub const LINK: u8 = b'1';
+    /// A symbolic link.
+    pub const SYMLINK: u8 = b'2';
+    /// A character device.
+    pub const CHAR: u8 = b'3';
+    /// A block device.
+    pub const BLOCK: u8 = b'4';
+    /// A directory.
+    pub const DIRECTORY: u8 = b'5';
+    /// A FIFO.
+    pub const FIFO: u8 = b'6';
+    /// A contiguous file, treated as a regular file.
+    pub const CONTIGUOUS: u8 = b'7';
+    /// A PAX extended header of the next entry.
+    pub const PAX_HEADER: u8 = b'x';
+    /// A PAX global extended header.
+    pub const PAX_GLOBAL: u8 = b'g';
+    /// A GNU long name of the next entry.
+    pub const GNU_LONGNAME: u8 = b'L';
+    /// A GNU long link target of the next entry.
+    pub const GNU_LONGLINK: u8 = b'K';
+    /// A GNU old-style sparse file.
+    pub const GNU_SPARSE: u8 = b'S';
+    /// A GNU volume header.
+    pub const GNU_VOLUME_HEADER: u8 = b'V';
+    /// A GNU continuation entry, which holds the rest of a file split across volumes.
+    pub const GNU_MULTIVOLUME: u8 = b'M';
+}
+
+/// The fields of a header block.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RawHeader<'a> {
+    /// The name, joined with the POSIX ustar prefix.
+    pub name: Cow<'a, str>,
+    /// The permission bits.
+    pub mode: u32,
+    /// The size of the data.
+    pub size: u64,
+    /// The modification time, in seconds since the epoch.
+    pub mtime: u64,
+    /// The type of the entry, see [`typeflag`].
+    pub typeflag: u8,
+    /// The target of a link.
+    pub linkname: &'a str,
+}
+
+/// Parse a header block. Returns `None` if the block is invalid.
+pub fn parse_header(block: &[u8]) -> Option<RawHeader<'_>> {
+    let block = block.get(..BLOCK_SIZE as usize)?;
+    if !verify_checksum(block) {
+        return None;
+    }
+    let name = parse_str(&block[0..100])?;
+    let mode = parse_octal(&block[100..108]).unwrap_or_default() as u32;
+    let size = parse_numeric(&block[124..136])?;
+    let mtime = parse_numeric(&block[136..148]).unwrap_or_default();
+    let typeflag = block[156];
+    let linkname = parse_str(&block[157..257])?;
+    // Only the POSIX ustar format has the prefix field, whatever the version is.
+    let name = if &block[257..263] == b"ustar\0" {
+        match parse_str(&block[prefix_range(block)])? {
+            "" => Cow::Borrowed(name),
+            prefix => Cow::Owned(format!("{prefix}/{name}")),
+        }
+    } else {
+        Cow::Borrowed(name)
+    };
+    Some(RawHeader {
+        name,
+        mode,
+        size,
+        mtime,
+        typeflag,
+        linkname,
+    })
+}
+
+/// The prefix field of a POSIX ustar header block.
+///
+/// The star format keeps the atime and the ctime after a shorter prefix,
+/// and marks the block with `tar\0` at the end.
+fn prefix_range(block: &[u8]) -> Range<usize> {
+    if is_star(block) {
+        345..476
+    } else {
+        345..500
+    }
+}
+
+fn is_star(block: &[u8]) -> bool {
+    &block[257..263] == b"ustar\0" && &block[508..512] == b"tar\0"
+}
+
+/// The atime and the ctime of a star header block.
+pub(crate) fn star_times(block: &[u8]) -> Option<(u64, u64)> {
+    let block = block.get(..BLOCK_SIZE as usize)?;
+    if !is_star(block) {
+        return None;
+    }
+    Some((
+        parse_numeric(&block[476..488])?,
+        parse_numeric(&block[488..500])?,
+    ))
+}
+
+/// Whether the checksum field of a header block matches.
+pub fn verify_checksum(block: &[u8]) -> bool {
+    block.len() >= BLOCK_SIZE as usize && parse_octal(&block[148..156]) == Some(checksum(block))
+}
+
+/// Whether the block is an end-of-archive zero block.
+pub fn is_zero_block(block: &[u8]) -> bool {
+    block.iter().all(|b| *b == 0)
+}
+
+/// Parse the data of a GNU long name or long link entry.
+pub fn parse_long_name(data: &[u8]) -> Option<&str> {
+    parse_str(data)
+}
+
+/// Parse the records of a PAX extended header. The values may be binary, e.g. the xattrs.
+pub fn parse_pax_records(data: &[u8]) -> Option<Vec<(&str, &[u8])>> {
+    try_parse_pax_records(data).ok()
+}
+
+/// Like [`parse_pax_records`], but tells why the header is malformed.
+pub fn try_parse_pax_records(mut data: &[u8]) -> Result<Vec<(&str, &[u8])>, &'static str> {
+    let mut records = vec![];
+    // Trailing NULs are padding.
+    while data.first().map(|b| *b != 0).unwrap_or(false) {
+        let space = data
+            .iter()
+            .position(|b| *b == b' ')
+            .ok_or("PAX record without a length")?;
+        let digits = &data[..space];
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+            return Err("invalid PAX record length");
+        }
+        let len = from_utf8(digits)
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .ok_or("invalid PAX record length")?;
+        let record = data
+            .get(space + 1..len)
+            .ok_or("PAX record length out of range")?;
+        let Some((b'\n', record)) = record.split_last() else {
+            return Err("PAX record without a newline");
+        };
+        let eq = record
+            .iter()
+            .position(|b| *b == b'=')
+            .ok_or("PAX record without a value")?;
+        let key = from_utf8(&record[..eq]).map_err(|_| "PAX key isn't UTF-8")?;
+        if key.is_empty() {
+            return Err("PAX record without a key");
+        }
+        records.push((key, &record[eq + 1..]));
+        data = &data[len..];
+    }
+    Ok(records)
+}
+
+/// Round up to the multiple of [`BLOCK_SIZE`], saturating at `u64::MAX`.
+pub fn align_block(n: u64) -> u64 {
+    n.div_ceil(BLOCK_SIZE).saturating_mul(BLOCK_SIZE)
+}
+
+pub(crate) fn checksum(block: &[u8]) -> u64 {
+    block
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (if (148..156).contains(&i) { b' ' } else { *b }) as u64)
+        .sum()
+}
+
+fn parse_str(i: &[u8]) -> Option<&str> {
+    let end = i.iter().position(|b| *b == 0).unwrap_or(i.len());
+    from_utf8(&i[..end]).ok()
+}
+
+fn parse_octal(i: &[u8]) -> Option<u64> {
+    let s = parse_str(i)?.trim_matches(' ');
+    if s.is_empty() {
+        Some(0)
+    } else {
+        u64::from_str_radix(s, 8).ok()
+    }
+}
+
+/// Parse an octal number, or a GNU base-256 number.
+pub(crate) fn parse_numeric(i: &[u8]) -> Option<u64> {
+    if i[0] & 0x80 != 0 {
+        let mut n = (i[0] & 0x7f) as u64;
+        for b in &i[1..] {
+            n = n.checked_mul(256)?.checked_add(*b as u64)?;
+        }
+        Some(n)
+    } else {
+        parse_octal(i)
+    }
+}
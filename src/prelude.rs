@@ -0,0 +1,12 @@
+//! The common types, and the one-line constructors of a root [`VfsPath`] from an archive.
+//!
+//! ```no_run
+//! use vfs_tar::prelude::*;
+//!
+//! let root = VfsPath::from_tar_path("assets.tar")?;
+//! let text = root.join("docs/readme.txt")?.read_to_string()?;
+//! # Ok::<(), vfs::VfsError>(())
+//! ```
+
+pub use crate::{TarFS, TarOptions, TarPathExt};
+pub use vfs::{FileSystem, VfsPath, VfsResult};
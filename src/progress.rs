@@ -0,0 +1,138 @@
+//! Progress of building a [`TarFS`](crate::TarFS), see [`TarOptions::progress`](crate::TarOptions::progress).
+
+use crate::{DirTreeBuilder, TarFsError};
+use std::{
+    fmt::Debug,
+    sync::{atomic::Ordering, Arc},
+};
+use vfs::VfsResult;
+
+/// The progress of building a [`TarFS`](crate::TarFS), passed to the callback.
+///
+/// It's also reported by `TarFS::extract_parallel`, with the bytes and the count
+/// of the files written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Progress {
+    /// The bytes of the archive processed.
+    pub bytes: u64,
+    /// The length of the archive.
+    pub total: u64,
+    /// The count of the entries processed, including the metadata entries.
+    pub entries: usize,
+}
+
+/// A callback of the progress.
+#[derive(Clone)]
+pub(crate) struct ProgressHook(Arc<dyn Fn(Progress) + Send + Sync>);
+
+impl ProgressHook {
+    pub fn new(hook: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+}
+
+impl Debug for ProgressHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressHook")
+    }
+}
+
+impl DirTreeBuilder<'_> {
+    /// Report the progress to the callback, if any.
+    pub(crate) fn report(&self, bytes: u64, entries: usize) {
+        if let Some(hook) = &self.options.progress {
+            (hook.0)(Progress {
+                bytes,
+                total: self.buf.len() as u64,
+                entries,
+            });
+        }
+    }
+
+    /// Fail if the cancellation token is set.
+    pub(crate) fn check_cancelled(&self) -> VfsResult<()> {
+        match &self.options.cancel {
+            Some(token) if token.load(Ordering::Relaxed) => {
+                let (index, offset) = self.position;
+                Err(TarFsError::Cancelled { index, offset }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Progress, TarFS, TarFsError, TarOptions};
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    };
+
+    #[test]
+    fn progress() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a", "b", "c"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+        let total = archive.len() as u64;
+
+        let reports = Arc::new(Mutex::new(vec![]));
+        let options = TarOptions::new().progress({
+            let reports = reports.clone();
+            move |progress| reports.lock().unwrap().push(progress)
+        });
+        TarFS::with_options(archive, options).unwrap();
+        let reports = reports.lock().unwrap();
+        let progress = |bytes, entries| Progress {
+            bytes,
+            total,
+            entries,
+        };
+        assert_eq!(
+            *reports,
+            [
+                progress(0, 0),
+                progress(1024, 1),
+                progress(2048, 2),
+                progress(total, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn cancel() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a", "b", "c"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+
+        let token = Arc::new(AtomicBool::new(false));
+        let options = TarOptions::new().cancel(token.clone()).progress({
+            let token = token.clone();
+            move |progress| token.store(progress.entries == 2, Ordering::Relaxed)
+        });
+        let err = TarFS::with_options(archive.clone(), options).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Cancelled {
+                index: 2,
+                offset: 2048
+            })
+        );
+
+        token.store(false, Ordering::Relaxed);
+        assert!(TarFS::with_options(archive, TarOptions::new().cancel(token)).is_ok());
+    }
+}
@@ -0,0 +1,153 @@
+//! A tar filesystem reloaded from its archive path, e.g. after the archive is replaced.
+
+use crate::{TarFS, TarOptions};
+use memmap2::{Mmap, MmapOptions};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+};
+use vfs::{error::VfsErrorKind, *};
+
+/// A readonly tar filesystem over a memory-mapped archive, which could be reloaded
+/// from the same path in place, created by [`ReloadableTarFS::new`].
+///
+/// The clones share the filesystem, so a clone kept aside could reload the one
+/// owned by a [`VfsPath`]. The files opened before reloading keep the old contents.
+#[derive(Debug, Clone)]
+pub struct ReloadableTarFS {
+    path: Arc<PathBuf>,
+    options: TarOptions,
+    fs: Arc<RwLock<Arc<TarFS<Mmap>>>>,
+}
+
+impl ReloadableTarFS {
+    /// Map the archive at the path.
+    pub fn new(path: impl AsRef<Path>) -> VfsResult<Self> {
+        Self::with_options(path, TarOptions::default())
+    }
+
+    /// Map the archive at the path with options, which are used for reloading too.
+    pub fn with_options(path: impl AsRef<Path>, options: TarOptions) -> VfsResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let fs = open(&path, options.clone())?;
+        Ok(Self {
+            path: Arc::new(path),
+            options,
+            fs: Arc::new(RwLock::new(Arc::new(fs))),
+        })
+    }
+
+    /// Map the archive at the path again and rebuild the filesystem.
+    ///
+    /// The archive should be replaced atomically, e.g. by renaming, rather than
+    /// written in place. If it fails, the current filesystem is kept.
+    pub fn reload(&self) -> VfsResult<()> {
+        let fs = open(&self.path, self.options.clone())?;
+        *self.fs.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(fs);
+        Ok(())
+    }
+
+    /// Get the path of the archive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the current filesystem.
+    pub fn current(&self) -> Arc<TarFS<Mmap>> {
+        self.fs
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+fn open(path: &Path, options: TarOptions) -> VfsResult<TarFS<Mmap>> {
+    // SAFETY: mmap with COW
+    let file = unsafe { MmapOptions::new().map_copy_read_only(&File::open(path)?) }?;
+    TarFS::with_options(file, options)
+}
+
+impl FileSystem for ReloadableTarFS {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        self.current().read_dir(path)
+    }
+
+    fn create_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        self.current().open_file(path)
+    }
+
+    fn create_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn append_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        self.current().metadata(path)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.current().exists(path)
+    }
+
+    fn remove_file(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ReloadableTarFS;
+    use vfs::VfsPath;
+
+    fn archive(name: &str) -> Vec<u8> {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, name, b"data".as_slice())
+            .unwrap();
+        archive.into_inner().unwrap()
+    }
+
+    #[test]
+    fn reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive.tar");
+        std::fs::write(&path, archive("old")).unwrap();
+
+        let fs = ReloadableTarFS::new(&path).unwrap();
+        let root = VfsPath::from(fs.clone());
+        let old = root.join("old").unwrap();
+        let mut file = old.open_file().unwrap();
+        assert!(old.exists().unwrap());
+
+        // Replace the archive atomically.
+        let temp = dir.path().join("archive.tar.tmp");
+        std::fs::write(&temp, archive("new")).unwrap();
+        std::fs::rename(&temp, &path).unwrap();
+        fs.reload().unwrap();
+        assert!(!old.exists().unwrap());
+        assert!(root.join("new").unwrap().exists().unwrap());
+
+        // The opened file keeps the old contents.
+        let mut buffer = String::new();
+        std::io::Read::read_to_string(&mut file, &mut buffer).unwrap();
+        assert_eq!(buffer, "data");
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(fs.reload().is_err());
+        assert!(root.join("new").unwrap().exists().unwrap());
+    }
+}
@@ -0,0 +1,592 @@
+//! A tar filesystem reading the archive lazily from a ranged source, e.g. over HTTP.
+
+use crate::{
+    parser::{typeflag::*, *},
+    sparse, DirTreeBuilder, EntryRef, TarFS, TarFsError, TarOptions,
+};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    sync::Arc,
+};
+use tar_parser2::{parse_entry_streaming, TarEntry, TypeFlag};
+use vfs::{error::VfsErrorKind, *};
+
+/// The files up to this length are read at once when opened,
+/// and the larger ones are read lazily in chunks of this length.
+const CHUNK_LEN: u64 = 1 << 20;
+
+/// The maximum length of a long name or a PAX header, which is read at once when building.
+const MAX_METADATA_LEN: u64 = 1 << 20;
+
+/// A source of an archive supporting reads of byte ranges.
+pub trait RangeSource: Debug + Send + Sync + 'static {
+    /// Read `len` bytes from `offset`. Fewer bytes are returned only at the end of the source.
+    fn read_range(&self, offset: u64, len: u64) -> io::Result<Vec<u8>>;
+}
+
+impl RangeSource for Vec<u8> {
+    fn read_range(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let start = offset.min(self.len() as u64) as usize;
+        let end = offset.saturating_add(len).min(self.len() as u64) as usize;
+        Ok(self[start..end].to_vec())
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl RangeSource for std::fs::File {
+    fn read_range(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        #[cfg(unix)]
+        use std::os::unix::fs::FileExt;
+        #[cfg(windows)]
+        use std::os::windows::fs::FileExt;
+
+        let mut buffer = vec![];
+        let end = offset.saturating_add(len);
+        let mut pos = offset;
+        while pos < end {
+            // The buffer grows by chunks, as the range may be beyond the end of the file.
+            let read = buffer.len();
+            buffer.resize(read + (end - pos).min(CHUNK_LEN) as usize, 0);
+            #[cfg(unix)]
+            let n = self.read_at(&mut buffer[read..], pos)?;
+            #[cfg(windows)]
+            let n = self.seek_read(&mut buffer[read..], pos)?;
+            buffer.truncate(read + n);
+            if n == 0 {
+                break;
+            }
+            pos += n as u64;
+        }
+        Ok(buffer)
+    }
+}
+
+/// A [`RangeSource`] over HTTP range requests.
+#[cfg(all(feature = "http", not(target_arch = "wasm32")))]
+#[derive(Debug, Clone)]
+pub struct HttpSource {
+    url: String,
+    agent: ureq::Agent,
+}
+
+#[cfg(all(feature = "http", not(target_arch = "wasm32")))]
+impl HttpSource {
+    /// Create [`HttpSource`] from the URL of the archive.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            agent: ureq::agent(),
+        }
+    }
+}
+
+#[cfg(all(feature = "http", not(target_arch = "wasm32")))]
+impl RangeSource for HttpSource {
+    fn read_range(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        if len == 0 {
+            return Ok(vec![]);
+        }
+        let last = offset
+            .checked_add(len - 1)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the range overflows"))?;
+        let response = match self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={offset}-{last}"))
+            .call()
+        {
+            Ok(response) => response,
+            // The range starts beyond the end.
+            Err(ureq::Error::Status(416, _)) => return Ok(vec![]),
+            Err(e) => return Err(io::Error::other(e.to_string())),
+        };
+        if response.status() != 206 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the server doesn't support range requests",
+            ));
+        }
+        // The length is untrusted, so the buffer grows with the response beyond a chunk.
+        let mut buffer = Vec::with_capacity(len.min(CHUNK_LEN) as usize);
+        response.into_reader().take(len).read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+/// A readonly tar archive filesystem over a [`RangeSource`].
+///
+/// Only the headers are read when building the filesystem,
+/// and the file contents are read when opening the files.
+/// The headers are read in chunks, and the contents of the small files between them are skipped.
+///
+/// The tree is built like [`TarFS`] with the same [`TarOptions`], except the ones
+/// about parsing a corrupt archive, like [`TarOptions::lenient`].
+/// The sparse files aren't expanded, and their stored data is read.
+///
+/// The offsets and the lengths are 64-bit, and the large files are read in chunks,
+/// so the archives larger than the address space, e.g. over 4 GiB on 32-bit targets,
+/// could be read with a [`std::fs::File`] source.
+#[derive(Debug)]
+pub struct RemoteTarFS<S: RangeSource> {
+    source: Arc<S>,
+    /// The headers and the metadata entries, with the files inserted empty.
+    fs: TarFS<Vec<u8>>,
+    /// The offsets and the lengths in the source of the files, by their offsets in `fs`.
+    files: HashMap<usize, (u64, u64)>,
+}
+
+impl<S: RangeSource> RemoteTarFS<S> {
+    /// Create [`RemoteTarFS`] by reading the headers from the source.
+    pub fn new(source: S) -> VfsResult<Self> {
+        Self::with_options(source, TarOptions::default())
+    }
+
+    /// Create [`RemoteTarFS`] by reading the headers from the source with options.
+    pub fn with_options(source: S, options: TarOptions) -> VfsResult<Self> {
+        let source = Arc::new(source);
+        let mut reader = RangeReader {
+            source: source.clone(),
+            offset: 0,
+            len: u64::MAX,
+            pos: 0,
+            chunk: (0, vec![]),
+        };
+        // The header blocks, followed by the contents of the metadata entries only.
+        let mut headers = vec![];
+        // The offsets of the entries in the source and in `headers`, and the lengths read.
+        let mut spans = vec![];
+        let mut files = HashMap::new();
+        let mut offset = 0;
+        let mut realsize = None;
+        loop {
+            let index = spans.len();
+            let mut block = [0; BLOCK_SIZE as usize];
+            // A zero block or the end of source ends the archive.
+            match reader.read_exact(&mut block) {
+                Ok(()) if !is_zero_block(&block) => {}
+                Err(e) if e.kind() != io::ErrorKind::UnexpectedEof => return Err(e.into()),
+                _ => break,
+            }
+            let header = parse_header(&block).ok_or(if verify_checksum(&block) {
+                TarFsError::InvalidHeader { index, offset }
+            } else {
+                TarFsError::Checksum { index, offset }
+            })?;
+            let start = headers.len();
+            headers.extend_from_slice(&block);
+            // The extension blocks of an old-style sparse entry are kept with the header.
+            let mut extended = header.typeflag == GNU_SPARSE && block[482] != 0;
+            while extended {
+                let mut block = [0; BLOCK_SIZE as usize];
+                reader.read_exact(&mut block).map_err(|e| match e.kind() {
+                    io::ErrorKind::UnexpectedEof => TarFsError::Truncated { index, offset }.into(),
+                    _ => VfsError::from(e),
+                })?;
+                extended = block[504] != 0;
+                headers.extend_from_slice(&block);
+            }
+            let data_offset = offset + (headers.len() - start) as u64;
+            let metadata = matches!(
+                header.typeflag,
+                GNU_LONGNAME | GNU_LONGLINK | PAX_HEADER | PAX_GLOBAL
+            );
+            let size = match header.typeflag {
+                PAX_GLOBAL | GNU_VOLUME_HEADER => header.size,
+                _ if metadata => header.size,
+                _ => realsize.take().unwrap_or(header.size),
+            };
+            // The vendor hook decides by the contents, so they're read like the metadata.
+            if metadata || options.vendor_hook.is_some() && is_vendor_specific(&block) {
+                if size > MAX_METADATA_LEN {
+                    return Err(TarFsError::Inconsistent {
+                        index,
+                        offset,
+                        reason: "metadata entry too large",
+                    }
+                    .into());
+                }
+                let mut data = vec![];
+                (&mut reader).take(size).read_to_end(&mut data)?;
+                // The size of the next entry is needed to skip its contents.
+                if header.typeflag == PAX_HEADER {
+                    for (key, value) in parse_pax_records(&data).unwrap_or_default() {
+                        if key == "size" {
+                            realsize = std::str::from_utf8(value).ok().and_then(|v| v.parse().ok());
+                        }
+                    }
+                }
+                spans.push((offset, start, data.len()));
+                headers.extend_from_slice(&data);
+                headers.resize(align_block(headers.len() as u64) as usize, 0);
+            } else {
+                if size > 0 {
+                    files.insert(headers.len(), (data_offset, size));
+                }
+                spans.push((offset, start, 0));
+            }
+            offset = data_offset
+                .checked_add(align_block(size))
+                .ok_or(TarFsError::Truncated { index, offset })?;
+            reader.seek(SeekFrom::Start(offset))?;
+        }
+        let built = {
+            let entries = spans
+                .iter()
+                .enumerate()
+                .map(|(index, &(offset, start, len))| {
+                    let contents = sparse::extension_end(&headers, start)
+                        .ok_or(TarFsError::InvalidHeader { index, offset })?;
+                    match parse_entry_streaming(&headers[start..contents]) {
+                        Ok((_, Some(entry))) => Ok(TarEntry {
+                            header: entry.header,
+                            contents: &headers[contents..contents + len],
+                        }),
+                        _ => Err(TarFsError::InvalidHeader { index, offset }),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut builder = DirTreeBuilder::new(&headers, options.clone());
+            builder.headers_only = true;
+            builder.build(&entries)?
+        };
+        Ok(Self {
+            source,
+            fs: TarFS::from_built(headers, built, &options),
+            files,
+        })
+    }
+
+    /// Get the reference of the source.
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// The offset and the length in the source of a file, if its contents aren't read yet.
+    fn find_file(&self, path: &str) -> Option<(u64, u64)> {
+        match self.fs.find_entry(path)? {
+            EntryRef::File(buf, _) => self.files.get(&self.fs.archive_offset(buf)).copied(),
+            _ => None,
+        }
+    }
+}
+
+impl<S: RangeSource> FileSystem for RemoteTarFS<S> {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        self.fs.read_dir(path)
+    }
+
+    fn create_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        let file: Box<dyn SeekAndRead + Send> = match self.find_file(path) {
+            Some((offset, len)) if len <= CHUNK_LEN => {
+                Box::new(Cursor::new(self.source.read_range(offset, len)?))
+            }
+            Some((offset, len)) => Box::new(RangeReader {
+                source: self.source.clone(),
+                offset,
+                len,
+                pos: 0,
+                chunk: (0, vec![]),
+            }),
+            // The empty files and the ones read with the metadata.
+            None => return self.fs.open_file(path),
+        };
+        Ok(self.fs.track_access(path, file))
+    }
+
+    fn create_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn append_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        let mut metadata = self.fs.metadata(path)?;
+        if let Some((_, len)) = self.find_file(path) {
+            metadata.len = len;
+        }
+        Ok(metadata)
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        self.fs.exists(path)
+    }
+
+    fn remove_file(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+}
+
+/// Whether the header is of a vendor-specific type, which is passed to [`TarOptions::vendor_hook`].
+fn is_vendor_specific(block: &[u8]) -> bool {
+    matches!(
+        parse_entry_streaming(block),
+        Ok((_, Some(entry))) if matches!(entry.header.typeflag, TypeFlag::VendorSpecific(_))
+    )
+}
+
+/// A reader of a large file in the source, reading a chunk at a time.
+#[derive(Debug)]
+struct RangeReader<S> {
+    source: Arc<S>,
+    offset: u64,
+    len: u64,
+    pos: u64,
+    /// The position in the file and the data of the last chunk.
+    chunk: (u64, Vec<u8>),
+}
+
+impl<S: RangeSource> Read for RangeReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len {
+            return Ok(0);
+        }
+        let (start, data) = &self.chunk;
+        if self.pos < *start || self.pos >= start + data.len() as u64 {
+            let len = CHUNK_LEN.min(self.len - self.pos);
+            let data = self.source.read_range(self.offset + self.pos, len)?;
+            // The source ends early.
+            if data.is_empty() {
+                return Ok(0);
+            }
+            self.chunk = (self.pos, data);
+        }
+        let (start, data) = &self.chunk;
+        let from = (self.pos - start) as usize;
+        let n = buf.len().min(data.len() - from);
+        buf[..n].copy_from_slice(&data[from..from + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<S> Seek for RangeReader<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(n) => (self.len, n),
+            SeekFrom::Current(n) => (self.pos, n),
+        };
+        match base.checked_add_signed(offset) {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{test_util::ArchiveBuilder, RangeSource, RemoteTarFS, TarFsError, TarOptions};
+    use std::{
+        io::{self, Read, Seek, SeekFrom},
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, UNIX_EPOCH},
+    };
+    use vfs::FileSystem;
+
+    #[derive(Debug)]
+    struct MemorySource {
+        data: Vec<u8>,
+        requests: AtomicUsize,
+    }
+
+    impl RangeSource for MemorySource {
+        fn read_range(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            let start = (offset as usize).min(self.data.len());
+            let end = ((offset + len) as usize).min(self.data.len());
+            Ok(self.data[start..end].to_vec())
+        }
+    }
+
+    #[test]
+    fn remote() {
+        let name = format!("dir/{}", "a".repeat(200));
+        let mut archive = tar::Builder::new(vec![]);
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(5);
+            archive
+                .append_data(&mut header, &name, b"hello".as_slice())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, "link", "dir").unwrap();
+        }
+        let source = MemorySource {
+            data: archive.into_inner().unwrap(),
+            requests: AtomicUsize::new(0),
+        };
+
+        let fs = RemoteTarFS::new(source).unwrap();
+        let requests = || fs.source().requests.load(Ordering::Relaxed);
+        let built = requests();
+
+        let path = format!("/link/{}", "a".repeat(200));
+        assert_eq!(fs.metadata(&path).unwrap().len, 5);
+        assert_eq!(fs.read_dir("/dir").unwrap().count(), 1);
+        assert_eq!(requests(), built);
+
+        // Only the opened file is read lazily.
+        let mut buffer = String::new();
+        fs.open_file(&path)
+            .unwrap()
+            .read_to_string(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, "hello");
+        assert_eq!(requests(), built + 1);
+    }
+
+    #[test]
+    fn options() {
+        let mut archive = tar::Builder::new(vec![]);
+        {
+            let pax = b"20 mtime=1234567890\n";
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(tar::EntryType::XGlobalHeader);
+            header.set_size(pax.len() as u64);
+            archive
+                .append_data(&mut header, "global", pax.as_slice())
+                .unwrap();
+        }
+        for i in 0..100 {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(5);
+            archive
+                .append_data(&mut header, format!("top/{i}"), b"hello".as_slice())
+                .unwrap();
+        }
+        let source = MemorySource {
+            data: archive.into_inner().unwrap(),
+            requests: AtomicUsize::new(0),
+        };
+
+        let fs = RemoteTarFS::with_options(source, TarOptions::new().strip_components(1)).unwrap();
+        // The headers are read in a chunk.
+        assert_eq!(fs.source().requests.load(Ordering::Relaxed), 1);
+        assert_eq!(fs.read_dir("/").unwrap().count(), 100);
+        let metadata = fs.metadata("/42").unwrap();
+        assert_eq!(metadata.len, 5);
+        assert_eq!(
+            metadata.modified,
+            Some(UNIX_EPOCH + Duration::from_secs(1234567890))
+        );
+        let mut buffer = String::new();
+        fs.open_file("/42")
+            .unwrap()
+            .read_to_string(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, "hello");
+    }
+
+    #[test]
+    fn huge_sizes() {
+        let archive = |ty, size| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(ty);
+            header.set_size(size);
+            header.set_path("a").unwrap();
+            header.set_cksum();
+            let mut data = header.as_bytes().to_vec();
+            data.resize(2048, 0);
+            data
+        };
+        let err = RemoteTarFS::new(archive(tar::EntryType::XHeader, 1 << 40)).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Inconsistent {
+                index: 0,
+                offset: 0,
+                reason: "metadata entry too large"
+            })
+        );
+        let err = RemoteTarFS::new(archive(tar::EntryType::Regular, u64::MAX)).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Truncated {
+                index: 0,
+                offset: 0
+            })
+        );
+    }
+
+    #[test]
+    fn sparse() {
+        let segments = [b"a", b"b", b"c", b"d", b"e", b"f"];
+        let segments = (0..6)
+            .map(|i| (i * 1000, segments[i as usize].as_slice()))
+            .collect::<Vec<_>>();
+        let archive = ArchiveBuilder::new()
+            .old_sparse("old.bin", 6000, &segments)
+            .sparse("pax.bin", 3000, &[(0, b"head"), (2000, b"tail")])
+            .file("next", b"data")
+            .finish();
+        let fs = RemoteTarFS::new(archive).unwrap();
+
+        // The sparse files aren't expanded, and their stored data is read.
+        let read = |path| {
+            let mut buffer = vec![];
+            fs.open_file(path)
+                .unwrap()
+                .read_to_end(&mut buffer)
+                .unwrap();
+            buffer
+        };
+        assert_eq!(read("/old.bin"), b"abcdef");
+        assert!(read("/pax.bin").ends_with(b"headtail"));
+        assert_eq!(read("/next"), b"data");
+    }
+
+    #[test]
+    fn chunks() {
+        let data = (0..3 << 20).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        archive
+            .append_data(&mut header, "large", data.as_slice())
+            .unwrap();
+        let source = MemorySource {
+            data: archive.into_inner().unwrap(),
+            requests: AtomicUsize::new(0),
+        };
+        let fs = RemoteTarFS::new(source).unwrap();
+        let requests = || fs.source().requests.load(Ordering::Relaxed);
+        let built = requests();
+
+        // The large file is read in chunks.
+        let mut file = fs.open_file("/large").unwrap();
+        assert_eq!(requests(), built);
+        let mut buffer = vec![];
+        file.read_to_end(&mut buffer).unwrap();
+        assert_eq!(buffer, data);
+        assert_eq!(requests(), built + 3);
+
+        file.seek(SeekFrom::End(-10)).unwrap();
+        let mut buffer = [0; 10];
+        file.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, data[data.len() - 10..]);
+    }
+}
@@ -0,0 +1,133 @@
+//! Redirecting the symbolic links leaving the archive into another filesystem.
+
+use crate::{normalize_path, strip_path, EntryRef, TarFS};
+use std::{
+    fmt::Debug,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use vfs::VfsPath;
+
+type ResolverFn = dyn Fn(&str, &str) -> Option<VfsPath> + Send + Sync;
+
+/// A resolver of the symbolic links leaving the archive.
+#[derive(Clone)]
+pub(crate) struct LinkResolver(Arc<ResolverFn>);
+
+impl LinkResolver {
+    pub fn new(resolver: impl Fn(&str, &str) -> Option<VfsPath> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(resolver))
+    }
+
+    /// Call the resolver with the path of the link in the archive and its target.
+    pub fn resolve(&self, link: &Path, target: &str) -> Option<VfsPath> {
+        (self.0)(&link.to_string_lossy(), target)
+    }
+}
+
+impl Debug for LinkResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("LinkResolver")
+    }
+}
+
+/// Whether the target of the link at the path is absolute, or escapes the root with `..`.
+pub(crate) fn escapes(link: &Path, target: &str) -> bool {
+    if target.starts_with('/') {
+        return true;
+    }
+    // The depth of the directory containing the link.
+    let mut depth = link.iter().count().saturating_sub(1);
+    for c in Path::new(target).iter() {
+        if c == ".." {
+            if depth == 0 {
+                return true;
+            }
+            depth -= 1;
+        } else if c != "." {
+            depth += 1;
+        }
+    }
+    false
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Resolve a path leaving the archive through a link, with the resolver.
+    ///
+    /// It's called only when the lookup in the archive fails,
+    /// as such links are treated as broken by [`TarFS::find_entry`].
+    pub(crate) fn resolve_external(&self, path: &str) -> Option<VfsPath> {
+        let resolver = self.link_resolver.as_ref()?;
+        let mut path = normalize_path(strip_path(path)).into_owned();
+        for _ in 0..self.max_links {
+            let mut components = path.iter().filter_map(|c| c.to_str());
+            let Some(EntryRef::Link(target)) = self.find_entry_impl(&self.root, &mut components)
+            else {
+                return None;
+            };
+            let rest = components.collect::<PathBuf>();
+            let depth = path.iter().count() - rest.iter().count();
+            let link = path.iter().take(depth).collect::<PathBuf>();
+            if escapes(&link, target) {
+                if let Some(base) = resolver.resolve(&link, target) {
+                    return match rest.to_str()? {
+                        "" => Some(base),
+                        rest => base.join(rest).ok(),
+                    };
+                }
+            }
+            let target = self.read_link(link.into(), target)?;
+            path = normalize_path(&target.join(rest)).into_owned();
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{TarFS, TarOptions};
+    use std::io::{Read, Write};
+    use vfs::{FileSystem, MemoryFS, VfsPath};
+
+    #[test]
+    fn link_resolver() {
+        let host = VfsPath::new(MemoryFS::new());
+        host.join("usr/lib").unwrap().create_dir_all().unwrap();
+        write!(
+            host.join("usr/lib/libc.so").unwrap().create_file().unwrap(),
+            "libc"
+        )
+        .unwrap();
+
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, target) in [("lib", "/usr/lib"), ("up", "../usr"), ("inner", "lib")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive.append_link(&mut header, name, target).unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+        let options = TarOptions::new().link_resolver(move |link, target| {
+            assert!(link == "lib" || link == "up");
+            host.join(target.trim_start_matches("../").trim_start_matches('/'))
+                .ok()
+        });
+        let fs = TarFS::with_options(archive, options).unwrap();
+
+        for path in ["/lib/libc.so", "/up/lib/libc.so", "/inner/libc.so"] {
+            let mut buffer = String::new();
+            fs.open_file(path)
+                .unwrap()
+                .read_to_string(&mut buffer)
+                .unwrap();
+            assert_eq!(buffer, "libc");
+        }
+        assert!(fs.exists("/lib").unwrap());
+        assert_eq!(
+            fs.read_dir("/lib").unwrap().collect::<Vec<_>>(),
+            ["libc.so"]
+        );
+        assert_eq!(fs.metadata("/lib/libc.so").unwrap().len, 4);
+        assert!(!fs.exists("/lib/missing").unwrap());
+    }
+}
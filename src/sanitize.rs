@@ -0,0 +1,82 @@
+//! Sanitizing the entry names which could break the terminals and the logs.
+
+/// The policy of the entry names with control characters, like newlines and escape sequences,
+/// or the bidirectional overrides which reorder the text in terminals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NamePolicy {
+    /// Keep the names as they are.
+    #[default]
+    PassThrough,
+    /// Escape the characters like [`char::escape_default`], e.g. a newline as `\n`.
+    /// The raw names are yielded by [`TarFS::raw_entries`](crate::TarFS::raw_entries).
+    Escape,
+    /// Fail to build the filesystem.
+    Reject,
+}
+
+/// Whether the character could break the terminals and the logs.
+pub(crate) fn is_unsafe(c: char) -> bool {
+    c.is_control() || matches!(c, '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Escape the unsafe characters of a name.
+pub(crate) fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if is_unsafe(c) {
+            escaped.extend(c.escape_default());
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{NamePolicy, TarFS, TarFsError, TarOptions};
+    use vfs::FileSystem;
+
+    #[test]
+    fn sanitize_names() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["safe.txt", "evil\n\u{1b}[31m.txt", "dir/\u{202e}txt.exe"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            header.set_path(name).unwrap();
+            header.set_cksum();
+            archive.append(&header, b"data".as_slice()).unwrap();
+        }
+        let buf = archive.into_inner().unwrap();
+
+        let fs = TarFS::new(buf.clone()).unwrap();
+        assert!(fs.exists("/evil\n\u{1b}[31m.txt").unwrap());
+
+        let options = TarOptions::new().sanitize_names(NamePolicy::Escape);
+        let fs = TarFS::with_options(buf.clone(), options).unwrap();
+        assert!(fs.exists("/safe.txt").unwrap());
+        assert!(fs.exists("/evil\\n\\u{1b}[31m.txt").unwrap());
+        assert!(fs.exists("/dir/\\u{202e}txt.exe").unwrap());
+        let names = fs.entries().map(|(path, ..)| path).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["safe.txt", "evil\\n\\u{1b}[31m.txt", "dir/\\u{202e}txt.exe"]
+        );
+        let raw = fs.raw_entries().map(|(path, ..)| path).collect::<Vec<_>>();
+        assert_eq!(
+            raw,
+            ["safe.txt", "evil\n\u{1b}[31m.txt", "dir/\u{202e}txt.exe"]
+        );
+
+        let options = TarOptions::new().sanitize_names(NamePolicy::Reject);
+        let err = TarFS::with_options(buf, options).unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::UnsafeName {
+                index: 1,
+                offset: 1024,
+                path: "evil\n\u{1b}[31m.txt".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Searching the file contents, like `grep -l`.
+
+use crate::TarFS;
+use std::ops::Deref;
+use vfs::VfsResult;
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Find the files whose contents match the predicate, called with the path and the contents.
+    ///
+    /// The paths are absolute like [`TarFS::walk`], and sorted.
+    /// The sparse files are expanded, and the device nodes and FIFOs are skipped.
+    pub fn search(&self, pred: impl Fn(&str, &[u8]) -> bool) -> VfsResult<Vec<String>> {
+        let mut paths = self
+            .file_paths()?
+            .into_iter()
+            .filter(|path| {
+                self.find_entry(path)
+                    .and_then(|entry| entry.contents())
+                    .is_some_and(|contents| pred(path, &contents))
+            })
+            .collect::<Vec<_>>();
+        paths.sort_unstable();
+        Ok(paths)
+    }
+
+    /// Find the files whose contents match the predicate in parallel, like [`TarFS::search`].
+    #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+    pub fn par_search(
+        &self,
+        pred: impl Fn(&str, &[u8]) -> bool + Send + Sync,
+    ) -> VfsResult<Vec<String>>
+    where
+        F: Send + Sync,
+    {
+        use rayon::prelude::*;
+
+        let mut paths = self
+            .file_paths()?
+            .into_par_iter()
+            .filter(|path| {
+                self.find_entry(path)
+                    .and_then(|entry| entry.contents())
+                    .is_some_and(|contents| pred(path, &contents))
+            })
+            .collect::<Vec<_>>();
+        paths.par_sort_unstable();
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+
+    #[test]
+    fn search() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in [
+            ("etc/passwd", "root:x:0:0"),
+            ("etc/hosts", "127.0.0.1 localhost"),
+            ("home/root/.profile", "export PATH"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let contains = |needle: &'static [u8]| {
+            move |_: &str, contents: &[u8]| contents.windows(needle.len()).any(|w| w == needle)
+        };
+        assert_eq!(fs.search(contains(b"root")).unwrap(), ["/etc/passwd"]);
+        assert_eq!(
+            fs.search(|path, _| path.starts_with("/etc/")).unwrap(),
+            ["/etc/hosts", "/etc/passwd"]
+        );
+        assert!(fs.search(contains(b"missing")).unwrap().is_empty());
+        #[cfg(all(feature = "rayon", not(target_arch = "wasm32")))]
+        assert_eq!(
+            fs.par_search(contains(b"o")).unwrap(),
+            fs.search(contains(b"o")).unwrap()
+        );
+    }
+}
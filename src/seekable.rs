@@ -0,0 +1,384 @@
+//! Random access into seekable compressed archives, with the `bgzf` or `zstd` feature.
+
+use crate::RangeSource;
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// The maximum length of the decompressed data of a BGZF block.
+const BGZF_MAX_BLOCK: u64 = 65536;
+
+/// A compressed frame, which could be decompressed independently.
+#[derive(Debug, Clone)]
+struct Frame {
+    /// The offset and the size in the compressed source.
+    offset: u64,
+    size: u64,
+    /// The offset and the length of the decompressed data.
+    start: u64,
+    len: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    #[cfg(feature = "bgzf")]
+    Bgzf,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// A [`RangeSource`] of the decompressed archive over a seekable compressed source,
+/// e.g. a `.tar.gz` in BGZF, or a `.tar.zst` in the zstd seekable format.
+///
+/// The frames are indexed once when creating the source, and only the frames covering
+/// the requested ranges are decompressed. The recently used frames are cached, so that
+/// the hot files aren't decompressed again. Use it with [`RemoteTarFS`](crate::RemoteTarFS).
+#[derive(Debug)]
+pub struct SeekableSource<S: RangeSource> {
+    source: S,
+    format: Format,
+    frames: Vec<Frame>,
+    /// The maximum count of the cached frames.
+    capacity: usize,
+    /// The decompressed frames, the most recently used first.
+    cache: Mutex<VecDeque<(usize, Arc<Vec<u8>>)>>,
+}
+
+impl<S: RangeSource> SeekableSource<S> {
+    fn with_frames(source: S, format: Format, frames: Vec<Frame>) -> Self {
+        Self {
+            source,
+            format,
+            frames,
+            capacity: 16,
+            cache: Mutex::default(),
+        }
+    }
+
+    /// Set the maximum count of the decompressed frames cached, 16 by default.
+    /// At least one frame is cached, as the headers and the files are usually read in order.
+    pub fn cache_frames(mut self, count: usize) -> Self {
+        self.capacity = count.max(1);
+        self
+    }
+
+    /// Create [`SeekableSource`] over a BGZF source, by reading the headers of all blocks.
+    #[cfg(feature = "bgzf")]
+    pub fn bgzf(source: S) -> io::Result<Self> {
+        let mut frames = vec![];
+        let (mut offset, mut start) = (0, 0);
+        loop {
+            let header = source.read_range(offset, 12)?;
+            if header.is_empty() {
+                break;
+            }
+            if header.len() < 12 || header[..4] != [0x1f, 0x8b, 8, 4] {
+                return Err(invalid_data("not a BGZF block"));
+            }
+            let xlen = u16::from_le_bytes([header[10], header[11]]) as u64;
+            let extra = source.read_range(offset + 12, xlen)?;
+            let size = bgzf_block_size(&extra)
+                .filter(|size| *size >= 12 + xlen + 8)
+                .ok_or_else(|| invalid_data("not a BGZF block"))?;
+            let footer = source.read_range(offset + size - 4, 4)?;
+            let len = u32::from_le_bytes(
+                footer
+                    .try_into()
+                    .map_err(|_| invalid_data("truncated BGZF block"))?,
+            ) as u64;
+            if len > BGZF_MAX_BLOCK {
+                return Err(invalid_data("the BGZF block is too large"));
+            }
+            // The empty block marks the end of file.
+            if len > 0 {
+                frames.push(Frame {
+                    offset,
+                    size,
+                    start,
+                    len,
+                });
+            }
+            offset += size;
+            start += len;
+        }
+        Ok(Self::with_frames(source, Format::Bgzf, frames))
+    }
+
+    /// Create [`SeekableSource`] over a source in the zstd seekable format,
+    /// by reading the seek table at the end. The length of the source is required to locate it.
+    #[cfg(feature = "zstd")]
+    pub fn zstd(source: S, len: u64) -> io::Result<Self> {
+        const SEEKABLE_MAGIC: u32 = 0x8F92EAB1;
+
+        let footer = source.read_range(len.saturating_sub(9), 9)?;
+        if footer.len() < 9 || footer[5..] != SEEKABLE_MAGIC.to_le_bytes() {
+            return Err(invalid_data("no zstd seek table"));
+        }
+        let count = u32::from_le_bytes(footer[..4].try_into().unwrap()) as u64;
+        let entry_size = if footer[4] & 0x80 != 0 { 12 } else { 8 };
+        let table_len = count * entry_size;
+        let table = source.read_range(len.saturating_sub(9 + table_len), table_len)?;
+        if table.len() as u64 != table_len {
+            return Err(invalid_data("truncated zstd seek table"));
+        }
+        let mut frames = vec![];
+        let (mut offset, mut start) = (0, 0);
+        for entry in table.chunks_exact(entry_size as usize) {
+            let size = u32::from_le_bytes(entry[..4].try_into().unwrap()) as u64;
+            let len = u32::from_le_bytes(entry[4..8].try_into().unwrap()) as u64;
+            if len > 0 {
+                frames.push(Frame {
+                    offset,
+                    size,
+                    start,
+                    len,
+                });
+            }
+            offset += size;
+            start += len;
+        }
+        Ok(Self::with_frames(source, Format::Zstd, frames))
+    }
+
+    /// Get the reference of the compressed source.
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    /// The length of the decompressed data.
+    pub fn len(&self) -> u64 {
+        self.frames.last().map(|f| f.start + f.len).unwrap_or(0)
+    }
+
+    /// Whether the decompressed data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    fn frame(&self, index: usize) -> io::Result<Arc<Vec<u8>>> {
+        if let Some(data) = self.cached(index) {
+            return Ok(data);
+        }
+        // Don't hold the lock while reading and decompressing, as the other frames
+        // could be served meanwhile.
+        let data = Arc::new(self.decompress(&self.frames[index])?);
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        // The frame may have been decompressed by another thread.
+        if let Some(pos) = cache.iter().position(|(i, _)| *i == index) {
+            cache.remove(pos);
+        }
+        cache.truncate(self.capacity - 1);
+        cache.push_front((index, data.clone()));
+        Ok(data)
+    }
+
+    /// Get a cached frame, and move it to the front.
+    fn cached(&self, index: usize) -> Option<Arc<Vec<u8>>> {
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        let pos = cache.iter().position(|(i, _)| *i == index)?;
+        let cached = cache.remove(pos)?;
+        let data = cached.1.clone();
+        cache.push_front(cached);
+        Some(data)
+    }
+
+    fn decompress(&self, frame: &Frame) -> io::Result<Vec<u8>> {
+        use std::io::Read;
+
+        let compressed = self.source.read_range(frame.offset, frame.size)?;
+        // The length is untrusted, so the output is limited to it, and it's only
+        // reserved up to the maximum BGZF block.
+        let mut data = Vec::with_capacity(frame.len.min(BGZF_MAX_BLOCK) as usize);
+        match self.format {
+            #[cfg(feature = "bgzf")]
+            Format::Bgzf => flate2::read::GzDecoder::new(compressed.as_slice())
+                .take(frame.len + 1)
+                .read_to_end(&mut data)?,
+            #[cfg(feature = "zstd")]
+            Format::Zstd => zstd::stream::read::Decoder::new(compressed.as_slice())?
+                .take(frame.len + 1)
+                .read_to_end(&mut data)?,
+        };
+        if data.len() as u64 != frame.len {
+            return Err(invalid_data("the frame length doesn't match the index"));
+        }
+        Ok(data)
+    }
+}
+
+impl<S: RangeSource> RangeSource for SeekableSource<S> {
+    fn read_range(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let end = offset.saturating_add(len).min(self.len());
+        let mut buffer = Vec::with_capacity(end.saturating_sub(offset) as usize);
+        let first = self.frames.partition_point(|f| f.start + f.len <= offset);
+        for (index, frame) in self.frames.iter().enumerate().skip(first) {
+            if frame.start >= end {
+                break;
+            }
+            let data = self.frame(index)?;
+            let from = offset.saturating_sub(frame.start) as usize;
+            let to = (end - frame.start).min(frame.len) as usize;
+            buffer.extend_from_slice(&data[from..to]);
+        }
+        Ok(buffer)
+    }
+}
+
+/// The total size of a BGZF block, from the `BC` subfield of the extra field.
+#[cfg(feature = "bgzf")]
+fn bgzf_block_size(mut extra: &[u8]) -> Option<u64> {
+    while extra.len() >= 4 {
+        let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        let data = extra.get(4..4 + len)?;
+        if extra[..2] == *b"BC" && len == 2 {
+            return Some(u16::from_le_bytes([data[0], data[1]]) as u64 + 1);
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{RemoteTarFS, SeekableSource};
+    use std::io::Read;
+    use vfs::FileSystem;
+
+    fn archive() -> Vec<u8> {
+        let mut archive = tar::Builder::new(vec![]);
+        for (i, name) in ["a.txt", "b.txt", "c.txt"].into_iter().enumerate() {
+            let data = vec![b'a' + i as u8; 100000];
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_slice())
+                .unwrap();
+        }
+        archive.into_inner().unwrap()
+    }
+
+    fn check(fs: RemoteTarFS<impl crate::RangeSource>) {
+        let mut buffer = vec![];
+        fs.open_file("/b.txt")
+            .unwrap()
+            .read_to_end(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, vec![b'b'; 100000]);
+        assert_eq!(fs.metadata("/c.txt").unwrap().len, 100000);
+    }
+
+    #[test]
+    #[cfg(feature = "bgzf")]
+    fn bgzf() {
+        use flate2::{write::DeflateEncoder, Compression, Crc};
+        use std::io::Write;
+
+        let mut compressed = vec![];
+        for chunk in archive().chunks(65280).chain([[].as_slice()]) {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+            encoder.write_all(chunk).unwrap();
+            let data = encoder.finish().unwrap();
+            let mut crc = Crc::new();
+            crc.update(chunk);
+            let size = (18 + data.len() + 8 - 1) as u16;
+            compressed.extend([
+                0x1f, 0x8b, 8, 4, 0, 0, 0, 0, 0, 0xff, 6, 0, b'B', b'C', 2, 0,
+            ]);
+            compressed.extend(size.to_le_bytes());
+            compressed.extend(data);
+            compressed.extend(crc.sum().to_le_bytes());
+            compressed.extend((chunk.len() as u32).to_le_bytes());
+        }
+
+        let source = SeekableSource::bgzf(compressed).unwrap();
+        assert_eq!(source.len(), archive().len() as u64);
+        check(RemoteTarFS::new(source).unwrap());
+    }
+
+    #[cfg(feature = "zstd")]
+    fn zstd_archive() -> Vec<u8> {
+        let mut compressed = vec![];
+        let mut table = vec![];
+        let chunks = archive().chunks(65536).map(Vec::from).collect::<Vec<_>>();
+        for chunk in &chunks {
+            let data = zstd::bulk::compress(chunk, 0).unwrap();
+            table.extend((data.len() as u32).to_le_bytes());
+            table.extend((chunk.len() as u32).to_le_bytes());
+            compressed.extend(data);
+        }
+        compressed.extend(0x184D2A5Eu32.to_le_bytes());
+        compressed.extend((table.len() as u32 + 9).to_le_bytes());
+        compressed.extend(table);
+        compressed.extend((chunks.len() as u32).to_le_bytes());
+        compressed.push(0);
+        compressed.extend(0x8F92EAB1u32.to_le_bytes());
+        compressed
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn zstd() {
+        let compressed = zstd_archive();
+        let len = compressed.len() as u64;
+        let source = SeekableSource::zstd(compressed, len).unwrap();
+        check(RemoteTarFS::new(source).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn untrusted_frame_len() {
+        use crate::RangeSource;
+
+        // The first frame claims to be 4 GiB long in the seek table.
+        let mut compressed = zstd_archive();
+        let len = compressed.len();
+        let count = u32::from_le_bytes(compressed[len - 9..len - 5].try_into().unwrap());
+        let entry = len - 9 - count as usize * 8;
+        compressed[entry + 4..entry + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let source = SeekableSource::zstd(compressed, len as u64).unwrap();
+        assert!(source.read_range(0, 1).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn cache_frames() {
+        use crate::RangeSource;
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        /// Count the reads of the compressed frames.
+        #[derive(Debug)]
+        struct Counted(Vec<u8>, Arc<AtomicUsize>);
+
+        impl RangeSource for Counted {
+            fn read_range(&self, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+                self.1.fetch_add(1, Ordering::Relaxed);
+                self.0.read_range(offset, len)
+            }
+        }
+
+        let compressed = zstd_archive();
+        let len = compressed.len() as u64;
+        let counter = Arc::new(AtomicUsize::new(0));
+        for (count, reads) in [(1, 4), (4, 2)] {
+            let counted = Counted(compressed.clone(), counter.clone());
+            let source = SeekableSource::zstd(counted, len)
+                .unwrap()
+                .cache_frames(count);
+            // The first and the last frames.
+            for _ in 0..2 {
+                source.read_range(0, 1).unwrap();
+                source.read_range(source.len() - 1, 1).unwrap();
+            }
+            assert_eq!(counter.swap(0, Ordering::Relaxed), 2 + reads);
+        }
+    }
+}
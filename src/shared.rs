@@ -0,0 +1,70 @@
+//! Constructors from the shared buffer types of network services.
+
+use crate::TarFS;
+#[cfg(feature = "bytes")]
+use std::ops::Deref;
+use std::sync::Arc;
+use vfs::VfsResult;
+
+impl TarFS<Arc<[u8]>> {
+    /// Create [`TarFS`] from a shared buffer, without copying it.
+    pub fn from_arc(buf: Arc<[u8]>) -> VfsResult<Self> {
+        Self::new(buf)
+    }
+}
+
+/// A [`bytes::Bytes`] backing of [`TarFS`], created by [`TarFS::from_bytes`].
+#[cfg(feature = "bytes")]
+#[derive(Debug, Clone)]
+pub struct TarBytes(bytes::Bytes);
+
+#[cfg(feature = "bytes")]
+impl TarBytes {
+    /// Get the inner [`bytes::Bytes`].
+    pub fn into_inner(self) -> bytes::Bytes {
+        self.0
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Deref for TarBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl TarFS<TarBytes> {
+    /// Create [`TarFS`] from [`bytes::Bytes`], without copying it.
+    pub fn from_bytes(buf: bytes::Bytes) -> VfsResult<Self> {
+        Self::new(TarBytes(buf))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use vfs::FileSystem;
+
+    #[test]
+    fn shared() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        archive
+            .append_data(&mut header, "a.txt", b"hello".as_slice())
+            .unwrap();
+        let buffer = archive.into_inner().unwrap();
+
+        let fs = TarFS::from_arc(buffer.clone().into()).unwrap();
+        assert_eq!(fs.metadata("/a.txt").unwrap().len, 5);
+
+        #[cfg(feature = "bytes")]
+        {
+            let fs = TarFS::from_bytes(buffer.into()).unwrap();
+            assert_eq!(fs.metadata("/a.txt").unwrap().len, 5);
+        }
+    }
+}
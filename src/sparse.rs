@@ -0,0 +1,456 @@
+//! Sparse files, whose holes aren't stored in the archive, in the GNU PAX 1.0 format
+//! and the old format of star and GNU tar, typeflag `S`.
+
+use crate::{
+    lenient::block_at,
+    offset_of,
+    parser::{align_block, parse_numeric, typeflag, verify_checksum, BLOCK_SIZE},
+    Attrs, EntryRef, TarFS,
+};
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    ops::{Deref, Range},
+    str::from_utf8,
+};
+use vfs::VfsResult;
+
+/// A sparse file, with the data segments stored contiguously in the archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SparseFile {
+    /// The byte range of the stored data in the archive.
+    pub data: Range<usize>,
+    /// The offsets and the lengths of the data segments in the file.
+    pub map: Vec<(u64, u64)>,
+    /// The length of the file, including the holes.
+    pub size: u64,
+    pub attrs: Attrs,
+}
+
+/// The real size of the next entry, if it is a sparse file in the PAX 1.0 format.
+/// The real name is stored in `GNU.sparse.name`.
+pub(crate) fn from_pax<'a>(records: &[(&'a str, &'a [u8])]) -> Option<(u64, Option<&'a str>)> {
+    let get = |key| {
+        records
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, v)| from_utf8(v).ok())
+    };
+    if get("GNU.sparse.major")? != "1" {
+        return None;
+    }
+    let size = get("GNU.sparse.realsize")?.parse().ok()?;
+    Some((size, get("GNU.sparse.name")))
+}
+
+/// The real size of the next entry from `SCHILY.realsize`, written by star
+/// for the old-style sparse files too large for the header field.
+pub(crate) fn schily_realsize(records: &[(&str, &[u8])]) -> Option<u64> {
+    let (_, size) = records
+        .iter()
+        .rev()
+        .find(|(k, _)| *k == "SCHILY.realsize")?;
+    from_utf8(size).ok()?.parse().ok()
+}
+
+/// Read the segments of an old-style sparse map, 24 bytes each. An empty one ends the map.
+fn read_segments(data: &[u8], map: &mut Vec<(u64, u64)>) -> Option<()> {
+    for segment in data.chunks_exact(24) {
+        if segment[0] == 0 {
+            break;
+        }
+        map.push((
+            parse_numeric(&segment[..12])?,
+            parse_numeric(&segment[12..])?,
+        ));
+    }
+    Some(())
+}
+
+/// The end of the header at `header` and its extension blocks, where the contents start.
+///
+/// The extension blocks follow an old-style sparse header while the `isextended` flag is set.
+pub(crate) fn extension_end(buf: &[u8], header: usize) -> Option<usize> {
+    let block_size = BLOCK_SIZE as usize;
+    let block = block_at(buf, header)?;
+    let mut extended = block[156] == typeflag::GNU_SPARSE && block[482] != 0;
+    let mut start = header.checked_add(block_size)?;
+    while extended {
+        extended = block_at(buf, start)?[504] != 0;
+        start += block_size;
+    }
+    Some(start)
+}
+
+/// The offset of the header of the entry whose contents start at `contents`.
+///
+/// The parser skips the extension blocks of an old-style sparse entry,
+/// so they are walked back to the header.
+pub(crate) fn header_offset(buf: &[u8], contents: usize) -> usize {
+    let block_size = BLOCK_SIZE as usize;
+    let last = contents.saturating_sub(block_size);
+    match block_at(buf, last) {
+        // An extension block isn't a valid header.
+        Some(block) if !verify_checksum(block) => (1..=last / block_size)
+            .map(|n| last - n * block_size)
+            .find(|offset| block_at(buf, *offset).is_some_and(verify_checksum))
+            .filter(|offset| extension_end(buf, *offset) == Some(contents))
+            .unwrap_or(last),
+        _ => last,
+    }
+}
+
+impl SparseFile {
+    /// Parse the sparse map at the start of the contents, which is followed by the data.
+    pub fn parse(buf: &[u8], contents: &[u8], size: u64, attrs: Attrs) -> Option<Self> {
+        let mut consumed = 0;
+        let map = {
+            let mut numbers = contents.split(|b| *b == b'\n').map(|line| {
+                consumed += line.len() + 1;
+                from_utf8(line).ok()?.parse::<u64>().ok()
+            });
+            let count = numbers.next()??;
+            let mut map = vec![];
+            for _ in 0..count {
+                let offset = numbers.next()??;
+                let len = numbers.next()??;
+                map.push((offset, len));
+            }
+            map
+        };
+        let data = contents.get(align_block(consumed as u64) as usize..)?;
+        Self::from_map(buf, data, map, size, attrs)
+    }
+
+    /// Parse an old-style sparse entry with the header at `header`.
+    ///
+    /// The header holds up to 4 segments and the real size, and the extension blocks
+    /// of 21 segments each follow it while the `isextended` flag is set.
+    /// The data follows the extension blocks.
+    pub fn parse_old(
+        buf: &[u8],
+        header: usize,
+        realsize: Option<u64>,
+        attrs: Attrs,
+    ) -> Option<Self> {
+        let block_size = BLOCK_SIZE as usize;
+        let block = buf.get(header..)?.get(..block_size)?;
+        let mut map = vec![];
+        read_segments(&block[386..482], &mut map)?;
+        let size = match realsize {
+            Some(size) => size,
+            None => parse_numeric(&block[483..495])?,
+        };
+        let stored = parse_numeric(&block[124..136])?;
+        let mut extended = block[482] != 0;
+        let mut start = header.checked_add(block_size)?;
+        while extended {
+            let block = buf.get(start..)?.get(..block_size)?;
+            read_segments(&block[..504], &mut map)?;
+            extended = block[504] != 0;
+            start += block_size;
+        }
+        let data = buf.get(start..)?.get(..usize::try_from(stored).ok()?)?;
+        Self::from_map(buf, data, map, size, attrs)
+    }
+
+    /// Check the map against the size, and locate the data segments at the start of `data`.
+    fn from_map(
+        buf: &[u8],
+        data: &[u8],
+        map: Vec<(u64, u64)>,
+        size: u64,
+        attrs: Attrs,
+    ) -> Option<Self> {
+        let mut total = 0u64;
+        for (offset, len) in &map {
+            if offset.checked_add(*len)? > size {
+                return None;
+            }
+            total = total.checked_add(*len)?;
+        }
+        let data = data.get(..usize::try_from(total).ok()?)?;
+        let start = offset_of(buf, data);
+        Some(Self {
+            data: start..start + data.len(),
+            map,
+            size,
+            attrs,
+        })
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// The data segments of a sparse file, as the offsets and the lengths in the file,
+    /// and the rest of the file are holes. Returns `None` if the file isn't sparse.
+    pub fn sparse_map(&self, path: &str) -> VfsResult<Option<Vec<(u64, u64)>>> {
+        match self.file_entry(path)? {
+            EntryRef::Sparse(_, file) => Ok(Some(file.map.clone())),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Read the sparse file from `pos` into the buffer, filling the holes with zeros.
+pub(crate) fn read_at(
+    data: &[u8],
+    map: &[(u64, u64)],
+    size: u64,
+    pos: u64,
+    buf: &mut [u8],
+) -> usize {
+    if pos >= size {
+        return 0;
+    }
+    let n = buf
+        .len()
+        .min(usize::try_from(size - pos).unwrap_or(usize::MAX));
+    let buf = &mut buf[..n];
+    buf.fill(0);
+    let end = pos + n as u64;
+    let mut data_offset = 0;
+    for &(offset, len) in map {
+        let start = offset.max(pos);
+        let stop = (offset + len).min(end);
+        if start < stop {
+            let from = (data_offset + start - offset) as usize;
+            buf[(start - pos) as usize..(stop - pos) as usize]
+                .copy_from_slice(&data[from..from + (stop - start) as usize]);
+        }
+        data_offset += len;
+    }
+    n
+}
+
+/// A reader of a sparse file, or a range of it.
+#[derive(Debug)]
+pub(crate) struct SparseReader<D> {
+    data: D,
+    map: Vec<(u64, u64)>,
+    start: u64,
+    end: u64,
+    /// The position from `start`.
+    pos: u64,
+}
+
+impl<D: AsRef<[u8]>> SparseReader<D> {
+    pub fn new(data: D, file: &SparseFile) -> Self {
+        Self::range(data, file, 0, file.size)
+    }
+
+    /// Read the range of the file, which should be in it.
+    pub fn range(data: D, file: &SparseFile, start: u64, end: u64) -> Self {
+        Self {
+            data,
+            map: file.map.clone(),
+            start,
+            end,
+            pos: 0,
+        }
+    }
+}
+
+impl<D: AsRef<[u8]>> Read for SparseReader<D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pos = self.start.saturating_add(self.pos);
+        let n = read_at(self.data.as_ref(), &self.map, self.end, pos, buf);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<D> Seek for SparseReader<D> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(n) => (self.end - self.start, n),
+            SeekFrom::Current(n) => (self.pos, n),
+        };
+        match base.checked_add_signed(offset) {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{test_util::ArchiveBuilder, TarFS};
+    use std::io::{Read, Seek, SeekFrom};
+    use vfs::FileSystem;
+
+    #[test]
+    fn sparse() {
+        let archive = ArchiveBuilder::new()
+            .sparse(
+                "sparse.bin",
+                3000,
+                &[(0, &[b'a'; 100]), (2000, &[b'b'; 50])],
+            )
+            .file("dense.bin", b"data")
+            .finish();
+        let fs = TarFS::new(archive).unwrap();
+
+        assert_eq!(fs.metadata("/sparse.bin").unwrap().len, 3000);
+        assert_eq!(
+            fs.sparse_map("/sparse.bin").unwrap(),
+            Some(vec![(0, 100), (2000, 50)])
+        );
+        assert_eq!(fs.sparse_map("/dense.bin").unwrap(), None);
+        assert!(fs.sparse_map("/missing").is_err());
+        let mut buffer = vec![];
+        fs.open_file("/sparse.bin")
+            .unwrap()
+            .read_to_end(&mut buffer)
+            .unwrap();
+        let mut expected = vec![0; 3000];
+        expected[..100].fill(b'a');
+        expected[2000..2050].fill(b'b');
+        assert_eq!(buffer, expected);
+
+        let mut file = fs.open_file("/sparse.bin").unwrap();
+        file.seek(SeekFrom::Start(1990)).unwrap();
+        let mut buffer = [0; 20];
+        file.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer[..10], &[0; 10]);
+        assert_eq!(&buffer[10..], &[b'b'; 10]);
+
+        let mut buffer = [0; 4];
+        assert_eq!(fs.read_at("/sparse.bin", 98, &mut buffer).unwrap(), 4);
+        assert_eq!(buffer, [b'a', b'a', 0, 0]);
+    }
+
+    #[test]
+    fn huge_range() {
+        let archive = ArchiveBuilder::new()
+            .sparse("huge.bin", 1 << 50, &[(1 << 40, b"data")])
+            .finish();
+        let fs = TarFS::new(archive).unwrap();
+
+        let mut file = fs
+            .open_file_range("/huge.bin", (1 << 40) - 2, u64::MAX)
+            .unwrap();
+        let mut buffer = [0xff; 8];
+        file.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, *b"\0\0data\0\0");
+        assert_eq!(
+            file.seek(SeekFrom::End(0)).unwrap(),
+            (1 << 50) - (1 << 40) + 2
+        );
+        let mut buffer = vec![];
+        let mut file = fs.open_file_range("/huge.bin", 1 << 40, 4).unwrap();
+        file.read_to_end(&mut buffer).unwrap();
+        assert_eq!(buffer, b"data");
+    }
+
+    #[test]
+    fn old_sparse() {
+        let octal = |n: u64| format!("{n:011o}\0").into_bytes();
+        let header = |map: &[(u64, u64)], realsize: u64| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::GNUSparse);
+            header.set_size(map.iter().map(|(_, len)| len).sum());
+            let bytes = header.as_mut_bytes();
+            for (i, (offset, len)) in map.iter().enumerate() {
+                bytes[386 + i * 24..][..12].copy_from_slice(&octal(*offset));
+                bytes[398 + i * 24..][..12].copy_from_slice(&octal(*len));
+            }
+            bytes[483..495].copy_from_slice(&octal(realsize));
+            header
+        };
+
+        let mut archive = tar::Builder::new(vec![]);
+        let mut gnu = header(&[(0, 4), (100, 4)], 200);
+        archive
+            .append_data(&mut gnu, "gnu.bin", b"headtail".as_slice())
+            .unwrap();
+        // star stores the real size in PAX if it's too large for the header.
+        let pax = b"27 SCHILY.realsize=5000000\n";
+        let mut header_pax = tar::Header::new_ustar();
+        header_pax.set_entry_type(tar::EntryType::XHeader);
+        header_pax.set_size(pax.len() as u64);
+        archive
+            .append_data(&mut header_pax, "PaxHeaders/star.bin", pax.as_slice())
+            .unwrap();
+        let mut star = header(&[(4000000, 4)], 0);
+        archive
+            .append_data(&mut star, "star.bin", b"data".as_slice())
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert_eq!(fs.metadata("/gnu.bin").unwrap().len, 200);
+        assert_eq!(
+            fs.sparse_map("/gnu.bin").unwrap(),
+            Some(vec![(0, 4), (100, 4)])
+        );
+        let mut buffer = vec![];
+        fs.open_file("/gnu.bin")
+            .unwrap()
+            .read_to_end(&mut buffer)
+            .unwrap();
+        let mut expected = vec![0; 200];
+        expected[..4].copy_from_slice(b"head");
+        expected[100..104].copy_from_slice(b"tail");
+        assert_eq!(buffer, expected);
+
+        assert_eq!(fs.metadata("/star.bin").unwrap().len, 5000000);
+        let mut buffer = [0; 6];
+        assert_eq!(fs.read_at("/star.bin", 3999999, &mut buffer).unwrap(), 6);
+        assert_eq!(&buffer, b"\0data\0");
+    }
+
+    #[test]
+    fn old_sparse_extended() {
+        let octal = |n: u64| format!("{n:011o}\0").into_bytes();
+        let segments = |block: &mut [u8], map: &[(u64, u64)]| {
+            for (i, (offset, len)) in map.iter().enumerate() {
+                block[i * 24..][..12].copy_from_slice(&octal(*offset));
+                block[12 + i * 24..][..12].copy_from_slice(&octal(*len));
+            }
+        };
+        // 4 segments in the header, and the rest in an extension block.
+        let map = (0..6).map(|i| (i * 1000, 4)).collect::<Vec<_>>();
+        let mut header = tar::Header::new_gnu();
+        header.set_path("big.bin").unwrap();
+        header.set_entry_type(tar::EntryType::GNUSparse);
+        header.set_size(24);
+        let bytes = header.as_mut_bytes();
+        segments(&mut bytes[386..482], &map[..4]);
+        bytes[482] = 1;
+        bytes[483..495].copy_from_slice(&octal(6000));
+        header.set_cksum();
+        let mut extension = [0; 512];
+        segments(&mut extension, &map[4..]);
+
+        let mut archive = header.as_bytes().to_vec();
+        archive.extend_from_slice(&extension);
+        archive.extend_from_slice(b"aaaabbbbccccddddeeeeffff");
+        archive.resize(1536, 0);
+        let mut archive = tar::Builder::new(archive);
+        let mut next = tar::Header::new_gnu();
+        next.set_size(4);
+        archive
+            .append_data(&mut next, "next.bin", b"data".as_slice())
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        assert_eq!(fs.sparse_map("/big.bin").unwrap(), Some(map));
+        assert_eq!(fs.metadata("/big.bin").unwrap().len, 6000);
+        let mut buffer = [0; 6];
+        assert_eq!(fs.read_at("/big.bin", 4999, &mut buffer).unwrap(), 6);
+        assert_eq!(&buffer, b"\0ffff\0");
+        // The header is before the extension block.
+        assert_eq!(fs.entry_location("/big.bin"), Some((0, 1024, 24)));
+        assert_eq!(fs.entry_location("/next.bin"), Some((1536, 2048, 4)));
+        assert_eq!(fs.metadata("/next.bin").unwrap().len, 4);
+    }
+}
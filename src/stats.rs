@@ -0,0 +1,127 @@
+//! Statistics of the files opened through the VFS, to find the unused ones.
+
+use crate::{path_key, TarFS};
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, SeekFrom},
+    ops::Deref,
+    sync::{Arc, Mutex, PoisonError},
+};
+use vfs::SeekAndRead;
+
+/// The statistics of a path, returned by [`TarFS::access_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AccessStats {
+    /// The count of the times the file is opened.
+    pub opens: u64,
+    /// The bytes read from the opened files.
+    pub bytes_read: u64,
+}
+
+/// The statistics shared by the clones of a [`TarFS`].
+pub(crate) type StatsMap = Arc<Mutex<HashMap<String, AccessStats>>>;
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Get the statistics of the files opened by [`vfs::FileSystem::open_file`], keyed by
+    /// the absolute paths as opened. The files never opened aren't included.
+    ///
+    /// Returns `None` if it isn't enabled by [`TarOptions::access_stats`](crate::TarOptions::access_stats)
+    /// or [`TarFS::set_access_stats`].
+    pub fn access_stats(&self) -> Option<HashMap<String, AccessStats>> {
+        let stats = self.access_stats.as_ref()?;
+        Some(stats.lock().unwrap_or_else(PoisonError::into_inner).clone())
+    }
+
+    /// Start or stop collecting the statistics. The collected ones are cleared.
+    pub fn set_access_stats(&mut self, enable: bool) {
+        self.access_stats = enable.then(StatsMap::default);
+    }
+
+    /// Count the opening of a file, and the bytes read from it.
+    pub(crate) fn track_access(
+        &self,
+        path: &str,
+        file: Box<dyn SeekAndRead + Send>,
+    ) -> Box<dyn SeekAndRead + Send> {
+        let Some(stats) = &self.access_stats else {
+            return file;
+        };
+        let path = format!("/{}", path_key(&path.replace('\\', "/")));
+        let mut map = stats.lock().unwrap_or_else(PoisonError::into_inner);
+        map.entry(path.clone()).or_default().opens += 1;
+        Box::new(StatsReader {
+            file,
+            path,
+            stats: stats.clone(),
+        })
+    }
+}
+
+struct StatsReader {
+    file: Box<dyn SeekAndRead + Send>,
+    path: String,
+    stats: StatsMap,
+}
+
+impl Read for StatsReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.file.read(buf)?;
+        if len > 0 {
+            let mut map = self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+            map.entry(self.path.clone()).or_default().bytes_read += len as u64;
+        }
+        Ok(len)
+    }
+}
+
+impl Seek for StatsReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{AccessStats, TarFS, TarOptions};
+    use std::io::Read;
+    use vfs::FileSystem;
+
+    #[test]
+    fn access_stats() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in [("used", "data"), ("unused", "data")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+
+        let fs = TarFS::new(archive.clone()).unwrap();
+        fs.open_file("/used").unwrap();
+        assert_eq!(fs.access_stats(), None);
+
+        let fs = TarFS::with_options(archive, TarOptions::new().access_stats(true)).unwrap();
+        // The clones share the statistics.
+        let clone = fs.clone();
+        let mut buffer = vec![];
+        clone
+            .open_file("used")
+            .unwrap()
+            .read_to_end(&mut buffer)
+            .unwrap();
+        let mut file = fs.open_file("/used").unwrap();
+        file.read_exact(&mut buffer[..2]).unwrap();
+        let stats = fs.access_stats().unwrap();
+        assert_eq!(
+            stats["/used"],
+            AccessStats {
+                opens: 2,
+                bytes_read: 6
+            }
+        );
+        assert!(!stats.contains_key("/unused"));
+    }
+}
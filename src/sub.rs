@@ -0,0 +1,140 @@
+//! A view of a directory in the archive.
+
+use crate::{
+    dir::DirNames, dir_error, not_dir_error, sparse::SparseReader, special_error, EntryRef, TarFS,
+};
+use std::{fmt::Debug, io::Cursor, ops::Deref};
+use vfs::{error::VfsErrorKind, *};
+
+/// A readonly filesystem rooted at a directory of a [`TarFS`], created by [`TarFS::subfs`].
+///
+/// The symbolic links are resolved within the directory, like `chroot`,
+/// so the entries outside the directory are never exposed.
+#[derive(Debug)]
+pub struct TarSubFS<F: Deref<Target = [u8]>> {
+    fs: TarFS<F>,
+    prefix: String,
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Create a filesystem rooted at the directory.
+    pub fn subfs(self, path: &str) -> VfsResult<TarSubFS<F>> {
+        match self.find_entry(path) {
+            Some(EntryRef::Directory(_)) => Ok(TarSubFS {
+                prefix: path.to_string(),
+                fs: self,
+            }),
+            _ => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarSubFS<F> {
+    /// Get the path of the root directory in the archive.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Get the underlying [`TarFS`].
+    pub fn into_inner(self) -> TarFS<F> {
+        self.fs
+    }
+
+    fn find_entry(&self, path: &str) -> Option<EntryRef<'_>> {
+        match self.fs.find_entry(&self.prefix) {
+            Some(EntryRef::Directory(root)) => self.fs.find_entry_from(root, path),
+            _ => None,
+        }
+    }
+}
+
+impl<F: Deref<Target = [u8]> + Debug + Send + Sync + 'static> FileSystem for TarSubFS<F> {
+    fn read_dir(&self, path: &str) -> VfsResult<Box<dyn Iterator<Item = String> + Send>> {
+        match self.find_entry(path) {
+            Some(EntryRef::Directory(dir)) => Ok(Box::new(DirNames::new(dir))),
+            Some(_) => Err(not_dir_error(path)),
+            None => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+
+    fn create_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn open_file(&self, path: &str) -> VfsResult<Box<dyn SeekAndRead + Send>> {
+        match self.find_entry(path) {
+            Some(EntryRef::File(buf, _)) => Ok(Box::new(Cursor::new(self.fs.share(buf)))),
+            Some(EntryRef::Sparse(data, sparse)) => {
+                Ok(Box::new(SparseReader::new(self.fs.share(data), sparse)))
+            }
+            Some(EntryRef::Special(..)) => Err(special_error(path)),
+            Some(EntryRef::Directory(_)) => Err(dir_error(path)),
+            _ => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+
+    fn create_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn append_file(&self, _path: &str) -> VfsResult<Box<dyn SeekAndWrite + Send>> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn metadata(&self, path: &str) -> VfsResult<VfsMetadata> {
+        match self.find_entry(path) {
+            Some(e) => Ok(self.fs.entry_metadata(&e)),
+            None => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+
+    fn exists(&self, path: &str) -> VfsResult<bool> {
+        Ok(self.find_entry(path).is_some())
+    }
+
+    fn remove_file(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+
+    fn remove_dir(&self, _path: &str) -> VfsResult<()> {
+        Err(VfsErrorKind::NotSupported.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use vfs::VfsPath;
+
+    #[test]
+    fn subfs() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in [("assets/a.txt", "a"), ("secret", "secret")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            archive
+                .append_link(&mut header, "assets/link", "../secret")
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+        let root = VfsPath::from(fs.subfs("assets").unwrap());
+
+        let mut files = root
+            .read_dir()
+            .unwrap()
+            .map(|p| p.filename())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(&files, &["a.txt", "link"]);
+        assert_eq!(root.join("a.txt").unwrap().read_to_string().unwrap(), "a");
+        // The link is resolved within the view.
+        assert!(!root.join("link").unwrap().exists().unwrap());
+    }
+}
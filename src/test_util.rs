@@ -0,0 +1,326 @@
+//! Generation of synthetic archives, to test the code using this crate
+//! without hand-crafted fixtures. Enabled with the `test-util` feature.
+//!
+//! ```
+//! use vfs::FileSystem;
+//! use vfs_tar::{test_util::ArchiveBuilder, TarFS};
+//!
+//! let archive = ArchiveBuilder::new()
+//!     .file(&format!("{}file", "a/".repeat(100)), b"deep")
+//!     .symlink("link", "a")
+//!     .finish();
+//! let fs = TarFS::new(archive).unwrap();
+//! assert!(fs.exists("/link").unwrap());
+//! ```
+
+use crate::{
+    parser::{align_block, checksum, typeflag::*, BLOCK_SIZE},
+    writer,
+};
+use std::io::{self, Write};
+
+/// A builder of tar archives in memory, in the POSIX ustar format with PAX extensions.
+///
+/// The long paths and link targets are written with PAX headers automatically.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveBuilder {
+    buf: Vec<u8>,
+}
+
+impl ArchiveBuilder {
+    /// Create an empty archive.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a regular file.
+    pub fn file(mut self, path: &str, data: &[u8]) -> Self {
+        self.header(path, REGULAR, data.len() as u64);
+        self.data(data);
+        self
+    }
+
+    /// Append a directory.
+    pub fn dir(mut self, path: &str) -> Self {
+        let path = format!("{}/", path.trim_end_matches('/'));
+        writer::write_header(&mut self.buf, &path, DIRECTORY, 0o755, 0, 0).unwrap();
+        self
+    }
+
+    /// Append a symbolic link.
+    pub fn symlink(mut self, path: &str, target: &str) -> Self {
+        writer::write_link_header(&mut self.buf, path, target, SYMLINK, 0).unwrap();
+        self
+    }
+
+    /// Append a hard link to a previous entry.
+    pub fn hard_link(mut self, path: &str, target: &str) -> Self {
+        writer::write_link_header(&mut self.buf, path, target, LINK, 0).unwrap();
+        self
+    }
+
+    /// Append a PAX extended header, which applies to the next entry.
+    pub fn pax(mut self, records: &[(&str, &str)]) -> Self {
+        writer::write_pax(&mut self.buf, records, 0).unwrap();
+        self
+    }
+
+    /// Append a PAX extended header with the raw data, like binary values
+    /// or repeated and malformed records, which applies to the next entry.
+    pub fn pax_data(mut self, data: &[u8]) -> Self {
+        writer::write_pax_data(&mut self.buf, PAX_HEADER, data, 0).unwrap();
+        self
+    }
+
+    /// Append a PAX global header, which applies to all the following entries.
+    pub fn global_pax(mut self, records: &[(&str, &str)]) -> Self {
+        let data = records
+            .iter()
+            .flat_map(|(key, value)| writer::pax_record(key, value))
+            .collect::<Vec<_>>();
+        writer::write_pax_data(&mut self.buf, PAX_GLOBAL, &data, 0).unwrap();
+        self
+    }
+
+    /// Append a GNU long name entry, which applies to the next entry.
+    pub fn gnu_long_name(mut self, name: &str) -> Self {
+        let mut data = name.as_bytes().to_vec();
+        data.push(0);
+        self.header("././@LongLink", GNU_LONGNAME, data.len() as u64);
+        self.data(&data);
+        self
+    }
+
+    /// Append a sparse file in the GNU PAX 1.0 format, with the data segments
+    /// at the offsets, and holes elsewhere.
+    pub fn sparse(mut self, path: &str, size: u64, segments: &[(u64, &[u8])]) -> Self {
+        let mut map = format!("{}\n", segments.len());
+        for (offset, data) in segments {
+            map.push_str(&format!("{offset}\n{}\n", data.len()));
+        }
+        let mut data = map.into_bytes();
+        data.resize(align_block(data.len() as u64) as usize, 0);
+        for (_, segment) in segments {
+            data.extend_from_slice(segment);
+        }
+        let size = size.to_string();
+        let name = path.rsplit('/').next().unwrap_or(path);
+        self = self.pax(&[
+            ("GNU.sparse.major", "1"),
+            ("GNU.sparse.minor", "0"),
+            ("GNU.sparse.name", path),
+            ("GNU.sparse.realsize", &size),
+        ]);
+        self.file(&format!("GNUSparseFile.0/{name}"), &data)
+    }
+
+    /// Append a sparse file in the old GNU format, with the data segments at the offsets,
+    /// and holes elsewhere. The header holds 4 segments of the map, and the extension
+    /// blocks following it hold 21 each.
+    pub fn old_sparse(mut self, path: &str, size: u64, segments: &[(u64, &[u8])]) -> Self {
+        let octal = |field: &mut [u8], n: u64| {
+            let digits = field.len() - 1;
+            field[..digits].copy_from_slice(format!("{n:0digits$o}").as_bytes());
+        };
+        let data = segments.iter().flat_map(|(_, data)| *data).copied();
+        let data = data.collect::<Vec<_>>();
+        let mut header = [0; BLOCK_SIZE as usize];
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        octal(&mut header[100..108], 0o644);
+        octal(&mut header[124..136], data.len() as u64);
+        header[156] = GNU_SPARSE;
+        header[257..265].copy_from_slice(b"ustar  \0");
+        octal(&mut header[483..495], size);
+        let mut extensions = vec![];
+        for (i, (offset, data)) in segments.iter().enumerate() {
+            let (block, start) = match i.checked_sub(4) {
+                None => (&mut header[..], 386 + i * 24),
+                Some(i) => {
+                    if i % 21 == 0 {
+                        extensions.push([0; BLOCK_SIZE as usize]);
+                    }
+                    (&mut extensions[i / 21][..], i % 21 * 24)
+                }
+            };
+            octal(&mut block[start..start + 12], *offset);
+            octal(&mut block[start + 12..start + 24], data.len() as u64);
+        }
+        // The `isextended` flags.
+        header[482] = u8::from(!extensions.is_empty());
+        let count = extensions.len();
+        for (i, block) in extensions.iter_mut().enumerate() {
+            block[504] = u8::from(i + 1 < count);
+        }
+        let sum = checksum(&header);
+        octal(&mut header[148..155], sum);
+        self.buf.extend_from_slice(&header);
+        for block in &extensions {
+            self.buf.extend_from_slice(block);
+        }
+        self.data(&data);
+        self
+    }
+
+    /// Finish the archive with the end-of-archive blocks.
+    pub fn finish(mut self) -> Vec<u8> {
+        writer::write_end(&mut self.buf).unwrap();
+        self.buf
+    }
+
+    fn header(&mut self, path: &str, typeflag: u8, size: u64) {
+        writer::write_header(&mut self.buf, path, typeflag, 0o644, 0, size).unwrap();
+    }
+
+    fn data(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+        writer::write_padding(&mut self.buf, data.len() as u64).unwrap();
+    }
+}
+
+/// An archive with a chain of nested directories, and a file `file` in the deepest one.
+pub fn deep_tree(depth: usize) -> Vec<u8> {
+    let path = (0..depth).map(|i| format!("{i}/")).collect::<String>();
+    ArchiveBuilder::new()
+        .file(&format!("{path}file"), b"deep")
+        .finish()
+}
+
+/// An archive with `count` small files, named from `0` in the directory `files`.
+pub fn many_files(count: usize) -> Vec<u8> {
+    (0..count)
+        .fold(ArchiveBuilder::new(), |builder, i| {
+            builder.file(&format!("files/{i}"), i.to_string().as_bytes())
+        })
+        .finish()
+}
+
+/// An archive with `count` small files spread in a tree of directories with `fanout`
+/// entries each, like `tree/0/3/1234`. See [`write_large_tree`].
+pub fn large_tree(count: usize, fanout: usize) -> Vec<u8> {
+    let mut buf = vec![];
+    write_large_tree(&mut buf, count, fanout).unwrap();
+    buf
+}
+
+/// Write the archive of [`large_tree`] to a writer, to generate a million-entry archive
+/// on the disk without holding it in memory. The contents of a file are its index.
+pub fn write_large_tree(mut out: impl Write, count: usize, fanout: usize) -> io::Result<()> {
+    let fanout = fanout.max(2);
+    // All files are at the same depth.
+    let mut depth = 0;
+    let mut capacity = fanout;
+    while capacity < count {
+        depth += 1;
+        capacity = capacity.saturating_mul(fanout);
+    }
+    for i in 0..count {
+        let mut path = String::from("tree");
+        let mut dirs = vec![];
+        let mut n = i / fanout;
+        for _ in 0..depth {
+            dirs.push(n % fanout);
+            n /= fanout;
+        }
+        for dir in dirs.iter().rev() {
+            path.push_str(&format!("/{dir}"));
+        }
+        let data = i.to_string();
+        writer::write_header(
+            &mut out,
+            &format!("{path}/{i}"),
+            REGULAR,
+            0o644,
+            0,
+            data.len() as u64,
+        )?;
+        out.write_all(data.as_bytes())?;
+        writer::write_padding(&mut out, data.len() as u64)?;
+    }
+    writer::write_end(&mut out)
+}
+
+/// An archive with `count` chains of `depth` symbolic links, ending at the file `target`.
+/// The link `links/{i}/{k}` points to `{k - 1}`, and `links/{i}/0` to the file.
+pub fn symlink_chains(count: usize, depth: usize) -> Vec<u8> {
+    let mut builder = ArchiveBuilder::new().file("target", b"target");
+    for i in 0..count {
+        builder = builder.symlink(&format!("links/{i}/0"), "../../target");
+        for k in 1..depth {
+            builder = builder.symlink(&format!("links/{i}/{k}"), &(k - 1).to_string());
+        }
+    }
+    builder.finish()
+}
+
+/// An archive with the edge cases of the format: long names in both PAX and GNU formats,
+/// PAX metadata, a sparse file, links, a duplicate path, and an empty directory.
+pub fn edge_cases() -> Vec<u8> {
+    let long = format!("{}/{}", "long".repeat(40), "name".repeat(30));
+    ArchiveBuilder::new()
+        .dir("empty")
+        .file(&long, b"pax long name")
+        .gnu_long_name(&format!("gnu/{long}"))
+        .file("gnu/short", b"gnu long name")
+        .pax(&[("mtime", "1700000000"), ("uname", "ünïcode")])
+        .file("pax.txt", b"pax metadata")
+        .sparse(
+            "sparse.bin",
+            1 << 20,
+            &[(0, b"head"), ((1 << 20) - 4, b"tail")],
+        )
+        .hard_link("hard", "pax.txt")
+        .symlink("symlink", &long)
+        .file("duplicate", b"first")
+        .file("duplicate", b"second")
+        .finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::TarFS;
+    use std::{
+        io::Read,
+        time::{Duration, UNIX_EPOCH},
+    };
+    use vfs::FileSystem;
+
+    #[test]
+    fn generate() {
+        let fs = TarFS::new(edge_cases()).unwrap();
+        let read = |path: &str| {
+            let mut buf = String::new();
+            fs.open_file(path)
+                .unwrap()
+                .read_to_string(&mut buf)
+                .unwrap();
+            buf
+        };
+        let long = format!("{}/{}", "long".repeat(40), "name".repeat(30));
+        assert_eq!(read(&long), "pax long name");
+        assert_eq!(read(&format!("gnu/{long}")), "gnu long name");
+        assert_eq!(read("symlink"), "pax long name");
+        assert_eq!(read("hard"), "pax metadata");
+        assert_eq!(read("duplicate"), "second");
+        assert_eq!(fs.header_for("pax.txt").unwrap().mtime, 1700000000);
+        let modified = fs.metadata("pax.txt").unwrap().modified.unwrap();
+        assert_eq!(modified, UNIX_EPOCH + Duration::from_secs(1700000000));
+        assert_eq!(fs.metadata("sparse.bin").unwrap().len, 1 << 20);
+        assert_eq!(fs.read_dir("empty").unwrap().count(), 0);
+
+        let fs = TarFS::new(deep_tree(200)).unwrap();
+        let path = (0..200).map(|i| format!("{i}/")).collect::<String>();
+        assert_eq!(fs.metadata(&format!("{path}file")).unwrap().len, 4);
+
+        let fs = TarFS::new(many_files(10000)).unwrap();
+        assert_eq!(fs.read_dir("files").unwrap().count(), 10000);
+
+        let fs = TarFS::new(large_tree(1000, 10)).unwrap();
+        assert_eq!(fs.entries().count(), 1000);
+        assert_eq!(fs.read_dir("tree/9").unwrap().count(), 10);
+        assert_eq!(fs.metadata("tree/9/9/999").unwrap().len, 3);
+
+        let fs = TarFS::new(symlink_chains(3, 10)).unwrap();
+        assert_eq!(fs.metadata("links/2/9").unwrap().len, 6);
+    }
+}
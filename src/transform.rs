@@ -0,0 +1,104 @@
+//! Transforms of the input stream before parsing, e.g. decryption or de-obfuscation.
+
+use crate::{TarFS, TarOptions};
+use std::io::{self, Read};
+use vfs::VfsResult;
+
+impl TarFS<Vec<u8>> {
+    /// Create [`TarFS`] from a stream wrapped by a transform, e.g. an age or GPG decryption
+    /// reader, so that the archive is never stored untransformed in a temporary file.
+    ///
+    /// The errors of the transform, like a wrong key, are returned as they are.
+    pub fn from_reader_with<R: Read, T: Read>(
+        reader: R,
+        transform: impl FnOnce(R) -> io::Result<T>,
+        options: TarOptions,
+    ) -> VfsResult<Self> {
+        let mut buffer = vec![];
+        transform(reader)?.read_to_end(&mut buffer)?;
+        Self::with_options(buffer, options)
+    }
+}
+
+/// A reader XOR-ing the stream with a repeated key, the obfuscation of some game asset packs.
+///
+/// The transform is symmetric, so it also obfuscates a plain archive.
+#[derive(Debug, Clone)]
+pub struct XorReader<R> {
+    inner: R,
+    key: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> XorReader<R> {
+    /// Wrap a reader with the key. An empty key leaves the stream as it is.
+    pub fn new(inner: R, key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            inner,
+            key: key.into(),
+            pos: 0,
+        }
+    }
+
+    /// Get the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for XorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        if !self.key.is_empty() {
+            for b in &mut buf[..len] {
+                *b ^= self.key[self.pos];
+                self.pos = (self.pos + 1) % self.key.len();
+            }
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{TarFS, TarOptions, XorReader};
+    use std::io::{self, Read};
+    use vfs::FileSystem;
+
+    #[test]
+    fn from_reader_with() {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, "a.txt", b"data".as_slice())
+            .unwrap();
+        let archive = archive.into_inner().unwrap();
+        let mut obfuscated = vec![];
+        XorReader::new(archive.as_slice(), *b"key")
+            .read_to_end(&mut obfuscated)
+            .unwrap();
+        assert_ne!(obfuscated, archive);
+
+        let fs = TarFS::from_reader_with(
+            obfuscated.as_slice(),
+            |r| Ok(XorReader::new(r, *b"key")),
+            TarOptions::new(),
+        )
+        .unwrap();
+        let mut buffer = String::new();
+        fs.open_file("/a.txt")
+            .unwrap()
+            .read_to_string(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, "data");
+
+        // The errors of the transform are returned.
+        let res = TarFS::from_reader_with(
+            obfuscated.as_slice(),
+            |_| Err::<&[u8], _>(io::Error::new(io::ErrorKind::InvalidData, "wrong key")),
+            TarOptions::new(),
+        );
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Printing the directory tree of the archive, like `tree`.
+
+use crate::{DirTree, Entry, SpecialFile, TarFS};
+use std::{fmt, ops::Deref};
+
+/// Options of [`TarFS::format_tree`].
+#[derive(Debug, Clone)]
+pub struct TreeOptions {
+    sizes: bool,
+    max_depth: Option<usize>,
+}
+
+impl Default for TreeOptions {
+    fn default() -> Self {
+        Self {
+            sizes: true,
+            max_depth: None,
+        }
+    }
+}
+
+impl TreeOptions {
+    /// Create the default options, which print the whole tree with the sizes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Print the sizes of the files.
+    pub fn sizes(mut self, sizes: bool) -> Self {
+        self.sizes = sizes;
+        self
+    }
+
+    /// Print the entries up to the depth, where the children of the root are at depth 1.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Print the tree of the archive, with the entries sorted by name.
+    ///
+    /// The directories end with `/`, and the links are printed with their targets unresolved,
+    /// so the names are shown exactly as stored, e.g. with a leading `.` component.
+    ///
+    /// ```text
+    /// /
+    /// ├── bin/
+    /// │   └── app [1024]
+    /// ├── dev/
+    /// │   └── null [char 1:3]
+    /// └── latest -> bin/app
+    /// ```
+    pub fn format_tree(&self, w: &mut impl fmt::Write, options: &TreeOptions) -> fmt::Result {
+        writeln!(w, "/")?;
+        format_dir(w, &self.root, "", 1, options)
+    }
+}
+
+fn format_dir(
+    w: &mut impl fmt::Write,
+    dir: &DirTree,
+    indent: &str,
+    depth: usize,
+    options: &TreeOptions,
+) -> fmt::Result {
+    if options.max_depth.is_some_and(|max| depth > max) {
+        return Ok(());
+    }
+    let mut entries = dir.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(a, _)| *a);
+    for (i, (name, entry)) in entries.iter().enumerate() {
+        let last = i + 1 == entries.len();
+        write!(w, "{indent}{}{name}", if last { "└── " } else { "├── " })?;
+        match entry {
+            Entry::File(range, _) if options.sizes => writeln!(w, " [{}]", range.len())?,
+            Entry::Sparse(sparse) if options.sizes => writeln!(w, " [{}, sparse]", sparse.size)?,
+            Entry::File(..) | Entry::Sparse(_) => writeln!(w)?,
+            Entry::Link(target) => writeln!(w, " -> {target}")?,
+            Entry::Special(special, ..) => match special {
+                SpecialFile::CharDevice { major, minor } => writeln!(w, " [char {major}:{minor}]")?,
+                SpecialFile::BlockDevice { major, minor } => {
+                    writeln!(w, " [block {major}:{minor}]")?
+                }
+                SpecialFile::Fifo => writeln!(w, " [fifo]")?,
+                SpecialFile::Socket => writeln!(w, " [socket]")?,
+            },
+            Entry::Directory(dir) => {
+                writeln!(w, "/")?;
+                let indent = format!("{indent}{}", if last { "    " } else { "│   " });
+                format_dir(w, dir, &indent, depth + 1, options)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{TarFS, TreeOptions};
+
+    #[test]
+    fn format_tree() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in [("./bin/app", "data"), ("etc/conf/a", ""), ("readme", "hi")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive
+            .append_link(&mut header, "latest", "bin/app")
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let mut tree = String::new();
+        fs.format_tree(&mut tree, &TreeOptions::new()).unwrap();
+        assert_eq!(
+            tree,
+            "/
+├── bin/
+│   └── app [4]
+├── etc/
+│   └── conf/
+│       └── a [0]
+├── latest -> bin/app
+└── readme [2]
+"
+        );
+
+        let mut tree = String::new();
+        let options = TreeOptions::new().sizes(false).max_depth(1);
+        fs.format_tree(&mut tree, &options).unwrap();
+        assert_eq!(
+            tree,
+            "/\n├── bin/\n├── etc/\n├── latest -> bin/app\n└── readme\n"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! Unicode normalization of the names, for the archives created on macOS.
+
+use std::borrow::Cow;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// Normalize a name to NFC, without allocating if it's already normalized.
+pub(crate) fn to_nfc(name: Cow<str>) -> Cow<str> {
+    if is_nfc(&name) {
+        name
+    } else {
+        Cow::Owned(name.nfc().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{TarFS, TarOptions};
+    use vfs::FileSystem;
+
+    #[test]
+    fn normalize_unicode() {
+        // The names are decomposed by macOS.
+        let (nfd, nfc) = (
+            "cafe\u{301}/re\u{301}sume\u{301}",
+            "caf\u{e9}/r\u{e9}sum\u{e9}",
+        );
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        archive
+            .append_data(&mut header, nfd, b"data".as_slice())
+            .unwrap();
+        let archive = archive.into_inner().unwrap();
+
+        let fs = TarFS::new(archive.clone()).unwrap();
+        assert!(fs.exists(nfd).unwrap());
+        assert!(!fs.exists(nfc).unwrap());
+
+        let options = TarOptions::new().normalize_unicode(true);
+        let fs = TarFS::with_options(archive, options).unwrap();
+        assert!(fs.exists(nfd).unwrap());
+        assert!(fs.exists(nfc).unwrap());
+        assert_eq!(fs.read_dir("").unwrap().collect::<Vec<_>>(), ["caf\u{e9}"]);
+    }
+}
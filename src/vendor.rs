@@ -0,0 +1,106 @@
+//! Hooks of the entries with vendor-specific typeflags, `A` to `Z`, see [`TarOptions::vendor_hook`](crate::TarOptions::vendor_hook).
+
+use crate::{
+    parser::{parse_header, RawHeader},
+    DirTreeBuilder,
+};
+use std::{fmt::Debug, ops::Range, sync::Arc};
+
+/// The action on an entry with a vendor-specific typeflag, returned by the hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VendorAction {
+    /// Store it as a regular file, like without the hook.
+    Store,
+    /// Skip it. It isn't yielded by [`TarFS::entries`](crate::TarFS::entries) either.
+    Skip,
+    /// Store it as a directory.
+    Directory,
+    /// Store a range of the contents as a regular file, e.g. without a vendor header.
+    Contents(Range<usize>),
+}
+
+type HookFn = dyn Fn(&str, &RawHeader, &[u8]) -> VendorAction + Send + Sync;
+
+/// A hook deciding the action on the vendor-specific entries.
+#[derive(Clone)]
+pub(crate) struct VendorHook(Arc<HookFn>);
+
+impl VendorHook {
+    pub fn new(
+        hook: impl Fn(&str, &RawHeader, &[u8]) -> VendorAction + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(hook))
+    }
+}
+
+impl Debug for VendorHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VendorHook")
+    }
+}
+
+impl DirTreeBuilder<'_> {
+    /// Call the hook with the entry at the current position.
+    /// The entries whose header can't be parsed are stored.
+    pub(crate) fn vendor_action(&self, path: &str, contents: &[u8]) -> VendorAction {
+        let Some(hook) = &self.options.vendor_hook else {
+            return VendorAction::Store;
+        };
+        let header = self
+            .buf
+            .get(self.position.1 as usize..)
+            .and_then(parse_header);
+        match header {
+            Some(header) => (hook.0)(path, &header, contents),
+            None => VendorAction::Store,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{TarFS, TarOptions, VendorAction};
+    use vfs::{FileSystem, VfsFileType};
+
+    #[test]
+    fn vendor_hook() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, typeflag, data) in [
+            ("skip", b'Q', b"".as_slice()),
+            ("dir", b'E', b""),
+            ("trim", b'I', b"HDRdata"),
+            ("keep", b'J', b"data"),
+        ] {
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(tar::EntryType::new(typeflag));
+            header.set_size(data.len() as u64);
+            archive.append_data(&mut header, name, data).unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+
+        let options = TarOptions::new().vendor_hook(|path, header, contents| {
+            assert_eq!(header.name, path);
+            match header.typeflag {
+                b'Q' => VendorAction::Skip,
+                b'E' => VendorAction::Directory,
+                b'I' if contents.starts_with(b"HDR") => VendorAction::Contents(3..contents.len()),
+                _ => VendorAction::Store,
+            }
+        });
+        let fs = TarFS::with_options(archive.clone(), options).unwrap();
+        assert!(!fs.exists("/skip").unwrap());
+        assert_eq!(
+            fs.metadata("/dir").unwrap().file_type,
+            VfsFileType::Directory
+        );
+        assert_eq!(fs.metadata("/trim").unwrap().len, 4);
+        assert_eq!(fs.metadata("/keep").unwrap().len, 4);
+        assert_eq!(fs.entries().count(), 3);
+
+        // Without the hook, they are regular files.
+        let fs = TarFS::new(archive).unwrap();
+        assert_eq!(fs.metadata("/dir").unwrap().file_type, VfsFileType::File);
+        assert_eq!(fs.metadata("/trim").unwrap().len, 7);
+    }
+}
@@ -0,0 +1,115 @@
+//! Older versions of the paths appearing several times, e.g. appended by `tar -r`.
+
+use crate::{dir_error, path_key, special_error, EntryRecord, TarFS};
+use std::{io::Cursor, ops::Deref};
+use tar_parser2::TypeFlag;
+use vfs::{error::VfsErrorKind, SeekAndRead, VfsResult};
+
+/// A version of a path, created by [`TarFS::versions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EntryVersion {
+    /// The index of the entry, not counting the metadata entries.
+    pub index: usize,
+    /// The byte offset of the header.
+    pub offset: u64,
+    /// The type flag.
+    pub typeflag: TypeFlag,
+    /// The length of the stored contents.
+    pub len: u64,
+    /// The modification time, in seconds since the Unix epoch, with the PAX `mtime` applied.
+    pub mtime: u64,
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Get the versions of a path in the archive order, the last of which is the current one.
+    /// The symbolic links are not followed.
+    pub fn versions(&self, path: &str) -> Vec<EntryVersion> {
+        self.version_records(path)
+            .map(|(index, record)| {
+                let header = self.record_header(record);
+                let mtime = record.overrides.as_ref().and_then(|o| o.mtime());
+                EntryVersion {
+                    index,
+                    offset: record.entry.start as u64,
+                    typeflag: header.typeflag,
+                    len: record.contents.len() as u64,
+                    mtime: mtime.unwrap_or(header.mtime),
+                }
+            })
+            .collect()
+    }
+
+    /// Open the `n`th version of a file, counting from 0, see [`TarFS::versions`].
+    ///
+    /// The contents are read as stored, so the holes of a sparse file aren't filled,
+    /// and a hard link has no contents.
+    pub fn open_version(&self, path: &str, n: usize) -> VfsResult<Box<dyn SeekAndRead + Send>>
+    where
+        F: Send + Sync + 'static,
+    {
+        let (_, record) = self
+            .version_records(path)
+            .nth(n)
+            .ok_or(VfsErrorKind::FileNotFound)?;
+        match self.record_header(record).typeflag {
+            TypeFlag::Directory | TypeFlag::GnuDirectory => Err(dir_error(path)),
+            TypeFlag::CharacterSpecial | TypeFlag::BlockSpecial | TypeFlag::Fifo => {
+                Err(special_error(path))
+            }
+            _ => {
+                let contents = self
+                    .buf()
+                    .get(record.contents.clone())
+                    .ok_or(VfsErrorKind::FileNotFound)?;
+                Ok(Box::new(Cursor::new(self.share(contents))))
+            }
+        }
+    }
+
+    fn version_records(&self, path: &str) -> impl Iterator<Item = (usize, &EntryRecord)> + '_ {
+        let key = path_key(path);
+        self.records
+            .iter()
+            .enumerate()
+            .filter(move |(_, record)| path_key(&record.path) == key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use std::io::Read;
+
+    #[test]
+    fn versions() {
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data, mtime) in [("a", "old", 1), ("b", "other", 2), ("a", "new", 3)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mtime(mtime);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let versions = fs.versions("/a");
+        assert_eq!(versions.len(), 2);
+        assert_eq!((versions[0].index, versions[0].offset), (0, 0));
+        assert_eq!((versions[1].index, versions[1].offset), (2, 2048));
+        assert_eq!(versions[1].mtime, 3);
+        let read = |n| {
+            let mut buf = String::new();
+            fs.open_version("/a", n)
+                .unwrap()
+                .read_to_string(&mut buf)
+                .unwrap();
+            buf
+        };
+        assert_eq!(read(0), "old");
+        assert_eq!(read(1), "new");
+        assert!(fs.open_version("/a", 2).is_err());
+        assert!(fs.versions("/c").is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+//! Constructors of a root [`VfsPath`] from an archive, in the [prelude](crate::prelude).
+
+use crate::TarFS;
+use std::{fmt::Debug, ops::Deref, path::Path};
+use vfs::{VfsPath, VfsResult};
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for vfs::VfsPath {}
+}
+
+/// Constructors of a root [`VfsPath`] from an archive.
+///
+/// It's sealed and only implemented for [`VfsPath`], so the methods behind the features
+/// don't break other implementations when enabled.
+pub trait TarPathExt: private::Sealed + Sized {
+    /// Open an archive path, mapped or read into memory by its size, see [`TarFS::open_path`].
+    fn from_tar_path(path: impl AsRef<Path>) -> VfsResult<Self>;
+
+    /// Create from an archive in memory, e.g. a `Vec<u8>` or an `Arc<[u8]>`.
+    fn from_tar_bytes<F>(bytes: F) -> VfsResult<Self>
+    where
+        F: Deref<Target = [u8]> + Debug + Send + Sync + 'static;
+
+    /// Open a gzip compressed archive path, decompressed into memory.
+    #[cfg(feature = "bgzf")]
+    fn from_tar_gz_path(path: impl AsRef<Path>) -> VfsResult<Self>;
+
+    /// Open a zstd compressed archive path, decompressed into memory.
+    #[cfg(feature = "zstd")]
+    fn from_tar_zst_path(path: impl AsRef<Path>) -> VfsResult<Self>;
+
+    /// Open an archive nested in a file of another archive, without copying it,
+    /// see [`TarFS::open_nested`].
+    fn from_nested_tar<F>(fs: &TarFS<F>, path: &str) -> VfsResult<Self>
+    where
+        F: Deref<Target = [u8]> + Debug + Send + Sync + 'static;
+}
+
+impl TarPathExt for VfsPath {
+    fn from_tar_path(path: impl AsRef<Path>) -> VfsResult<Self> {
+        Ok(TarFS::open_path(path)?.into())
+    }
+
+    fn from_tar_bytes<F>(bytes: F) -> VfsResult<Self>
+    where
+        F: Deref<Target = [u8]> + Debug + Send + Sync + 'static,
+    {
+        Ok(TarFS::new(bytes)?.into())
+    }
+
+    #[cfg(feature = "bgzf")]
+    fn from_tar_gz_path(path: impl AsRef<Path>) -> VfsResult<Self> {
+        let fs = TarFS::from_reader_with(
+            std::fs::File::open(path)?,
+            |r| Ok(flate2::read::MultiGzDecoder::new(r)),
+            crate::TarOptions::default(),
+        )?;
+        Ok(fs.into())
+    }
+
+    #[cfg(feature = "zstd")]
+    fn from_tar_zst_path(path: impl AsRef<Path>) -> VfsResult<Self> {
+        let fs = TarFS::from_reader_with(
+            std::fs::File::open(path)?,
+            zstd::stream::read::Decoder::new,
+            crate::TarOptions::default(),
+        )?;
+        Ok(fs.into())
+    }
+
+    fn from_nested_tar<F>(fs: &TarFS<F>, path: &str) -> VfsResult<Self>
+    where
+        F: Deref<Target = [u8]> + Debug + Send + Sync + 'static,
+    {
+        Ok(fs.open_nested(path)?.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{TarFS, TarPathExt};
+    use std::io::Write;
+    use vfs::VfsPath;
+
+    fn archive(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut archive = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        archive.append_data(&mut header, name, data).unwrap();
+        archive.into_inner().unwrap()
+    }
+
+    #[test]
+    fn from_tar() {
+        let inner = archive("a.txt", b"inner");
+        let outer = archive("inner.tar", &inner);
+
+        let root = VfsPath::from_tar_bytes(inner.clone()).unwrap();
+        assert_eq!(
+            root.join("a.txt").unwrap().read_to_string().unwrap(),
+            "inner"
+        );
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&outer).unwrap();
+        let root = VfsPath::from_tar_path(file.path()).unwrap();
+        assert!(root.join("inner.tar").unwrap().is_file().unwrap());
+
+        let fs = TarFS::new(outer).unwrap();
+        let root = VfsPath::from_nested_tar(&fs, "/inner.tar").unwrap();
+        assert_eq!(
+            root.join("a.txt").unwrap().read_to_string().unwrap(),
+            "inner"
+        );
+
+        #[cfg(feature = "bgzf")]
+        {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(&inner).unwrap();
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            file.write_all(&encoder.finish().unwrap()).unwrap();
+            let root = VfsPath::from_tar_gz_path(file.path()).unwrap();
+            assert_eq!(
+                root.join("a.txt").unwrap().read_to_string().unwrap(),
+                "inner"
+            );
+        }
+    }
+}
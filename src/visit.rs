@@ -0,0 +1,198 @@
+//! Visiting the entries of a streamed archive in one pass, without building the tree.
+
+use crate::{parser::*, TarFsError};
+use std::io::{self, Read};
+use vfs::VfsResult;
+
+/// An entry passed to [`Visitor::visit`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct VisitedEntry<'a> {
+    /// The full path, with the GNU long name or the PAX `path` applied.
+    pub path: &'a str,
+    /// The link target, with the GNU long link or the PAX `linkpath` applied.
+    pub link_target: &'a str,
+    /// The fields of the header block.
+    /// The typeflag `\0` of the regular files in the old archives is normalized to `0`.
+    pub header: RawHeader<'a>,
+    /// The byte offset of the header block in the stream.
+    pub offset: u64,
+}
+
+/// The callbacks of [`visit`].
+///
+/// It's implemented for the closures with the same signature as [`Visitor::visit`].
+pub trait Visitor {
+    /// Visit an entry with the reader of its stored contents. The contents not read are skipped.
+    ///
+    /// The metadata entries, like GNU long names and PAX headers, are applied to the following
+    /// entries and not visited. The data of a sparse file is read as stored, without the holes.
+    fn visit(&mut self, entry: &VisitedEntry, contents: &mut dyn Read) -> io::Result<()>;
+}
+
+impl<T: FnMut(&VisitedEntry, &mut dyn Read) -> io::Result<()>> Visitor for T {
+    fn visit(&mut self, entry: &VisitedEntry, contents: &mut dyn Read) -> io::Result<()> {
+        self(entry, contents)
+    }
+}
+
+/// Visit the entries of an archive read in one pass, e.g. from a pipe or a decompressor,
+/// until the end-of-archive block or the end of the stream.
+///
+/// It stops at the first error, either returned by the visitor or of parsing.
+pub fn visit<R: Read>(mut reader: R, visitor: &mut impl Visitor) -> VfsResult<()> {
+    let mut block = [0; BLOCK_SIZE as usize];
+    let (mut index, mut offset) = (0, 0);
+    let mut long_name = None;
+    let mut long_link = None;
+    let mut pax_name = None;
+    let mut pax_link = None;
+    loop {
+        let truncated = TarFsError::Truncated { index, offset };
+        if !read_block(&mut reader, &mut block).map_err(|_| truncated.clone())? {
+            return Ok(());
+        }
+        if is_zero_block(&block) {
+            return Ok(());
+        }
+        if !verify_checksum(&block) {
+            return Err(TarFsError::Checksum { index, offset }.into());
+        }
+        let header = parse_header(&block).ok_or(TarFsError::InvalidHeader { index, offset })?;
+        let mut contents = (&mut reader).take(header.size);
+        match header.typeflag {
+            typeflag::GNU_LONGNAME | typeflag::GNU_LONGLINK | typeflag::PAX_HEADER => {
+                let mut data = vec![];
+                contents.read_to_end(&mut data)?;
+                if data.len() as u64 != header.size {
+                    return Err(truncated.into());
+                }
+                let invalid = || TarFsError::InvalidHeader { index, offset };
+                match header.typeflag {
+                    typeflag::GNU_LONGNAME => {
+                        long_name = Some(parse_long_name(&data).ok_or_else(invalid)?.to_string())
+                    }
+                    typeflag::GNU_LONGLINK => {
+                        long_link = Some(parse_long_name(&data).ok_or_else(invalid)?.to_string())
+                    }
+                    _ => {
+                        for (key, value) in parse_pax_records(&data).ok_or_else(invalid)? {
+                            let value = String::from_utf8_lossy(value).into_owned();
+                            match key {
+                                "path" => pax_name = Some(value),
+                                "linkpath" => pax_link = Some(value),
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+            // The global records don't change the paths.
+            typeflag::PAX_GLOBAL => {
+                io::copy(&mut contents, &mut io::sink())?;
+            }
+            _ => {
+                let path = pax_name.take().or(long_name.take());
+                let link = pax_link.take().or(long_link.take());
+                let mut entry = VisitedEntry {
+                    path: path.as_deref().unwrap_or(&header.name),
+                    link_target: link.as_deref().unwrap_or(header.linkname),
+                    header: header.clone(),
+                    offset,
+                };
+                if entry.header.typeflag == typeflag::AREGULAR {
+                    entry.header.typeflag = typeflag::REGULAR;
+                }
+                visitor.visit(&entry, &mut contents)?;
+                io::copy(&mut contents, &mut io::sink())?;
+            }
+        }
+        if contents.limit() > 0 {
+            return Err(truncated.into());
+        }
+        let padding = align_block(header.size) - header.size;
+        io::copy(&mut (&mut reader).take(padding), &mut io::sink())?;
+        index += 1;
+        offset += BLOCK_SIZE + align_block(header.size);
+    }
+}
+
+/// Read a whole block. Returns `false` at the end of the stream.
+fn read_block(reader: &mut impl Read, block: &mut [u8]) -> io::Result<bool> {
+    let mut len = 0;
+    while len < block.len() {
+        match reader.read(&mut block[len..]) {
+            Ok(0) if len == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parser::typeflag, visit, TarFsError, VisitedEntry};
+    use std::io::Read;
+
+    #[test]
+    fn visit_entries() {
+        let long = format!("{}file", "long/".repeat(30));
+        let mut archive = tar::Builder::new(vec![]);
+        for (name, data) in [
+            ("a.txt", "hello"),
+            (long.as_str(), "skipped"),
+            ("b.txt", ""),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_bytes())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive.append_link(&mut header, "link", "a.txt").unwrap();
+        let archive = archive.into_inner().unwrap();
+
+        let mut visited = vec![];
+        visit(
+            archive.as_slice(),
+            &mut |entry: &VisitedEntry, contents: &mut dyn Read| {
+                let mut data = String::new();
+                // Only the first entry is read, and the others are skipped.
+                if entry.path == "a.txt" {
+                    contents.read_to_string(&mut data)?;
+                }
+                visited.push((entry.path.to_string(), entry.header.typeflag, data));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            visited,
+            [
+                ("a.txt".to_string(), typeflag::REGULAR, "hello".to_string()),
+                (long.clone(), typeflag::REGULAR, String::new()),
+                ("b.txt".to_string(), typeflag::REGULAR, String::new()),
+                ("link".to_string(), typeflag::SYMLINK, String::new()),
+            ]
+        );
+
+        // The stream ends in the middle of the second entry.
+        let err = visit(
+            &archive[..1536],
+            &mut |_: &VisitedEntry, _: &mut dyn Read| Ok(()),
+        )
+        .unwrap_err();
+        assert_eq!(
+            TarFsError::downcast(&err),
+            Some(&TarFsError::Truncated {
+                index: 1,
+                offset: 1024
+            })
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Recursive walking and globbing of the archive.
+
+use crate::{DirTree, Entry, EntryRef, TarFS};
+use std::{collections::hash_map, ops::Deref, sync::Arc};
+use vfs::{error::VfsErrorKind, VfsFileType, VfsMetadata, VfsResult};
+
+/// An iterator of the descendants of a directory, created by [`TarFS::walk`].
+///
+/// The paths are absolute, and the symbolic links are resolved but not descended into:
+/// a link to a directory is yielded as a directory, without its contents, so the loops
+/// don't recurse. Broken links are skipped.
+pub struct Walk<'a, F: Deref<Target = [u8]>> {
+    fs: &'a TarFS<F>,
+    stack: Vec<(String, hash_map::Iter<'a, Arc<str>, Entry>)>,
+}
+
+impl<'a, F: Deref<Target = [u8]>> Walk<'a, F> {
+    fn new(fs: &'a TarFS<F>, prefix: String, dir: &'a DirTree) -> Self {
+        Self {
+            fs,
+            stack: vec![(prefix, dir.iter())],
+        }
+    }
+}
+
+impl<F: Deref<Target = [u8]>> Iterator for Walk<'_, F> {
+    type Item = (String, VfsMetadata);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (prefix, iter) = self.stack.last_mut()?;
+            let Some((name, entry)) = iter.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let path = format!("{prefix}/{name}");
+            let entry = match entry {
+                Entry::File(range, attrs) => EntryRef::File(&self.fs.buf()[range.clone()], attrs),
+                Entry::Sparse(sparse) => {
+                    EntryRef::Sparse(&self.fs.buf()[sparse.data.clone()], sparse)
+                }
+                Entry::Special(special, attrs, ino) => EntryRef::Special(*special, attrs, *ino),
+                Entry::Directory(dir) => {
+                    self.stack.push((path.clone(), dir.iter()));
+                    EntryRef::Directory(dir)
+                }
+                Entry::Link(_) => match self.fs.find_entry(&path) {
+                    Some(entry) => entry,
+                    None => continue,
+                },
+            };
+            return Some((path, self.fs.entry_metadata(&entry)));
+        }
+    }
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Walk all descendants of a directory recursively, with their metadata.
+    pub fn walk(&self, path: &str) -> VfsResult<Walk<'_, F>> {
+        let path = path.trim_matches('/');
+        let prefix = if path.is_empty() {
+            String::new()
+        } else {
+            format!("/{path}")
+        };
+        match self.find_entry(path) {
+            Some(EntryRef::Directory(dir)) => Ok(Walk::new(self, prefix, dir)),
+            _ => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+
+    /// Find the paths matching a glob pattern, relative to the archive root.
+    ///
+    /// `*` matches any characters in a component, `?` matches one character,
+    /// and a `**` component matches any count of components.
+    pub fn glob(
+        &self,
+        pattern: &str,
+    ) -> VfsResult<impl Iterator<Item = (String, VfsMetadata)> + '_> {
+        let pattern = pattern.to_string();
+        Ok(self
+            .walk("")?
+            .filter(move |(path, _)| glob_matches(&pattern, path)))
+    }
+
+    /// Get the absolute paths of the files, including the device nodes and FIFOs.
+    pub(crate) fn file_paths(&self) -> VfsResult<Vec<String>> {
+        Ok(self
+            .walk("")?
+            .filter(|(_, metadata)| metadata.file_type == VfsFileType::File)
+            .map(|(path, _)| path)
+            .collect())
+    }
+
+    /// Get the absolute paths of all files, directories and links in the archive,
+    /// in no particular order. The links are neither resolved nor descended into.
+    pub fn paths(&self) -> impl Iterator<Item = String> + '_ {
+        let mut stack = vec![(String::new(), self.root.iter())];
+        std::iter::from_fn(move || loop {
+            let (prefix, iter) = stack.last_mut()?;
+            let Some((name, entry)) = iter.next() else {
+                stack.pop();
+                continue;
+            };
+            let path = format!("{prefix}/{name}");
+            if let Entry::Directory(dir) = entry {
+                stack.push((path.clone(), dir.iter()));
+            }
+            return Some(path);
+        })
+    }
+
+    /// Get the count of the paths yielded by [`TarFS::paths`].
+    pub fn path_count(&self) -> usize {
+        fn count(dir: &DirTree) -> usize {
+            dir.values()
+                .map(|entry| match entry {
+                    Entry::Directory(dir) => 1 + count(dir),
+                    _ => 1,
+                })
+                .sum()
+        }
+        count(&self.root)
+    }
+}
+
+/// Whether the path matches the glob pattern. Both are relative to the archive root.
+pub(crate) fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern
+        .trim_matches('/')
+        .split('/')
+        .map(String::from)
+        .collect::<Vec<_>>();
+    let path = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    glob_match(&pattern, &path)
+}
+
+fn glob_match(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((p, rest)) if p == "**" => (0..=path.len()).any(|i| glob_match(rest, &path[i..])),
+        Some((p, rest)) => match path.split_first() {
+            Some((s, path)) => component_match(p, s) && glob_match(rest, path),
+            None => false,
+        },
+    }
+}
+
+fn component_match(pattern: &str, s: &str) -> bool {
+    let mut pattern_chars = pattern.chars();
+    let mut chars = s.chars();
+    match pattern_chars.next() {
+        None => s.is_empty(),
+        Some('*') => {
+            let rest = pattern_chars.as_str();
+            s.char_indices()
+                .map(|(i, _)| i)
+                .chain([s.len()])
+                .any(|i| component_match(rest, &s[i..]))
+        }
+        Some('?') => {
+            chars.next().is_some() && component_match(pattern_chars.as_str(), chars.as_str())
+        }
+        Some(c) => {
+            chars.next() == Some(c) && component_match(pattern_chars.as_str(), chars.as_str())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::TarFS;
+    use vfs::VfsFileType;
+
+    #[test]
+    fn walk() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a/b.png", "a/c/d.png", "a/e.txt", "f.png"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            archive
+                .append_data(&mut header, name, b"data".as_slice())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive.append_link(&mut header, "a/up", "..").unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let mut paths = fs.walk("/a").unwrap().collect::<Vec<_>>();
+        paths.sort_by(|(a, _), (b, _)| a.cmp(b));
+        assert_eq!(paths.len(), 5);
+        assert_eq!(paths[1].0, "/a/c");
+        assert_eq!(paths[1].1.file_type, VfsFileType::Directory);
+        assert_eq!(paths[2].0, "/a/c/d.png");
+        assert_eq!(paths[2].1.len, 4);
+        // The link to the parent is a directory, which isn't descended into.
+        assert_eq!(paths[4].0, "/a/up");
+        assert_eq!(paths[4].1.file_type, VfsFileType::Directory);
+
+        let glob = |pattern| {
+            let mut paths = fs
+                .glob(pattern)
+                .unwrap()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>();
+            paths.sort();
+            paths
+        };
+        assert_eq!(glob("**/*.png"), ["/a/b.png", "/a/c/d.png", "/f.png"]);
+        assert_eq!(glob("a/*.png"), ["/a/b.png"]);
+        assert_eq!(glob("a/?.txt"), ["/a/e.txt"]);
+    }
+
+    #[test]
+    fn paths() {
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a/b", "a/c/d", "e"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(0);
+            archive
+                .append_data(&mut header, name, [].as_slice())
+                .unwrap();
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        archive
+            .append_link(&mut header, "broken", "missing")
+            .unwrap();
+        let fs = TarFS::new(archive.into_inner().unwrap()).unwrap();
+
+        let mut paths = fs.paths().collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, ["/a", "/a/b", "/a/c", "/a/c/d", "/broken", "/e"]);
+        assert_eq!(fs.path_count(), 6);
+    }
+}
@@ -0,0 +1,128 @@
+//! A [`RangeSource`] mapping only the regions of an archive being read.
+
+use crate::RangeSource;
+use memmap2::{Mmap, MmapOptions};
+use std::{
+    fs::File,
+    io,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// The alignment of the windows, the allocation granularity on Windows,
+/// which is a multiple of the page size on the other platforms.
+const ALIGN: u64 = 1 << 16;
+
+/// A [`RangeSource`] of a file mapped in windows, instead of as a whole.
+///
+/// Only the windows covering the requested ranges are mapped, e.g. the headers when
+/// building, and the file contents when reading, so the archives larger than
+/// the address space could be read with [`RemoteTarFS`](crate::RemoteTarFS).
+#[derive(Debug)]
+pub struct WindowedMmap {
+    file: File,
+    len: u64,
+    window: u64,
+    /// The last mapped window, as the headers and the files are usually read in order.
+    cache: Mutex<Option<(u64, Arc<Mmap>)>>,
+}
+
+impl WindowedMmap {
+    /// Create [`WindowedMmap`] from [`File`], with 64 MiB windows.
+    pub fn new(file: File) -> io::Result<Self> {
+        Ok(Self {
+            len: file.metadata()?.len(),
+            file,
+            window: 1 << 26,
+            cache: Mutex::default(),
+        })
+    }
+
+    /// Create [`WindowedMmap`] from the archive path.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::open(path)?)
+    }
+
+    /// Set the length of the windows, rounded up to a multiple of 64 KiB.
+    pub fn window(mut self, window: u64) -> Self {
+        self.window = window.max(1).div_ceil(ALIGN).saturating_mul(ALIGN);
+        self
+    }
+
+    /// Get the length of the file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Map the window starting at `start`, or reuse the last one.
+    fn map(&self, start: u64) -> io::Result<Arc<Mmap>> {
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((offset, map)) = cache.as_ref() {
+            if *offset == start {
+                return Ok(map.clone());
+            }
+        }
+        let len = self.window.min(self.len - start) as usize;
+        // SAFETY: the archive shouldn't be modified while being read, like `TarFS::new_mmap`.
+        let map = Arc::new(unsafe { MmapOptions::new().offset(start).len(len).map(&self.file) }?);
+        *cache = Some((start, map.clone()));
+        Ok(map)
+    }
+}
+
+impl RangeSource for WindowedMmap {
+    fn read_range(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let end = offset.saturating_add(len).min(self.len);
+        let mut buffer = Vec::with_capacity(end.saturating_sub(offset) as usize);
+        let mut pos = offset;
+        while pos < end {
+            let start = pos - pos % self.window;
+            let map = self.map(start)?;
+            let from = (pos - start) as usize;
+            let to = (end - start).min(map.len() as u64) as usize;
+            buffer.extend_from_slice(&map[from..to]);
+            pos = start + to as u64;
+        }
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{RemoteTarFS, WindowedMmap};
+    use std::io::Read;
+    use vfs::FileSystem;
+
+    #[test]
+    fn windowed() {
+        let data = (0..300000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut archive = tar::Builder::new(vec![]);
+        for name in ["a", "b"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            archive
+                .append_data(&mut header, name, data.as_slice())
+                .unwrap();
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive.tar");
+        std::fs::write(&path, archive.into_inner().unwrap()).unwrap();
+
+        let source = WindowedMmap::open(&path).unwrap().window(1);
+        let fs = RemoteTarFS::new(source).unwrap();
+        for path in ["/a", "/b"] {
+            // The files span several windows.
+            let mut buffer = vec![];
+            fs.open_file(path)
+                .unwrap()
+                .read_to_end(&mut buffer)
+                .unwrap();
+            assert_eq!(buffer, data);
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! A block-level writer of tar headers, in the POSIX ustar format with PAX extensions.
+
+use crate::parser::{
+    align_block,
+    typeflag::{PAX_GLOBAL, PAX_HEADER},
+    BLOCK_SIZE,
+};
+use std::io::{self, Write};
+
+/// The largest size in the 12-byte octal field.
+const MAX_OCTAL_SIZE: u64 = 0o77777777777;
+
+/// Write the header of an entry. A PAX extended header is written before it,
+/// if the path or the size doesn't fit in the ustar fields.
+pub(crate) fn write_header(
+    out: &mut impl Write,
+    path: &str,
+    typeflag: u8,
+    mode: u32,
+    mtime: u64,
+    size: u64,
+) -> io::Result<()> {
+    write_entry_header(out, path, "", typeflag, mode, mtime, size)
+}
+
+/// Write the header of a hard link or a symbolic link. A PAX extended header is written
+/// before it, if the path or the target doesn't fit in the ustar fields.
+pub(crate) fn write_link_header(
+    out: &mut impl Write,
+    path: &str,
+    target: &str,
+    typeflag: u8,
+    mtime: u64,
+) -> io::Result<()> {
+    write_entry_header(out, path, target, typeflag, 0o777, mtime, 0)
+}
+
+/// Write a PAX extended header with the records, which applies to the next entry.
+pub(crate) fn write_pax(
+    out: &mut impl Write,
+    records: &[(&str, &str)],
+    mtime: u64,
+) -> io::Result<()> {
+    let records = records
+        .iter()
+        .flat_map(|(key, value)| pax_record(key, value))
+        .collect::<Vec<_>>();
+    write_pax_data(out, PAX_HEADER, &records, mtime)
+}
+
+/// Write a PAX extended or global header with the encoded records.
+pub(crate) fn write_pax_data(
+    out: &mut impl Write,
+    typeflag: u8,
+    data: &[u8],
+    mtime: u64,
+) -> io::Result<()> {
+    let name = if typeflag == PAX_GLOBAL {
+        "././@GlobalHead"
+    } else {
+        "././@PaxHeader"
+    };
+    out.write_all(&ustar_block(
+        ("", name),
+        "",
+        typeflag,
+        0o644,
+        mtime,
+        data.len() as u64,
+    ))?;
+    out.write_all(data)?;
+    write_padding(out, data.len() as u64)
+}
+
+fn write_entry_header(
+    out: &mut impl Write,
+    path: &str,
+    linkname: &str,
+    typeflag: u8,
+    mode: u32,
+    mtime: u64,
+    size: u64,
+) -> io::Result<()> {
+    let split = split_path(path);
+    let mut records = vec![];
+    if split.is_none() {
+        records.push(("path", path.to_string()));
+    }
+    if linkname.len() > 100 {
+        records.push(("linkpath", linkname.to_string()));
+    }
+    if size > MAX_OCTAL_SIZE {
+        records.push(("size", size.to_string()));
+    }
+    if !records.is_empty() {
+        let records = records
+            .iter()
+            .map(|(key, value)| (*key, value.as_str()))
+            .collect::<Vec<_>>();
+        write_pax(out, &records, mtime)?;
+    }
+    // The truncated names are for the readers without PAX support.
+    let (prefix, name) = split.unwrap_or(("", truncate(path, 100)));
+    out.write_all(&ustar_block(
+        (prefix, name),
+        truncate(linkname, 100),
+        typeflag,
+        mode,
+        mtime,
+        size.min(MAX_OCTAL_SIZE),
+    ))
+}
+
+/// Write the zeros after the contents of `size` bytes, up to the block boundary.
+pub(crate) fn write_padding(out: &mut impl Write, size: u64) -> io::Result<()> {
+    let padding = (align_block(size) - size) as usize;
+    out.write_all(&[0; BLOCK_SIZE as usize][..padding])
+}
+
+/// Write the end-of-archive blocks.
+pub(crate) fn write_end(out: &mut impl Write) -> io::Result<()> {
+    out.write_all(&[0; 2 * BLOCK_SIZE as usize])
+}
+
+/// Split the path into the ustar prefix and name fields, if it fits.
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= 100 {
+        return Some(("", path));
+    }
+    path.match_indices('/')
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100 && !name.is_empty())
+}
+
+/// Truncate at a char boundary.
+fn truncate(s: &str, len: usize) -> &str {
+    let mut end = s.len().min(len);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// A PAX record, `<len> <key>=<value>\n`, where the length includes its own digits.
+/// The value may be binary, like an extended attribute.
+pub(crate) fn pax_record(key: &str, value: impl AsRef<[u8]>) -> Vec<u8> {
+    let value = value.as_ref();
+    let len = key.len() + value.len() + 3;
+    let mut total = len + len.to_string().len();
+    if total.to_string().len() > len.to_string().len() {
+        total += 1;
+    }
+    let mut record = format!("{total} {key}=").into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+fn ustar_block(
+    (prefix, name): (&str, &str),
+    linkname: &str,
+    typeflag: u8,
+    mode: u32,
+    mtime: u64,
+    size: u64,
+) -> [u8; BLOCK_SIZE as usize] {
+    let mut block = [0; BLOCK_SIZE as usize];
+    block[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut block[100..108], mode as u64);
+    write_octal(&mut block[108..116], 0);
+    write_octal(&mut block[116..124], 0);
+    write_octal(&mut block[124..136], size);
+    write_octal(&mut block[136..148], mtime);
+    block[156] = typeflag;
+    block[157..157 + linkname.len()].copy_from_slice(linkname.as_bytes());
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+    block[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    block[148..156].fill(b' ');
+    let checksum = block.iter().map(|b| *b as u64).sum::<u64>();
+    write_octal(&mut block[148..155], checksum);
+    block
+}
+
+/// Write a zero-padded octal number, terminated by NUL.
+fn write_octal(field: &mut [u8], n: u64) {
+    let digits = field.len() - 1;
+    let s = format!("{n:0digits$o}");
+    field[..digits].copy_from_slice(&s.as_bytes()[s.len() - digits..]);
+    field[digits] = 0;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{parse_header, typeflag::REGULAR};
+
+    #[test]
+    fn writer() {
+        let long = format!("{}/{}", "a".repeat(120), "b".repeat(90));
+        let longer = "c".repeat(300);
+        let mut archive = vec![];
+        for path in ["short.txt", long.as_str(), longer.as_str()] {
+            write_header(&mut archive, path, REGULAR, 0o644, 0, 5).unwrap();
+            archive.extend(b"hello");
+            write_padding(&mut archive, 5).unwrap();
+        }
+        write_end(&mut archive).unwrap();
+
+        let header = parse_header(&archive[..512]).unwrap();
+        assert_eq!((header.name.as_ref(), header.size), ("short.txt", 5));
+        assert_eq!(parse_header(&archive[1024..1536]).unwrap().name, long);
+
+        let mut tar = tar::Archive::new(archive.as_slice());
+        let paths = tar
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["short.txt", long.as_str(), longer.as_str()]);
+    }
+}
@@ -0,0 +1,56 @@
+//! Extended attributes stored in PAX headers.
+
+use crate::{path_key, TarFS, Xattrs};
+use std::ops::Deref;
+use vfs::{error::VfsErrorKind, VfsResult};
+
+/// The PAX record prefix of xattrs, used by GNU tar and bsdtar.
+const XATTR_PREFIX: &str = "SCHILY.xattr.";
+
+/// Collect the xattrs from the PAX records.
+pub(crate) fn from_pax(records: Vec<(&str, &[u8])>) -> Xattrs {
+    records
+        .into_iter()
+        .filter_map(|(key, value)| {
+            Some((key.strip_prefix(XATTR_PREFIX)?.to_string(), value.to_vec()))
+        })
+        .collect()
+}
+
+impl<F: Deref<Target = [u8]>> TarFS<F> {
+    /// Get the extended attributes of an entry, from the `SCHILY.xattr.*` PAX records.
+    /// The symbolic links are not followed.
+    pub fn xattrs(&self, path: &str) -> VfsResult<Xattrs> {
+        let key = path_key(path);
+        match self.xattrs.get(&key) {
+            Some(xattrs) => Ok(xattrs.clone()),
+            None if self.find_entry(&key).is_some() => Ok(Xattrs::new()),
+            None => Err(VfsErrorKind::FileNotFound.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{test_util::ArchiveBuilder, writer::pax_record, TarFS};
+
+    #[test]
+    fn xattrs() {
+        let mut data = pax_record("SCHILY.xattr.user.mime_type", "text/plain");
+        data.extend(pax_record("SCHILY.xattr.security.capability", [1, 0xff, 0]));
+        data.extend(pax_record("mtime", "0"));
+        let archive = ArchiveBuilder::new()
+            .pax_data(&data)
+            .file("a", b"")
+            .file("b", b"")
+            .finish();
+        let fs = TarFS::new(archive).unwrap();
+
+        let xattrs = fs.xattrs("/a").unwrap();
+        assert_eq!(xattrs.len(), 2);
+        assert_eq!(xattrs["user.mime_type"], b"text/plain");
+        assert_eq!(xattrs["security.capability"], [1, 0xff, 0]);
+        assert!(fs.xattrs("/b").unwrap().is_empty());
+        assert!(fs.xattrs("/c").is_err());
+    }
+}